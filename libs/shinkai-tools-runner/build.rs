@@ -14,5 +14,22 @@ fn main() {
             Some(PathBuf::from("../../target").join(&profile)),
         )
         .unwrap();
+        // The Node.js runner binaries are only downloaded when asked for
+        if env::var("CARGO_FEATURE_NODE").is_ok() {
+            copy_assets::copy_node(
+                copy_assets::NODE_VERSION,
+                Some(PathBuf::from(".")),
+                Some(PathBuf::from("../../target").join(&profile)),
+            )
+            .unwrap();
+        }
+        if env::var("CARGO_FEATURE_BUN").is_ok() {
+            copy_assets::copy_bun(
+                copy_assets::BUN_VERSION,
+                Some(PathBuf::from(".")),
+                Some(PathBuf::from("../../target").join(&profile)),
+            )
+            .unwrap();
+        }
     }
 }
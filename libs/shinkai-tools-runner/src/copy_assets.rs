@@ -7,11 +7,19 @@ use std::path::PathBuf;
 
 pub static DENO_VERSION: &str = "v2.1.1";
 pub static UV_VERSION: &str = "v0.5.7";
+pub static NODE_VERSION: &str = "v22.12.0";
+pub static BUN_VERSION: &str = "v1.1.38";
 static PROFILE: Lazy<String> =
     Lazy::new(|| std::env::var("PROFILE").unwrap_or_else(|_| "debug".to_string()));
 
 static RESOURCES_FOLDER_NAME: &str = "shinkai-tools-runner-resources";
 
+/// Modules imported by the Deno tool definition extraction, cached at build time so it works offline
+pub static DENO_DEFINITION_DEPENDENCIES: &[&str] =
+    &["jsr:@std/path@1.0.8", "npm:ts-json-schema-generator@2.3.0"];
+/// `DENO_DIR` holding `DENO_DEFINITION_DEPENDENCIES`, next to the deno binary
+pub static DENO_DEFINITION_CACHE_FOLDER_NAME: &str = "deno-definition-cache";
+
 pub fn add_exec_permissions(binary_path: &PathBuf) -> Result<(), std::io::Error> {
     if !cfg!(target_os = "windows") {
         let output = std::process::Command::new("chmod")
@@ -43,6 +51,23 @@ pub fn deno_binary_name() -> String {
     }
 }
 
+pub fn bun_binary_name() -> String {
+    if cfg!(target_os = "windows") {
+        "bun.exe".to_string()
+    } else {
+        "bun".to_string()
+    }
+}
+
+/// Node binary inside the distribution folder copied by `copy_node`
+pub fn node_binary_relative_path() -> PathBuf {
+    if cfg!(target_os = "windows") {
+        PathBuf::from("node").join("node.exe")
+    } else {
+        PathBuf::from("node").join("bin").join("node")
+    }
+}
+
 pub fn get_source_path() -> PathBuf {
     Path::new(env!("CARGO_MANIFEST_DIR")).to_path_buf()
 }
@@ -109,6 +134,7 @@ pub fn copy_assets(
     target_path: Option<PathBuf>,
 ) -> Result<(), std::io::Error> {
     copy_deno(DENO_VERSION, source_path.clone(), target_path.clone())?;
+    cache_deno_definition_dependencies(source_path.clone(), target_path.clone())?;
     copy_uv(UV_VERSION, source_path.clone(), target_path.clone())?;
     Ok(())
 }
@@ -192,6 +218,58 @@ pub fn copy_deno(
     Ok(())
 }
 
+/// Caches `DENO_DEFINITION_DEPENDENCIES` with the downloaded deno binary into
+/// `shinkai-tools-runner-resources/deno-definition-cache`
+///
+/// Best-effort, skipped for offline builds (`CARGO_NET_OFFLINE=true`) and only warning on failure
+/// since the runner downloads the dependencies when the cache is missing.
+pub fn cache_deno_definition_dependencies(
+    source_path: Option<PathBuf>,
+    target_path: Option<PathBuf>,
+) -> Result<(), std::io::Error> {
+    if env::var("CARGO_NET_OFFLINE").is_ok_and(|offline| offline == "true") {
+        println!("cargo:warning=offline build, skipping the deno definition dependencies cache");
+        return Ok(());
+    }
+    let source_path = source_path
+        .unwrap_or_else(get_source_path)
+        .join(RESOURCES_FOLDER_NAME);
+    let target_path = target_path
+        .unwrap_or_else(get_target_path)
+        .join(RESOURCES_FOLDER_NAME);
+    let cache_source_path = source_path.join(DENO_DEFINITION_CACHE_FOLDER_NAME);
+
+    println!(
+        "caching deno definition dependencies in {}",
+        cache_source_path.display()
+    );
+    let output = std::process::Command::new(source_path.join(deno_binary_name()))
+        .arg("cache")
+        .args(DENO_DEFINITION_DEPENDENCIES)
+        .env("DENO_DIR", &cache_source_path)
+        .env("NO_COLOR", "true")
+        .output();
+    let error = match output {
+        Ok(output) if output.status.success() => None,
+        Ok(output) => Some(String::from_utf8_lossy(&output.stderr).to_string()),
+        Err(err) => Some(err.to_string()),
+    };
+    if let Some(error) = error {
+        println!(
+            "cargo:warning=failed to cache deno definition dependencies, they will be downloaded when needed: {}",
+            error.lines().next().unwrap_or_default()
+        );
+        return Ok(());
+    }
+
+    copy_folder(
+        &cache_source_path,
+        &target_path.join(DENO_DEFINITION_CACHE_FOLDER_NAME),
+    )?;
+    println!("successfully cached deno definition dependencies");
+    Ok(())
+}
+
 pub fn copy_uv(
     version: &str,
     source_path: Option<PathBuf>,
@@ -354,6 +432,208 @@ pub fn copy_uv(
     Ok(())
 }
 
+/// Downloads `url` to `archive_path` and extracts it into `folder_path`, zip or tar.gz by extension
+fn download_and_extract(url: &str, archive_path: &Path, folder_path: &Path) {
+    println!("downloading {} to {}", url, archive_path.display());
+    let mut response = reqwest::blocking::Client::new()
+        .get(url)
+        .send()
+        .unwrap_or_else(|err| panic!("failed to download {}: {}", url, err));
+    if !response.status().is_success() {
+        panic!("failed to download {}: {}", url, response.status());
+    }
+    let mut file = fs::File::create(archive_path)
+        .unwrap_or_else(|err| panic!("failed to create archive file: {}", err));
+    std::io::copy(&mut response, &mut file)
+        .unwrap_or_else(|err| panic!("failed to write archive to file: {}", err));
+    file.flush().expect("failed to flush archive file");
+
+    println!("extracting archive to: {}", folder_path.display());
+    let archive_file = fs::File::open(archive_path).expect("failed to read archive");
+    if url.ends_with(".zip") {
+        zip::ZipArchive::new(archive_file)
+            .expect("failed to open zip archive")
+            .extract(folder_path)
+            .expect("failed to extract zip archive");
+    } else {
+        tar::Archive::new(flate2::read::GzDecoder::new(archive_file))
+            .unpack(folder_path)
+            .expect("failed to extract tar.gz archive");
+    }
+}
+
+/// Copies a folder recursively, symlinks are skipped since the runners never go through them
+fn copy_folder(source_path: &Path, target_path: &Path) -> Result<(), std::io::Error> {
+    fs::create_dir_all(target_path)?;
+    for entry in fs::read_dir(source_path)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        let target = target_path.join(entry.file_name());
+        if file_type.is_dir() {
+            copy_folder(&entry.path(), &target)?;
+        } else if file_type.is_file() {
+            fs::copy(entry.path(), &target)?;
+        }
+    }
+    Ok(())
+}
+
+/// Copies the Node distribution, with npm, to `shinkai-tools-runner-resources/node`
+pub fn copy_node(
+    version: &str,
+    source_path: Option<PathBuf>,
+    target_path: Option<PathBuf>,
+) -> Result<(), std::io::Error> {
+    let source_path = source_path
+        .unwrap_or_else(get_source_path)
+        .join(RESOURCES_FOLDER_NAME);
+    let target_path = target_path
+        .unwrap_or_else(get_target_path)
+        .join(RESOURCES_FOLDER_NAME);
+    println!("resources path: {}", source_path.display());
+    println!("target path: {}", target_path.display());
+    fs::create_dir_all(&source_path).unwrap_or_else(|err| {
+        panic!("failed to create resources directory: {}", err);
+    });
+
+    let platform = if cfg!(target_os = "windows") {
+        if cfg!(target_arch = "x86_64") {
+            "win-x64"
+        } else if cfg!(target_arch = "aarch64") {
+            "win-arm64"
+        } else {
+            panic!("unsupported Windows architecture");
+        }
+    } else if cfg!(target_os = "macos") {
+        if cfg!(target_arch = "x86_64") {
+            "darwin-x64"
+        } else if cfg!(target_arch = "aarch64") {
+            "darwin-arm64"
+        } else {
+            panic!("unsupported macOS architecture");
+        }
+    } else if cfg!(target_arch = "x86_64") {
+        "linux-x64"
+    } else if cfg!(target_arch = "aarch64") {
+        "linux-arm64"
+    } else {
+        panic!("unsupported Linux architecture");
+    };
+    let distribution_name = format!("node-{}-{}", version, platform);
+    let distribution_source_path = source_path.join(&distribution_name);
+    if !distribution_source_path.exists() {
+        println!("node distribution does not exist, downloading...");
+        let extension = if cfg!(windows) { "zip" } else { "tar.gz" };
+        download_and_extract(
+            &format!(
+                "https://nodejs.org/dist/{}/{}.{}",
+                version, distribution_name, extension
+            ),
+            &source_path.join(format!("{}.{}", distribution_name, extension)),
+            &source_path,
+        );
+        println!("successfully extracted node distribution");
+    } else {
+        println!("node distribution already exists, skipping download");
+    }
+
+    let node_target_path = target_path.join("node");
+    println!(
+        "copying node distribution from {} to {}",
+        distribution_source_path.display(),
+        node_target_path.display()
+    );
+    if node_target_path.exists() {
+        fs::remove_dir_all(&node_target_path)?;
+    }
+    copy_folder(&distribution_source_path, &node_target_path).unwrap_or_else(|err| {
+        panic!("failed to copy node distribution to target path: {}", err);
+    });
+
+    let node_binary_target_path = target_path.join(node_binary_relative_path());
+    println!("adding executable permissions to node binary");
+    add_exec_permissions(&node_binary_target_path)?;
+    Ok(())
+}
+
+pub fn copy_bun(
+    version: &str,
+    source_path: Option<PathBuf>,
+    target_path: Option<PathBuf>,
+) -> Result<(), std::io::Error> {
+    let source_path = source_path
+        .unwrap_or_else(get_source_path)
+        .join(RESOURCES_FOLDER_NAME);
+    let target_path = target_path
+        .unwrap_or_else(get_target_path)
+        .join(RESOURCES_FOLDER_NAME);
+    println!("resources path: {}", source_path.display());
+    println!("target path: {}", target_path.display());
+    fs::create_dir_all(&source_path).unwrap_or_else(|err| {
+        panic!("failed to create resources directory: {}", err);
+    });
+    fs::create_dir_all(&target_path).unwrap_or_else(|err| {
+        panic!("failed to create target directory: {}", err);
+    });
+
+    let platform = if cfg!(target_os = "windows") {
+        "windows-x64"
+    } else if cfg!(target_os = "macos") {
+        if cfg!(target_arch = "x86_64") {
+            "darwin-x64"
+        } else if cfg!(target_arch = "aarch64") {
+            "darwin-aarch64"
+        } else {
+            panic!("unsupported macOS architecture");
+        }
+    } else if cfg!(target_arch = "x86_64") {
+        "linux-x64"
+    } else if cfg!(target_arch = "aarch64") {
+        "linux-aarch64"
+    } else {
+        panic!("unsupported Linux architecture");
+    };
+    let bun_binary_name = bun_binary_name();
+    let bun_binary_source_path = source_path.join(&bun_binary_name);
+    let bun_binary_target_path = target_path.join(&bun_binary_name);
+    if !bun_binary_source_path.exists() {
+        println!("bun binary does not exist, downloading...");
+        download_and_extract(
+            &format!(
+                "https://github.com/oven-sh/bun/releases/download/bun-{}/bun-{}.zip",
+                version, platform
+            ),
+            &source_path.join("bun.zip"),
+            &source_path,
+        );
+        let extracted_folder = source_path.join(format!("bun-{}", platform));
+        fs::rename(
+            extracted_folder.join(&bun_binary_name),
+            &bun_binary_source_path,
+        )
+        .expect("failed to move bun binary from extracted folder");
+        fs::remove_dir_all(extracted_folder).expect("failed to remove extracted folder");
+        println!("successfully extracted bun binary");
+    } else {
+        println!("bun binary already exists, skipping download");
+    }
+
+    println!(
+        "copying bun binary from {} to {}",
+        bun_binary_source_path.display(),
+        bun_binary_target_path.display()
+    );
+    fs::copy(&bun_binary_source_path, &bun_binary_target_path).unwrap_or_else(|err| {
+        panic!(
+            "failed to copy downloaded bun binary to target path: {}",
+            err
+        );
+    });
+    println!("adding executable permissions to bun binary");
+    add_exec_permissions(&bun_binary_target_path)?;
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -370,4 +650,18 @@ mod tests {
         .await
         .unwrap();
     }
+
+    #[test]
+    fn test_cache_deno_definition_dependencies_is_best_effort() {
+        let folder = tempfile::tempdir().unwrap();
+        let source = folder.path().join("source");
+        let destination = folder.path().join("destination");
+        fs::create_dir_all(source.join(RESOURCES_FOLDER_NAME)).unwrap();
+        // No deno binary in the resources folder, the build keeps going without the cache
+        cache_deno_definition_dependencies(Some(source), Some(destination.clone())).unwrap();
+        assert!(!destination
+            .join(RESOURCES_FOLDER_NAME)
+            .join(DENO_DEFINITION_CACHE_FOLDER_NAME)
+            .exists());
+    }
 }
@@ -0,0 +1,260 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use regex::Regex;
+
+use super::{
+    code_files::CodeFiles,
+    deno_runner::DenoRunner,
+    deno_runner_options::DenoRunnerOptions,
+    execution_storage::ExecutionStorage,
+    runner_type::RunnerType,
+    tool_definition::{EmbeddingMetadata, ToolDefinition},
+};
+
+pub static DEFAULT_EMBEDDING_MODEL_NAME: &str = "snowflake-arctic-embed:xs";
+pub static ESBUILD_VERSION: &str = "0.23.0";
+
+/// A tool packed into a single self-contained source file plus its extended definition
+#[derive(Debug, Clone)]
+pub struct ToolBundle {
+    pub code: String,
+    pub definition: ToolDefinition,
+}
+
+impl ToolBundle {
+    pub const CODE_FILE_NAME: &'static str = "index.ts";
+    pub const DEFINITION_FILE_NAME: &'static str = "definition.json";
+
+    /// Writes `index.ts` and `definition.json` into the output folder, creating it if needed
+    pub fn write(&self, output_folder: &Path) -> anyhow::Result<()> {
+        std::fs::create_dir_all(output_folder).map_err(|e| {
+            log::error!(
+                "failed to create output folder {}: {}",
+                output_folder.display(),
+                e
+            );
+            e
+        })?;
+        let code_file_path = output_folder.join(Self::CODE_FILE_NAME);
+        log::info!("writing bundled code to {}", code_file_path.display());
+        std::fs::write(&code_file_path, &self.code)?;
+
+        let definition_file_path = output_folder.join(Self::DEFINITION_FILE_NAME);
+        log::info!("writing definition to {}", definition_file_path.display());
+        std::fs::write(
+            &definition_file_path,
+            serde_json::to_string_pretty(&self.definition)?,
+        )?;
+        Ok(())
+    }
+}
+
+/// Bundles multi-file Deno tools into a single file using the managed deno binary
+///
+/// Local modules are inlined with esbuild while remote specifiers (`npm:`, `jsr:`, `node:`,
+/// `http(s):`) are kept as imports so they are resolved by deno when the tool runs.
+pub struct ToolBundler {
+    options: DenoRunnerOptions,
+}
+
+impl ToolBundler {
+    pub const BUNDLER_ENTRYPOINT_FILE_NAME: &'static str = "__shinkai_bundler.ts";
+    pub const BUNDLE_OUTPUT_FILE_NAME: &'static str = "__shinkai_bundle.js";
+
+    pub fn new(options: Option<DenoRunnerOptions>) -> Self {
+        ToolBundler {
+            options: options.unwrap_or_default(),
+        }
+    }
+
+    /// Bundles the code and extracts its definition
+    ///
+    /// # Returns
+    ///
+    /// Returns a Result containing:
+    /// - Ok(ToolBundle): The single-file code and the definition extended with code and embedding metadata
+    /// - Err(anyhow::Error): Any errors that occurred while bundling or extracting the definition
+    pub async fn bundle(&self, code_files: CodeFiles) -> anyhow::Result<ToolBundle> {
+        let code = self.bundle_code(code_files.clone()).await?;
+
+        // Types are erased by the bundler so schemas are built from the original sources
+        let mut definition = DenoRunner::new(
+            code_files,
+            serde_json::Value::Null,
+            Some(self.options.clone()),
+        )
+        .definition()
+        .await
+        .map_err(|e| anyhow::anyhow!("failed to extract tool definition: {}", e))?;
+        definition.code = Some(code.clone());
+        definition.embedding_metadata = Some(EmbeddingMetadata {
+            model_name: DEFAULT_EMBEDDING_MODEL_NAME.to_string(),
+            embeddings: vec![],
+        });
+        Ok(ToolBundle { code, definition })
+    }
+
+    /// Inlines every local module reachable from the entrypoint into a single ES module
+    pub async fn bundle_code(&self, code_files: CodeFiles) -> anyhow::Result<String> {
+        if !code_files.files.contains_key(&code_files.entrypoint) {
+            return Err(anyhow::anyhow!(
+                "no entrypoint found {}",
+                code_files.entrypoint
+            ));
+        }
+
+        let mut files = HashMap::from([(
+            Self::BUNDLER_ENTRYPOINT_FILE_NAME.to_string(),
+            r#"
+            import * as esbuild from "npm:esbuild@{esbuild_version}";
+
+            const [entryPoint, outfile] = Deno.args;
+            try {
+                await esbuild.build({
+                    entryPoints: [entryPoint],
+                    outfile,
+                    bundle: true,
+                    format: "esm",
+                    platform: "neutral",
+                    target: "esnext",
+                    treeShaking: false,
+                    external: ["npm:*", "jsr:*", "node:*", "http://*", "https://*"],
+                    logLevel: "error",
+                });
+            } finally {
+                await esbuild.stop();
+            }
+        "#
+            .replace("{esbuild_version}", ESBUILD_VERSION),
+        )]);
+        files.extend(code_files.files.clone());
+        let bundler_code = CodeFiles {
+            files,
+            entrypoint: Self::BUNDLER_ENTRYPOINT_FILE_NAME.to_string(),
+        };
+
+        let execution_storage = ExecutionStorage::new(bundler_code, self.options.context.clone());
+        let _lease = execution_storage.acquire_lease()?;
+        execution_storage.init_for_deno(None, RunnerType::Host)?;
+        let entrypoint_file_path = execution_storage
+            .code_folder_path
+            .join(&code_files.entrypoint);
+        // The code folder is shared by every bundle of the same code, so each call gets its own output
+        let output_file_path = execution_storage.code_folder_path.join(format!(
+            "{}.{}",
+            nanoid::nanoid!(),
+            Self::BUNDLE_OUTPUT_FILE_NAME
+        ));
+
+        let binary_path = std::path::absolute(self.options.deno_binary_path.clone())?;
+        let deno_cache_folder_path =
+            std::path::absolute(execution_storage.deno_cache_folder_path(RunnerType::Host))?;
+        log::info!("bundling tool with deno at path: {}", binary_path.display());
+
+        // Downloads esbuild first, its native binary has to exist to be the only allowed command
+        let output = tokio::process::Command::new(&binary_path)
+            .arg("cache")
+            .arg(execution_storage.code_entrypoint_file_path.clone())
+            .env("DENO_DIR", &deno_cache_folder_path)
+            .env("NO_COLOR", "true")
+            .current_dir(execution_storage.code_folder_path.clone())
+            .kill_on_drop(true)
+            .output()
+            .await?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+            log::error!("failed to download esbuild: {}", stderr);
+            return Err(anyhow::anyhow!("failed to download esbuild: {}", stderr));
+        }
+
+        let esbuild_binary_path = esbuild_binary_path(&deno_cache_folder_path)?;
+        let mut command = tokio::process::Command::new(binary_path);
+        command
+            .args(["run", "--allow-env", "--allow-sys"])
+            .arg(format!(
+                "--allow-read={},{}",
+                execution_storage.code_folder_path.to_string_lossy(),
+                deno_cache_folder_path.to_string_lossy()
+            ))
+            .arg(format!(
+                "--allow-write={}",
+                output_file_path.to_string_lossy()
+            ))
+            .arg(format!(
+                "--allow-run={}",
+                esbuild_binary_path.to_string_lossy()
+            ))
+            .arg(execution_storage.code_entrypoint_file_path.clone())
+            .arg(entrypoint_file_path)
+            .arg(output_file_path.clone())
+            .env("DENO_DIR", &deno_cache_folder_path)
+            .env("ESBUILD_BINARY_PATH", &esbuild_binary_path)
+            .env("NO_COLOR", "true")
+            .current_dir(execution_storage.code_folder_path.clone())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .kill_on_drop(true);
+        let output = command.spawn()?.wait_with_output().await?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+            log::error!("failed to bundle tool: {}", stderr);
+            return Err(anyhow::anyhow!("failed to bundle tool: {}", stderr));
+        }
+
+        let code = std::fs::read_to_string(&output_file_path).map_err(|e| {
+            log::error!("failed to read bundled code: {}", e);
+            e
+        })?;
+        let _ = std::fs::remove_file(&output_file_path);
+
+        // The runner wrapper calls `run` from the module scope, so it must survive bundling
+        let run_regex =
+            Regex::new(r"(?m)^(?:async\s+)?function\s*\*?\s*run\s*\(|^(?:const|let|var)\s+run\s*=")
+                .unwrap();
+        if !run_regex.is_match(&code) {
+            return Err(anyhow::anyhow!(
+                "bundled code does not define a top-level run function"
+            ));
+        }
+        log::info!("tool bundled successfully ({} bytes)", code.len());
+        Ok(code)
+    }
+}
+
+/// esbuild native binary for this platform, installed by deno next to the `esbuild` package
+fn esbuild_binary_path(deno_cache_folder_path: &Path) -> anyhow::Result<PathBuf> {
+    let platform = match std::env::consts::OS {
+        "windows" => "win32",
+        os => os,
+    };
+    let arch = match std::env::consts::ARCH {
+        "x86_64" => "x64",
+        "aarch64" => "arm64",
+        arch => arch,
+    };
+    let package_folder = deno_cache_folder_path
+        .join("npm")
+        .join("registry.npmjs.org")
+        .join("@esbuild")
+        .join(format!("{}-{}", platform, arch))
+        .join(ESBUILD_VERSION);
+    let binary_path = if cfg!(windows) {
+        package_folder.join("esbuild.exe")
+    } else {
+        package_folder.join("bin").join("esbuild")
+    };
+    if !binary_path.exists() {
+        return Err(anyhow::anyhow!(
+            "esbuild binary not found at {}",
+            binary_path.display()
+        ));
+    }
+    Ok(binary_path)
+}
+
+#[cfg(test)]
+#[path = "bundle.test.rs"]
+mod tests;
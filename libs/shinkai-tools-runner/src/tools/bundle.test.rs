@@ -0,0 +1,137 @@
+use std::collections::HashMap;
+
+use serde_json::json;
+
+use crate::tools::{
+    bundle::{esbuild_binary_path, ToolBundle, ToolBundler, DEFAULT_EMBEDDING_MODEL_NAME},
+    code_files::CodeFiles,
+    deno_runner::DenoRunner,
+    deno_runner_options::DenoRunnerOptions,
+    runner_type::RunnerType,
+};
+
+#[tokio::test]
+async fn bundle_multiple_files() {
+    let _ = env_logger::builder()
+        .filter_level(log::LevelFilter::Info)
+        .is_test(true)
+        .try_init();
+
+    let code_files = CodeFiles {
+        files: HashMap::from([
+            (
+                "main.ts".to_string(),
+                r#"
+                import { greet } from "./utils/greet.ts";
+
+                export const metadata = {
+                    id: "shinkai-tool-greet",
+                    name: "Shinkai: Greet",
+                    description: "Greets someone",
+                    author: "Shinkai",
+                    keywords: ["greet"],
+                };
+
+                type CONFIG = {};
+                type INPUTS = { name: string };
+                type OUTPUT = { message: string };
+
+                export async function run(config: CONFIG, inputs: INPUTS): Promise<OUTPUT> {
+                    return { message: greet(inputs.name) };
+                }
+            "#
+                .to_string(),
+            ),
+            (
+                "utils/greet.ts".to_string(),
+                r#"
+                export const greet = (name: string) => `Hello, ${name}!`;
+            "#
+                .to_string(),
+            ),
+        ]),
+        entrypoint: "main.ts".to_string(),
+    };
+
+    let bundle = ToolBundler::new(None).bundle(code_files).await.unwrap();
+    assert!(!bundle.code.contains("./utils/greet.ts"));
+    assert_eq!(bundle.definition.id, "shinkai-tool-greet");
+    assert_eq!(bundle.definition.code.as_ref().unwrap(), &bundle.code);
+    assert_eq!(
+        bundle
+            .definition
+            .embedding_metadata
+            .as_ref()
+            .unwrap()
+            .model_name,
+        DEFAULT_EMBEDDING_MODEL_NAME
+    );
+    assert_eq!(
+        bundle.definition.parameters["properties"]["name"]["type"],
+        "string"
+    );
+
+    let output_folder = tempfile::tempdir().unwrap();
+    bundle.write(output_folder.path()).unwrap();
+    let definition_json =
+        std::fs::read_to_string(output_folder.path().join(ToolBundle::DEFINITION_FILE_NAME))
+            .unwrap();
+    assert!(definition_json.contains("shinkai-tool-greet"));
+
+    let deno_runner = DenoRunner::new(
+        CodeFiles {
+            files: HashMap::from([("index.ts".to_string(), bundle.code)]),
+            entrypoint: "index.ts".to_string(),
+        },
+        json!({}),
+        Some(DenoRunnerOptions {
+            force_runner_type: Some(RunnerType::Host),
+            ..Default::default()
+        }),
+    );
+    let result = deno_runner
+        .run(None, json!({ "name": "world" }), None)
+        .await
+        .unwrap();
+    assert_eq!(result.data["message"], "Hello, world!");
+}
+
+#[tokio::test]
+async fn bundle_without_entrypoint_fails() {
+    let code_files = CodeFiles {
+        files: HashMap::new(),
+        entrypoint: "main.ts".to_string(),
+    };
+    let result = ToolBundler::new(None).bundle_code(code_files).await;
+    assert!(result.is_err());
+}
+
+#[test]
+fn esbuild_binary_is_looked_up_in_the_deno_cache() {
+    let deno_cache = tempfile::tempdir().unwrap();
+    assert!(esbuild_binary_path(deno_cache.path()).is_err());
+
+    let platform = if cfg!(windows) {
+        "win32"
+    } else {
+        std::env::consts::OS
+    };
+    let arch = if cfg!(target_arch = "x86_64") {
+        "x64"
+    } else {
+        "arm64"
+    };
+    let package_folder = deno_cache
+        .path()
+        .join("npm/registry.npmjs.org/@esbuild")
+        .join(format!("{}-{}", platform, arch))
+        .join(crate::tools::bundle::ESBUILD_VERSION);
+    let binary = if cfg!(windows) {
+        package_folder.join("esbuild.exe")
+    } else {
+        package_folder.join("bin").join("esbuild")
+    };
+    std::fs::create_dir_all(binary.parent().unwrap()).unwrap();
+    std::fs::write(&binary, "").unwrap();
+    assert_eq!(esbuild_binary_path(deno_cache.path()).unwrap(), binary);
+}
@@ -0,0 +1,540 @@
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv4Addr},
+    path::PathBuf,
+    sync::Arc,
+};
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+use subtle::ConstantTimeEq;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader},
+    net::TcpListener,
+    task::JoinHandle,
+};
+
+use super::{
+    container_runtime::{BindMount, ContainerRuntime},
+    execution_error::{ExecutionError, InfrastructureError},
+    host_capabilities::{HostCallContext, HostCapabilities, HttpFetchRequest, LlmRequest},
+    runner_type::RunnerType,
+    tool_call::{ToolCallConfig, ToolCallRequest},
+};
+
+/// What the callback server exposes to the running tool
+#[derive(Clone, Default)]
+pub struct CallbackHandlers {
+    pub context: HostCallContext,
+    pub tool_calls: Option<ToolCallConfig>,
+    pub host_capabilities: Option<Arc<dyn HostCapabilities>>,
+}
+
+impl CallbackHandlers {
+    pub fn is_empty(&self) -> bool {
+        self.tool_calls.is_none() && self.host_capabilities.is_none()
+    }
+}
+
+#[derive(Deserialize)]
+struct SecretRequest {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct KvRequest {
+    key: String,
+    #[serde(default)]
+    value: Value,
+}
+
+/// Loopback HTTP endpoint a running tool uses to call back into the Rust parent
+///
+/// Every request must be a `POST` with a JSON body and an `Authorization: Bearer <token>` header,
+/// responses are `{ "data": ... }` on success or `{ "error": { "message": ... } }` otherwise.
+/// Routes:
+/// - `/call_tool`: runs another tool, body is a `ToolCallRequest`
+/// - `/host/get_secret`: `{ name }`, returns the secret or null
+/// - `/host/kv_get`: `{ key }`, returns the value or null
+/// - `/host/kv_set`: `{ key, value }`
+/// - `/host/http_fetch`: body is a `HttpFetchRequest`, returns a `HttpFetchResponse`
+/// - `/host/call_llm`: body is a `LlmRequest`, returns a `LlmResponse`
+///
+/// Sandbox runs have no network, they reach the server through a unix socket instead, see
+/// `SOCKET_ENV`.
+///
+/// The server stops when dropped.
+pub struct CallbackServer {
+    bind_address: IpAddr,
+    port: u16,
+    /// Unix socket of sandbox runs, in a private folder removed with the server
+    socket: Option<(tempfile::TempDir, PathBuf)>,
+    token: String,
+    task: JoinHandle<()>,
+}
+
+impl CallbackServer {
+    pub const URL_ENV: &'static str = "SHINKAI_CALLBACK_URL";
+    pub const TOKEN_ENV: &'static str = "SHINKAI_CALLBACK_TOKEN";
+    pub const SOCKET_ENV: &'static str = "SHINKAI_CALLBACK_SOCKET";
+    const MAX_BODY_SIZE: usize = 16 * 1024 * 1024;
+
+    /// Client functions prepended to the Deno wrapper so tools can use the server
+    pub const DENO_CLIENT_CODE: &'static str = r#"
+async function shinkaiCallbackPost(url, route, headers, body) {
+    const socketPath = Deno.env.get("SHINKAI_CALLBACK_SOCKET");
+    if (!socketPath) {
+        return await fetch(`${url}${route}`, { method: "POST", headers, body });
+    }
+    // Sandboxed tools have no network, the server is reached through a unix socket
+    const encoder = new TextEncoder();
+    const payload = encoder.encode(body);
+    const head = encoder.encode([
+        `POST ${route} HTTP/1.1`,
+        "Host: localhost",
+        ...Object.entries(headers).map(([name, value]) => `${name}: ${value}`),
+        `Content-Length: ${payload.length}`,
+        "Connection: close",
+        "",
+        "",
+    ].join("\r\n"));
+    const request = new Uint8Array(head.length + payload.length);
+    request.set(head);
+    request.set(payload, head.length);
+    const conn = await Deno.connect({ transport: "unix", path: socketPath });
+    for (let written = 0; written < request.length;) {
+        written += await conn.write(request.subarray(written));
+    }
+    const raw = new TextDecoder().decode(await new Response(conn.readable).arrayBuffer());
+    const separator = raw.indexOf("\r\n\r\n");
+    const status = Number(raw.slice(0, separator).split(" ")[1]);
+    return new Response(raw.slice(separator + 4), { status });
+}
+async function shinkaiCallback(route, body) {
+    const url = Deno.env.get("SHINKAI_CALLBACK_URL");
+    if (!url) {
+        throw new Error("callbacks are not enabled for this tool");
+    }
+    const response = await shinkaiCallbackPost(url, route, {
+        "Authorization": `Bearer ${Deno.env.get("SHINKAI_CALLBACK_TOKEN")}`,
+        "Content-Type": "application/json",
+    }, JSON.stringify(body));
+    const responseBody = await response.json();
+    if (!response.ok) {
+        throw new Error(responseBody?.error?.message ?? `callback ${route} failed with status ${response.status}`);
+    }
+    return responseBody.data;
+}
+async function shinkaiCallTool(toolId, parameters) {
+    return await shinkaiCallback("/call_tool", { tool_id: toolId, parameters: parameters ?? {} });
+}
+const shinkaiHost = {
+    getSecret: (name) => shinkaiCallback("/host/get_secret", { name }),
+    kvGet: (key) => shinkaiCallback("/host/kv_get", { key }),
+    kvSet: (key, value) => shinkaiCallback("/host/kv_set", { key, value }),
+    httpFetch: (request) => shinkaiCallback("/host/http_fetch", request),
+    callLlm: (request) => shinkaiCallback("/host/call_llm", request),
+};
+"#;
+
+    /// Client functions prepended to the Python wrapper so tools can use the server
+    pub const PYTHON_CLIENT_CODE: &'static str = r#"
+def shinkai_callback(route, body):
+    import json as _json
+    import os as _os
+    import urllib.error as _urllib_error
+    import urllib.request as _urllib_request
+    url = _os.environ.get("SHINKAI_CALLBACK_URL")
+    if not url:
+        raise RuntimeError("callbacks are not enabled for this tool")
+    data = _json.dumps(body).encode("utf-8")
+    headers = {
+        "Authorization": "Bearer " + _os.environ.get("SHINKAI_CALLBACK_TOKEN", ""),
+        "Content-Type": "application/json",
+    }
+    socket_path = _os.environ.get("SHINKAI_CALLBACK_SOCKET")
+    if socket_path:
+        # Sandboxed tools have no network, the server is reached through a unix socket
+        import http.client as _http_client
+        import socket as _socket
+
+        class _UnixConnection(_http_client.HTTPConnection):
+            def connect(self):
+                self.sock = _socket.socket(_socket.AF_UNIX, _socket.SOCK_STREAM)
+                self.sock.connect(socket_path)
+
+        connection = _UnixConnection("localhost")
+        try:
+            connection.request("POST", route, body=data, headers=headers)
+            response = connection.getresponse()
+            status = response.status
+            response_body = _json.loads(response.read() or b"{}")
+        finally:
+            connection.close()
+        if status >= 400:
+            raise RuntimeError(response_body.get("error", {}).get("message", "callback " + route + " failed with status " + str(status)))
+        return response_body["data"]
+    request = _urllib_request.Request(url + route, data=data, headers=headers, method="POST")
+    opener = _urllib_request.build_opener(_urllib_request.ProxyHandler({}))
+    try:
+        with opener.open(request) as response:
+            return _json.loads(response.read())["data"]
+    except _urllib_error.HTTPError as e:
+        error_body = _json.loads(e.read() or b"{}")
+        raise RuntimeError(error_body.get("error", {}).get("message", str(e)))
+
+def shinkai_call_tool(tool_id, parameters=None):
+    return shinkai_callback("/call_tool", {"tool_id": tool_id, "parameters": parameters or {}})
+
+def shinkai_get_secret(name):
+    return shinkai_callback("/host/get_secret", {"name": name})
+
+def shinkai_kv_get(key):
+    return shinkai_callback("/host/kv_get", {"key": key})
+
+def shinkai_kv_set(key, value):
+    return shinkai_callback("/host/kv_set", {"key": key, "value": value})
+
+def shinkai_http_fetch(url, method="GET", headers=None, body=None):
+    return shinkai_callback("/host/http_fetch", {"url": url, "method": method, "headers": headers or {}, "body": body})
+
+def shinkai_call_llm(prompt, model=None, options=None):
+    return shinkai_callback("/host/call_llm", {"prompt": prompt, "model": model, "options": options})
+"#;
+
+    /// Starts the server, listening on loopback for host runs, on a unix socket for sandbox runs,
+    /// and on the container runtime host gateway for docker runs so it is only reachable from the
+    /// host and its containers, failing with an `InfrastructureError` when the gateway is unknown
+    pub async fn start(
+        runner_type: &RunnerType,
+        container_runtime: Option<&ContainerRuntime>,
+        handlers: CallbackHandlers,
+    ) -> anyhow::Result<Self> {
+        let bind_address = match runner_type {
+            RunnerType::Host | RunnerType::Sandbox => IpAddr::V4(Ipv4Addr::LOCALHOST),
+            RunnerType::Docker => {
+                let container_runtime = container_runtime
+                    .cloned()
+                    .or_else(ContainerRuntime::detect)
+                    .unwrap_or_default();
+                // A loopback listener can't be reached from containers on Linux
+                container_runtime
+                    .host_gateway_address()
+                    .await
+                    .ok_or_else(|| {
+                        log::error!("unable to find the container host gateway for callbacks");
+                        InfrastructureError(String::from(
+                            "unable to find the container host gateway, tools running in containers can't reach the callback server",
+                        ))
+                    })?
+            }
+        };
+        let token = nanoid::nanoid!(32);
+        let shared_token = Arc::new(token.clone());
+
+        #[cfg(unix)]
+        if matches!(runner_type, RunnerType::Sandbox) {
+            let folder = tempfile::Builder::new()
+                .prefix("shinkai-callback-")
+                .tempdir()?;
+            let socket_path = folder.path().join("callback.sock");
+            let listener = tokio::net::UnixListener::bind(&socket_path)?;
+            log::info!("callback server listening on {}", socket_path.display());
+            let task = tokio::spawn(async move {
+                loop {
+                    match listener.accept().await {
+                        Ok((stream, _)) => {
+                            Self::spawn_connection(stream, shared_token.clone(), handlers.clone())
+                        }
+                        Err(e) => {
+                            log::error!("callback server failed to accept connection: {}", e)
+                        }
+                    }
+                }
+            });
+            return Ok(CallbackServer {
+                bind_address,
+                port: 0,
+                socket: Some((folder, socket_path)),
+                token,
+                task,
+            });
+        }
+
+        let listener = TcpListener::bind((bind_address, 0)).await?;
+        let port = listener.local_addr()?.port();
+        log::info!("callback server listening on {}:{}", bind_address, port);
+        let task = tokio::spawn(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, address)) => {
+                        log::debug!("callback server connection from {}", address);
+                        Self::spawn_connection(stream, shared_token.clone(), handlers.clone())
+                    }
+                    Err(e) => log::error!("callback server failed to accept connection: {}", e),
+                }
+            }
+        });
+        Ok(CallbackServer {
+            bind_address,
+            port,
+            socket: None,
+            token,
+            task,
+        })
+    }
+
+    fn spawn_connection(
+        stream: impl AsyncRead + AsyncWrite + Send + 'static,
+        token: Arc<String>,
+        handlers: CallbackHandlers,
+    ) {
+        tokio::spawn(async move {
+            if let Err(e) = Self::handle_connection(stream, &token, &handlers).await {
+                log::error!("callback server failed to handle request: {}", e);
+            }
+        });
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
+    /// Unix socket sandbox runs connect to
+    pub fn socket_path(&self) -> Option<&PathBuf> {
+        self.socket.as_ref().map(|(_, socket_path)| socket_path)
+    }
+
+    pub fn url(&self, runner_type: &RunnerType) -> String {
+        if self.socket.is_some() {
+            // Only used to build the request, the connection goes through the socket
+            return String::from("http://localhost");
+        }
+        let host = match runner_type {
+            RunnerType::Host | RunnerType::Sandbox => String::from("127.0.0.1"),
+            // Only bound on loopback outside Linux, where Docker Desktop forwards it to the host
+            RunnerType::Docker if self.bind_address.is_loopback() => {
+                String::from("host.docker.internal")
+            }
+            RunnerType::Docker => self.bind_address.to_string(),
+        };
+        format!("http://{}:{}", host, self.port)
+    }
+
+    /// Environment variables telling the tool how to reach the server
+    pub fn envs(&self, runner_type: &RunnerType) -> HashMap<String, String> {
+        let mut envs = HashMap::from([
+            (Self::URL_ENV.to_string(), self.url(runner_type)),
+            (Self::TOKEN_ENV.to_string(), self.token.clone()),
+        ]);
+        if let Some(socket_path) = self.socket_path() {
+            envs.insert(
+                Self::SOCKET_ENV.to_string(),
+                socket_path.to_string_lossy().to_string(),
+            );
+        }
+        envs
+    }
+
+    /// Folder of the socket given in `envs`, to bind into the sandbox so the tool can connect
+    pub fn sandbox_mounts(envs: Option<&HashMap<String, String>>) -> Vec<BindMount> {
+        envs.and_then(|envs| envs.get(Self::SOCKET_ENV))
+            .and_then(|socket_path| PathBuf::from(socket_path).parent().map(PathBuf::from))
+            .map(|folder| {
+                let folder = folder.to_string_lossy().to_string();
+                BindMount {
+                    source: folder.clone(),
+                    target: folder,
+                    readonly: false,
+                }
+            })
+            .into_iter()
+            .collect()
+    }
+
+    async fn handle_connection(
+        stream: impl AsyncRead + AsyncWrite,
+        token: &str,
+        handlers: &CallbackHandlers,
+    ) -> anyhow::Result<()> {
+        let (read, mut write) = tokio::io::split(stream);
+        let mut reader = BufReader::new(read);
+
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line).await?;
+        let mut request_line_parts = request_line.split_whitespace();
+        let method = request_line_parts.next().unwrap_or_default().to_string();
+        let path = request_line_parts.next().unwrap_or_default().to_string();
+
+        let mut content_length = 0;
+        let mut authorization = String::new();
+        loop {
+            let mut header = String::new();
+            if reader.read_line(&mut header).await? == 0 {
+                break;
+            }
+            let header = header.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = header.split_once(':') {
+                match name.trim().to_ascii_lowercase().as_str() {
+                    "content-length" => content_length = value.trim().parse().unwrap_or(0),
+                    "authorization" => authorization = value.trim().to_string(),
+                    _ => {}
+                }
+            }
+        }
+
+        let authorized: bool = authorization
+            .as_bytes()
+            .ct_eq(format!("Bearer {}", token).as_bytes())
+            .into();
+        let (status, response) = if !authorized {
+            (401, Self::error_body("unauthorized"))
+        } else if method != "POST" {
+            (405, Self::error_body("method not allowed"))
+        } else if content_length > Self::MAX_BODY_SIZE {
+            (413, Self::error_body("request body too large"))
+        } else {
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).await?;
+            match serde_json::from_slice::<Value>(&body) {
+                Ok(body) => Self::route(&path, body, handlers).await,
+                Err(e) => (400, Self::error_body(&format!("invalid json body: {}", e))),
+            }
+        };
+
+        let response = response.to_string();
+        let reason = match status {
+            200 => "OK",
+            400 => "Bad Request",
+            401 => "Unauthorized",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            413 => "Payload Too Large",
+            _ => "Internal Server Error",
+        };
+        write
+            .write_all(
+                format!(
+                    "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    reason,
+                    response.len(),
+                    response
+                )
+                .as_bytes(),
+            )
+            .await?;
+        write.shutdown().await?;
+        Ok(())
+    }
+
+    async fn route(path: &str, body: Value, handlers: &CallbackHandlers) -> (u16, Value) {
+        if path == "/call_tool" {
+            let Some(tool_calls) = &handlers.tool_calls else {
+                return (404, Self::error_body("tool calls are not enabled"));
+            };
+            return match serde_json::from_value::<ToolCallRequest>(body) {
+                Ok(request) => Self::result_response(tool_calls.call(request).await),
+                Err(e) => (
+                    400,
+                    Self::error_body(&format!("invalid tool call request: {}", e)),
+                ),
+            };
+        }
+
+        let Some(capability) = path.strip_prefix("/host/") else {
+            return (404, Self::error_body(&format!("unknown route {}", path)));
+        };
+        let Some(host_capabilities) = &handlers.host_capabilities else {
+            return (404, Self::error_body("host capabilities are not enabled"));
+        };
+        log::info!(
+            "host capability {} called from context {} execution {}",
+            capability,
+            handlers.context.context_id,
+            handlers.context.execution_id
+        );
+        let context = handlers.context.clone();
+        let result = match capability {
+            "get_secret" => match serde_json::from_value::<SecretRequest>(body) {
+                Ok(request) => host_capabilities
+                    .get_secret(context, request.name)
+                    .await
+                    .map(|secret| json!(secret)),
+                Err(e) => return Self::invalid_request(capability, e),
+            },
+            "kv_get" => match serde_json::from_value::<KvRequest>(body) {
+                Ok(request) => host_capabilities
+                    .kv_get(context, request.key)
+                    .await
+                    .map(|value| value.unwrap_or(Value::Null)),
+                Err(e) => return Self::invalid_request(capability, e),
+            },
+            "kv_set" => match serde_json::from_value::<KvRequest>(body) {
+                Ok(request) => host_capabilities
+                    .kv_set(context, request.key, request.value)
+                    .await
+                    .map(|_| Value::Null),
+                Err(e) => return Self::invalid_request(capability, e),
+            },
+            "http_fetch" => match serde_json::from_value::<HttpFetchRequest>(body) {
+                Ok(request) => host_capabilities
+                    .http_fetch(context, request)
+                    .await
+                    .map(|response| json!(response)),
+                Err(e) => return Self::invalid_request(capability, e),
+            },
+            "call_llm" => match serde_json::from_value::<LlmRequest>(body) {
+                Ok(request) => host_capabilities
+                    .call_llm(context, request)
+                    .await
+                    .map(|response| json!(response)),
+                Err(e) => return Self::invalid_request(capability, e),
+            },
+            _ => return (404, Self::error_body(&format!("unknown route {}", path))),
+        };
+        match result {
+            Ok(data) => (200, json!({ "data": data })),
+            Err(e) => {
+                log::error!("host capability {} failed: {}", capability, e);
+                (500, Self::error_body(&e.to_string()))
+            }
+        }
+    }
+
+    fn invalid_request(capability: &str, error: serde_json::Error) -> (u16, Value) {
+        (
+            400,
+            Self::error_body(&format!("invalid {} request: {}", capability, error)),
+        )
+    }
+
+    fn result_response(result: Result<Value, ExecutionError>) -> (u16, Value) {
+        match result {
+            Ok(data) => (200, json!({ "data": data })),
+            Err(e) => (500, Self::error_body(e.message())),
+        }
+    }
+
+    fn error_body(message: &str) -> Value {
+        json!({ "error": { "message": message } })
+    }
+}
+
+impl Drop for CallbackServer {
+    fn drop(&mut self) {
+        log::debug!("stopping callback server on port {}", self.port);
+        self.task.abort();
+    }
+}
+
+#[cfg(test)]
+#[path = "callback_server.test.rs"]
+mod tests;
@@ -0,0 +1,401 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use futures::future::BoxFuture;
+use serde_json::{json, Value};
+
+use crate::tools::{
+    callback_server::{CallbackHandlers, CallbackServer},
+    container_runtime::{ContainerRuntime, ContainerRuntimeKind},
+    execution_error::{ExecutionError, InfrastructureError},
+    host_capabilities::{HostCallContext, HostCapabilities, LlmRequest, LlmResponse},
+    runner_type::RunnerType,
+    tool_call::{ToolCallConfig, ToolResolver},
+};
+
+#[derive(Default)]
+struct RecordingResolver {
+    call_chains: Mutex<Vec<Vec<String>>>,
+}
+
+impl ToolResolver for RecordingResolver {
+    fn call_tool(
+        &self,
+        tool_id: String,
+        parameters: Value,
+        tool_calls: ToolCallConfig,
+    ) -> BoxFuture<'_, Result<Value, ExecutionError>> {
+        self.call_chains
+            .lock()
+            .unwrap()
+            .push(tool_calls.call_chain.clone());
+        Box::pin(async move { Ok(json!({ "tool_id": tool_id, "parameters": parameters })) })
+    }
+}
+
+fn tool_call_handlers(tool_calls: ToolCallConfig) -> CallbackHandlers {
+    CallbackHandlers {
+        tool_calls: Some(tool_calls),
+        ..Default::default()
+    }
+}
+
+async fn post(server: &CallbackServer, token: &str, route: &str, body: Value) -> (u16, Value) {
+    let response = reqwest::Client::builder()
+        .no_proxy()
+        .build()
+        .unwrap()
+        .post(format!("{}{}", server.url(&RunnerType::Host), route))
+        .header("Authorization", format!("Bearer {}", token))
+        .body(body.to_string())
+        .send()
+        .await
+        .unwrap();
+    let status = response.status().as_u16();
+    let body = serde_json::from_str(&response.text().await.unwrap()).unwrap();
+    (status, body)
+}
+
+#[tokio::test]
+async fn call_tool_reaches_resolver_with_call_chain() {
+    let resolver = Arc::new(RecordingResolver::default());
+    let server = CallbackServer::start(
+        &RunnerType::Host,
+        None,
+        tool_call_handlers(ToolCallConfig::new(resolver.clone(), "parent@1.0.0")),
+    )
+    .await
+    .unwrap();
+
+    let (status, body) = post(
+        &server,
+        server.token(),
+        "/call_tool",
+        json!({ "tool_id": "child@^1", "parameters": { "message": "hi" } }),
+    )
+    .await;
+    assert_eq!(status, 200);
+    assert_eq!(
+        body,
+        json!({ "data": { "tool_id": "child@^1", "parameters": { "message": "hi" } } })
+    );
+    assert_eq!(
+        resolver.call_chains.lock().unwrap().clone(),
+        vec![vec!["parent".to_string(), "child".to_string()]]
+    );
+
+    let envs = server.envs(&RunnerType::Docker);
+    assert_eq!(
+        envs.get(CallbackServer::URL_ENV).unwrap(),
+        &format!("http://host.docker.internal:{}", server.port())
+    );
+    assert_eq!(envs.get(CallbackServer::TOKEN_ENV).unwrap(), server.token());
+}
+
+#[tokio::test]
+async fn rejects_invalid_token_and_unknown_routes() {
+    let resolver = Arc::new(RecordingResolver::default());
+    let server = CallbackServer::start(
+        &RunnerType::Host,
+        None,
+        tool_call_handlers(ToolCallConfig::new(resolver.clone(), "parent")),
+    )
+    .await
+    .unwrap();
+
+    let (status, _) = post(
+        &server,
+        "wrong",
+        "/call_tool",
+        json!({ "tool_id": "child" }),
+    )
+    .await;
+    assert_eq!(status, 401);
+    let (status, _) = post(&server, server.token(), "/unknown", json!({})).await;
+    assert_eq!(status, 404);
+    let (status, _) = post(&server, server.token(), "/call_tool", json!({})).await;
+    assert_eq!(status, 400);
+    assert!(resolver.call_chains.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn enforces_cycles_and_depth_limit() {
+    let resolver = Arc::new(RecordingResolver::default());
+    let tool_calls = ToolCallConfig::new(resolver.clone(), "a")
+        .nested("b")
+        .unwrap()
+        .with_max_depth(3);
+    let server = CallbackServer::start(&RunnerType::Host, None, tool_call_handlers(tool_calls))
+        .await
+        .unwrap();
+
+    let (status, body) = post(
+        &server,
+        server.token(),
+        "/call_tool",
+        json!({ "tool_id": "a@2.0.0" }),
+    )
+    .await;
+    assert_eq!(status, 500);
+    assert!(body["error"]["message"]
+        .as_str()
+        .unwrap()
+        .contains("cycle detected: a -> b -> a"));
+
+    let (status, _) = post(
+        &server,
+        server.token(),
+        "/call_tool",
+        json!({ "tool_id": "c" }),
+    )
+    .await;
+    assert_eq!(status, 200);
+    assert_eq!(
+        resolver.call_chains.lock().unwrap().clone(),
+        vec![vec!["a".to_string(), "b".to_string(), "c".to_string()]]
+    );
+
+    let deepest = ToolCallConfig::new(resolver.clone(), "a")
+        .with_max_depth(3)
+        .nested("b")
+        .unwrap()
+        .nested("c")
+        .unwrap();
+    assert!(deepest.nested("d").is_err());
+}
+
+#[derive(Default)]
+struct InMemoryHost {
+    kv: Mutex<HashMap<String, Value>>,
+}
+
+impl HostCapabilities for InMemoryHost {
+    fn get_secret(
+        &self,
+        context: HostCallContext,
+        name: String,
+    ) -> BoxFuture<'_, anyhow::Result<Option<String>>> {
+        Box::pin(async move {
+            Ok((name == "API_KEY").then(|| format!("secret-for-{}", context.context_id)))
+        })
+    }
+
+    fn kv_get(
+        &self,
+        _context: HostCallContext,
+        key: String,
+    ) -> BoxFuture<'_, anyhow::Result<Option<Value>>> {
+        Box::pin(async move { Ok(self.kv.lock().unwrap().get(&key).cloned()) })
+    }
+
+    fn kv_set(
+        &self,
+        _context: HostCallContext,
+        key: String,
+        value: Value,
+    ) -> BoxFuture<'_, anyhow::Result<()>> {
+        Box::pin(async move {
+            self.kv.lock().unwrap().insert(key, value);
+            Ok(())
+        })
+    }
+
+    fn call_llm(
+        &self,
+        _context: HostCallContext,
+        request: LlmRequest,
+    ) -> BoxFuture<'_, anyhow::Result<LlmResponse>> {
+        Box::pin(async move {
+            Ok(LlmResponse {
+                content: request.prompt.to_uppercase(),
+                metadata: json!({ "model": request.model }),
+            })
+        })
+    }
+}
+
+#[tokio::test]
+async fn host_capabilities_are_exposed() {
+    let server = CallbackServer::start(
+        &RunnerType::Host,
+        None,
+        CallbackHandlers {
+            context: HostCallContext {
+                context_id: "context".to_string(),
+                ..Default::default()
+            },
+            host_capabilities: Some(Arc::new(InMemoryHost::default())),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+
+    let (status, body) = post(
+        &server,
+        server.token(),
+        "/host/get_secret",
+        json!({ "name": "API_KEY" }),
+    )
+    .await;
+    assert_eq!(
+        (status, body),
+        (200, json!({ "data": "secret-for-context" }))
+    );
+    let (_, body) = post(
+        &server,
+        server.token(),
+        "/host/get_secret",
+        json!({ "name": "OTHER" }),
+    )
+    .await;
+    assert_eq!(body, json!({ "data": null }));
+
+    let (status, _) = post(
+        &server,
+        server.token(),
+        "/host/kv_set",
+        json!({ "key": "counter", "value": { "count": 1 } }),
+    )
+    .await;
+    assert_eq!(status, 200);
+    let (_, body) = post(
+        &server,
+        server.token(),
+        "/host/kv_get",
+        json!({ "key": "counter" }),
+    )
+    .await;
+    assert_eq!(body, json!({ "data": { "count": 1 } }));
+
+    let (_, body) = post(
+        &server,
+        server.token(),
+        "/host/call_llm",
+        json!({ "prompt": "hello", "model": "llama" }),
+    )
+    .await;
+    assert_eq!(
+        body,
+        json!({ "data": { "content": "HELLO", "metadata": { "model": "llama" } } })
+    );
+
+    let (status, body) = post(
+        &server,
+        server.token(),
+        "/host/http_fetch",
+        json!({ "url": "https://shinkai.com" }),
+    )
+    .await;
+    assert_eq!(status, 500);
+    assert!(body["error"]["message"]
+        .as_str()
+        .unwrap()
+        .contains("not supported"));
+
+    let (status, _) = post(&server, server.token(), "/host/kv_get", json!({})).await;
+    assert_eq!(status, 400);
+    let (status, _) = post(
+        &server,
+        server.token(),
+        "/call_tool",
+        json!({ "tool_id": "child" }),
+    )
+    .await;
+    assert_eq!(status, 404);
+}
+
+#[tokio::test]
+async fn docker_server_never_listens_on_every_interface() {
+    // Without a reachable runtime the gateway is unknown
+    let runtime = ContainerRuntime::new(
+        ContainerRuntimeKind::Docker,
+        std::path::PathBuf::from("/nonexistent/shinkai/docker"),
+    );
+    let result = CallbackServer::start(
+        &RunnerType::Docker,
+        Some(&runtime),
+        tool_call_handlers(ToolCallConfig::new(
+            Arc::new(RecordingResolver::default()),
+            "parent",
+        )),
+    )
+    .await;
+    if cfg!(target_os = "linux") {
+        // Containers can't reach a loopback listener, so it fails instead of binding there
+        let error = result.err().unwrap();
+        assert!(error.downcast_ref::<InfrastructureError>().is_some());
+        return;
+    }
+    // Docker Desktop forwards host.docker.internal to the host loopback
+    let server = result.unwrap();
+    assert_eq!(
+        server.url(&RunnerType::Docker),
+        format!("http://host.docker.internal:{}", server.port())
+    );
+    let (status, _) = post(&server, server.token(), "/unknown", json!({})).await;
+    assert_eq!(status, 404);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn sandbox_server_is_reached_through_a_bound_socket() {
+    let resolver = Arc::new(RecordingResolver::default());
+    let server = CallbackServer::start(
+        &RunnerType::Sandbox,
+        None,
+        tool_call_handlers(ToolCallConfig::new(resolver, "parent")),
+    )
+    .await
+    .unwrap();
+    let envs = server.envs(&RunnerType::Sandbox);
+    let socket_path = std::path::PathBuf::from(&envs[CallbackServer::SOCKET_ENV]);
+    let socket_folder = socket_path.parent().unwrap().to_path_buf();
+    let mounts = CallbackServer::sandbox_mounts(Some(&envs));
+    assert_eq!(mounts.len(), 1);
+    assert_eq!(mounts[0].source, socket_folder.to_string_lossy());
+    assert_eq!(mounts[0].target, mounts[0].source);
+    assert!(!mounts[0].readonly);
+    assert!(CallbackServer::sandbox_mounts(None).is_empty());
+
+    // The same client the python wrapper gets, talking over the socket
+    let script = format!(
+            "{}\nprint(__import__('json').dumps(shinkai_callback('/call_tool', {{'tool_id': 'child'}})))",
+            CallbackServer::PYTHON_CLIENT_CODE
+        );
+    // Skipped when python3 isn't installed
+    if let Ok(output) = tokio::process::Command::new("python3")
+        .arg("-c")
+        .arg(script)
+        .envs(&envs)
+        .output()
+        .await
+    {
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        let data: Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(data["tool_id"], "child");
+    }
+
+    let mut stream = tokio::net::UnixStream::connect(&socket_path).await.unwrap();
+    let request = format!(
+        "POST /unknown HTTP/1.1\r\nHost: localhost\r\nAuthorization: Bearer {}\r\nContent-Length: 2\r\nConnection: close\r\n\r\n{{}}",
+        server.token()
+    );
+    tokio::io::AsyncWriteExt::write_all(&mut stream, request.as_bytes())
+        .await
+        .unwrap();
+    let mut response = String::new();
+    tokio::io::AsyncReadExt::read_to_string(&mut stream, &mut response)
+        .await
+        .unwrap();
+    assert!(response.starts_with("HTTP/1.1 404"), "{}", response);
+
+    drop(server);
+    assert!(!socket_folder.exists());
+}
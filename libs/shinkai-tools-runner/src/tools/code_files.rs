@@ -1,7 +1,64 @@
 use std::collections::HashMap;
 
-#[derive(Default, Clone)]
+use sha2::{Digest, Sha256};
+
+#[derive(Default, Clone, Debug)]
 pub struct CodeFiles {
     pub files: HashMap<String, String>,
     pub entrypoint: String,
 }
+
+impl CodeFiles {
+    /// Deterministic sha256 (hex encoded) of the entrypoint name and every file path and content
+    pub fn content_hash(&self) -> String {
+        let mut paths = self.files.keys().collect::<Vec<_>>();
+        paths.sort();
+        let mut hasher = Sha256::new();
+        hasher.update(self.entrypoint.as_bytes());
+        hasher.update([0u8]);
+        for path in paths {
+            let content = &self.files[path];
+            hasher.update((path.len() as u64).to_le_bytes());
+            hasher.update(path.as_bytes());
+            hasher.update((content.len() as u64).to_le_bytes());
+            hasher.update(content.as_bytes());
+        }
+        hex_simd::encode_to_string(hasher.finalize(), hex_simd::AsciiCase::Lower)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn content_hash_is_order_independent_and_content_sensitive() {
+        let code_files = CodeFiles {
+            files: HashMap::from([
+                ("main.ts".to_string(), "import './a.ts';".to_string()),
+                ("a.ts".to_string(), "console.log('a');".to_string()),
+            ]),
+            entrypoint: "main.ts".to_string(),
+        };
+        let mut reordered = CodeFiles {
+            files: HashMap::new(),
+            entrypoint: "main.ts".to_string(),
+        };
+        reordered
+            .files
+            .insert("a.ts".to_string(), "console.log('a');".to_string());
+        reordered
+            .files
+            .insert("main.ts".to_string(), "import './a.ts';".to_string());
+        assert_eq!(code_files.content_hash(), reordered.content_hash());
+
+        reordered
+            .files
+            .insert("a.ts".to_string(), "console.log('b');".to_string());
+        assert_ne!(code_files.content_hash(), reordered.content_hash());
+
+        let mut other_entrypoint = code_files.clone();
+        other_entrypoint.entrypoint = "a.ts".to_string();
+        assert_ne!(code_files.content_hash(), other_entrypoint.content_hash());
+    }
+}
@@ -0,0 +1,243 @@
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv4Addr},
+    path::PathBuf,
+    process::Command,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+
+use super::{container_utils::DockerStatus, docker_engine_api::DockerEngineClient};
+
+/// Last `info` result of every runtime binary, see `ContainerRuntime::cached_status`
+static STATUS_CACHE: Lazy<Mutex<HashMap<PathBuf, (Instant, DockerStatus)>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Last rootless check of every runtime binary, see `ContainerRuntime::cached_is_rootless`
+static ROOTLESS_CACHE: Lazy<Mutex<HashMap<PathBuf, (Instant, bool)>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ContainerRuntimeKind {
+    Docker,
+    Podman,
+}
+
+/// Container engine CLI used by the Docker runner type
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContainerRuntime {
+    pub kind: ContainerRuntimeKind,
+    /// Binary invoked for every container command, a bare name is looked up in PATH
+    pub binary_path: PathBuf,
+    /// Runs containers through the Docker Engine API on this unix socket instead of the cli
+    pub engine_api_socket: Option<PathBuf>,
+}
+
+/// Host path bind mounted in a container
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BindMount {
+    pub source: String,
+    pub target: String,
+    pub readonly: bool,
+}
+
+impl Default for ContainerRuntime {
+    fn default() -> Self {
+        Self::docker()
+    }
+}
+
+impl ContainerRuntime {
+    pub const STATUS_CACHE_TTL: Duration = Duration::from_secs(30);
+
+    pub fn new(kind: ContainerRuntimeKind, binary_path: PathBuf) -> Self {
+        ContainerRuntime {
+            kind,
+            binary_path,
+            engine_api_socket: None,
+        }
+    }
+
+    pub fn with_engine_api(mut self, socket_path: PathBuf) -> Self {
+        self.engine_api_socket = Some(socket_path);
+        self
+    }
+
+    pub fn engine_client(&self) -> Option<DockerEngineClient> {
+        self.engine_api_socket.clone().map(DockerEngineClient::new)
+    }
+
+    pub fn docker() -> Self {
+        Self::new(ContainerRuntimeKind::Docker, PathBuf::from("docker"))
+    }
+
+    pub fn podman() -> Self {
+        Self::new(ContainerRuntimeKind::Podman, PathBuf::from("podman"))
+    }
+
+    /// First runtime with a running engine, Docker is preferred over Podman
+    pub fn detect() -> Option<Self> {
+        [Self::docker(), Self::podman()]
+            .into_iter()
+            .find(|runtime| runtime.cached_status() == DockerStatus::Running)
+    }
+
+    /// Runs `<binary> info` to check the CLI is installed and its engine reachable
+    pub fn status(&self) -> DockerStatus {
+        match Command::new(&self.binary_path).arg("info").output() {
+            Ok(output) if output.status.success() => DockerStatus::Running,
+            Ok(_) => DockerStatus::NotRunning,
+            Err(_) => DockerStatus::NotInstalled,
+        }
+    }
+
+    /// `status` reused for `STATUS_CACHE_TTL`, runner type resolution calls it on every run
+    pub fn cached_status(&self) -> DockerStatus {
+        self.cached(&STATUS_CACHE, || self.status())
+    }
+
+    /// Forgets cached statuses and rootless checks, e.g. after the user started the engine
+    pub fn clear_status_cache() {
+        STATUS_CACHE.lock().unwrap().clear();
+        ROOTLESS_CACHE.lock().unwrap().clear();
+    }
+
+    /// First line of `<binary> --version`, None when the binary can't run
+    pub fn version(&self) -> Option<String> {
+        let output = Command::new(&self.binary_path)
+            .arg("--version")
+            .output()
+            .ok()?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        output
+            .status
+            .success()
+            .then(|| stdout.lines().next().unwrap_or_default().trim().to_string())
+    }
+
+    /// Whether the image is available locally, without pulling it
+    pub fn image_exists(&self, image: &str) -> bool {
+        Command::new(&self.binary_path)
+            .args(["image", "inspect", image])
+            .output()
+            .is_ok_and(|output| output.status.success())
+    }
+
+    /// Whether the engine runs without root privileges
+    pub fn is_rootless(&self) -> bool {
+        let format = match self.kind {
+            ContainerRuntimeKind::Docker => "{{.SecurityOptions}}",
+            ContainerRuntimeKind::Podman => "{{.Host.Security.Rootless}}",
+        };
+        let Ok(output) = Command::new(&self.binary_path)
+            .args(["info", "--format", format])
+            .output()
+        else {
+            return false;
+        };
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        output.status.success()
+            && match self.kind {
+                ContainerRuntimeKind::Docker => stdout.contains("rootless"),
+                ContainerRuntimeKind::Podman => stdout.trim() == "true",
+            }
+    }
+
+    /// `is_rootless` reused for `STATUS_CACHE_TTL`, `run_args` calls it on every container run
+    pub fn cached_is_rootless(&self) -> bool {
+        self.cached(&ROOTLESS_CACHE, || self.is_rootless())
+    }
+
+    /// Cached value of this binary younger than `STATUS_CACHE_TTL`, probed otherwise
+    ///
+    /// The lock isn't held while probing, a hung engine would block every runtime behind it.
+    fn cached<T: Copy>(
+        &self,
+        cache: &Mutex<HashMap<PathBuf, (Instant, T)>>,
+        probe: impl FnOnce() -> T,
+    ) -> T {
+        let cached = cache.lock().unwrap().get(&self.binary_path).copied();
+        if let Some((checked_at, value)) = cached {
+            if checked_at.elapsed() < Self::STATUS_CACHE_TTL {
+                return value;
+            }
+        }
+        let value = probe();
+        cache
+            .lock()
+            .unwrap()
+            .insert(self.binary_path.clone(), (Instant::now(), value));
+        value
+    }
+
+    /// Host address containers reach through `host.docker.internal`, where host services for
+    /// containers should listen
+    ///
+    /// Docker Desktop and Podman machines forward it to the host loopback, on Linux it is the
+    /// gateway of the default bridge network. None when the network can't be inspected.
+    pub async fn host_gateway_address(&self) -> Option<IpAddr> {
+        if !cfg!(target_os = "linux") {
+            return Some(IpAddr::V4(Ipv4Addr::LOCALHOST));
+        }
+        let (network, format) = match self.kind {
+            ContainerRuntimeKind::Docker => {
+                ("bridge", "{{range .IPAM.Config}}{{.Gateway}} {{end}}")
+            }
+            ContainerRuntimeKind::Podman => ("podman", "{{range .Subnets}}{{.Gateway}} {{end}}"),
+        };
+        let output = self
+            .command()
+            .args(["network", "inspect", network, "--format", format])
+            .output()
+            .await
+            .ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        String::from_utf8_lossy(&output.stdout)
+            .split_whitespace()
+            .find_map(|address| address.parse::<Ipv4Addr>().ok())
+            .map(IpAddr::V4)
+    }
+
+    pub fn command(&self) -> tokio::process::Command {
+        tokio::process::Command::new(&self.binary_path)
+    }
+
+    /// Extra `run` flags so bind mounts behave like they do with Docker
+    ///
+    /// Rootless Podman maps the container root to the host user only with `keep-id`, otherwise
+    /// files written to home and mount files end up owned by a subordinate uid.
+    pub fn run_args(&self) -> Vec<String> {
+        match self.kind {
+            ContainerRuntimeKind::Docker => Vec::new(),
+            ContainerRuntimeKind::Podman if self.cached_is_rootless() => {
+                vec![String::from("--userns=keep-id")]
+            }
+            ContainerRuntimeKind::Podman => Vec::new(),
+        }
+    }
+
+    /// Value of a `--mount` bind parameter
+    ///
+    /// Podman relabels the source so SELinux hosts allow the container to access it.
+    pub fn bind_mount(&self, mount: &BindMount) -> String {
+        let mut mount_param = String::from("type=bind");
+        if mount.readonly {
+            mount_param.push_str(",readonly=true");
+        }
+        mount_param.push_str(&format!(",source={},target={}", mount.source, mount.target));
+        if self.kind == ContainerRuntimeKind::Podman {
+            mount_param.push_str(",relabel=shared");
+        }
+        mount_param
+    }
+}
+
+#[cfg(test)]
+#[path = "container_runtime.test.rs"]
+mod tests;
@@ -0,0 +1,148 @@
+use std::path::PathBuf;
+
+#[cfg(unix)]
+use crate::tools::test_utils::fake_binary;
+use crate::tools::{
+    container_runtime::{BindMount, ContainerRuntime, ContainerRuntimeKind},
+    container_utils::DockerStatus,
+};
+
+fn bind_mount(source: &str, target: &str, readonly: bool) -> BindMount {
+    BindMount {
+        source: source.to_string(),
+        target: target.to_string(),
+        readonly,
+    }
+}
+
+#[test]
+fn missing_binary_is_not_installed() {
+    let runtime = ContainerRuntime::new(
+        ContainerRuntimeKind::Podman,
+        PathBuf::from("/nonexistent/shinkai/podman"),
+    );
+    assert_eq!(runtime.status(), DockerStatus::NotInstalled);
+    assert!(!runtime.is_rootless());
+    assert!(runtime.run_args().is_empty());
+}
+
+#[test]
+fn bind_mount_params() {
+    assert_eq!(
+        ContainerRuntime::docker().bind_mount(&bind_mount("/host/home", "/app/home", false)),
+        "type=bind,source=/host/home,target=/app/home"
+    );
+    assert_eq!(
+        ContainerRuntime::docker().bind_mount(&bind_mount(
+            "/host/a.txt",
+            "/app/assets/a.txt",
+            true
+        )),
+        "type=bind,readonly=true,source=/host/a.txt,target=/app/assets/a.txt"
+    );
+    assert_eq!(
+        ContainerRuntime::podman().bind_mount(&bind_mount("/host/home", "/app/home", false)),
+        "type=bind,source=/host/home,target=/app/home,relabel=shared"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn rootless_podman_keeps_user_id() {
+    let folder = tempfile::tempdir().unwrap();
+    let binary_path = fake_binary(folder.path(), "fake-runtime", "echo true");
+    let runtime = ContainerRuntime::new(ContainerRuntimeKind::Podman, binary_path.clone());
+    assert_eq!(runtime.status(), DockerStatus::Running);
+    assert!(runtime.is_rootless());
+    assert_eq!(runtime.run_args(), vec!["--userns=keep-id".to_string()]);
+
+    // Docker never gets podman specific flags, even when rootless
+    let runtime = ContainerRuntime::new(ContainerRuntimeKind::Docker, binary_path);
+    assert!(runtime.run_args().is_empty());
+}
+
+#[cfg(unix)]
+#[test]
+fn stopped_engine_is_not_running() {
+    let folder = tempfile::tempdir().unwrap();
+    let binary_path = fake_binary(folder.path(), "fake-runtime", "exit 125");
+    let runtime = ContainerRuntime::new(ContainerRuntimeKind::Podman, binary_path);
+    assert_eq!(runtime.status(), DockerStatus::NotRunning);
+    assert!(!runtime.is_rootless());
+}
+
+#[cfg(unix)]
+#[test]
+fn cached_status_reuses_info_result() {
+    let folder = tempfile::tempdir().unwrap();
+    let calls = folder.path().join("calls.log");
+    let binary_path = fake_binary(
+        folder.path(),
+        "fake-runtime",
+        &format!("echo \"$@\" >> \"{}\"", calls.display()),
+    );
+    let runtime = ContainerRuntime::new(ContainerRuntimeKind::Docker, binary_path);
+    assert_eq!(runtime.cached_status(), DockerStatus::Running);
+    assert_eq!(runtime.cached_status(), DockerStatus::Running);
+    assert_eq!(std::fs::read_to_string(&calls).unwrap().lines().count(), 1);
+}
+
+#[cfg(unix)]
+#[test]
+fn slow_engine_doesnt_block_other_runtimes_status() {
+    let slow_folder = tempfile::tempdir().unwrap();
+    let slow = ContainerRuntime::new(
+        ContainerRuntimeKind::Docker,
+        fake_binary(slow_folder.path(), "fake-runtime", "sleep 3"),
+    );
+    let fast_folder = tempfile::tempdir().unwrap();
+    let fast = ContainerRuntime::new(
+        ContainerRuntimeKind::Podman,
+        fake_binary(fast_folder.path(), "fake-runtime", "exit 0"),
+    );
+
+    let slow_check = std::thread::spawn(move || slow.cached_status());
+    std::thread::sleep(std::time::Duration::from_millis(300));
+    let started_at = std::time::Instant::now();
+    assert_eq!(fast.cached_status(), DockerStatus::Running);
+    assert!(started_at.elapsed() < std::time::Duration::from_secs(2));
+    assert_eq!(slow_check.join().unwrap(), DockerStatus::Running);
+}
+
+#[cfg(unix)]
+#[test]
+fn run_args_reuse_rootless_check() {
+    let folder = tempfile::tempdir().unwrap();
+    let calls = folder.path().join("calls.log");
+    let binary_path = fake_binary(
+        folder.path(),
+        "fake-runtime",
+        &format!("echo \"$@\" >> \"{}\"; echo true", calls.display()),
+    );
+    let runtime = ContainerRuntime::new(ContainerRuntimeKind::Podman, binary_path);
+    assert_eq!(runtime.run_args(), vec!["--userns=keep-id".to_string()]);
+    assert_eq!(runtime.run_args(), vec!["--userns=keep-id".to_string()]);
+    assert_eq!(std::fs::read_to_string(&calls).unwrap().lines().count(), 1);
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn host_gateway_address_is_the_bridge_gateway() {
+    let folder = tempfile::tempdir().unwrap();
+    let binary_path = fake_binary(
+        folder.path(),
+        "fake-runtime",
+        r#"[ "$1 $2 $3" = "network inspect bridge" ] && echo "172.17.0.1 " && exit 0; exit 1"#,
+    );
+    let runtime = ContainerRuntime::new(ContainerRuntimeKind::Docker, binary_path);
+    assert_eq!(
+        runtime.host_gateway_address().await,
+        Some("172.17.0.1".parse().unwrap())
+    );
+
+    let runtime = ContainerRuntime::new(
+        ContainerRuntimeKind::Docker,
+        PathBuf::from("/nonexistent/shinkai/docker"),
+    );
+    assert_eq!(runtime.host_gateway_address().await, None);
+}
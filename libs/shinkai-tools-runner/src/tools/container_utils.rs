@@ -1,6 +1,10 @@
-use std::process::Command;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, PartialEq)]
+use super::container_runtime::ContainerRuntime;
+
+/// State of a container runtime, Docker or Podman
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum DockerStatus {
     NotInstalled,
     NotRunning,
@@ -25,17 +29,8 @@ pub enum DockerStatus {
 ///   - Docker daemon is not running
 ///   - User lacks permissions
 ///   - Other Docker configuration issues
+///
+/// Use `ContainerRuntime::detect` to also consider Podman.
 pub fn is_docker_available() -> DockerStatus {
-    let docker_check = Command::new("docker").arg("info").output();
-
-    match docker_check {
-        Ok(output) => {
-            if output.status.success() {
-                DockerStatus::Running
-            } else {
-                DockerStatus::NotRunning
-            }
-        }
-        Err(_) => DockerStatus::NotInstalled,
-    }
+    ContainerRuntime::docker().status()
 }
@@ -0,0 +1,378 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::File,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::{
+    code_files::CodeFiles, execution_context::ExecutionContext,
+    execution_storage::ExecutionStorage, storage_manager::StorageManager,
+};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SnapshotOptions {
+    /// Also archive the context cache folder, the home folder is always included
+    pub include_cache: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RestoreMode {
+    /// Deletes the restored folders before extracting, so the context matches the snapshot
+    #[default]
+    Replace,
+    /// Overwrites the files in the snapshot and keeps the others
+    Merge,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotFileEntry {
+    /// Path inside the archive, starting with `home/` or `cache/`
+    pub path: String,
+    pub sha256: String,
+    pub size: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotManifest {
+    pub format_version: u32,
+    pub context_id: String,
+    /// RFC 3339 UTC timestamp
+    pub created_at: String,
+    pub include_cache: bool,
+    pub files: Vec<SnapshotFileEntry>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SnapshotDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub modified: Vec<String>,
+}
+
+impl SnapshotDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.modified.is_empty()
+    }
+}
+
+impl SnapshotManifest {
+    /// Files added, removed and modified going from `self` to `other`
+    pub fn diff(&self, other: &SnapshotManifest) -> SnapshotDiff {
+        let before = self
+            .files
+            .iter()
+            .map(|file| (file.path.as_str(), file.sha256.as_str()))
+            .collect::<BTreeMap<_, _>>();
+        let after = other
+            .files
+            .iter()
+            .map(|file| (file.path.as_str(), file.sha256.as_str()))
+            .collect::<BTreeMap<_, _>>();
+        let mut diff = SnapshotDiff::default();
+        for (path, sha256) in after.iter() {
+            match before.get(path) {
+                None => diff.added.push(path.to_string()),
+                Some(previous) if previous != sha256 => diff.modified.push(path.to_string()),
+                _ => {}
+            }
+        }
+        for path in before.keys() {
+            if !after.contains_key(path) {
+                diff.removed.push(path.to_string());
+            }
+        }
+        diff
+    }
+}
+
+/// Exports a context's persistent state to a `.tar.gz` archive and restores it
+///
+/// The archive holds `manifest.json` first, followed by the `home/` and optionally `cache/`
+/// folders. Every file is verified against the manifest hashes on restore.
+pub struct ContextSnapshot;
+
+impl ContextSnapshot {
+    pub const FORMAT_VERSION: u32 = 1;
+    pub const MANIFEST_FILE_NAME: &'static str = "manifest.json";
+    const HOME_FOLDER: &'static str = "home";
+    const CACHE_FOLDER: &'static str = "cache";
+
+    /// Archives the context into `output` and returns its manifest
+    pub fn create(
+        context: &ExecutionContext,
+        options: &SnapshotOptions,
+        output: &Path,
+    ) -> anyhow::Result<SnapshotManifest> {
+        let storage = ExecutionStorage::new(CodeFiles::default(), context.clone());
+        let _lease = storage.acquire_lease()?;
+        log::info!(
+            "creating snapshot of context {} into {}",
+            context.context_id,
+            output.display()
+        );
+
+        let mut folders = vec![(Self::HOME_FOLDER, storage.context_home_folder_path.clone())];
+        if options.include_cache {
+            folders.push((Self::CACHE_FOLDER, storage.cache_folder_path.clone()));
+        }
+        let mut files = Vec::new();
+        for (name, path) in folders.iter() {
+            Self::collect_files(path, name, &mut files)?;
+        }
+        files.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let mut entries = Vec::new();
+        for (archive_path, path) in files.iter() {
+            let mut file = File::open(path)?;
+            let mut hasher = Sha256::new();
+            let size = std::io::copy(&mut file, &mut hasher)?;
+            entries.push(SnapshotFileEntry {
+                path: archive_path.clone(),
+                sha256: hex_simd::encode_to_string(hasher.finalize(), hex_simd::AsciiCase::Lower),
+                size,
+            });
+        }
+        let manifest = SnapshotManifest {
+            format_version: Self::FORMAT_VERSION,
+            context_id: context.context_id.clone(),
+            created_at: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+            include_cache: options.include_cache,
+            files: entries,
+        };
+
+        if let Some(parent) = output.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let parent = output
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        let temp_file = tempfile::NamedTempFile::new_in(parent)?;
+        let mut builder =
+            tar::Builder::new(GzEncoder::new(temp_file.reopen()?, Compression::default()));
+        let manifest_content = serde_json::to_vec_pretty(&manifest)?;
+        let mut header = tar::Header::new_gnu();
+        header.set_size(manifest_content.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(
+            &mut header,
+            Self::MANIFEST_FILE_NAME,
+            manifest_content.as_slice(),
+        )?;
+        for ((archive_path, path), entry) in files.iter().zip(manifest.files.iter()) {
+            let file = File::open(path)?;
+            let mut header = tar::Header::new_gnu();
+            header.set_size(entry.size);
+            header.set_mode(0o644);
+            header.set_cksum();
+            // Limited to the hashed size so a file growing meanwhile doesn't corrupt the archive
+            builder.append_data(&mut header, archive_path, file.take(entry.size))?;
+        }
+        builder.into_inner()?.finish()?.flush()?;
+        temp_file.persist(output).map_err(|e| {
+            log::error!("failed to write snapshot {}: {}", output.display(), e);
+            e.error
+        })?;
+        Ok(manifest)
+    }
+
+    /// Reads the manifest of a snapshot without extracting it
+    pub fn read_manifest(snapshot: &Path) -> anyhow::Result<SnapshotManifest> {
+        let mut archive = tar::Archive::new(GzDecoder::new(File::open(snapshot)?));
+        let mut entries = archive.entries()?;
+        let mut first = entries
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("empty snapshot {}", snapshot.display()))??;
+        Self::read_manifest_entry(&mut first)
+    }
+
+    /// Restores a snapshot into `context`, which may be a different or new context
+    ///
+    /// Fails while a live execution uses the context.
+    pub fn restore(
+        snapshot: &Path,
+        context: &ExecutionContext,
+        mode: RestoreMode,
+    ) -> anyhow::Result<SnapshotManifest> {
+        let storage = ExecutionStorage::new(CodeFiles::default(), context.clone());
+        std::fs::create_dir_all(&storage.root_folder_path)?;
+        // Exclusive, a live execution must not see its home replaced under it
+        let Some(_lock) = StorageManager::try_lock_context(&storage.root_folder_path)? else {
+            return Err(anyhow::anyhow!(
+                "context {} is in use by a live execution",
+                context.context_id
+            ));
+        };
+        log::info!(
+            "restoring snapshot {} into context {}",
+            snapshot.display(),
+            context.context_id
+        );
+
+        let mut archive = tar::Archive::new(GzDecoder::new(File::open(snapshot)?));
+        let mut entries = archive.entries()?;
+        let mut first = entries
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("empty snapshot {}", snapshot.display()))??;
+        let manifest = Self::read_manifest_entry(&mut first)?;
+        if manifest.format_version != Self::FORMAT_VERSION {
+            return Err(anyhow::anyhow!(
+                "unsupported snapshot format version {}",
+                manifest.format_version
+            ));
+        }
+        let expected = manifest
+            .files
+            .iter()
+            .map(|file| (file.path.clone(), file))
+            .collect::<HashMap<_, _>>();
+
+        // Extracted next to the target folders so they can be swapped with renames
+        let staging = tempfile::Builder::new()
+            .prefix(".restore-")
+            .tempdir_in(&storage.root_folder_path)?;
+        let mut restored = 0;
+        for entry in entries {
+            let mut entry = entry?;
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+            let archive_path = entry.path()?.to_string_lossy().replace('\\', "/");
+            Self::validate_entry_path(&archive_path)?;
+            let expected_file = expected.get(&archive_path).ok_or_else(|| {
+                anyhow::anyhow!("snapshot entry {} is not in the manifest", archive_path)
+            })?;
+            let target = staging.path().join(&archive_path);
+            if let Some(parent) = target.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            let mut file = File::create(&target)?;
+            let mut hasher = Sha256::new();
+            let mut buffer = [0u8; 64 * 1024];
+            loop {
+                let read = entry.read(&mut buffer)?;
+                if read == 0 {
+                    break;
+                }
+                hasher.update(&buffer[..read]);
+                file.write_all(&buffer[..read])?;
+            }
+            let sha256 = hex_simd::encode_to_string(hasher.finalize(), hex_simd::AsciiCase::Lower);
+            if sha256 != expected_file.sha256 {
+                return Err(anyhow::anyhow!(
+                    "snapshot entry {} doesn't match its manifest hash",
+                    archive_path
+                ));
+            }
+            restored += 1;
+        }
+        if restored != manifest.files.len() {
+            return Err(anyhow::anyhow!(
+                "snapshot is missing {} files listed in its manifest",
+                manifest.files.len() - restored
+            ));
+        }
+
+        let mut folders = vec![(Self::HOME_FOLDER, storage.context_home_folder_path.clone())];
+        if manifest.include_cache {
+            folders.push((Self::CACHE_FOLDER, storage.cache_folder_path.clone()));
+        }
+        for (name, target) in folders {
+            let staged = staging.path().join(name);
+            std::fs::create_dir_all(&staged)?;
+            match mode {
+                RestoreMode::Replace => {
+                    if target.exists() {
+                        std::fs::remove_dir_all(&target)?;
+                    }
+                    std::fs::rename(&staged, &target)?;
+                }
+                RestoreMode::Merge => Self::merge_folder(&staged, &target)?,
+            }
+        }
+        Ok(manifest)
+    }
+
+    /// Archive paths and absolute paths of the regular files under `folder`
+    fn collect_files(
+        folder: &Path,
+        archive_folder: &str,
+        files: &mut Vec<(String, PathBuf)>,
+    ) -> anyhow::Result<()> {
+        if !folder.exists() {
+            return Ok(());
+        }
+        let mut pending = vec![(folder.to_path_buf(), archive_folder.to_string())];
+        while let Some((path, archive_path)) = pending.pop() {
+            for entry in std::fs::read_dir(&path)? {
+                let entry = entry?;
+                let file_type = entry.file_type()?;
+                let name = entry.file_name().to_string_lossy().to_string();
+                let entry_archive_path = format!("{}/{}", archive_path, name);
+                if file_type.is_dir() {
+                    pending.push((entry.path(), entry_archive_path));
+                } else if file_type.is_file() {
+                    files.push((entry_archive_path, entry.path()));
+                } else {
+                    log::warn!("skipping non regular file {}", entry.path().display());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn merge_folder(source: &Path, target: &Path) -> anyhow::Result<()> {
+        std::fs::create_dir_all(target)?;
+        for entry in std::fs::read_dir(source)? {
+            let entry = entry?;
+            let target_path = target.join(entry.file_name());
+            if entry.file_type()?.is_dir() {
+                Self::merge_folder(&entry.path(), &target_path)?;
+            } else {
+                if target_path.is_dir() {
+                    std::fs::remove_dir_all(&target_path)?;
+                }
+                std::fs::rename(entry.path(), &target_path)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn read_manifest_entry(
+        entry: &mut tar::Entry<'_, impl Read>,
+    ) -> anyhow::Result<SnapshotManifest> {
+        if entry.path()?.to_string_lossy() != Self::MANIFEST_FILE_NAME {
+            return Err(anyhow::anyhow!(
+                "snapshot must start with {}",
+                Self::MANIFEST_FILE_NAME
+            ));
+        }
+        let mut content = Vec::new();
+        entry.read_to_end(&mut content)?;
+        Ok(serde_json::from_slice(&content)?)
+    }
+
+    fn validate_entry_path(path: &str) -> anyhow::Result<()> {
+        let components = path.split('/').collect::<Vec<_>>();
+        let is_valid = components.len() > 1
+            && (components[0] == Self::HOME_FOLDER || components[0] == Self::CACHE_FOLDER)
+            && !path.contains(':')
+            && components
+                .iter()
+                .all(|component| !component.is_empty() && *component != ".." && *component != ".");
+        if !is_valid {
+            return Err(anyhow::anyhow!("invalid path in snapshot: {}", path));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[path = "context_snapshot.test.rs"]
+mod tests;
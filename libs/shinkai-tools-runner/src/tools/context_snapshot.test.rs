@@ -0,0 +1,145 @@
+use crate::tools::{
+    code_files::CodeFiles,
+    context_snapshot::{ContextSnapshot, RestoreMode, SnapshotOptions},
+    execution_context::ExecutionContext,
+    execution_storage::ExecutionStorage,
+};
+
+fn context(storage: &std::path::Path, context_id: &str) -> (ExecutionContext, ExecutionStorage) {
+    let context = ExecutionContext {
+        storage: storage.to_path_buf(),
+        context_id: context_id.to_string(),
+        ..Default::default()
+    };
+    let execution_storage = ExecutionStorage::new(CodeFiles::default(), context.clone());
+    execution_storage.init(None).unwrap();
+    (context, execution_storage)
+}
+
+#[test]
+fn snapshot_and_restore_into_new_context() {
+    let storage = tempfile::tempdir().unwrap();
+    let (source, source_storage) = context(storage.path(), "source");
+    std::fs::create_dir_all(source_storage.home_folder_path.join("nested")).unwrap();
+    std::fs::write(source_storage.home_folder_path.join("state.json"), "{}").unwrap();
+    std::fs::write(
+        source_storage
+            .home_folder_path
+            .join("nested")
+            .join("data.bin"),
+        [0u8, 1, 2, 3],
+    )
+    .unwrap();
+    std::fs::write(source_storage.cache_folder_path.join("cached"), "cache").unwrap();
+
+    let snapshot_path = storage.path().join("snapshots").join("source.tar.gz");
+    let manifest =
+        ContextSnapshot::create(&source, &SnapshotOptions::default(), &snapshot_path).unwrap();
+    assert_eq!(
+        manifest
+            .files
+            .iter()
+            .map(|f| f.path.as_str())
+            .collect::<Vec<_>>(),
+        vec!["home/nested/data.bin", "home/state.json"]
+    );
+    assert_eq!(
+        ContextSnapshot::read_manifest(&snapshot_path).unwrap(),
+        manifest
+    );
+
+    let (target, target_storage) = context(storage.path(), "target");
+    std::fs::write(target_storage.home_folder_path.join("stale.txt"), "old").unwrap();
+    ContextSnapshot::restore(&snapshot_path, &target, RestoreMode::Replace).unwrap();
+    assert_eq!(
+        std::fs::read(
+            target_storage
+                .home_folder_path
+                .join("nested")
+                .join("data.bin")
+        )
+        .unwrap(),
+        vec![0u8, 1, 2, 3]
+    );
+    assert!(!target_storage.home_folder_path.join("stale.txt").exists());
+    assert!(!target_storage.cache_folder_path.join("cached").exists());
+}
+
+#[test]
+fn merge_restore_keeps_other_files_and_includes_cache() {
+    let storage = tempfile::tempdir().unwrap();
+    let (source, source_storage) = context(storage.path(), "source");
+    std::fs::write(
+        source_storage.home_folder_path.join("a.txt"),
+        "from snapshot",
+    )
+    .unwrap();
+    std::fs::write(source_storage.cache_folder_path.join("cached"), "cache").unwrap();
+    let snapshot_path = storage.path().join("source.tar.gz");
+    ContextSnapshot::create(
+        &source,
+        &SnapshotOptions {
+            include_cache: true,
+        },
+        &snapshot_path,
+    )
+    .unwrap();
+
+    let (target, target_storage) = context(storage.path(), "target");
+    std::fs::write(target_storage.home_folder_path.join("a.txt"), "local").unwrap();
+    std::fs::write(target_storage.home_folder_path.join("b.txt"), "local").unwrap();
+    ContextSnapshot::restore(&snapshot_path, &target, RestoreMode::Merge).unwrap();
+    assert_eq!(
+        std::fs::read_to_string(target_storage.home_folder_path.join("a.txt")).unwrap(),
+        "from snapshot"
+    );
+    assert!(target_storage.home_folder_path.join("b.txt").exists());
+    assert_eq!(
+        std::fs::read_to_string(target_storage.cache_folder_path.join("cached")).unwrap(),
+        "cache"
+    );
+}
+
+#[test]
+fn diff_between_snapshots() {
+    let storage = tempfile::tempdir().unwrap();
+    let (source, source_storage) = context(storage.path(), "source");
+    let home = &source_storage.home_folder_path;
+    std::fs::write(home.join("kept.txt"), "same").unwrap();
+    std::fs::write(home.join("changed.txt"), "v1").unwrap();
+    std::fs::write(home.join("removed.txt"), "bye").unwrap();
+    let before = ContextSnapshot::create(
+        &source,
+        &SnapshotOptions::default(),
+        &storage.path().join("before.tar.gz"),
+    )
+    .unwrap();
+
+    std::fs::write(home.join("changed.txt"), "v2").unwrap();
+    std::fs::remove_file(home.join("removed.txt")).unwrap();
+    std::fs::write(home.join("added.txt"), "hi").unwrap();
+    let after = ContextSnapshot::create(
+        &source,
+        &SnapshotOptions::default(),
+        &storage.path().join("after.tar.gz"),
+    )
+    .unwrap();
+
+    let diff = before.diff(&after);
+    assert_eq!(diff.added, vec!["home/added.txt"]);
+    assert_eq!(diff.removed, vec!["home/removed.txt"]);
+    assert_eq!(diff.modified, vec!["home/changed.txt"]);
+    assert!(after.diff(&after).is_empty());
+}
+
+#[test]
+fn restore_refuses_context_in_use() {
+    let storage = tempfile::tempdir().unwrap();
+    let (source, _) = context(storage.path(), "source");
+    let snapshot_path = storage.path().join("source.tar.gz");
+    ContextSnapshot::create(&source, &SnapshotOptions::default(), &snapshot_path).unwrap();
+
+    let (target, target_storage) = context(storage.path(), "target");
+    let _lease = target_storage.acquire_lease().unwrap();
+    assert!(ContextSnapshot::restore(&snapshot_path, &target, RestoreMode::Replace).is_err());
+}
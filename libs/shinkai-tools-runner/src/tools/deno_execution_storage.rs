@@ -7,18 +7,49 @@ impl ExecutionStorage {
     fn deno_cache_folder_path_docker(&self) -> std::path::PathBuf {
         self.global_cache_folder_path.join("deno-cache-docker")
     }
+    /// The sandbox mounts its cache writable, sharing the host one would let a sandboxed tool
+    /// tamper with modules host runs load
+    fn deno_cache_folder_path_sandbox(&self) -> std::path::PathBuf {
+        self.global_cache_folder_path.join("deno-cache-sandbox")
+    }
     pub fn deno_cache_folder_path(&self, runner_type: RunnerType) -> std::path::PathBuf {
         match runner_type {
             RunnerType::Host => self.deno_cache_folder_path_host(),
+            RunnerType::Sandbox => self.deno_cache_folder_path_sandbox(),
             RunnerType::Docker => self.deno_cache_folder_path_docker(),
         }
     }
+    /// Copies the files of a pre-populated `DENO_DIR` missing from the deno cache
+    pub fn seed_deno_cache(
+        &self,
+        runner_type: RunnerType,
+        seed_folder_path: &std::path::Path,
+    ) -> anyhow::Result<()> {
+        fn copy_missing(source: &std::path::Path, target: &std::path::Path) -> std::io::Result<()> {
+            std::fs::create_dir_all(target)?;
+            for entry in std::fs::read_dir(source)? {
+                let entry = entry?;
+                let file_type = entry.file_type()?;
+                let target = target.join(entry.file_name());
+                if file_type.is_dir() {
+                    copy_missing(&entry.path(), &target)?;
+                } else if file_type.is_file() && !target.exists() {
+                    std::fs::copy(entry.path(), &target)?;
+                }
+            }
+            Ok(())
+        }
+        log::info!("seeding deno cache from {}", seed_folder_path.display());
+        copy_missing(seed_folder_path, &self.deno_cache_folder_path(runner_type))?;
+        Ok(())
+    }
     pub fn init_for_deno(
         &self,
         pristine_cache: Option<bool>,
         runner_type: RunnerType,
     ) -> anyhow::Result<()> {
-        self.init(pristine_cache)?;
+        // Empty deno.json so deno doesn't pick up a configuration from a parent folder
+        self.init_with_generated_files(pristine_cache, &[("deno.json", "")])?;
 
         log::info!("creating deno cache directory");
         let deno_cache_dir = self.deno_cache_folder_path(runner_type);
@@ -28,15 +59,9 @@ impl ExecutionStorage {
         })?;
 
         // Workaround for puppeteer. Cosmiconfig try to read a forbidden folder
-        log::info!("creating .config directory");
-        let config_dir = self.root_folder_path.join(".config");
-        std::fs::create_dir_all(&config_dir).map_err(|e| {
-            log::error!("failed to create .config directory: {}", e);
-            e
-        })?;
         log::info!("creating config.json file");
-        let config_json_path = config_dir.join("config.json");
-        std::fs::write(
+        let config_json_path = self.root_folder_path.join(".config").join("config.json");
+        Self::write_file_atomic(
             &config_json_path,
             r#"
         {
@@ -44,20 +69,14 @@ impl ExecutionStorage {
                 "option":  "value"
             }
         }
-        "#,
+        "#
+            .as_bytes(),
         )
         .map_err(|e| {
             log::error!("failed to write config.json file: {}", e);
             e
         })?;
 
-        log::info!("creating deno.json file");
-        let deno_json_path = self.code_folder_path.join("deno.json");
-        std::fs::write(&deno_json_path, "").map_err(|e| {
-            log::error!("failed to write deno.json file: {}", e);
-            e
-        })?;
-
         Ok(())
     }
 }
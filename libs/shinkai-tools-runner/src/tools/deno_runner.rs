@@ -6,24 +6,49 @@ use tokio::{
 };
 
 use crate::tools::{
+    callback_server::{CallbackHandlers, CallbackServer},
     check_utils::normalize_error_message,
+    container_runtime::{BindMount, ContainerRuntime},
+    docker_engine_api::{run_in_engine, ContainerSpec},
+    execution_log::LogStream,
     execution_storage::ExecutionStorage,
     file_name_utils::{adapt_paths_in_value, normalize_for_docker_path},
+    host_capabilities::HostCallContext,
+    image_manager::ImageManager,
     path_buf_ext::PathBufExt,
-    runner_type::{resolve_runner_type, RunnerType},
+    process_output::{code_result_text, parse_code_result, run_step},
+    runner_type::{resolve_runner_type_for_policy, RunnerType},
+    secret_configurations::SecretConfigurations,
 };
 
 use super::{
-    code_files::CodeFiles, deno_runner_options::DenoRunnerOptions, execution_error::ExecutionError,
+    code_files::CodeFiles,
+    deno_runner_options::DenoRunnerOptions,
+    execution_error::{ExecutionError, InfrastructureError},
     run_result::RunResult,
+    tool_definition::ToolDefinition,
 };
 use std::{
     collections::{HashMap, HashSet},
-    path::{self, PathBuf},
+    path::{self, Path, PathBuf},
     sync::Arc,
     time::Duration,
 };
 
+/// Browsers Playwright and Puppeteer look for, readable by host runs
+pub(crate) const BROWSER_EXECUTABLE_PATHS: &[&str] = &[
+    "/Applications/Google Chrome.app/Contents/MacOS/Google Chrome",
+    "/Applications/Google Chrome Canary.app/Contents/MacOS/Google Chrome Canary",
+    "/Applications/Chromium.app/Contents/MacOS/Chromium",
+    "C:\\Program Files (x86)\\Google\\Chrome\\Application\\chrome.exe",
+    "C:\\Program Files (x86)\\Google\\Chrome SxS\\Application\\chrome.exe",
+    "C:\\Program Files (x86)\\Chromium\\Application\\chrome.exe",
+    "C:\\Program Files\\Google\\Chrome\\Application\\chrome.exe",
+    "C:\\Program Files\\Google\\Chrome SxS\\Application\\chrome.exe",
+    "C:\\Program Files\\Chromium\\Application\\chrome.exe",
+    "/usr/bin/chromium",
+];
+
 #[derive(Default)]
 pub struct DenoRunner {
     code: CodeFiles,
@@ -33,6 +58,7 @@ pub struct DenoRunner {
 
 impl DenoRunner {
     pub const MAX_EXECUTION_TIME_MS_INTERNAL_OPS: u64 = 1000;
+    pub const DEFINITION_ENTRYPOINT_FILE_NAME: &'static str = "__shinkai_definition.ts";
 
     pub fn new(
         code_files: CodeFiles,
@@ -57,6 +83,7 @@ impl DenoRunner {
     pub async fn check(&self) -> anyhow::Result<Vec<String>> {
         let execution_storage =
             ExecutionStorage::new(self.code.clone(), self.options.context.clone());
+        let _lease = execution_storage.acquire_lease()?;
         execution_storage.init_for_deno(None, RunnerType::Host)?;
 
         let binary_path = path::absolute(self.options.deno_binary_path.clone())
@@ -114,6 +141,161 @@ impl DenoRunner {
         }
     }
 
+    /// Extracts the tool definition from the code without executing `run`
+    ///
+    /// The entrypoint is imported from a generated introspection module that reads the exported
+    /// `metadata` (or `definition`) object and builds JSON Schemas from the `CONFIG`, `INPUTS`
+    /// and `OUTPUT` types declared in the entrypoint.
+    ///
+    /// # Returns
+    ///
+    /// Returns a Result containing:
+    /// - Ok(ToolDefinition): The definition with configurations, parameters and result schemas
+    /// - Err(ExecutionError): Any errors that occurred while loading the code or building schemas
+    pub async fn definition(&self) -> Result<ToolDefinition, ExecutionError> {
+        log::info!("preparing to extract tool definition");
+        self.options
+            .trust_store
+            .verify_code_files(&self.code, self.options.code_signature.as_ref())?;
+        let entrypoint_code = match self.code.files.get(&self.code.entrypoint) {
+            Some(entrypoint_code) => entrypoint_code.clone(),
+            None => {
+                return Err(ExecutionError::new(
+                    format!("no entrypoint found {}", self.code.entrypoint),
+                    None,
+                ))
+            }
+        };
+
+        let mut code = self.code.clone();
+        code.files.insert(
+            Self::DEFINITION_ENTRYPOINT_FILE_NAME.to_string(),
+            format!(
+                r#"
+            import * as tool from "./{entrypoint}";
+            import {{ fromFileUrl }} from "jsr:@std/path@1.0.8";
+            import {{ createGenerator }} from "npm:ts-json-schema-generator@2.3.0";
+
+            const entrypointPath = fromFileUrl(new URL("./{entrypoint}", import.meta.url));
+            const schemaFor = (type: string) => {{
+                try {{
+                    const schema = createGenerator({{
+                        path: entrypointPath,
+                        type,
+                        skipTypeCheck: true,
+                        expose: "none",
+                        topRef: false,
+                        additionalProperties: true,
+                    }}).createSchema(type);
+                    delete schema["$schema"];
+                    return schema;
+                }} catch (e) {{
+                    console.error(`unable to build schema for type ${{type}}: ${{e}}`);
+                    return {{ type: "object", properties: {{}} }};
+                }}
+            }};
+
+            const metadata = tool.metadata ?? tool.definition ?? {{}};
+            const definition = {{
+                id: metadata.id ?? "",
+                name: metadata.name ?? "",
+                description: metadata.description ?? "",
+                author: metadata.author ?? "",
+                keywords: metadata.keywords ?? [],
+                configurations: metadata.configurations ?? schemaFor("CONFIG"),
+                parameters: metadata.parameters ?? schemaFor("INPUTS"),
+                result: metadata.result ?? schemaFor("OUTPUT"),
+            }};
+            console.log("<shinkai-code-result>");
+            console.log(JSON.stringify(definition));
+            console.log("</shinkai-code-result>");
+            Deno.exit(0);
+        "#,
+                entrypoint = self.code.entrypoint.replace("\\", "/"),
+            ),
+        );
+        code.entrypoint = Self::DEFINITION_ENTRYPOINT_FILE_NAME.to_string();
+
+        let resolved_runner_type = resolve_runner_type_for_policy(
+            self.options.force_runner_type.clone(),
+            &self.options.runner_policy,
+            self.options.container_runtime.as_ref(),
+        );
+        let no_secrets =
+            SecretConfigurations::default().with_redaction_rules(&self.options.redaction_rules);
+        let no_inputs = (&Value::Null, &Value::Null);
+        let (output, _) = self
+            .options
+            .runner_policy
+            .run_with_fallback(
+                self.options.force_runner_type.as_ref(),
+                resolved_runner_type,
+                |runner_type| {
+                    let code = code.clone();
+                    let no_secrets = &no_secrets;
+                    async move {
+                        self.seed_definition_cache(&code, runner_type.clone())?;
+                        match runner_type {
+                            RunnerType::Host | RunnerType::Sandbox => {
+                                self.run_in_host(
+                                    &runner_type,
+                                    code,
+                                    None,
+                                    no_inputs,
+                                    no_secrets,
+                                    None,
+                                )
+                                .await
+                            }
+                            RunnerType::Docker => {
+                                self.run_in_docker(code, None, no_inputs, no_secrets, None)
+                                    .await
+                            }
+                        }
+                        .map_err(|e| ExecutionError::from_runner_error(e.to_string(), &e))
+                    }
+                },
+            )
+            .await?;
+        let result_text = code_result_text(&output);
+
+        let mut definition: ToolDefinition = serde_json::from_str(&result_text).map_err(|e| {
+            log::info!("failed to parse tool definition: {}", e);
+            ExecutionError::new(format!("failed to parse tool definition: {}", e), None)
+        })?;
+        definition.code = Some(entrypoint_code);
+        log::info!(
+            "successfully extracted tool definition: {}",
+            definition.name
+        );
+        Ok(definition)
+    }
+
+    /// Seeds the deno cache with the definition dependencies cached at build time, next to the
+    /// deno binary, so the extraction doesn't need the network
+    fn seed_definition_cache(
+        &self,
+        code: &CodeFiles,
+        runner_type: RunnerType,
+    ) -> Result<(), ExecutionError> {
+        let seed_folder_path = self
+            .options
+            .deno_binary_path
+            .parent()
+            .unwrap_or(Path::new("."))
+            .join(crate::copy_assets::DENO_DEFINITION_CACHE_FOLDER_NAME);
+        if !seed_folder_path.exists() {
+            log::warn!(
+                "deno definition cache not found at {}, dependencies will be downloaded",
+                seed_folder_path.display()
+            );
+            return Ok(());
+        }
+        ExecutionStorage::new(code.clone(), self.options.context.clone())
+            .seed_deno_cache(runner_type, &seed_folder_path)
+            .map_err(|e| ExecutionError::new(format!("failed to seed deno cache: {}", e), None))
+    }
+
     pub async fn run(
         &self,
         envs: Option<HashMap<String, String>>,
@@ -121,12 +303,68 @@ impl DenoRunner {
         max_execution_timeout: Option<Duration>,
     ) -> Result<RunResult, ExecutionError> {
         log::info!("preparing to run tool");
-        log::info!("configurations: {}", self.configurations.to_string());
-        log::info!("parameters: {}", parameters.to_string());
+        self.options
+            .trust_store
+            .verify_code_files(&self.code, self.options.code_signature.as_ref())?;
+        let (public_configurations, secrets) = SecretConfigurations::split(
+            &self.configurations,
+            &self.options.secret_configuration_keys,
+        );
+        let secrets = secrets.with_redaction_rules(&self.options.redaction_rules);
+        log::info!("configurations: {}", secrets.masked(&self.configurations));
+        log::info!(
+            "parameters: {}",
+            secrets.redactor().redact(&parameters.to_string())
+        );
 
-        let resolved_runner_type = resolve_runner_type(self.options.force_runner_type.clone());
+        let resolved_runner_type = resolve_runner_type_for_policy(
+            self.options.force_runner_type.clone(),
+            &self.options.runner_policy,
+            self.options.container_runtime.as_ref(),
+        );
 
-        let mut adapted_configurations = self.configurations.clone();
+        let (output, metadata) = self
+            .options
+            .runner_policy
+            .run_with_fallback(
+                self.options.force_runner_type.as_ref(),
+                resolved_runner_type,
+                |runner_type| {
+                    let envs = envs.clone();
+                    let (parameters, public_configurations, secrets) =
+                        (&parameters, &public_configurations, &secrets);
+                    async move {
+                        self.run_with_runner_type(
+                            &runner_type,
+                            envs,
+                            parameters,
+                            public_configurations,
+                            secrets,
+                            max_execution_timeout,
+                        )
+                        .await
+                    }
+                },
+            )
+            .await?;
+        Ok(RunResult {
+            data: parse_code_result(&output, secrets.redactor())?,
+            metadata,
+        })
+    }
+
+    /// Wraps the entrypoint for the runner type and runs it, returning the output lines
+    #[allow(clippy::too_many_arguments)]
+    async fn run_with_runner_type(
+        &self,
+        resolved_runner_type: &RunnerType,
+        envs: Option<HashMap<String, String>>,
+        parameters: &Value,
+        public_configurations: &Value,
+        secrets: &SecretConfigurations,
+        max_execution_timeout: Option<Duration>,
+    ) -> Result<Vec<String>, ExecutionError> {
+        let mut adapted_configurations = public_configurations.clone();
         if !self.options.context.mount_files.is_empty()
             && matches!(resolved_runner_type, RunnerType::Docker)
         {
@@ -157,14 +395,19 @@ impl DenoRunner {
             adapted_parameters = adapt_paths_in_value(&adapted_parameters, &mount_files);
         }
 
+        // The inputs are read from the inputs file so the code folder, and its hash, only depend
+        // on the tool code
         let mut code = self.code.clone();
         let entrypoint_code = code.files.get(&self.code.entrypoint.clone());
         if let Some(entrypoint_code) = entrypoint_code {
             let adapted_entrypoint_code = format!(
                 r#"
             {}
-            const configurations = JSON.parse('{}');
-            const parameters = JSON.parse('{}');
+            {}
+            const shinkaiInputs = JSON.parse(Deno.readTextFileSync(Deno.env.get("{}")));
+            const configurations = shinkaiInputs.configurations;
+            {}
+            const parameters = shinkaiInputs.parameters;
 
             const result = await run(configurations, parameters);
             const adaptedResult = result === undefined ? null : result;
@@ -174,52 +417,82 @@ impl DenoRunner {
             Deno.exit(0);
         "#,
                 &entrypoint_code,
-                serde_json::to_string(&adapted_configurations)
-                    .unwrap()
-                    .replace("\\", "\\\\")
-                    .replace("'", "\\'")
-                    .replace("\"", "\\\"")
-                    .replace("`", "\\`"),
-                serde_json::to_string(&adapted_parameters)
-                    .unwrap()
-                    .replace("\\", "\\\\")
-                    .replace("'", "\\'")
-                    .replace("\"", "\\\"")
-                    .replace("`", "\\`")
+                CallbackServer::DENO_CLIENT_CODE,
+                ExecutionStorage::INPUTS_FILE_ENV,
+                secrets.deno_merge_code(),
             );
             code.files
                 .insert(self.code.entrypoint.clone(), adapted_entrypoint_code);
         }
 
-        let result = match resolved_runner_type {
-            RunnerType::Host => self.run_in_host(code, envs, max_execution_timeout).await,
-            RunnerType::Docker => self.run_in_docker(code, envs, max_execution_timeout).await,
+        // Kept alive until the process exits so the tool can call back into the host
+        let mut envs = envs;
+        let callback_handlers = CallbackHandlers {
+            context: HostCallContext {
+                context_id: self.options.context.context_id.clone(),
+                execution_id: self.options.context.execution_id.clone(),
+                tool_id: self
+                    .options
+                    .tool_calls
+                    .as_ref()
+                    .and_then(|tool_calls| tool_calls.call_chain.last().cloned()),
+            },
+            tool_calls: self
+                .options
+                .tool_calls
+                .clone()
+                .map(|tool_calls| tool_calls.with_timeout(max_execution_timeout)),
+            host_capabilities: self.options.host_capabilities.clone(),
+        };
+        let _callback_server = if callback_handlers.is_empty() {
+            None
+        } else {
+            let callback_server = CallbackServer::start(
+                resolved_runner_type,
+                self.options.container_runtime.as_ref(),
+                callback_handlers,
+            )
+            .await
+            .map_err(|e| {
+                ExecutionError::from_runner_error(
+                    format!("failed to start callback server: {}", e),
+                    &e,
+                )
+            })?;
+            envs.get_or_insert_with(HashMap::new)
+                .extend(callback_server.envs(resolved_runner_type));
+            Some(callback_server)
+        };
+
+        let inputs = (&adapted_configurations, &adapted_parameters);
+        match resolved_runner_type {
+            RunnerType::Host | RunnerType::Sandbox => {
+                self.run_in_host(
+                    resolved_runner_type,
+                    code,
+                    envs,
+                    inputs,
+                    secrets,
+                    max_execution_timeout,
+                )
+                .await
+            }
+            RunnerType::Docker => {
+                self.run_in_docker(code, envs, inputs, secrets, max_execution_timeout)
+                    .await
+            }
         }
-        .map_err(|e| ExecutionError::new(e.to_string(), None))?;
-
-        let result_text = result
-            .iter()
-            .skip_while(|line| !line.contains("<shinkai-code-result>"))
-            .skip(1)
-            .take_while(|line| !line.contains("</shinkai-code-result>"))
-            .map(|s| s.to_string())
-            .collect::<Vec<String>>()
-            .join("\n");
-
-        log::info!("result text: {:?}", result);
-
-        let result: Value = serde_json::from_str(&result_text).map_err(|e| {
-            log::info!("failed to parse result: {}", e);
-            ExecutionError::new(format!("failed to parse result: {}", e), None)
-        })?;
-        log::info!("successfully parsed run result: {:?}", result);
-        Ok(RunResult { data: result })
+        .map_err(|e| {
+            ExecutionError::from_runner_error(secrets.redactor().redact(&e.to_string()), &e)
+        })
     }
 
     async fn run_in_docker(
         &self,
         code_files: CodeFiles,
         envs: Option<HashMap<String, String>>,
+        (configurations, parameters): (&Value, &Value),
+        secrets: &SecretConfigurations,
         max_execution_timeout: Option<Duration>,
     ) -> anyhow::Result<Vec<String>> {
         log::info!(
@@ -227,10 +500,30 @@ impl DenoRunner {
             self.options.code_runner_docker_image_name
         );
 
-        let execution_storage = ExecutionStorage::new(code_files, self.options.context.clone());
+        let execution_storage = ExecutionStorage::new(code_files, self.options.context.clone())
+            .with_redactor(secrets.redactor().clone());
+        let _lease = execution_storage.acquire_lease()?;
         execution_storage.init_for_deno(None, RunnerType::Docker)?;
+        let home = execution_storage.prepare_home()?;
+        let inputs = execution_storage.write_inputs(configurations, parameters)?;
+
+        let container_runtime = self
+            .options
+            .container_runtime
+            .clone()
+            .or_else(ContainerRuntime::detect)
+            .unwrap_or_default();
+        log::info!("using container runtime: {:?}", container_runtime);
+        // Pulled here so a missing image doesn't eat the execution timeout
+        let image = ImageManager::new(container_runtime.clone())
+            .prepare(
+                &self.options.code_runner_docker_image_name,
+                self.options.code_runner_docker_image_digest.as_deref(),
+            )
+            .await
+            .map_err(|e| InfrastructureError(format!("failed to prepare image: {}", e)))?;
 
-        let mut mount_params = Vec::<String>::new();
+        let mut mounts = Vec::<BindMount>::new();
 
         let mount_dirs = [
             (
@@ -249,14 +542,25 @@ impl DenoRunner {
             ),
             (
                 execution_storage.home_folder_path.as_normalized_string(),
-                execution_storage.relative_to_root(execution_storage.home_folder_path.clone()),
+                String::from("home"),
             ),
         ];
         for (dir, relative_path) in mount_dirs {
-            let mount_param = format!(r#"type=bind,source={},target=/app/{}"#, dir, relative_path);
-            log::info!("mount parameter created: {}", mount_param);
-            mount_params.extend([String::from("--mount"), mount_param]);
+            mounts.push(BindMount {
+                source: dir,
+                target: format!("/app/{}", relative_path),
+                readonly: false,
+            });
         }
+        let inputs_file_path = format!(
+            "/app/{}",
+            execution_storage.relative_to_root(inputs.path().to_path_buf())
+        );
+        mounts.push(BindMount {
+            source: inputs.path().to_path_buf().as_normalized_string(),
+            target: inputs_file_path.clone(),
+            readonly: true,
+        });
 
         let mut mount_env = String::from("");
         log::info!("mount files: {:?}", self.options.context.mount_files);
@@ -265,10 +569,12 @@ impl DenoRunner {
             // Copy the files to the exact same path in the volume.
             // This will allow to run the same code in the host and in the container.
             let path = normalize_for_docker_path(file.to_path_buf());
-            let mount_param = format!(r#"type=bind,source={},target={}"#, path, path);
-            log::info!("mount parameter created: {}", mount_param);
             mount_env += &format!("{},", path);
-            mount_params.extend([String::from("--mount"), mount_param]);
+            mounts.push(BindMount {
+                source: path.clone(),
+                target: path,
+                readonly: false,
+            });
         }
 
         let mut mount_assets_env = String::from("");
@@ -279,22 +585,18 @@ impl DenoRunner {
                 execution_storage.relative_to_root(execution_storage.assets_folder_path.clone()),
                 file.file_name().unwrap().to_str().unwrap()
             );
-            let mount_param = format!(
-                r#"type=bind,readonly=true,source={},target={}"#,
-                path::absolute(file).unwrap().as_normalized_string(),
-                target_path,
-            );
-            log::debug!("mount parameter created: {}", mount_param);
             mount_assets_env += &format!("{},", target_path);
-            mount_params.extend([String::from("--mount"), mount_param]);
+            mounts.push(BindMount {
+                source: path::absolute(file).unwrap().as_normalized_string(),
+                target: target_path,
+                readonly: true,
+            });
         }
 
         let mut container_envs = Vec::<String>::new();
 
-        container_envs.push(String::from("-e"));
         container_envs.push("NO_COLOR=true".to_string());
 
-        container_envs.push(String::from("-e"));
         container_envs.push(format!(
             "DENO_DIR={}",
             execution_storage.relative_to_global_cache(
@@ -304,36 +606,42 @@ impl DenoRunner {
             )
         ));
 
-        container_envs.push(String::from("-e"));
         container_envs.push(format!(
             "SHINKAI_NODE_LOCATION={}://host.docker.internal:{}",
             self.options.shinkai_node_location.protocol, self.options.shinkai_node_location.port
         ));
 
-        container_envs.push(String::from("-e"));
         container_envs.push(String::from("SHINKAI_HOME=/app/home"));
-        container_envs.push(String::from("-e"));
         container_envs.push(format!("SHINKAI_ASSETS={}", mount_assets_env));
-        container_envs.push(String::from("-e"));
         container_envs.push(format!("SHINKAI_MOUNT={}", mount_env));
-        container_envs.push(String::from("-e"));
         container_envs.push(format!(
             "SHINKAI_CONTEXT_ID={}",
             self.options.context.context_id
         ));
-        container_envs.push(String::from("-e"));
         container_envs.push(format!(
             "SHINKAI_EXECUTION_ID={}",
             self.options.context.execution_id
         ));
+        container_envs.push(format!(
+            "{}={}",
+            ExecutionStorage::INPUTS_FILE_ENV,
+            inputs_file_path
+        ));
 
-        if let Some(envs) = envs {
+        // Passed by name on the docker cli so their values never show up in its arguments
+        let mut named_envs = Vec::new();
+        if let Some(mut envs) = envs {
+            if let Some(token) = envs.remove(CallbackServer::TOKEN_ENV) {
+                named_envs.push((CallbackServer::TOKEN_ENV.to_string(), token));
+            }
             for (key, value) in envs {
                 let env = format!("{}={}", key, value);
-                container_envs.push(String::from("-e"));
                 container_envs.push(env);
             }
         }
+        if !secrets.is_empty() {
+            named_envs.push((SecretConfigurations::ENV.to_string(), secrets.env_value()));
+        }
 
         let deno_permissions = self.get_deno_permissions(
             RunnerType::Docker,
@@ -365,32 +673,68 @@ impl DenoRunner {
 
         let code_entrypoint =
             execution_storage.relative_to_root(execution_storage.code_entrypoint_file_path.clone());
-        let mut command = tokio::process::Command::new("docker");
-        let mut args = vec!["run", "--rm"];
-        args.extend(mount_params.iter().map(|s| s.as_str()));
-        args.extend(container_envs.iter().map(|s| s.as_str()));
-        args.extend([
-            "--workdir",
-            "/app",
-            self.options.code_runner_docker_image_name.as_str(),
-            "deno",
-            "run",
-            "--ext",
-            "ts",
-        ]);
-        args.extend(deno_permissions.iter().map(|s| s.as_str()));
-        args.extend([code_entrypoint.as_str()]);
+        let mut container_command = ["deno", "run", "--ext", "ts"].map(String::from).to_vec();
+        container_command.extend(deno_permissions);
+        container_command.push(code_entrypoint);
+
+        if let Some(engine_client) = container_runtime.engine_client() {
+            let mut env = container_envs;
+            // Sent over the engine socket, they never show up in a process command line
+            env.extend(
+                named_envs
+                    .iter()
+                    .map(|(key, value)| format!("{}={}", key, value)),
+            );
+            let spec = ContainerSpec {
+                image,
+                cmd: container_command,
+                env,
+                working_dir: Some(String::from("/app")),
+                mounts,
+            };
+            let stdout = run_in_engine(
+                &engine_client,
+                &spec,
+                &execution_storage,
+                "deno",
+                max_execution_timeout,
+            )
+            .await?;
+            home.succeeded()?;
+            return Ok(stdout);
+        }
+
+        let mut command = container_runtime.command();
+        let mut args = vec![String::from("run"), String::from("--rm")];
+        args.extend(container_runtime.run_args());
+        for mount in &mounts {
+            let mount_param = container_runtime.bind_mount(mount);
+            log::info!("mount parameter created: {}", mount_param);
+            args.extend([String::from("--mount"), mount_param]);
+        }
+        for env in container_envs {
+            args.extend([String::from("-e"), env]);
+        }
+        for (key, _) in &named_envs {
+            args.extend([String::from("-e"), key.clone()]);
+        }
+        args.extend([String::from("--workdir"), String::from("/app"), image]);
+        args.extend(container_command);
         let command = command
             .args(args)
             .stdout(std::process::Stdio::piped())
             .stderr(std::process::Stdio::piped())
             .kill_on_drop(true);
+        command.envs(named_envs);
 
         log::info!("spawning docker command");
         let mut child = command.spawn().map_err(|e| {
-            let error_msg = format!("failed to spawn command: {:?}, error: {}", command, e);
+            let error_msg = execution_storage.redactor.redact(&format!(
+                "failed to spawn command: {:?}, error: {}",
+                command, e
+            ));
             log::error!("{}", error_msg);
-            anyhow::anyhow!("{}", error_msg)
+            InfrastructureError(error_msg)
         })?;
 
         let stdout = child.stdout.take().expect("Failed to get stdout");
@@ -410,9 +754,16 @@ impl DenoRunner {
         let stdout_task = tokio::task::spawn_blocking(move || {
             tokio::runtime::Runtime::new().unwrap().block_on(async {
                 while let Ok(Some(line)) = stdout_stream.next_line().await {
-                    log::info!("from deno: {}", line);
+                    log::info!(
+                        "from deno: {}",
+                        execution_storage_clone.redactor.redact(&line)
+                    );
                     stdout_lines_clone.lock().await.push(line.clone());
-                    let _ = execution_storage_clone.append_log(line.as_str());
+                    let _ = execution_storage_clone.append_log_entry(
+                        LogStream::Stdout,
+                        None,
+                        line.as_str(),
+                    );
                 }
             });
         });
@@ -420,9 +771,16 @@ impl DenoRunner {
         let stderr_task = tokio::task::spawn_blocking(move || {
             tokio::runtime::Runtime::new().unwrap().block_on(async {
                 while let Ok(Some(line)) = stderr_stream.next_line().await {
-                    log::info!("from deno: {}", line);
+                    log::info!(
+                        "from deno: {}",
+                        execution_storage_clone2.redactor.redact(&line)
+                    );
                     stderr_lines_clone.lock().await.push(line.clone());
-                    let _ = execution_storage_clone2.append_log(line.as_str());
+                    let _ = execution_storage_clone2.append_log_entry(
+                        LogStream::Stderr,
+                        None,
+                        line.as_str(),
+                    );
                 }
             });
         });
@@ -450,27 +808,70 @@ impl DenoRunner {
         };
         let _ = std_tasks.await;
         if !output.status.success() {
-            let stderr = stderr_lines.lock().await.to_vec().join("\n");
+            let stderr = execution_storage
+                .redactor
+                .redact(&stderr_lines.lock().await.to_vec().join("\n"));
             log::error!("command execution failed: {}", stderr);
-            return Err(anyhow::Error::new(std::io::Error::new(
-                std::io::ErrorKind::Other,
+            // The container never ran the tool, e.g. a rejected mount or a missing interpreter
+            if InfrastructureError::is_infrastructure_exit_code(output.status.code()) {
+                return Err(InfrastructureError(stderr).into());
+            }
+            return Err(anyhow::Error::new(std::io::Error::other(
                 stderr.to_string(),
             )));
         }
 
         let stdout: Vec<String> = stdout_lines.lock().await.to_vec();
-        log::info!("command completed successfully with output: {:?}", stdout);
+        log::info!(
+            "command completed successfully with output: {}",
+            execution_storage.redactor.redact(&format!("{:?}", stdout))
+        );
+        home.succeeded()?;
         Ok(stdout)
     }
 
+    /// Fetches the remote, npm and jsr imports of the code into the sandbox deno cache, inside the
+    /// sandbox with the host network shared
+    async fn cache_sandbox_dependencies(
+        &self,
+        execution_storage: &ExecutionStorage,
+        binary_path: &Path,
+    ) -> anyhow::Result<()> {
+        let deno_cache_folder_path = execution_storage.deno_cache_folder_path(RunnerType::Sandbox);
+        let mounts =
+            execution_storage.sandbox_mounts(std::slice::from_ref(&deno_cache_folder_path));
+        let mut command = self.options.sandbox.fetch_command(
+            binary_path,
+            &mounts,
+            &execution_storage.root_folder_path,
+        )?;
+        command
+            // The lockfile would go to the read-only root folder
+            .args(["cache", "--no-lock"])
+            .arg(&execution_storage.code_entrypoint_file_path)
+            .current_dir(&execution_storage.root_folder_path)
+            .env("NO_COLOR", "true")
+            .env("DENO_DIR", &deno_cache_folder_path)
+            .env("HOME", &execution_storage.home_folder_path);
+        run_step(&mut command, "cache dependencies").await
+    }
+
+    /// Runs with the host binary, inside the Linux sandbox for `RunnerType::Sandbox`
     async fn run_in_host(
         &self,
+        runner_type: &RunnerType,
         code_files: CodeFiles,
         envs: Option<HashMap<String, String>>,
+        (configurations, parameters): (&Value, &Value),
+        secrets: &SecretConfigurations,
         max_execution_timeout: Option<Duration>,
     ) -> anyhow::Result<Vec<String>> {
-        let execution_storage = ExecutionStorage::new(code_files, self.options.context.clone());
-        execution_storage.init_for_deno(None, RunnerType::Host)?;
+        let execution_storage = ExecutionStorage::new(code_files, self.options.context.clone())
+            .with_redactor(secrets.redactor().clone());
+        let _lease = execution_storage.acquire_lease()?;
+        execution_storage.init_for_deno(None, runner_type.clone())?;
+        let home = execution_storage.prepare_home()?;
+        let inputs = execution_storage.write_inputs(configurations, parameters)?;
 
         let binary_path = path::absolute(self.options.deno_binary_path.clone())
             .unwrap()
@@ -479,7 +880,7 @@ impl DenoRunner {
         log::info!("using deno from host at path: {:?}", binary_path.clone());
 
         let deno_permissions: Vec<String> = self.get_deno_permissions(
-            RunnerType::Host,
+            runner_type.clone(),
             binary_path.clone().as_str(),
             execution_storage
                 .home_folder_path
@@ -502,9 +903,36 @@ impl DenoRunner {
                 .collect::<Vec<_>>(),
         );
 
-        let mut command = tokio::process::Command::new(binary_path);
+        if matches!(runner_type, RunnerType::Sandbox) {
+            self.cache_sandbox_dependencies(&execution_storage, Path::new(&binary_path))
+                .await?;
+        }
+        let mut command = match runner_type {
+            RunnerType::Sandbox => {
+                let mut mounts = execution_storage.sandbox_mounts(&[
+                    execution_storage.deno_cache_folder_path(RunnerType::Sandbox)
+                ]);
+                let inputs_file_path = inputs.path().to_string_lossy().to_string();
+                mounts.push(BindMount {
+                    source: inputs_file_path.clone(),
+                    target: inputs_file_path,
+                    readonly: true,
+                });
+                mounts.extend(CallbackServer::sandbox_mounts(envs.as_ref()));
+                self.options.sandbox.command(
+                    Path::new(&binary_path),
+                    &mounts,
+                    &execution_storage.root_folder_path,
+                )?
+            }
+            _ => tokio::process::Command::new(binary_path),
+        };
+        command.args(["run", "--ext", "ts"]);
+        // The imports are cached already, the tool stays off the network
+        if matches!(runner_type, RunnerType::Sandbox) {
+            command.args(["--cached-only", "--no-lock"]);
+        }
         let command = command
-            .args(["run", "--ext", "ts"])
             .args(deno_permissions)
             .arg(execution_storage.code_entrypoint_file_path.clone())
             .current_dir(execution_storage.root_folder_path.clone())
@@ -516,9 +944,13 @@ impl DenoRunner {
         command.env(
             "DENO_DIR",
             execution_storage
-                .deno_cache_folder_path(RunnerType::Host)
+                .deno_cache_folder_path(runner_type.clone())
                 .clone(),
         );
+        // The host home isn't visible in the sandbox
+        if matches!(runner_type, RunnerType::Sandbox) {
+            command.env("HOME", execution_storage.home_folder_path.clone());
+        }
         command.env(
             "SHINKAI_NODE_LOCATION",
             format!(
@@ -559,13 +991,23 @@ impl DenoRunner {
             "SHINKAI_EXECUTION_ID",
             self.options.context.execution_id.clone(),
         );
+        command.env(ExecutionStorage::INPUTS_FILE_ENV, inputs.path());
 
         if let Some(envs) = envs {
             command.envs(envs);
         }
-        log::info!("prepared command with arguments: {:?}", command);
+        if !secrets.is_empty() {
+            command.env(SecretConfigurations::ENV, secrets.env_value());
+        }
+        log::info!(
+            "prepared command with arguments: {}",
+            execution_storage.redactor.redact(&format!("{:?}", command))
+        );
         let mut child = command.spawn().map_err(|e| {
-            let error_msg = format!("failed to spawn command: {:?} error: {}", command, e);
+            let error_msg = execution_storage.redactor.redact(&format!(
+                "failed to spawn command: {:?} error: {}",
+                command, e
+            ));
             log::error!("{}", error_msg);
             anyhow::anyhow!("{}", error_msg)
         })?;
@@ -587,9 +1029,16 @@ impl DenoRunner {
         let stdout_task = tokio::task::spawn_blocking(move || {
             tokio::runtime::Runtime::new().unwrap().block_on(async {
                 while let Ok(Some(line)) = stdout_stream.next_line().await {
-                    log::info!("from deno: {}", line);
+                    log::info!(
+                        "from deno: {}",
+                        execution_storage_clone.redactor.redact(&line)
+                    );
                     stdout_lines_clone.lock().await.push(line.clone());
-                    let _ = execution_storage_clone.append_log(line.as_str());
+                    let _ = execution_storage_clone.append_log_entry(
+                        LogStream::Stdout,
+                        None,
+                        line.as_str(),
+                    );
                 }
             });
         });
@@ -597,9 +1046,16 @@ impl DenoRunner {
         let stderr_task = tokio::task::spawn_blocking(move || {
             tokio::runtime::Runtime::new().unwrap().block_on(async {
                 while let Ok(Some(line)) = stderr_stream.next_line().await {
-                    log::info!("from deno: {}", line);
+                    log::info!(
+                        "from deno: {}",
+                        execution_storage_clone2.redactor.redact(&line)
+                    );
                     stderr_lines_clone.lock().await.push(line.clone());
-                    let _ = execution_storage_clone2.append_log(line.as_str());
+                    let _ = execution_storage_clone2.append_log_entry(
+                        LogStream::Stderr,
+                        None,
+                        line.as_str(),
+                    );
                 }
             });
         });
@@ -627,15 +1083,20 @@ impl DenoRunner {
         };
         let _ = std_tasks.await;
         if !output.status.success() {
-            let stderr = stderr_lines.lock().await.to_vec().join("\n");
+            let stderr = execution_storage
+                .redactor
+                .redact(&stderr_lines.lock().await.to_vec().join("\n"));
             log::error!("command execution failed: {}", stderr);
-            return Err(anyhow::Error::new(std::io::Error::new(
-                std::io::ErrorKind::Other,
+            return Err(anyhow::Error::new(std::io::Error::other(
                 stderr.to_string(),
             )));
         }
         let stdout: Vec<String> = stdout_lines.lock().await.to_vec();
-        log::info!("command completed successfully with output: {:?}", stdout);
+        log::info!(
+            "command completed successfully with output: {}",
+            execution_storage.redactor.redact(&format!("{:?}", stdout))
+        );
+        home.succeeded()?;
         Ok(stdout)
     }
 
@@ -658,11 +1119,9 @@ impl DenoRunner {
             "--allow-scripts".to_string(),
             "--allow-ffi".to_string(),
             "--allow-import".to_string(),
-
             // Engine folders
             "--allow-read=.".to_string(),
             format!("--allow-write={}", home_path.to_string()),
-
             // Playwright/Chrome folders
             format!("--allow-read={}", exec_path.to_string()),
             "--allow-write=/var/folders".to_string(),
@@ -671,17 +1130,12 @@ impl DenoRunner {
             "--allow-write=/tmp".to_string(),
             format!("--allow-read={}", std::env::temp_dir().to_string_lossy()),
             format!("--allow-write={}", std::env::temp_dir().to_string_lossy()),
-            "--allow-read=/Applications/Google Chrome.app/Contents/MacOS/Google Chrome".to_string(),
-            "--allow-read=/Applications/Google Chrome Canary.app/Contents/MacOS/Google Chrome Canary".to_string(),
-            "--allow-read=/Applications/Chromium.app/Contents/MacOS/Chromium".to_string(),
-            "--allow-read=C:\\Program Files (x86)\\Google\\Chrome\\Application\\chrome.exe".to_string(),
-            "--allow-read=C:\\Program Files (x86)\\Google\\Chrome SxS\\Application\\chrome.exe".to_string(),
-            "--allow-read=C:\\Program Files (x86)\\Chromium\\Application\\chrome.exe".to_string(),
-            "--allow-read=C:\\Program Files\\Google\\Chrome\\Application\\chrome.exe".to_string(),
-            "--allow-read=C:\\Program Files\\Google\\Chrome SxS\\Application\\chrome.exe".to_string(),
-            "--allow-read=C:\\Program Files\\Chromium\\Application\\chrome.exe".to_string(),
-            "--allow-read=/usr/bin/chromium".to_string(),
         ];
+        deno_permissions.extend(
+            BROWSER_EXECUTABLE_PATHS
+                .iter()
+                .map(|path| format!("--allow-read={}", path)),
+        );
 
         if matches!(runner_type, RunnerType::Docker) {
             deno_permissions.push("--allow-read=/".to_string());
@@ -689,7 +1143,7 @@ impl DenoRunner {
 
         for file in mount_files {
             let path = match runner_type {
-                RunnerType::Host => file.to_string_lossy().to_string(),
+                RunnerType::Host | RunnerType::Sandbox => file.to_string_lossy().to_string(),
                 RunnerType::Docker => normalize_for_docker_path(file.to_path_buf()),
             };
             let mount_param = format!(r#"--allow-read={},--allow-write={}"#, path, path);
@@ -2,13 +2,23 @@ use rstest::rstest;
 use serde_json::json;
 use serde_json::Value;
 
+#[cfg(unix)]
+use crate::tools::test_utils::{fake_binary, fake_docker};
 use crate::tools::{
-    code_files::CodeFiles, deno_runner::DenoRunner, deno_runner_options::DenoRunnerOptions,
-    execution_context::ExecutionContext, execution_storage::ExecutionStorage,
-    runner_type::RunnerType, shinkai_node_location::ShinkaiNodeLocation,
+    code_files::CodeFiles,
+    deno_runner::DenoRunner,
+    deno_runner_options::DenoRunnerOptions,
+    execution_context::ExecutionContext,
+    execution_error::{ExecutionError, ExecutionErrorKind},
+    execution_storage::ExecutionStorage,
+    host_capabilities::{HostCallContext, HostCapabilities},
+    runner_type::{RunnerPolicy, RunnerType},
+    shinkai_node_location::ShinkaiNodeLocation,
+    tool_call::{ToolCallConfig, ToolResolver},
 };
+use futures::future::BoxFuture;
 
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::Arc};
 
 #[rstest]
 #[case::host(RunnerType::Host)]
@@ -137,6 +147,54 @@ async fn write_forbidden_folder(#[case] runner_type: RunnerType) {
     assert!(result.is_err());
 }
 
+#[rstest]
+#[case::host(RunnerType::Host)]
+#[case::docker(RunnerType::Docker)]
+#[tokio::test]
+async fn code_folder_is_reused_across_inputs(#[case] runner_type: RunnerType) {
+    let _ = env_logger::builder()
+        .filter_level(log::LevelFilter::Info)
+        .is_test(true)
+        .try_init();
+
+    let code_files = CodeFiles {
+        files: HashMap::from([(
+            "main.ts".to_string(),
+            r#"
+                async function run(configurations, params) {
+                    return { message: `${configurations.greeting} ${params.name}` };
+                }
+            "#
+            .to_string(),
+        )]),
+        entrypoint: "main.ts".to_string(),
+    };
+    let context = ExecutionContext {
+        context_id: nanoid::nanoid!(),
+        ..Default::default()
+    };
+    for name in ["deno", "again"] {
+        let deno_runner = DenoRunner::new(
+            code_files.clone(),
+            json!({ "greeting": "hi" }),
+            Some(DenoRunnerOptions {
+                context: context.clone(),
+                force_runner_type: Some(runner_type.clone()),
+                ..Default::default()
+            }),
+        );
+        let result = deno_runner
+            .run(None, json!({ "name": name }), None)
+            .await
+            .unwrap();
+        assert_eq!(result.data["message"], format!("hi {}", name));
+    }
+
+    let storage = ExecutionStorage::new(code_files, context);
+    let code_folders = std::fs::read_dir(storage.root_folder_path.join("code")).unwrap();
+    assert_eq!(code_folders.count(), 1);
+}
+
 #[rstest]
 #[case::host(RunnerType::Host)]
 #[case::docker(RunnerType::Docker)]
@@ -1453,3 +1511,483 @@ export async function run(config: Config, inputs: Inputs): Promise<Output> {
         .unwrap()
         .contains("Hello, world parameters!"));
 }
+
+#[rstest]
+#[case::host(RunnerType::Host)]
+#[case::docker(RunnerType::Docker)]
+#[tokio::test]
+async fn definition_from_code(#[case] runner_type: RunnerType) {
+    let _ = env_logger::builder()
+        .filter_level(log::LevelFilter::Info)
+        .is_test(true)
+        .try_init();
+
+    let code_files = CodeFiles {
+        files: HashMap::from([(
+            "main.ts".to_string(),
+            r#"
+            export const metadata = {
+                id: "shinkai-tool-echo",
+                name: "Shinkai: Echo",
+                description: "Echoes the message",
+                author: "Shinkai",
+                keywords: ["echo"],
+            };
+
+            type CONFIG = {
+                prefix?: string;
+            };
+            type INPUTS = {
+                message: string;
+            };
+            type OUTPUT = {
+                message: string;
+            };
+
+            export async function run(config: CONFIG, inputs: INPUTS): Promise<OUTPUT> {
+                throw new Error("run should not be executed");
+            }
+            "#
+            .to_string(),
+        )]),
+        entrypoint: "main.ts".to_string(),
+    };
+
+    let deno_runner = DenoRunner::new(
+        code_files,
+        json!({}),
+        Some(DenoRunnerOptions {
+            force_runner_type: Some(runner_type),
+            ..Default::default()
+        }),
+    );
+
+    let definition = deno_runner.definition().await.unwrap();
+    assert_eq!(definition.id, "shinkai-tool-echo");
+    assert_eq!(definition.name, "Shinkai: Echo");
+    assert_eq!(definition.keywords, vec!["echo".to_string()]);
+    assert_eq!(
+        definition.parameters["properties"]["message"]["type"],
+        "string"
+    );
+    assert_eq!(definition.parameters["required"], json!(["message"]));
+    assert_eq!(
+        definition.configurations["properties"]["prefix"]["type"],
+        "string"
+    );
+    assert_eq!(definition.result["properties"]["message"]["type"], "string");
+    assert!(definition
+        .code
+        .unwrap()
+        .contains("run should not be executed"));
+}
+
+struct EchoResolver;
+
+impl ToolResolver for EchoResolver {
+    fn call_tool(
+        &self,
+        tool_id: String,
+        parameters: Value,
+        tool_calls: ToolCallConfig,
+    ) -> BoxFuture<'_, Result<Value, ExecutionError>> {
+        Box::pin(async move {
+            Ok(json!({
+                "tool_id": tool_id,
+                "parameters": parameters,
+                "call_chain": tool_calls.call_chain,
+            }))
+        })
+    }
+}
+
+#[rstest]
+#[case::host(RunnerType::Host)]
+#[case::docker(RunnerType::Docker)]
+#[tokio::test]
+async fn call_other_tool(#[case] runner_type: RunnerType) {
+    let _ = env_logger::builder()
+        .filter_level(log::LevelFilter::Info)
+        .is_test(true)
+        .try_init();
+
+    let code_files = CodeFiles {
+        files: HashMap::from([(
+            "main.ts".to_string(),
+            r#"
+                async function run(configurations, params) {
+                    const child = await shinkaiCallTool("child", { message: params.message });
+                    let cycleError = "";
+                    try {
+                        await shinkaiCallTool("parent", {});
+                    } catch (e) {
+                        cycleError = e.message;
+                    }
+                    return { child, cycleError };
+                }
+            "#
+            .to_string(),
+        )]),
+        entrypoint: "main.ts".to_string(),
+    };
+
+    let deno_runner = DenoRunner::new(
+        code_files,
+        json!({}),
+        Some(DenoRunnerOptions {
+            force_runner_type: Some(runner_type),
+            tool_calls: Some(ToolCallConfig::new(Arc::new(EchoResolver), "parent")),
+            ..Default::default()
+        }),
+    );
+
+    let result = deno_runner
+        .run(None, json!({ "message": "hi" }), None)
+        .await
+        .unwrap();
+
+    assert_eq!(result.data["child"]["tool_id"], "child");
+    assert_eq!(result.data["child"]["parameters"]["message"], "hi");
+    assert_eq!(
+        result.data["child"]["call_chain"],
+        json!(["parent", "child"])
+    );
+    assert!(result.data["cycleError"]
+        .as_str()
+        .unwrap()
+        .contains("cycle detected"));
+}
+
+struct SecretsHost;
+
+impl HostCapabilities for SecretsHost {
+    fn get_secret(
+        &self,
+        _context: HostCallContext,
+        name: String,
+    ) -> BoxFuture<'_, anyhow::Result<Option<String>>> {
+        Box::pin(async move { Ok(Some(format!("{}-value", name))) })
+    }
+}
+
+#[rstest]
+#[case::host(RunnerType::Host)]
+#[case::docker(RunnerType::Docker)]
+#[tokio::test]
+async fn use_host_capabilities(#[case] runner_type: RunnerType) {
+    let _ = env_logger::builder()
+        .filter_level(log::LevelFilter::Info)
+        .is_test(true)
+        .try_init();
+
+    let code_files = CodeFiles {
+        files: HashMap::from([(
+            "main.ts".to_string(),
+            r#"
+                async function run(configurations, params) {
+                    const secret = await shinkaiHost.getSecret("API_KEY");
+                    let kvError = "";
+                    try {
+                        await shinkaiHost.kvGet("key");
+                    } catch (e) {
+                        kvError = e.message;
+                    }
+                    return { secret, kvError };
+                }
+            "#
+            .to_string(),
+        )]),
+        entrypoint: "main.ts".to_string(),
+    };
+
+    let deno_runner = DenoRunner::new(
+        code_files,
+        json!({}),
+        Some(DenoRunnerOptions {
+            force_runner_type: Some(runner_type),
+            host_capabilities: Some(Arc::new(SecretsHost)),
+            ..Default::default()
+        }),
+    );
+
+    let result = deno_runner.run(None, json!({}), None).await.unwrap();
+
+    assert_eq!(result.data["secret"], "API_KEY-value");
+    assert!(result.data["kvError"]
+        .as_str()
+        .unwrap()
+        .contains("not supported"));
+}
+
+#[rstest]
+#[case::host(RunnerType::Host)]
+#[case::docker(RunnerType::Docker)]
+#[tokio::test]
+async fn secret_configurations_are_not_persisted(#[case] runner_type: RunnerType) {
+    let _ = env_logger::builder()
+        .filter_level(log::LevelFilter::Info)
+        .is_test(true)
+        .try_init();
+
+    let code_files = CodeFiles {
+        files: HashMap::from([(
+            "main.ts".to_string(),
+            r#"
+                async function run(configurations, params) {
+                    console.log("using key", configurations.api_key);
+                    return { api_key: configurations.api_key, model: configurations.model };
+                }
+            "#
+            .to_string(),
+        )]),
+        entrypoint: "main.ts".to_string(),
+    };
+
+    let context = ExecutionContext {
+        context_id: nanoid::nanoid!(),
+        ..Default::default()
+    };
+    let deno_runner = DenoRunner::new(
+        code_files.clone(),
+        json!({ "api_key": "sk-very-secret", "model": "llama" }),
+        Some(DenoRunnerOptions {
+            context: context.clone(),
+            force_runner_type: Some(runner_type),
+            secret_configuration_keys: vec!["api_key".to_string()],
+            ..Default::default()
+        }),
+    );
+
+    let result = deno_runner.run(None, json!({}), None).await.unwrap();
+    assert_eq!(result.data["api_key"], "sk-very-secret");
+    assert_eq!(result.data["model"], "llama");
+
+    let storage = ExecutionStorage::new(code_files, context);
+    let entrypoint = std::fs::read_to_string(storage.code_entrypoint_file_path).unwrap();
+    assert!(entrypoint.contains("llama"));
+    assert!(!entrypoint.contains("sk-very-secret"));
+    let log = std::fs::read_to_string(storage.log_file_path).unwrap();
+    assert!(log.contains("using key [REDACTED]"));
+    assert!(!log.contains("sk-very-secret"));
+}
+
+/// Deno look-alike printing a fixed result with its home
+#[cfg(unix)]
+fn fake_deno(folder: &std::path::Path) -> std::path::PathBuf {
+    fake_binary(
+        folder,
+        "deno",
+        r#"echo "<shinkai-code-result>"
+echo "{\"message\":\"hello from host\",\"home\":\"$HOME\"}"
+echo "</shinkai-code-result>""#,
+    )
+}
+
+/// Docker cli look-alike whose `run` exits with `run_exit_code`, and a deno look-alike
+#[cfg(unix)]
+fn fake_docker_and_deno(
+    folder: &std::path::Path,
+    run_exit_code: i32,
+) -> (
+    crate::tools::container_runtime::ContainerRuntime,
+    std::path::PathBuf,
+) {
+    (
+        fake_docker(
+            folder,
+            &format!(
+                r#"    echo "docker: failed in docker" >&2; exit {}"#,
+                run_exit_code
+            ),
+        ),
+        fake_deno(folder),
+    )
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn docker_cli_gets_the_callback_token_by_name() {
+    let folder = tempfile::tempdir().unwrap();
+    let args_file = folder.path().join("args");
+    let token_file = folder.path().join("token");
+    let docker = fake_binary(
+        folder.path(),
+        "docker",
+        &format!(
+            r#"case "$1" in
+  info) exit 0 ;;
+  image) echo '{{"Id":"sha256:1111","RepoDigests":[]}}'; exit 0 ;;
+  network) echo "127.0.0.1"; exit 0 ;;
+  run)
+    echo "$@" > {}
+    echo "$SHINKAI_CALLBACK_TOKEN" > {}
+    echo "<shinkai-code-result>"; echo "{{}}"; echo "</shinkai-code-result>"; exit 0 ;;
+esac
+exit 1"#,
+            args_file.display(),
+            token_file.display()
+        ),
+    );
+    let code_files = CodeFiles {
+        files: HashMap::from([(
+            "main.ts".to_string(),
+            "async function run() { return {}; }".to_string(),
+        )]),
+        entrypoint: "main.ts".to_string(),
+    };
+    let deno_runner = DenoRunner::new(
+        code_files,
+        json!({}),
+        Some(DenoRunnerOptions {
+            context: ExecutionContext {
+                storage: folder.path().join("storage"),
+                ..Default::default()
+            },
+            container_runtime: Some(crate::tools::container_runtime::ContainerRuntime::new(
+                crate::tools::container_runtime::ContainerRuntimeKind::Docker,
+                docker,
+            )),
+            force_runner_type: Some(RunnerType::Docker),
+            tool_calls: Some(ToolCallConfig::new(Arc::new(EchoResolver), "parent")),
+            ..Default::default()
+        }),
+    );
+
+    deno_runner.run(None, json!({}), None).await.unwrap();
+    let args = std::fs::read_to_string(args_file).unwrap();
+    let token = std::fs::read_to_string(token_file).unwrap();
+    assert_eq!(token.trim().len(), 32);
+    assert!(args.contains("-e SHINKAI_CALLBACK_TOKEN "));
+    assert!(!args.contains(token.trim()));
+}
+
+#[cfg(unix)]
+#[rstest]
+#[case::infrastructure_failure_falls_back(125, RunnerPolicy::DockerPreferred, true)]
+#[case::tool_missing_command_does_not_fall_back(127, RunnerPolicy::DockerPreferred, false)]
+#[case::tool_failure_does_not_fall_back(1, RunnerPolicy::DockerPreferred, false)]
+#[case::docker_only_does_not_fall_back(125, RunnerPolicy::DockerOnly, false)]
+#[tokio::test]
+async fn docker_preferred_falls_back_to_host_on_infrastructure_failure(
+    #[case] run_exit_code: i32,
+    #[case] runner_policy: RunnerPolicy,
+    #[case] falls_back: bool,
+) {
+    let folder = tempfile::tempdir().unwrap();
+    let (container_runtime, deno_binary_path) = fake_docker_and_deno(folder.path(), run_exit_code);
+    let code_files = CodeFiles {
+        files: HashMap::from([(
+            "main.ts".to_string(),
+            "async function run() { return {}; }".to_string(),
+        )]),
+        entrypoint: "main.ts".to_string(),
+    };
+    let deno_runner = DenoRunner::new(
+        code_files,
+        json!({}),
+        Some(DenoRunnerOptions {
+            context: ExecutionContext {
+                storage: folder.path().join("storage"),
+                ..Default::default()
+            },
+            deno_binary_path,
+            container_runtime: Some(container_runtime),
+            runner_policy,
+            ..Default::default()
+        }),
+    );
+
+    let result = deno_runner.run(None, json!({}), None).await;
+    if falls_back {
+        let result = result.unwrap();
+        assert_eq!(result.data["message"], "hello from host");
+        assert_eq!(result.metadata.runner_type, Some(RunnerType::Host));
+        assert!(result
+            .metadata
+            .fallback_reason
+            .unwrap()
+            .contains("failed in docker"));
+    } else {
+        let error = result.unwrap_err();
+        assert!(error.message().contains("failed in docker"));
+        let expected_kind = if run_exit_code == 125 {
+            ExecutionErrorKind::Infrastructure
+        } else {
+            ExecutionErrorKind::Execution
+        };
+        assert_eq!(*error.kind(), expected_kind);
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn sandbox_runs_host_binary_through_bwrap() {
+    use crate::tools::linux_sandbox::LinuxSandbox;
+
+    let folder = tempfile::tempdir().unwrap();
+    // Records each call on a line and the seccomp filter size, then runs the program after `--`
+    let bwrap_binary_path = fake_binary(
+        folder.path(),
+        "bwrap",
+        &format!(
+            r#"echo "$@" >> "{folder}/bwrap-args"
+wc -c < /proc/self/fd/10 > "{folder}/seccomp-size"
+while [ "$1" != "--" ]; do shift; done
+shift
+exec "$@""#,
+            folder = folder.path().display()
+        ),
+    );
+    let code_files = CodeFiles {
+        files: HashMap::from([(
+            "main.ts".to_string(),
+            "async function run() { return {}; }".to_string(),
+        )]),
+        entrypoint: "main.ts".to_string(),
+    };
+    let context = ExecutionContext {
+        storage: folder.path().join("storage"),
+        ..Default::default()
+    };
+    let deno_runner = DenoRunner::new(
+        code_files.clone(),
+        json!({}),
+        Some(DenoRunnerOptions {
+            context: context.clone(),
+            deno_binary_path: fake_deno(folder.path()),
+            force_runner_type: Some(RunnerType::Sandbox),
+            sandbox: LinuxSandbox {
+                bwrap_binary_path,
+                ..Default::default()
+            },
+            ..Default::default()
+        }),
+    );
+
+    let result = deno_runner.run(None, json!({}), None).await.unwrap();
+    assert_eq!(result.metadata.runner_type, Some(RunnerType::Sandbox));
+    let storage = ExecutionStorage::new(code_files, context);
+    assert_eq!(
+        result.data["home"],
+        storage.home_folder_path.to_string_lossy().to_string()
+    );
+    let calls = std::fs::read_to_string(folder.path().join("bwrap-args")).unwrap();
+    let calls = calls.lines().collect::<Vec<_>>();
+    assert_eq!(calls.len(), 2);
+    // The imports are cached with the network shared, the tool stays offline on the cache
+    assert!(calls[0].contains("--share-net"), "{}", calls[0]);
+    assert!(calls[0].contains(" cache --no-lock "), "{}", calls[0]);
+    assert!(!calls[1].contains("--share-net"), "{}", calls[1]);
+    assert!(
+        calls[1].contains(" run --ext ts --cached-only "),
+        "{}",
+        calls[1]
+    );
+    let args = calls[1];
+    // The code folder is named after the wrapped code, only its parent is known here
+    let code_root = storage.root_code_folder_path.to_string_lossy().to_string();
+    assert!(args.contains(&format!("--ro-bind-try {}/", code_root)));
+    assert!(args.contains("--seccomp 10 --"));
+    let seccomp_size = std::fs::read_to_string(folder.path().join("seccomp-size")).unwrap();
+    assert!(seccomp_size.trim().parse::<usize>().unwrap() > 0);
+}
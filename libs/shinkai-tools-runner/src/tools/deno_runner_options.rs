@@ -1,8 +1,15 @@
-use std::path::PathBuf;
+use std::{path::PathBuf, sync::Arc};
 
 use super::{
-    execution_context::ExecutionContext, runner_type::RunnerType,
+    container_runtime::ContainerRuntime,
+    execution_context::ExecutionContext,
+    host_capabilities::HostCapabilities,
+    linux_sandbox::LinuxSandbox,
+    redaction::RedactionRule,
+    runner_type::{RunnerPolicy, RunnerType},
     shinkai_node_location::ShinkaiNodeLocation,
+    tool_call::ToolCallConfig,
+    tool_signature::{ToolSignature, TrustStore},
 };
 
 #[derive(Clone)]
@@ -10,8 +17,26 @@ pub struct DenoRunnerOptions {
     pub context: ExecutionContext,
     pub deno_binary_path: PathBuf,
     pub code_runner_docker_image_name: String,
+    /// Pins the code runner image, it is pulled by digest and verified before running
+    pub code_runner_docker_image_digest: Option<String>,
     pub force_runner_type: Option<RunnerType>,
+    /// Runner types allowed when `force_runner_type` isn't set, and whether Docker falls back to Host
+    pub runner_policy: RunnerPolicy,
+    /// Container runtime used by the Docker runner type, detected when None
+    pub container_runtime: Option<ContainerRuntime>,
+    /// bubblewrap sandbox used by the Sandbox runner type
+    pub sandbox: LinuxSandbox,
     pub shinkai_node_location: ShinkaiNodeLocation,
+    pub trust_store: TrustStore,
+    pub code_signature: Option<ToolSignature>,
+    /// Lets the tool call other tools through the callback server
+    pub tool_calls: Option<ToolCallConfig>,
+    /// Secrets, key-value store, http and llm access mediated by the embedding application
+    pub host_capabilities: Option<Arc<dyn HostCapabilities>>,
+    /// Top level configurations keys delivered out-of-band and redacted from logs
+    pub secret_configuration_keys: Vec<String>,
+    /// Extra redaction applied to logs and execution log entries
+    pub redaction_rules: Vec<Arc<dyn RedactionRule>>,
 }
 
 impl Default for DenoRunnerOptions {
@@ -19,17 +44,27 @@ impl Default for DenoRunnerOptions {
         Self {
             context: ExecutionContext::default(),
             code_runner_docker_image_name: String::from("dcspark/shinkai-code-runner:0.9.4"),
+            code_runner_docker_image_digest: None,
             deno_binary_path: PathBuf::from(if cfg!(windows) {
                 "./shinkai-tools-runner-resources/deno.exe"
             } else {
                 "./shinkai-tools-runner-resources/deno"
             }),
             force_runner_type: None,
+            runner_policy: RunnerPolicy::default(),
+            container_runtime: None,
+            sandbox: LinuxSandbox::default(),
             shinkai_node_location: ShinkaiNodeLocation {
                 protocol: String::from("http"),
                 host: String::from("127.0.0.1"),
                 port: 9550,
             },
+            trust_store: TrustStore::default(),
+            code_signature: None,
+            tool_calls: None,
+            host_capabilities: None,
+            secret_configuration_keys: Vec::new(),
+            redaction_rules: Vec::new(),
         }
     }
 }
@@ -0,0 +1,199 @@
+use std::{
+    collections::HashMap,
+    path::{self, PathBuf},
+    process::Command,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    container_runtime::{ContainerRuntime, ContainerRuntimeKind},
+    container_utils::DockerStatus,
+    deno_runner_options::DenoRunnerOptions,
+    execution_context::ExecutionContext,
+    python_runner_options::PythonRunnerOptions,
+};
+
+static REPORT_CACHE: Lazy<Mutex<HashMap<String, (Instant, DiagnosticsReport)>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// What `diagnose` checks
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiagnosticsOptions {
+    pub deno_binary_path: PathBuf,
+    pub uv_binary_path: PathBuf,
+    /// Runtimes to probe, the first running one is reported as selected
+    pub container_runtimes: Vec<ContainerRuntime>,
+    pub code_runner_docker_image_name: String,
+    pub storage: PathBuf,
+    /// How long a report is reused by `diagnose`
+    pub cache_ttl: Duration,
+}
+
+impl Default for DiagnosticsOptions {
+    fn default() -> Self {
+        let deno_options = DenoRunnerOptions::default();
+        Self {
+            deno_binary_path: deno_options.deno_binary_path,
+            uv_binary_path: PythonRunnerOptions::default().uv_binary_path,
+            container_runtimes: vec![ContainerRuntime::docker(), ContainerRuntime::podman()],
+            code_runner_docker_image_name: deno_options.code_runner_docker_image_name,
+            storage: ExecutionContext::default().storage,
+            cache_ttl: Duration::from_secs(60),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BinaryDiagnostics {
+    pub path: PathBuf,
+    pub present: bool,
+    /// First line of `--version`
+    pub version: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ContainerRuntimeDiagnostics {
+    pub kind: ContainerRuntimeKind,
+    pub binary_path: PathBuf,
+    pub status: DockerStatus,
+    pub version: Option<String>,
+    /// Only meaningful when running
+    pub rootless: bool,
+    /// Whether the code runner image is available locally, only checked when running
+    pub image_present: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StorageDiagnostics {
+    pub path: PathBuf,
+    /// False until the first execution creates it
+    pub exists: bool,
+    pub writable: bool,
+    pub error: Option<String>,
+}
+
+/// Host readiness report, serializable to show it in a settings page
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DiagnosticsReport {
+    /// RFC 3339 UTC timestamp
+    pub generated_at: String,
+    pub deno: BinaryDiagnostics,
+    pub uv: BinaryDiagnostics,
+    pub container_runtimes: Vec<ContainerRuntimeDiagnostics>,
+    /// First running container runtime, the one runners pick when none is configured
+    pub selected_container_runtime: Option<ContainerRuntimeKind>,
+    pub code_runner_docker_image_name: String,
+    pub storage: StorageDiagnostics,
+}
+
+/// Report for the options, reused until `cache_ttl` elapses
+pub fn diagnose(options: &DiagnosticsOptions) -> DiagnosticsReport {
+    let key = format!("{:?}", options);
+    if let Some((generated_at, report)) = REPORT_CACHE.lock().unwrap().get(&key) {
+        if generated_at.elapsed() < options.cache_ttl {
+            return report.clone();
+        }
+    }
+    let report = diagnose_uncached(options);
+    REPORT_CACHE
+        .lock()
+        .unwrap()
+        .insert(key, (Instant::now(), report.clone()));
+    report
+}
+
+/// Runs every check again, ignoring and not updating the cache
+pub fn diagnose_uncached(options: &DiagnosticsOptions) -> DiagnosticsReport {
+    log::info!("diagnosing runtimes with options: {:?}", options);
+    let container_runtimes = options
+        .container_runtimes
+        .iter()
+        .map(|runtime| container_runtime_diagnostics(runtime, options))
+        .collect::<Vec<_>>();
+    let selected_container_runtime = container_runtimes
+        .iter()
+        .find(|runtime| runtime.status == DockerStatus::Running)
+        .map(|runtime| runtime.kind);
+    DiagnosticsReport {
+        generated_at: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+        deno: binary_diagnostics(&options.deno_binary_path),
+        uv: binary_diagnostics(&options.uv_binary_path),
+        container_runtimes,
+        selected_container_runtime,
+        code_runner_docker_image_name: options.code_runner_docker_image_name.clone(),
+        storage: storage_diagnostics(&options.storage),
+    }
+}
+
+/// Drops cached reports, e.g. after the user installed a runtime
+pub fn clear_diagnostics_cache() {
+    REPORT_CACHE.lock().unwrap().clear();
+}
+
+fn binary_diagnostics(binary_path: &PathBuf) -> BinaryDiagnostics {
+    let path = path::absolute(binary_path).unwrap_or(binary_path.clone());
+    let output = Command::new(&path).arg("--version").output();
+    let version = output
+        .as_ref()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| {
+            String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .next()
+                .map(|line| line.trim().to_string())
+        });
+    BinaryDiagnostics {
+        present: path.is_file() || output.is_ok(),
+        path,
+        version,
+    }
+}
+
+fn container_runtime_diagnostics(
+    runtime: &ContainerRuntime,
+    options: &DiagnosticsOptions,
+) -> ContainerRuntimeDiagnostics {
+    let status = runtime.status();
+    let is_running = status == DockerStatus::Running;
+    ContainerRuntimeDiagnostics {
+        kind: runtime.kind,
+        binary_path: runtime.binary_path.clone(),
+        status,
+        version: runtime.version(),
+        rootless: is_running && runtime.is_rootless(),
+        image_present: is_running && runtime.image_exists(&options.code_runner_docker_image_name),
+    }
+}
+
+/// Writes a probe file in the storage folder, or in the nearest parent it would be created in
+/// when it doesn't exist yet, without creating anything
+fn storage_diagnostics(storage: &PathBuf) -> StorageDiagnostics {
+    let path = path::absolute(storage).unwrap_or(storage.clone());
+    let probe_folder = path
+        .ancestors()
+        .find(|ancestor| ancestor.exists())
+        .unwrap_or(&path);
+    let result = if probe_folder.is_dir() {
+        tempfile::NamedTempFile::new_in(probe_folder).map(|_| ())
+    } else {
+        Err(std::io::Error::other(format!(
+            "{} is not a folder",
+            probe_folder.display()
+        )))
+    };
+    StorageDiagnostics {
+        exists: path.is_dir(),
+        writable: result.is_ok(),
+        error: result.err().map(|e| e.to_string()),
+        path,
+    }
+}
+
+#[cfg(test)]
+#[path = "diagnostics.test.rs"]
+mod tests;
@@ -0,0 +1,145 @@
+#![cfg(unix)]
+
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::tools::{
+    container_runtime::{ContainerRuntime, ContainerRuntimeKind},
+    container_utils::DockerStatus,
+    diagnostics::{clear_diagnostics_cache, diagnose, diagnose_uncached, DiagnosticsOptions},
+    test_utils::fake_binary,
+};
+
+/// Rootless podman look-alike that logs every invocation to `calls.log`
+fn fake_podman(folder: &Path) -> PathBuf {
+    let calls = folder.join("calls.log");
+    fake_binary(
+        folder,
+        "podman",
+        &format!(
+            r#"echo "$@" >> "{}"
+case "$1" in
+  info) if [ "$2" = "--format" ]; then echo true; fi; exit 0 ;;
+  --version) echo "podman version 5.2.0"; exit 0 ;;
+  image) if [ "$3" = "present:latest" ]; then exit 0; fi; exit 1 ;;
+esac
+exit 1"#,
+            calls.display()
+        ),
+    )
+}
+
+fn options(folder: &Path) -> DiagnosticsOptions {
+    DiagnosticsOptions {
+        deno_binary_path: fake_binary(
+            folder,
+            "deno",
+            "echo 'deno 2.1.1 (stable, release, x86_64-unknown-linux-gnu)'\necho 'v8 13.0'",
+        ),
+        uv_binary_path: folder.join("missing-uv"),
+        container_runtimes: vec![
+            ContainerRuntime::new(ContainerRuntimeKind::Docker, folder.join("missing-docker")),
+            ContainerRuntime::new(ContainerRuntimeKind::Podman, fake_podman(folder)),
+        ],
+        code_runner_docker_image_name: String::from("present:latest"),
+        storage: folder.join("storage"),
+        cache_ttl: Duration::from_secs(60),
+    }
+}
+
+#[test]
+fn report_covers_binaries_runtimes_and_storage() {
+    let folder = tempfile::tempdir().unwrap();
+    let report = diagnose_uncached(&options(folder.path()));
+
+    assert!(report.deno.present);
+    assert_eq!(
+        report.deno.version.as_deref(),
+        Some("deno 2.1.1 (stable, release, x86_64-unknown-linux-gnu)")
+    );
+    assert!(!report.uv.present);
+    assert_eq!(report.uv.version, None);
+
+    assert_eq!(report.container_runtimes.len(), 2);
+    let docker = &report.container_runtimes[0];
+    assert_eq!(docker.status, DockerStatus::NotInstalled);
+    assert!(!docker.image_present);
+    let podman = &report.container_runtimes[1];
+    assert_eq!(podman.status, DockerStatus::Running);
+    assert_eq!(podman.version.as_deref(), Some("podman version 5.2.0"));
+    assert!(podman.rootless);
+    assert!(podman.image_present);
+    assert_eq!(
+        report.selected_container_runtime,
+        Some(ContainerRuntimeKind::Podman)
+    );
+
+    assert!(report.storage.writable);
+    assert!(!report.storage.exists);
+    assert!(!folder.path().join("storage").exists());
+
+    std::fs::create_dir(folder.path().join("storage")).unwrap();
+    let report = diagnose_uncached(&options(folder.path()));
+    assert!(report.storage.writable);
+    assert!(report.storage.exists);
+    assert_eq!(
+        std::fs::read_dir(folder.path().join("storage"))
+            .unwrap()
+            .count(),
+        0
+    );
+
+    let value = serde_json::to_value(&report).unwrap();
+    assert_eq!(value["container_runtimes"][1]["kind"], "podman");
+    assert_eq!(value["container_runtimes"][0]["status"], "not_installed");
+    assert_eq!(value["selected_container_runtime"], "podman");
+}
+
+#[test]
+fn missing_image_and_unwritable_storage() {
+    let folder = tempfile::tempdir().unwrap();
+    let storage_file = folder.path().join("storage-file");
+    std::fs::write(&storage_file, "not a folder").unwrap();
+    let options = DiagnosticsOptions {
+        code_runner_docker_image_name: String::from("absent:latest"),
+        storage: storage_file,
+        ..options(folder.path())
+    };
+    let report = diagnose_uncached(&options);
+    assert!(!report.container_runtimes[1].image_present);
+    assert!(!report.storage.writable);
+    assert!(report.storage.error.is_some());
+}
+
+#[test]
+fn diagnose_reuses_cached_report() {
+    let folder = tempfile::tempdir().unwrap();
+    let options = options(folder.path());
+    let calls = || {
+        std::fs::read_to_string(folder.path().join("calls.log"))
+            .unwrap()
+            .lines()
+            .count()
+    };
+
+    let first = diagnose(&options);
+    let calls_after_first = calls();
+    let second = diagnose(&options);
+    assert_eq!(first, second);
+    assert_eq!(calls(), calls_after_first);
+
+    clear_diagnostics_cache();
+    diagnose(&options);
+    assert!(calls() > calls_after_first);
+
+    let expired = DiagnosticsOptions {
+        cache_ttl: Duration::ZERO,
+        ..options.clone()
+    };
+    let calls_before_expired = calls();
+    diagnose(&expired);
+    diagnose(&expired);
+    assert_eq!(calls(), calls_before_expired + 2 * calls_after_first);
+}
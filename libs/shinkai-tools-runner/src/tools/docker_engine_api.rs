@@ -0,0 +1,630 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use hyper::{
+    body::HttpBody,
+    header::{CONNECTION, CONTENT_TYPE, HOST, UPGRADE},
+    Body, Method, Request, StatusCode,
+};
+use serde_json::{json, Value};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt},
+    sync::Mutex,
+};
+
+use super::{
+    container_runtime::BindMount,
+    execution_error::InfrastructureError,
+    execution_log::LogStream,
+    execution_storage::ExecutionStorage,
+    image_manager::{parse_loaded_images, ImageInfo, ImagePullProgress},
+};
+
+/// Error response of the engine, `status` is the HTTP status code
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DockerEngineApiError {
+    pub status: u16,
+    pub message: String,
+}
+
+impl std::fmt::Display for DockerEngineApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "docker engine api error (status {}): {}",
+            self.status, self.message
+        )
+    }
+}
+
+impl std::error::Error for DockerEngineApiError {}
+
+/// Container to create, the equivalent of the `docker run` arguments used by the runners
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ContainerSpec {
+    pub image: String,
+    pub cmd: Vec<String>,
+    /// `KEY=value` entries
+    pub env: Vec<String>,
+    pub working_dir: Option<String>,
+    pub mounts: Vec<BindMount>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContainerWaitResult {
+    pub status_code: i64,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ContainerState {
+    pub status: String,
+    pub exit_code: i64,
+    pub oom_killed: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ContainerStats {
+    pub memory_usage_bytes: u64,
+    pub memory_limit_bytes: u64,
+    pub cpu_total_usage_nanos: u64,
+}
+
+/// Outcome of `DockerEngineClient::run_container`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ContainerRunOutcome {
+    pub exit_code: i64,
+    pub oom_killed: bool,
+    pub timed_out: bool,
+    /// Peak memory and last cpu usage sampled while the container was running
+    pub stats: ContainerStats,
+}
+
+/// Minimal Docker Engine API client over a unix socket
+///
+/// Podman exposes the same API through its compatibility socket.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DockerEngineClient {
+    socket_path: PathBuf,
+}
+
+impl Default for DockerEngineClient {
+    fn default() -> Self {
+        Self::new(PathBuf::from(Self::DEFAULT_SOCKET_PATH))
+    }
+}
+
+impl DockerEngineClient {
+    pub const DEFAULT_SOCKET_PATH: &'static str = "/var/run/docker.sock";
+    const STATS_INTERVAL: Duration = Duration::from_secs(1);
+
+    pub fn new(socket_path: PathBuf) -> Self {
+        DockerEngineClient { socket_path }
+    }
+
+    pub fn socket_path(&self) -> &Path {
+        &self.socket_path
+    }
+
+    pub async fn ping(&self) -> anyhow::Result<()> {
+        self.request(Method::GET, "/_ping", None).await?;
+        Ok(())
+    }
+
+    pub async fn create_container(&self, spec: &ContainerSpec) -> anyhow::Result<String> {
+        let mounts = spec
+            .mounts
+            .iter()
+            .map(|mount| {
+                json!({
+                    "Type": "bind",
+                    "Source": mount.source,
+                    "Target": mount.target,
+                    "ReadOnly": mount.readonly,
+                })
+            })
+            .collect::<Vec<_>>();
+        let mut body = json!({
+            "Image": spec.image,
+            "Cmd": spec.cmd,
+            "Env": spec.env,
+            "AttachStdout": true,
+            "AttachStderr": true,
+            "Tty": false,
+            "OpenStdin": false,
+            "HostConfig": { "Mounts": mounts },
+        });
+        if let Some(working_dir) = &spec.working_dir {
+            body["WorkingDir"] = json!(working_dir);
+        }
+        let response = self
+            .request(Method::POST, "/containers/create", Some(body))
+            .await?;
+        response["Id"]
+            .as_str()
+            .map(|id| id.to_string())
+            .ok_or_else(|| anyhow::anyhow!("container create response without id: {}", response))
+    }
+
+    pub async fn start_container(&self, id: &str) -> anyhow::Result<()> {
+        self.request(Method::POST, &format!("/containers/{}/start", id), None)
+            .await?;
+        Ok(())
+    }
+
+    /// Attaches to stdout and stderr, call it before starting the container to get all the output
+    pub async fn attach_container(
+        &self,
+        id: &str,
+    ) -> anyhow::Result<impl AsyncRead + Unpin + Send + 'static> {
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(format!(
+                "/containers/{}/attach?stream=1&stdout=1&stderr=1",
+                id
+            ))
+            .header(HOST, "docker")
+            .header(CONNECTION, "Upgrade")
+            .header(UPGRADE, "tcp")
+            .body(Body::empty())?;
+        let response = self.send(request).await?;
+        if response.status() != StatusCode::SWITCHING_PROTOCOLS && !response.status().is_success() {
+            return Err(Self::error_from_response(response).await.into());
+        }
+        Ok(hyper::upgrade::on(response).await?)
+    }
+
+    pub async fn wait_container(&self, id: &str) -> anyhow::Result<ContainerWaitResult> {
+        let response = self
+            .request(Method::POST, &format!("/containers/{}/wait", id), None)
+            .await?;
+        Ok(ContainerWaitResult {
+            status_code: response["StatusCode"].as_i64().unwrap_or(-1),
+            error: response["Error"]["Message"]
+                .as_str()
+                .filter(|message| !message.is_empty())
+                .map(|message| message.to_string()),
+        })
+    }
+
+    pub async fn inspect_container(&self, id: &str) -> anyhow::Result<ContainerState> {
+        let response = self
+            .request(Method::GET, &format!("/containers/{}/json", id), None)
+            .await?;
+        let state = &response["State"];
+        Ok(ContainerState {
+            status: state["Status"].as_str().unwrap_or_default().to_string(),
+            exit_code: state["ExitCode"].as_i64().unwrap_or(-1),
+            oom_killed: state["OOMKilled"].as_bool().unwrap_or(false),
+        })
+    }
+
+    pub async fn container_stats(&self, id: &str) -> anyhow::Result<ContainerStats> {
+        let response = self
+            .request(
+                Method::GET,
+                &format!("/containers/{}/stats?stream=false&one-shot=true", id),
+                None,
+            )
+            .await?;
+        Ok(ContainerStats {
+            memory_usage_bytes: response["memory_stats"]["usage"].as_u64().unwrap_or(0),
+            memory_limit_bytes: response["memory_stats"]["limit"].as_u64().unwrap_or(0),
+            cpu_total_usage_nanos: response["cpu_stats"]["cpu_usage"]["total_usage"]
+                .as_u64()
+                .unwrap_or(0),
+        })
+    }
+
+    pub async fn kill_container(&self, id: &str) -> anyhow::Result<()> {
+        self.request(Method::POST, &format!("/containers/{}/kill", id), None)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn remove_container(&self, id: &str) -> anyhow::Result<()> {
+        self.request(
+            Method::DELETE,
+            &format!("/containers/{}?force=true&v=true", id),
+            None,
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// None when the image isn't available locally
+    pub async fn inspect_image(&self, image: &str) -> anyhow::Result<Option<ImageInfo>> {
+        match self
+            .request(Method::GET, &format!("/images/{}/json", image), None)
+            .await
+        {
+            Ok(response) => Ok(Some(ImageInfo::from_inspect(&response))),
+            Err(e)
+                if e.downcast_ref::<DockerEngineApiError>()
+                    .is_some_and(|e| e.status == 404) =>
+            {
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    pub async fn pull_image(
+        &self,
+        image: &str,
+        on_progress: &mut (impl FnMut(ImagePullProgress) + Send),
+    ) -> anyhow::Result<()> {
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(format!("/images/create?fromImage={}", image))
+            .header(HOST, "docker")
+            .body(Body::empty())?;
+        self.request_stream(request, |message| {
+            on_progress(ImagePullProgress {
+                id: message["id"].as_str().map(|id| id.to_string()),
+                status: message["status"].as_str().unwrap_or_default().to_string(),
+                current: message["progressDetail"]["current"].as_u64(),
+                total: message["progressDetail"]["total"].as_u64(),
+            });
+        })
+        .await
+    }
+
+    /// Loads an image tarball, as written by `docker save`, and returns the loaded image names
+    pub async fn load_image(&self, tarball: &Path) -> anyhow::Result<Vec<String>> {
+        let mut file = tokio::fs::File::open(tarball).await.map_err(|e| {
+            anyhow::anyhow!("failed to open image tarball {}: {}", tarball.display(), e)
+        })?;
+        let (mut sender, body) = Body::channel();
+        tokio::spawn(async move {
+            let mut buffer = vec![0u8; 64 * 1024];
+            loop {
+                match file.read(&mut buffer).await {
+                    Ok(0) => break,
+                    Ok(read) => {
+                        let chunk = hyper::body::Bytes::copy_from_slice(&buffer[..read]);
+                        if sender.send_data(chunk).await.is_err() {
+                            break;
+                        }
+                    }
+                    Err(e) => {
+                        log::error!("failed to read image tarball: {}", e);
+                        sender.abort();
+                        break;
+                    }
+                }
+            }
+        });
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri("/images/load?quiet=1")
+            .header(HOST, "docker")
+            .header(CONTENT_TYPE, "application/x-tar")
+            .body(body)?;
+        let mut loaded = Vec::new();
+        self.request_stream(request, |message| {
+            if let Some(stream) = message["stream"].as_str() {
+                loaded.extend(parse_loaded_images(stream));
+            }
+        })
+        .await?;
+        Ok(loaded)
+    }
+
+    /// Creates, attaches, starts and waits on a container, then removes it
+    ///
+    /// Output is delivered line by line to `on_output`. When the timeout elapses the container is
+    /// killed and the outcome is flagged as timed out.
+    pub async fn run_container(
+        &self,
+        spec: &ContainerSpec,
+        timeout: Option<Duration>,
+        mut on_output: impl FnMut(LogStream, String) + Send,
+    ) -> anyhow::Result<ContainerRunOutcome> {
+        // The tool never ran, everything after start is reported as is
+        let id = self
+            .create_container(spec)
+            .await
+            .map_err(|e| InfrastructureError(format!("failed to create container: {}", e)))?;
+        log::info!("created container {}", id);
+        let outcome = self
+            .run_created_container(&id, timeout, &mut on_output)
+            .await;
+        if let Err(e) = self.remove_container(&id).await {
+            log::warn!("failed to remove container {}: {}", id, e);
+        }
+        outcome
+    }
+
+    async fn run_created_container(
+        &self,
+        id: &str,
+        timeout: Option<Duration>,
+        on_output: &mut (impl FnMut(LogStream, String) + Send),
+    ) -> anyhow::Result<ContainerRunOutcome> {
+        let output = self
+            .attach_container(id)
+            .await
+            .map_err(|e| InfrastructureError(format!("failed to attach to container: {}", e)))?;
+        self.start_container(id)
+            .await
+            .map_err(|e| InfrastructureError(format!("failed to start container: {}", e)))?;
+
+        let stats = Arc::new(Mutex::new(ContainerStats::default()));
+        let stats_task = {
+            let client = self.clone();
+            let id = id.to_string();
+            let stats = stats.clone();
+            tokio::spawn(async move {
+                loop {
+                    if let Ok(sample) = client.container_stats(&id).await {
+                        let mut stats = stats.lock().await;
+                        stats.memory_usage_bytes =
+                            stats.memory_usage_bytes.max(sample.memory_usage_bytes);
+                        stats.memory_limit_bytes = sample.memory_limit_bytes;
+                        stats.cpu_total_usage_nanos = stats
+                            .cpu_total_usage_nanos
+                            .max(sample.cpu_total_usage_nanos);
+                    }
+                    tokio::time::sleep(Self::STATS_INTERVAL).await;
+                }
+            })
+        };
+
+        let run = async {
+            let (read_result, wait_result) =
+                futures::join!(demux_output(output, on_output), self.wait_container(id));
+            if let Err(e) = read_result {
+                log::warn!("failed to read container {} output: {}", id, e);
+            }
+            wait_result
+        };
+        let wait_result = match timeout {
+            Some(timeout) => tokio::time::timeout(timeout, run).await.ok(),
+            None => Some(run.await),
+        };
+        stats_task.abort();
+
+        let mut outcome = ContainerRunOutcome {
+            stats: *stats.lock().await,
+            ..Default::default()
+        };
+        match wait_result {
+            Some(wait_result) => {
+                let wait_result = wait_result?;
+                if let Some(error) = wait_result.error {
+                    log::warn!("container {} wait error: {}", id, error);
+                }
+                outcome.exit_code = wait_result.status_code;
+            }
+            None => {
+                log::error!("container {} timed out, killing it", id);
+                if let Err(e) = self.kill_container(id).await {
+                    log::warn!("failed to kill container {}: {}", id, e);
+                }
+                outcome.timed_out = true;
+            }
+        }
+        let state = self.inspect_container(id).await?;
+        outcome.oom_killed = state.oom_killed;
+        if outcome.timed_out {
+            outcome.exit_code = state.exit_code;
+        }
+        Ok(outcome)
+    }
+
+    async fn request(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<Value>,
+    ) -> anyhow::Result<Value> {
+        let mut request = Request::builder()
+            .method(method)
+            .uri(path)
+            .header(HOST, "docker");
+        let body = match body {
+            Some(body) => {
+                request = request.header(CONTENT_TYPE, "application/json");
+                Body::from(serde_json::to_vec(&body)?)
+            }
+            None => Body::empty(),
+        };
+        let response = self.send(request.body(body)?).await?;
+        if !response.status().is_success() {
+            return Err(Self::error_from_response(response).await.into());
+        }
+        let bytes = hyper::body::to_bytes(response.into_body()).await?;
+        if bytes.is_empty() {
+            return Ok(Value::Null);
+        }
+        Ok(serde_json::from_slice(&bytes)
+            .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(&bytes).to_string())))
+    }
+
+    /// Sends a request answered with a stream of JSON messages, failing on an `error` message
+    async fn request_stream(
+        &self,
+        request: Request<Body>,
+        mut on_message: impl FnMut(Value),
+    ) -> anyhow::Result<()> {
+        let response = self.send(request).await?;
+        if !response.status().is_success() {
+            return Err(Self::error_from_response(response).await.into());
+        }
+        let mut body = response.into_body();
+        let mut buffer = Vec::<u8>::new();
+        let mut handle_line = |line: &[u8]| -> anyhow::Result<()> {
+            let Ok(message) = serde_json::from_slice::<Value>(line) else {
+                return Ok(());
+            };
+            if let Some(error) = message["error"].as_str() {
+                return Err(anyhow::anyhow!("{}", error));
+            }
+            on_message(message);
+            Ok(())
+        };
+        while let Some(chunk) = body.data().await {
+            buffer.extend_from_slice(&chunk?);
+            while let Some(position) = buffer.iter().position(|byte| *byte == b'\n') {
+                let line = buffer.drain(..=position).collect::<Vec<u8>>();
+                handle_line(&line)?;
+            }
+        }
+        handle_line(&buffer)
+    }
+
+    #[cfg(unix)]
+    async fn send(&self, request: Request<Body>) -> anyhow::Result<hyper::Response<Body>> {
+        let stream = tokio::net::UnixStream::connect(&self.socket_path)
+            .await
+            .map_err(|e| {
+                anyhow::anyhow!(
+                    "failed to connect to docker engine socket {}: {}",
+                    self.socket_path.display(),
+                    e
+                )
+            })?;
+        let (mut sender, connection) = hyper::client::conn::handshake(stream).await?;
+        tokio::spawn(async move {
+            if let Err(e) = connection.await {
+                log::debug!("docker engine connection closed: {}", e);
+            }
+        });
+        Ok(sender.send_request(request).await?)
+    }
+
+    #[cfg(not(unix))]
+    async fn send(&self, _request: Request<Body>) -> anyhow::Result<hyper::Response<Body>> {
+        Err(anyhow::anyhow!(
+            "the docker engine api client only supports unix sockets"
+        ))
+    }
+
+    async fn error_from_response(response: hyper::Response<Body>) -> DockerEngineApiError {
+        let status = response.status().as_u16();
+        let mut body = response.into_body();
+        let mut bytes = Vec::new();
+        while let Some(Ok(chunk)) = body.data().await {
+            bytes.extend_from_slice(&chunk);
+        }
+        let message = serde_json::from_slice::<Value>(&bytes)
+            .ok()
+            .and_then(|value| value["message"].as_str().map(|s| s.to_string()))
+            .unwrap_or_else(|| String::from_utf8_lossy(&bytes).trim().to_string());
+        DockerEngineApiError { status, message }
+    }
+}
+
+/// Splits the multiplexed attach stream into stdout and stderr lines
+///
+/// Every frame has an 8 bytes header, the stream type in the first byte and the big endian payload
+/// size in the last four.
+async fn demux_output(
+    mut output: impl AsyncRead + Unpin,
+    on_output: &mut (impl FnMut(LogStream, String) + Send),
+) -> anyhow::Result<()> {
+    let mut stdout = Vec::<u8>::new();
+    let mut stderr = Vec::<u8>::new();
+    let mut header = [0u8; 8];
+    loop {
+        match output.read_exact(&mut header).await {
+            Ok(_) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e.into()),
+        }
+        let size = u32::from_be_bytes([header[4], header[5], header[6], header[7]]) as usize;
+        let mut payload = vec![0u8; size];
+        output.read_exact(&mut payload).await?;
+        let (stream, buffer) = match header[0] {
+            2 => (LogStream::Stderr, &mut stderr),
+            _ => (LogStream::Stdout, &mut stdout),
+        };
+        buffer.extend_from_slice(&payload);
+        while let Some(position) = buffer.iter().position(|byte| *byte == b'\n') {
+            let line = buffer.drain(..=position).collect::<Vec<u8>>();
+            on_output(stream, trim_line(&line));
+        }
+    }
+    for (stream, buffer) in [(LogStream::Stdout, stdout), (LogStream::Stderr, stderr)] {
+        if !buffer.is_empty() {
+            on_output(stream, trim_line(&buffer));
+        }
+    }
+    Ok(())
+}
+
+fn trim_line(line: &[u8]) -> String {
+    String::from_utf8_lossy(line)
+        .trim_end_matches(['\n', '\r'])
+        .to_string()
+}
+
+/// Runs a container for a runner and returns its stdout lines, mirroring the docker cli path
+///
+/// Output is logged and written to the execution log, a non zero exit code fails with stderr.
+pub(crate) async fn run_in_engine(
+    client: &DockerEngineClient,
+    spec: &ContainerSpec,
+    execution_storage: &ExecutionStorage,
+    source: &str,
+    max_execution_timeout: Option<Duration>,
+) -> anyhow::Result<Vec<String>> {
+    log::info!(
+        "running container through the docker engine api at {}",
+        client.socket_path().display()
+    );
+    let mut stdout_lines = Vec::<String>::new();
+    let mut stderr_lines = Vec::<String>::new();
+    let outcome = client
+        .run_container(spec, max_execution_timeout, |stream, line| {
+            log::info!(
+                "from {}: {}",
+                source,
+                execution_storage.redactor.redact(&line)
+            );
+            let _ = execution_storage.append_log_entry(stream, None, line.as_str());
+            match stream {
+                LogStream::Stderr => stderr_lines.push(line),
+                _ => stdout_lines.push(line),
+            }
+        })
+        .await
+        .map_err(|e| match e.downcast::<InfrastructureError>() {
+            Ok(e) => InfrastructureError(format!("container engine error: {}", e)).into(),
+            Err(e) => anyhow::anyhow!("container engine error: {}", e),
+        })?;
+    log::info!(
+        "container finished with exit code {}, peak memory {} bytes",
+        outcome.exit_code,
+        outcome.stats.memory_usage_bytes
+    );
+
+    if outcome.timed_out {
+        let timeout = max_execution_timeout.unwrap_or_default();
+        log::error!("command execution timed out after {}[s]", timeout.as_secs());
+        return Err(anyhow::anyhow!(
+            "process timed out after {}[s]",
+            timeout.as_secs()
+        ));
+    }
+    if outcome.oom_killed || outcome.exit_code != 0 {
+        let mut stderr = execution_storage.redactor.redact(&stderr_lines.join("\n"));
+        if outcome.oom_killed {
+            stderr = format!(
+                "container was killed for running out of memory (exit code {})\n{}",
+                outcome.exit_code, stderr
+            );
+        }
+        log::error!("command execution failed: {}", stderr);
+        return Err(anyhow::Error::new(std::io::Error::other(stderr)));
+    }
+    Ok(stdout_lines)
+}
+
+#[cfg(test)]
+#[path = "docker_engine_api.test.rs"]
+mod tests;
@@ -0,0 +1,480 @@
+#![cfg(unix)]
+
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use hyper::{
+    header::{CONNECTION, UPGRADE},
+    service::service_fn,
+    Body, Method, Request, Response, StatusCode,
+};
+use rstest::rstest;
+use serde_json::{json, Value};
+use tokio::{io::AsyncWriteExt, sync::Notify};
+
+use crate::tools::{
+    code_files::CodeFiles,
+    container_runtime::BindMount,
+    docker_engine_api::{run_in_engine, ContainerSpec, DockerEngineApiError, DockerEngineClient},
+    execution_context::ExecutionContext,
+    execution_error::InfrastructureError,
+    execution_log::{LogEntry, LogStream},
+    execution_storage::ExecutionStorage,
+};
+
+/// Behaviour and recorded requests of the fake engine
+#[derive(Default)]
+struct FakeEngine {
+    requests: Vec<(Method, String, Value)>,
+    /// Attach frames as (stream type, payload)
+    output: Vec<(u8, &'static str)>,
+    exit_code: i64,
+    oom_killed: bool,
+    /// Wait only returns once the container is killed
+    hang: bool,
+    killed: bool,
+    create_error: Option<(u16, &'static str)>,
+    /// Local images by name, as returned by image inspect
+    images: HashMap<String, Value>,
+    loaded_bytes: usize,
+}
+
+/// Docker Engine API look-alike served on a unix socket
+struct FakeEngineServer {
+    engine: Arc<Mutex<FakeEngine>>,
+    socket_path: PathBuf,
+    _folder: tempfile::TempDir,
+}
+
+impl FakeEngineServer {
+    fn start(engine: FakeEngine) -> Self {
+        let folder = tempfile::tempdir().unwrap();
+        let socket_path = folder.path().join("docker.sock");
+        let listener = tokio::net::UnixListener::bind(&socket_path).unwrap();
+        let engine = Arc::new(Mutex::new(engine));
+        let kill_notify = Arc::new(Notify::new());
+        let server_engine = engine.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let engine = server_engine.clone();
+                let kill_notify = kill_notify.clone();
+                tokio::spawn(async move {
+                    let service = service_fn(move |request| {
+                        handle(engine.clone(), kill_notify.clone(), request)
+                    });
+                    let _ = hyper::server::conn::Http::new()
+                        .http1_only(true)
+                        .serve_connection(stream, service)
+                        .with_upgrades()
+                        .await;
+                });
+            }
+        });
+        FakeEngineServer {
+            engine,
+            socket_path,
+            _folder: folder,
+        }
+    }
+
+    fn client(&self) -> DockerEngineClient {
+        DockerEngineClient::new(self.socket_path.clone())
+    }
+
+    fn requested(&self, method: Method, path_prefix: &str) -> Option<Value> {
+        self.engine
+            .lock()
+            .unwrap()
+            .requests
+            .iter()
+            .find(|(m, path, _)| *m == method && path.starts_with(path_prefix))
+            .map(|(_, _, body)| body.clone())
+    }
+}
+
+fn json_response(status: u16, body: Value) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header("content-type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+async fn handle(
+    engine: Arc<Mutex<FakeEngine>>,
+    kill_notify: Arc<Notify>,
+    request: Request<Body>,
+) -> Result<Response<Body>, Infallible> {
+    let method = request.method().clone();
+    let path = request.uri().path_and_query().unwrap().to_string();
+
+    if path.contains("/attach") {
+        let output = {
+            let mut engine = engine.lock().unwrap();
+            engine.requests.push((method, path, Value::Null));
+            engine.output.clone()
+        };
+        tokio::spawn(async move {
+            let mut upgraded = hyper::upgrade::on(request).await.unwrap();
+            for (stream_type, payload) in output {
+                let mut frame = vec![stream_type, 0, 0, 0];
+                frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+                frame.extend_from_slice(payload.as_bytes());
+                upgraded.write_all(&frame).await.unwrap();
+            }
+            let _ = upgraded.shutdown().await;
+        });
+        return Ok(Response::builder()
+            .status(StatusCode::SWITCHING_PROTOCOLS)
+            .header(CONNECTION, "Upgrade")
+            .header(UPGRADE, "tcp")
+            .body(Body::empty())
+            .unwrap());
+    }
+
+    let bytes = hyper::body::to_bytes(request.into_body()).await.unwrap();
+    let body = serde_json::from_slice(&bytes).unwrap_or(Value::Null);
+    engine
+        .lock()
+        .unwrap()
+        .requests
+        .push((method.clone(), path.clone(), body));
+
+    if let Some(image) = path.strip_prefix("/images/create?fromImage=") {
+        let messages = if image.starts_with("fail/") {
+            vec![json!({ "error": format!("pull access denied for {}", image) })]
+        } else {
+            engine.lock().unwrap().images.insert(
+                image.to_string(),
+                json!({ "Id": "sha256:1111", "RepoDigests": [format!("{}@sha256:abc", image)] }),
+            );
+            vec![
+                json!({ "status": format!("Pulling from {}", image) }),
+                json!({
+                    "id": "a1b2c3",
+                    "status": "Downloading",
+                    "progressDetail": { "current": 5, "total": 10 },
+                }),
+            ]
+        };
+        let body = messages
+            .iter()
+            .map(|message| format!("{}\r\n", message))
+            .collect::<String>();
+        return Ok(json_response(200, Value::Null).map(|_| Body::from(body)));
+    }
+    if path.starts_with("/images/load") {
+        let mut engine = engine.lock().unwrap();
+        engine.loaded_bytes = bytes.len();
+        engine.images.insert(
+            String::from("offline/runner:1.0"),
+            json!({ "Id": "sha256:2222", "RepoDigests": [] }),
+        );
+        return Ok(json_response(
+            200,
+            json!({ "stream": "Loaded image: offline/runner:1.0\n" }),
+        ));
+    }
+    if let Some(image) = path
+        .strip_prefix("/images/")
+        .and_then(|path| path.strip_suffix("/json"))
+    {
+        return Ok(match engine.lock().unwrap().images.get(image) {
+            Some(info) => json_response(200, info.clone()),
+            None => json_response(
+                404,
+                json!({ "message": format!("No such image: {}", image) }),
+            ),
+        });
+    }
+
+    let response = if path == "/_ping" {
+        Response::new(Body::from("OK"))
+    } else if path.starts_with("/containers/create") {
+        match engine.lock().unwrap().create_error {
+            Some((status, message)) => json_response(status, json!({ "message": message })),
+            None => json_response(201, json!({ "Id": "fake-container", "Warnings": [] })),
+        }
+    } else if path.ends_with("/wait") {
+        let hang = engine.lock().unwrap().hang;
+        if hang {
+            kill_notify.notified().await;
+        }
+        let exit_code = engine.lock().unwrap().exit_code;
+        json_response(200, json!({ "StatusCode": exit_code, "Error": null }))
+    } else if path.ends_with("/kill") {
+        engine.lock().unwrap().killed = true;
+        kill_notify.notify_one();
+        json_response(204, Value::Null)
+    } else if path.contains("/stats") {
+        json_response(
+            200,
+            json!({
+                "memory_stats": { "usage": 1024, "limit": 4096 },
+                "cpu_stats": { "cpu_usage": { "total_usage": 5 } },
+            }),
+        )
+    } else if path.ends_with("/json") {
+        let engine = engine.lock().unwrap();
+        let exit_code = if engine.killed { 137 } else { engine.exit_code };
+        json_response(
+            200,
+            json!({
+                "State": {
+                    "Status": "exited",
+                    "ExitCode": exit_code,
+                    "OOMKilled": engine.oom_killed,
+                }
+            }),
+        )
+    } else {
+        json_response(204, Value::Null)
+    };
+    Ok(response)
+}
+
+fn spec() -> ContainerSpec {
+    ContainerSpec {
+        image: String::from("dcspark/shinkai-code-runner:0.9.4"),
+        cmd: vec![
+            String::from("deno"),
+            String::from("run"),
+            String::from("main.ts"),
+        ],
+        env: vec![String::from("SHINKAI_HOME=/app/home")],
+        working_dir: Some(String::from("/app")),
+        mounts: vec![
+            BindMount {
+                source: String::from("/host/home"),
+                target: String::from("/app/home"),
+                readonly: false,
+            },
+            BindMount {
+                source: String::from("/host/a.txt"),
+                target: String::from("/app/assets/a.txt"),
+                readonly: true,
+            },
+        ],
+    }
+}
+
+#[tokio::test]
+async fn ping_and_api_errors() {
+    let server = FakeEngineServer::start(FakeEngine {
+        create_error: Some((404, "No such image: dcspark/shinkai-code-runner:0.9.4")),
+        ..Default::default()
+    });
+    let client = server.client();
+    client.ping().await.unwrap();
+
+    let error = client.create_container(&spec()).await.unwrap_err();
+    let error = error.downcast_ref::<DockerEngineApiError>().unwrap();
+    assert_eq!(error.status, 404);
+    assert_eq!(
+        error.message,
+        "No such image: dcspark/shinkai-code-runner:0.9.4"
+    );
+
+    let missing = DockerEngineClient::new(PathBuf::from("/nonexistent/docker.sock"));
+    assert!(missing.ping().await.is_err());
+}
+
+#[tokio::test]
+async fn run_container_demuxes_output_and_removes_container() {
+    let server = FakeEngineServer::start(FakeEngine {
+        output: vec![(1, "hello\nwor"), (2, "oops\n"), (1, "ld\n"), (1, "tail")],
+        ..Default::default()
+    });
+    let mut lines = Vec::new();
+    let outcome = server
+        .client()
+        .run_container(&spec(), None, |stream, line| lines.push((stream, line)))
+        .await
+        .unwrap();
+
+    assert_eq!(outcome.exit_code, 0);
+    assert!(!outcome.oom_killed);
+    assert!(!outcome.timed_out);
+    assert_eq!(
+        lines,
+        vec![
+            (LogStream::Stdout, String::from("hello")),
+            (LogStream::Stderr, String::from("oops")),
+            (LogStream::Stdout, String::from("world")),
+            (LogStream::Stdout, String::from("tail")),
+        ]
+    );
+
+    let create = server
+        .requested(Method::POST, "/containers/create")
+        .unwrap();
+    assert_eq!(create["Image"], "dcspark/shinkai-code-runner:0.9.4");
+    assert_eq!(create["WorkingDir"], "/app");
+    assert_eq!(create["Env"], json!(["SHINKAI_HOME=/app/home"]));
+    assert_eq!(
+        create["HostConfig"]["Mounts"][1],
+        json!({
+            "Type": "bind",
+            "Source": "/host/a.txt",
+            "Target": "/app/assets/a.txt",
+            "ReadOnly": true,
+        })
+    );
+    assert!(server
+        .requested(Method::POST, "/containers/fake-container/start")
+        .is_some());
+    assert!(server
+        .requested(Method::DELETE, "/containers/fake-container?force=true")
+        .is_some());
+}
+
+#[tokio::test]
+async fn run_container_reports_exit_code_and_oom() {
+    let server = FakeEngineServer::start(FakeEngine {
+        exit_code: 137,
+        oom_killed: true,
+        ..Default::default()
+    });
+    let outcome = server
+        .client()
+        .run_container(&spec(), None, |_, _| {})
+        .await
+        .unwrap();
+    assert_eq!(outcome.exit_code, 137);
+    assert!(outcome.oom_killed);
+    assert_eq!(outcome.stats.memory_usage_bytes, 1024);
+    assert_eq!(outcome.stats.memory_limit_bytes, 4096);
+}
+
+#[tokio::test]
+async fn run_container_kills_on_timeout() {
+    let server = FakeEngineServer::start(FakeEngine {
+        hang: true,
+        ..Default::default()
+    });
+    let outcome = server
+        .client()
+        .run_container(&spec(), Some(Duration::from_millis(200)), |_, _| {})
+        .await
+        .unwrap();
+    assert!(outcome.timed_out);
+    assert_eq!(outcome.exit_code, 137);
+    assert!(server
+        .requested(Method::POST, "/containers/fake-container/kill")
+        .is_some());
+    assert!(server
+        .requested(Method::DELETE, "/containers/fake-container")
+        .is_some());
+}
+
+#[tokio::test]
+async fn run_in_engine_fails_with_stderr_and_writes_execution_log() {
+    let server = FakeEngineServer::start(FakeEngine {
+        output: vec![(1, "partial result\n"), (2, "out of memory\n")],
+        exit_code: 137,
+        oom_killed: true,
+        ..Default::default()
+    });
+    let storage = tempfile::tempdir().unwrap();
+    let execution_storage = ExecutionStorage::new(
+        CodeFiles {
+            files: HashMap::from([("main.ts".to_string(), "".to_string())]),
+            entrypoint: "main.ts".to_string(),
+        },
+        ExecutionContext {
+            storage: storage.path().to_path_buf(),
+            ..Default::default()
+        },
+    );
+    execution_storage.init(None).unwrap();
+
+    let error = run_in_engine(&server.client(), &spec(), &execution_storage, "deno", None)
+        .await
+        .unwrap_err()
+        .to_string();
+    assert!(error.contains("out of memory (exit code 137)"));
+    assert!(error.ends_with("out of memory"));
+
+    let entries = LogEntry::read_all(&execution_storage.log_file_path).unwrap();
+    let messages = entries
+        .iter()
+        .map(|entry| (entry.stream, entry.message.as_str()))
+        .collect::<Vec<_>>();
+    assert!(messages.contains(&(LogStream::Stdout, "partial result")));
+    assert!(messages.contains(&(LogStream::Stderr, "out of memory")));
+}
+
+#[rstest]
+#[case::create_failure(Some((404, "No such image: dcspark/shinkai-code-runner:0.9.4")), 0, true)]
+#[case::tool_missing_command(None, 127, false)]
+#[case::tool_exit_125(None, 125, false)]
+#[tokio::test]
+async fn run_in_engine_reports_only_create_and_start_failures_as_infrastructure(
+    #[case] create_error: Option<(u16, &'static str)>,
+    #[case] exit_code: i64,
+    #[case] infrastructure: bool,
+) {
+    let server = FakeEngineServer::start(FakeEngine {
+        create_error,
+        exit_code,
+        ..Default::default()
+    });
+    let storage = tempfile::tempdir().unwrap();
+    let execution_storage = ExecutionStorage::new(
+        CodeFiles {
+            files: HashMap::from([("main.ts".to_string(), "".to_string())]),
+            entrypoint: "main.ts".to_string(),
+        },
+        ExecutionContext {
+            storage: storage.path().to_path_buf(),
+            ..Default::default()
+        },
+    );
+    execution_storage.init(None).unwrap();
+
+    let error = run_in_engine(&server.client(), &spec(), &execution_storage, "deno", None)
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.downcast_ref::<InfrastructureError>().is_some(),
+        infrastructure
+    );
+}
+
+#[tokio::test]
+async fn image_inspect_pull_and_load() {
+    let server = FakeEngineServer::start(FakeEngine::default());
+    let client = server.client();
+    let image = "dcspark/shinkai-code-runner:0.9.4";
+    assert_eq!(client.inspect_image(image).await.unwrap(), None);
+
+    let mut progress = Vec::new();
+    client
+        .pull_image(image, &mut |event| progress.push(event))
+        .await
+        .unwrap();
+    assert_eq!(progress.len(), 2);
+    assert_eq!(progress[1].id.as_deref(), Some("a1b2c3"));
+    assert_eq!(progress[1].current, Some(5));
+    assert_eq!(progress[1].total, Some(10));
+    let info = client.inspect_image(image).await.unwrap().unwrap();
+    assert!(info.matches_digest("sha256:abc"));
+
+    let error = client
+        .pull_image("fail/runner:1.0", &mut |_| {})
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains("pull access denied"));
+
+    let folder = tempfile::tempdir().unwrap();
+    let tarball = folder.path().join("runner.tar");
+    std::fs::write(&tarball, vec![7u8; 200 * 1024]).unwrap();
+    let loaded = client.load_image(&tarball).await.unwrap();
+    assert_eq!(loaded, vec!["offline/runner:1.0"]);
+    assert_eq!(server.engine.lock().unwrap().loaded_bytes, 200 * 1024);
+}
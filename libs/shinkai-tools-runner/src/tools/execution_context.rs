@@ -1,5 +1,25 @@
 use std::path::PathBuf;
 
+use super::log_retention::LogRetentionPolicy;
+
+/// How executions of a context get their home folder
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HomeMode {
+    /// Every execution shares the context home, the right default for agents
+    #[default]
+    Shared,
+    /// Each execution gets its own home, discarded once it finishes
+    Ephemeral(EphemeralHome),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EphemeralHome {
+    /// Starts from a copy of the context home instead of an empty folder
+    pub copy_context_home: bool,
+    /// Copies the execution home files back to the context home when the execution succeeds
+    pub commit_on_success: bool,
+}
+
 #[derive(Clone)]
 pub struct ExecutionContext {
     pub context_id: String,
@@ -8,6 +28,9 @@ pub struct ExecutionContext {
     pub storage: PathBuf,
     pub assets_files: Vec<PathBuf>,
     pub mount_files: Vec<PathBuf>,
+    /// Size cap of execution logs and retention of the context logs folder
+    pub log_retention: LogRetentionPolicy,
+    pub home_mode: HomeMode,
 }
 
 impl Default for ExecutionContext {
@@ -19,6 +42,8 @@ impl Default for ExecutionContext {
             storage: PathBuf::from("./shinkai-tools-runner-execution-storage"),
             assets_files: Vec::new(),
             mount_files: Vec::new(),
+            log_retention: LogRetentionPolicy::default(),
+            home_mode: HomeMode::default(),
         }
     }
 }
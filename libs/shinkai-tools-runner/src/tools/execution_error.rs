@@ -1,12 +1,49 @@
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum ExecutionErrorKind {
+    /// The tool failed while running or its result could not be parsed
+    #[default]
+    Execution,
+    /// The code was refused because its signature didn't satisfy the trust store policy
+    SignatureRejected(SignatureRejection),
+    /// A call to another tool was refused before reaching the resolver
+    ToolCallRejected(ToolCallRejection),
+    /// The runner environment failed before the tool started, e.g. a missing image or a rejected mount
+    Infrastructure,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ToolCallRejection {
+    DepthLimitExceeded,
+    Cycle,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SignatureRejection {
+    MissingSignature,
+    UntrustedKey,
+    InvalidSignature,
+    ContentHashMismatch,
+}
+
 #[derive(Clone, Debug)]
 pub struct ExecutionError {
     message: String,
     stack: Option<String>,
+    kind: ExecutionErrorKind,
 }
 
 impl ExecutionError {
     pub fn new(message: String, stack: Option<String>) -> Self {
-        ExecutionError { message, stack }
+        ExecutionError {
+            message,
+            stack,
+            kind: ExecutionErrorKind::default(),
+        }
+    }
+
+    pub fn with_kind(mut self, kind: ExecutionErrorKind) -> Self {
+        self.kind = kind;
+        self
     }
 
     pub fn message(&self) -> &str {
@@ -16,6 +53,20 @@ impl ExecutionError {
     pub fn stack(&self) -> Option<&str> {
         self.stack.as_deref()
     }
+
+    pub fn kind(&self) -> &ExecutionErrorKind {
+        &self.kind
+    }
+
+    /// Error for a failed runner execution, keeping the infrastructure kind of the source
+    pub(crate) fn from_runner_error(message: String, source: &anyhow::Error) -> Self {
+        let error = ExecutionError::new(message, None);
+        if source.downcast_ref::<InfrastructureError>().is_some() {
+            error.with_kind(ExecutionErrorKind::Infrastructure)
+        } else {
+            error
+        }
+    }
 }
 
 impl std::fmt::Display for ExecutionError {
@@ -23,3 +74,24 @@ impl std::fmt::Display for ExecutionError {
         write!(f, "ExecutionError: {}", self.message)
     }
 }
+
+/// Failure of the runner environment rather than of the tool, reported as `ExecutionErrorKind::Infrastructure`
+#[derive(Debug)]
+pub struct InfrastructureError(pub String);
+
+impl InfrastructureError {
+    /// Exit code of `docker run` itself, 126 and 127 may as well come from the tool so they aren't
+    pub const EXIT_CODE: i32 = 125;
+
+    pub fn is_infrastructure_exit_code(code: Option<i32>) -> bool {
+        code == Some(Self::EXIT_CODE)
+    }
+}
+
+impl std::fmt::Display for InfrastructureError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for InfrastructureError {}
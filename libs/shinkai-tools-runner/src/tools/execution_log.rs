@@ -0,0 +1,78 @@
+use std::{
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogStream {
+    Stdout,
+    Stderr,
+    /// Lines written by the runner itself
+    System,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl LogStream {
+    /// Level used for lines coming from this stream when the caller doesn't provide one
+    pub fn default_level(&self) -> LogLevel {
+        match self {
+            LogStream::Stdout | LogStream::System => LogLevel::Info,
+            LogStream::Stderr => LogLevel::Warn,
+        }
+    }
+}
+
+/// One line of an execution log file, stored as JSON Lines
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LogEntry {
+    /// Position of the entry in its log file, starting at 0
+    pub sequence: u64,
+    /// RFC 3339 UTC timestamp with milliseconds
+    pub timestamp: String,
+    pub context_id: String,
+    pub execution_id: String,
+    pub code_id: String,
+    pub stream: LogStream,
+    pub level: LogLevel,
+    pub message: String,
+}
+
+impl LogEntry {
+    pub fn now_timestamp() -> String {
+        chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true)
+    }
+
+    /// Reads every entry of a JSON Lines log file, skipping lines that aren't log entries
+    pub fn read_all(path: &Path) -> anyhow::Result<Vec<LogEntry>> {
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+        let file = std::fs::File::open(path).map_err(|e| {
+            log::error!("failed to open log file {}: {}", path.display(), e);
+            e
+        })?;
+        let mut entries = Vec::new();
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str::<LogEntry>(&line) {
+                Ok(entry) => entries.push(entry),
+                Err(e) => log::warn!("skipping malformed log line in {}: {}", path.display(), e),
+            }
+        }
+        Ok(entries)
+    }
+}
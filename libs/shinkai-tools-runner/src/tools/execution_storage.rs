@@ -1,10 +1,25 @@
 use std::{
-    io::Write,
-    path::{self, PathBuf},
+    fs::File,
+    io::{BufRead, BufReader, Write},
+    path::{self, Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::SystemTime,
 };
 
-use super::{code_files::CodeFiles, path_buf_ext::PathBufExt};
-use super::{execution_context::ExecutionContext, file_name_utils::sanitize_for_file_name};
+use fs4::fs_std::FileExt;
+
+use super::{
+    code_files::CodeFiles,
+    execution_log::{LogEntry, LogLevel, LogStream},
+    log_retention::LogCleanupReport,
+    path_buf_ext::PathBufExt,
+    redaction::Redactor,
+    storage_manager::StorageManager,
+};
+use super::{
+    execution_context::{ExecutionContext, HomeMode},
+    file_name_utils::sanitize_for_file_name,
+};
 
 #[derive(Default, Clone)]
 pub struct ExecutionStorage {
@@ -19,12 +34,29 @@ pub struct ExecutionStorage {
     pub cache_folder_path: PathBuf,
     pub logs_folder_path: PathBuf,
     pub log_file_path: PathBuf,
+    /// Home of this execution, the context home unless the context uses an ephemeral home
     pub home_folder_path: PathBuf,
+    /// Home shared by every execution of the context
+    pub context_home_folder_path: PathBuf,
     pub assets_folder_path: PathBuf,
     pub mount_folder_path: PathBuf,
+    /// Applied to every line written by `append_log`
+    pub redactor: Redactor,
+    /// Position of the log file writer, lazily read from the file and shared between clones
+    log_writer: Arc<Mutex<Option<LogWriterState>>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct LogWriterState {
+    next_sequence: u64,
+    written_bytes: u64,
+    truncated: bool,
 }
 
 impl ExecutionStorage {
+    const EPHEMERAL_HOMES_FOLDER_NAME: &'static str = "ephemeral-homes";
+    const INIT_LOCK_FILE_NAME: &'static str = ".init.lock";
+
     pub fn new(code: CodeFiles, context: ExecutionContext) -> Self {
         let code_id = context.code_id.clone();
         let root_folder_path = path::absolute(
@@ -37,16 +69,29 @@ impl ExecutionStorage {
         let global_cache_folder_path =
             path::absolute(context.storage.join("global-cache")).unwrap();
         let root_code_folder_path = path::absolute(root_folder_path.join("code")).unwrap();
-        let code_folder_path = path::absolute(root_code_folder_path.join(code_id.clone())).unwrap();
+        // Suffixed with the content hash so different code sharing a code id never share a folder
+        let code_folder_path = path::absolute(root_code_folder_path.join(format!(
+            "{}-{}",
+            sanitize_for_file_name(code_id.clone()),
+            &code.content_hash()[..16]
+        )))
+        .unwrap();
         let logs_folder_path = path::absolute(root_folder_path.join("logs")).unwrap();
         let log_file_path = path::absolute(logs_folder_path.join(format!(
-            "log_{}_{}.log",
+            "log_{}_{}.jsonl",
             sanitize_for_file_name(context.context_id.clone()),
             sanitize_for_file_name(context.execution_id.clone())
         )))
         .unwrap();
         let cache_folder_path = path::absolute(root_folder_path.join("cache")).unwrap();
         let code_entrypoint_file_path = code_folder_path.join(&code.entrypoint);
+        let context_home_folder_path = root_folder_path.join("home");
+        let home_folder_path = match context.home_mode {
+            HomeMode::Shared => context_home_folder_path.clone(),
+            HomeMode::Ephemeral(_) => root_folder_path
+                .join(Self::EPHEMERAL_HOMES_FOLDER_NAME)
+                .join(sanitize_for_file_name(context.execution_id.clone())),
+        };
         Self {
             code_files: code,
             context,
@@ -58,21 +103,41 @@ impl ExecutionStorage {
             cache_folder_path,
             logs_folder_path: logs_folder_path.clone(),
             log_file_path,
-            home_folder_path: root_folder_path.join("home"),
+            home_folder_path,
+            context_home_folder_path,
             assets_folder_path: root_folder_path.join("assets"),
             mount_folder_path: root_folder_path.join("mount"),
             global_cache_folder_path,
+            redactor: Redactor::default(),
+            log_writer: Arc::new(Mutex::new(None)),
         }
     }
 
+    pub fn with_redactor(mut self, redactor: Redactor) -> Self {
+        self.redactor = redactor;
+        self
+    }
+
     pub fn init(&self, pristine_cache: Option<bool>) -> anyhow::Result<()> {
+        self.init_with_generated_files(pristine_cache, &[])
+    }
+
+    /// Creates the context folders and the code folder, adding `generated_files` to the code
+    ///
+    /// Initialization is serialized per context with a file lock. The code folder is written to a
+    /// staging folder and renamed into place, so concurrent runs never see partial code and,
+    /// being content addressed, its files are never rewritten once published.
+    pub(crate) fn init_with_generated_files(
+        &self,
+        pristine_cache: Option<bool>,
+        generated_files: &[(&str, &str)],
+    ) -> anyhow::Result<()> {
         for dir in [
             &self.root_folder_path,
             &self.root_code_folder_path,
-            &self.code_folder_path,
             &self.cache_folder_path,
             &self.logs_folder_path,
-            &self.home_folder_path,
+            &self.context_home_folder_path,
         ] {
             log::info!("creating directory: {}", dir.display());
             std::fs::create_dir_all(dir).map_err(|e| {
@@ -80,15 +145,80 @@ impl ExecutionStorage {
                 e
             })?;
         }
+        let _init_lock = self.lock_init()?;
+
+        if pristine_cache.unwrap_or(false) {
+            self.clear_cache()?;
+        }
 
         log::info!(
             "creating project files, entrypoint: {}",
             self.code_files.entrypoint
         );
+        if self.code_folder_path.exists() {
+            log::info!("reusing code folder: {}", self.code_folder_path.display());
+            for (path, content) in generated_files {
+                let file_path = self.code_folder_path.join(path);
+                if !file_path.exists() {
+                    Self::write_file_atomic(&file_path, content.as_bytes())?;
+                }
+            }
+            // Keeps the least recently used eviction of the storage manager accurate
+            if let Ok(file) = File::options()
+                .write(true)
+                .open(&self.code_entrypoint_file_path)
+            {
+                let _ = file.set_modified(SystemTime::now());
+            }
+        } else {
+            self.publish_code_folder(generated_files)?;
+        }
+
+        log::info!(
+            "creating log file if not exists: {}",
+            self.log_file_path.display()
+        );
+        if let Err(e) = File::options()
+            .create_new(true)
+            .write(true)
+            .open(&self.log_file_path)
+        {
+            if e.kind() != std::io::ErrorKind::AlreadyExists {
+                log::error!("failed to create log file: {}", e);
+                return Err(e.into());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Exclusive lock serializing initialization of the context, released on drop
+    fn lock_init(&self) -> anyhow::Result<File> {
+        let lock_file = File::options()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(self.root_folder_path.join(Self::INIT_LOCK_FILE_NAME))?;
+        FileExt::lock_exclusive(&lock_file)?;
+        Ok(lock_file)
+    }
 
-        for (path, content) in self.code_files.files.iter() {
-            let file_path = self.code_folder_path.join(path);
-            log::info!("writing file: {}", file_path.display());
+    fn publish_code_folder(&self, generated_files: &[(&str, &str)]) -> anyhow::Result<()> {
+        let staging = tempfile::Builder::new()
+            .prefix(".staging-")
+            .tempdir_in(&self.root_code_folder_path)?;
+        let files = self
+            .code_files
+            .files
+            .iter()
+            .map(|(path, content)| (path.as_str(), content.as_str()))
+            .chain(generated_files.iter().copied());
+        for (path, content) in files {
+            let file_path = staging.path().join(path);
+            log::info!(
+                "writing file: {}",
+                self.code_folder_path.join(path).display()
+            );
             if let Some(parent) = file_path.parent() {
                 std::fs::create_dir_all(parent).map_err(|e| {
                     log::error!(
@@ -104,36 +234,226 @@ impl ExecutionStorage {
                 e
             })?;
         }
+        let staging_path = staging.into_path();
+        if let Err(e) = std::fs::rename(&staging_path, &self.code_folder_path) {
+            let _ = std::fs::remove_dir_all(&staging_path);
+            // Another process published the same content first
+            if !self.code_folder_path.exists() {
+                log::error!(
+                    "failed to publish code folder {}: {}",
+                    self.code_folder_path.display(),
+                    e
+                );
+                return Err(e.into());
+            }
+        }
+        Ok(())
+    }
 
+    /// Empties the context cache, refusing while a live execution holds a lease on the context
+    fn clear_cache(&self) -> anyhow::Result<()> {
+        let Some(_lock) = StorageManager::try_lock_context(&self.root_folder_path)? else {
+            return Err(anyhow::anyhow!(
+                "can't clear the cache of context {} while it's in use",
+                self.context.context_id
+            ));
+        };
+        // Renamed away first so the cache folder is never seen half deleted
+        let trash = self
+            .root_folder_path
+            .join(format!(".cache-trash-{}", nanoid::nanoid!()));
+        std::fs::rename(&self.cache_folder_path, &trash)?;
+        std::fs::create_dir_all(&self.cache_folder_path)?;
+        std::fs::remove_dir_all(&trash)?;
         log::info!(
-            "creating log file if not exists: {}",
-            self.log_file_path.display()
+            "cleared cache directory: {}",
+            self.cache_folder_path.display()
         );
-        if !self.log_file_path.exists() {
-            std::fs::write(&self.log_file_path, "").map_err(|e| {
-                log::error!("failed to create log file: {}", e);
-                e
-            })?;
-        }
+        Ok(())
+    }
 
-        if pristine_cache.unwrap_or(false) {
-            std::fs::remove_dir_all(&self.cache_folder_path)?;
-            std::fs::create_dir(&self.cache_folder_path)?;
+    /// Writes to a temporary file next to `path` and renames it into place
+    pub(crate) fn write_file_atomic(path: &Path, content: &[u8]) -> anyhow::Result<()> {
+        let parent = path
+            .parent()
+            .ok_or_else(|| anyhow::anyhow!("invalid file path {}", path.display()))?;
+        std::fs::create_dir_all(parent)?;
+        let mut temp_file = tempfile::NamedTempFile::new_in(parent)?;
+        temp_file.write_all(content)?;
+        temp_file.persist(path).map_err(|e| {
+            log::error!("failed to write file {}: {}", path.display(), e);
+            e.error
+        })?;
+        Ok(())
+    }
+
+    /// Creates the ephemeral home of this execution, returning a guard that discards it on drop
+    ///
+    /// With a shared home nothing is created and the guard does nothing.
+    pub fn prepare_home(&self) -> anyhow::Result<EphemeralHomeGuard<'_>> {
+        if let HomeMode::Ephemeral(ephemeral_home) = self.context.home_mode {
             log::info!(
-                "cleared cache directory: {}",
-                self.cache_folder_path.display()
+                "creating ephemeral home: {}",
+                self.home_folder_path.display()
             );
+            if self.home_folder_path.exists() {
+                std::fs::remove_dir_all(&self.home_folder_path)?;
+            }
+            std::fs::create_dir_all(&self.home_folder_path)?;
+            if ephemeral_home.copy_context_home {
+                Self::copy_folder(&self.context_home_folder_path, &self.home_folder_path)?;
+            }
+        }
+        Ok(EphemeralHomeGuard { storage: self })
+    }
+
+    /// Copies the ephemeral home files over the context home, files only in the context home are kept
+    pub fn commit_home(&self) -> anyhow::Result<()> {
+        if !matches!(self.context.home_mode, HomeMode::Ephemeral(_)) {
+            return Ok(());
+        }
+        log::info!(
+            "committing ephemeral home {} into {}",
+            self.home_folder_path.display(),
+            self.context_home_folder_path.display()
+        );
+        Self::copy_folder(&self.home_folder_path, &self.context_home_folder_path)
+    }
+
+    /// Deletes the ephemeral home of this execution
+    pub fn discard_home(&self) -> anyhow::Result<()> {
+        if !matches!(self.context.home_mode, HomeMode::Ephemeral(_))
+            || !self.home_folder_path.exists()
+        {
+            return Ok(());
         }
+        log::info!(
+            "discarding ephemeral home {}",
+            self.home_folder_path.display()
+        );
+        std::fs::remove_dir_all(&self.home_folder_path)?;
+        Ok(())
+    }
 
+    fn copy_folder(source: &PathBuf, target: &PathBuf) -> anyhow::Result<()> {
+        std::fs::create_dir_all(target)?;
+        if !source.exists() {
+            return Ok(());
+        }
+        for entry in std::fs::read_dir(source)? {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+            let target_path = target.join(entry.file_name());
+            if file_type.is_dir() {
+                Self::copy_folder(&entry.path(), &target_path)?;
+            } else if file_type.is_file() {
+                std::fs::copy(entry.path(), &target_path)?;
+            } else {
+                log::warn!("skipping non regular file {}", entry.path().display());
+            }
+        }
         Ok(())
     }
 
     pub fn append_log(&self, log: &str) -> anyhow::Result<()> {
-        let timestamp = chrono::Local::now().format("%Y%m%d_%H%M%S");
-        let log_line = format!(
-            "{},{},{},{},{}\n",
-            timestamp, self.context.context_id, self.context.execution_id, self.code_id, log,
-        );
+        self.append_log_entry(LogStream::System, None, log)?;
+        Ok(())
+    }
+
+    /// Appends a JSON Lines entry to the execution log, redacting the message
+    ///
+    /// When `level` is None the stream default level is used. Once the log reaches the
+    /// `max_bytes_per_execution` of the context retention policy a single truncation notice is
+    /// written and later entries are returned but dropped.
+    pub fn append_log_entry(
+        &self,
+        stream: LogStream,
+        level: Option<LogLevel>,
+        message: &str,
+    ) -> anyhow::Result<LogEntry> {
+        let max_bytes = self.context.log_retention.max_bytes_per_execution;
+        // Held while writing so entries from concurrent streams keep their sequence order
+        let mut log_writer = self
+            .log_writer
+            .lock()
+            .map_err(|_| anyhow::anyhow!("log writer lock poisoned"))?;
+        let mut state = match *log_writer {
+            Some(state) => state,
+            None => {
+                let written_bytes = self.log_file_size();
+                LogWriterState {
+                    next_sequence: self.count_log_lines()?,
+                    written_bytes,
+                    truncated: max_bytes.is_some_and(|max_bytes| written_bytes >= max_bytes),
+                }
+            }
+        };
+        let entry = self.new_log_entry(state.next_sequence, stream, level, message);
+        let log_line = Self::serialize_log_entry(&entry)?;
+
+        let exceeds_limit = max_bytes
+            .is_some_and(|max_bytes| state.written_bytes + log_line.len() as u64 > max_bytes);
+        if state.truncated {
+            *log_writer = Some(state);
+            return Ok(entry);
+        }
+        if exceeds_limit {
+            let notice = self.new_log_entry(
+                state.next_sequence,
+                LogStream::System,
+                Some(LogLevel::Warn),
+                &format!(
+                    "log truncated: limit of {} bytes per execution reached, further output is dropped",
+                    max_bytes.unwrap_or_default()
+                ),
+            );
+            let notice_line = Self::serialize_log_entry(&notice)?;
+            self.write_log_line(&notice_line)?;
+            log::warn!(
+                "execution log {} truncated at {} bytes",
+                self.log_file_path.display(),
+                state.written_bytes
+            );
+            state.next_sequence += 1;
+            state.written_bytes += notice_line.len() as u64;
+            state.truncated = true;
+            *log_writer = Some(state);
+            return Ok(entry);
+        }
+
+        self.write_log_line(&log_line)?;
+        state.next_sequence += 1;
+        state.written_bytes += log_line.len() as u64;
+        *log_writer = Some(state);
+        Ok(entry)
+    }
+
+    fn new_log_entry(
+        &self,
+        sequence: u64,
+        stream: LogStream,
+        level: Option<LogLevel>,
+        message: &str,
+    ) -> LogEntry {
+        LogEntry {
+            sequence,
+            timestamp: LogEntry::now_timestamp(),
+            context_id: self.context.context_id.clone(),
+            execution_id: self.context.execution_id.clone(),
+            code_id: self.code_id.clone(),
+            stream,
+            level: level.unwrap_or(stream.default_level()),
+            message: self.redactor.redact(message),
+        }
+    }
+
+    fn serialize_log_entry(entry: &LogEntry) -> anyhow::Result<String> {
+        let mut log_line = serde_json::to_string(entry)?;
+        log_line.push('\n');
+        Ok(log_line)
+    }
+
+    fn write_log_line(&self, log_line: &str) -> anyhow::Result<()> {
         let mut file = std::fs::OpenOptions::new()
             .append(true)
             .create(true) // Create the file if it doesn't exist
@@ -146,6 +466,67 @@ impl ExecutionStorage {
         Ok(())
     }
 
+    /// Deletes the logs of this context outside its retention policy, keeping the current one
+    ///
+    /// Skipped while a live execution holds a lease on the context, its log may still be written.
+    pub fn cleanup_logs(&self) -> anyhow::Result<LogCleanupReport> {
+        self.context.log_retention.cleanup_context_logs(
+            &self.root_folder_path,
+            std::slice::from_ref(&self.log_file_path),
+        )
+    }
+
+    /// Typed entries of this execution log
+    pub fn read_log_entries(&self) -> anyhow::Result<Vec<LogEntry>> {
+        LogEntry::read_all(&self.log_file_path)
+    }
+
+    fn log_file_size(&self) -> u64 {
+        std::fs::metadata(&self.log_file_path)
+            .map(|metadata| metadata.len())
+            .unwrap_or_default()
+    }
+
+    fn count_log_lines(&self) -> anyhow::Result<u64> {
+        if !self.log_file_path.exists() {
+            return Ok(0);
+        }
+        let file = std::fs::File::open(&self.log_file_path)?;
+        Ok(BufReader::new(file)
+            .lines()
+            .map_while(Result::ok)
+            .filter(|line| !line.trim().is_empty())
+            .count() as u64)
+    }
+
+    /// Environment variable with the path of the inputs file inside the runner
+    pub const INPUTS_FILE_ENV: &'static str = "SHINKAI_INPUTS_FILE";
+
+    /// File holding the configurations and parameters of this execution, kept out of the code
+    /// folder so the code folder only depends on the tool code
+    pub fn inputs_file_path(&self) -> PathBuf {
+        self.root_folder_path.join("inputs").join(format!(
+            "{}.json",
+            sanitize_for_file_name(self.context.execution_id.clone())
+        ))
+    }
+
+    /// Writes `{"configurations": ..., "parameters": ...}` to `inputs_file_path`, the returned
+    /// guard deletes it on drop
+    pub fn write_inputs(
+        &self,
+        configurations: &serde_json::Value,
+        parameters: &serde_json::Value,
+    ) -> anyhow::Result<InputsFileGuard> {
+        let path = self.inputs_file_path();
+        let inputs = serde_json::json!({
+            "configurations": configurations,
+            "parameters": parameters,
+        });
+        Self::write_file_atomic(&path, inputs.to_string().as_bytes())?;
+        Ok(InputsFileGuard { path })
+    }
+
     pub fn relative_to_root(&self, path: PathBuf) -> String {
         log::info!(
             "getting relative path from {} to {}",
@@ -171,6 +552,54 @@ impl ExecutionStorage {
     }
 }
 
+/// Discards the ephemeral home of an execution when dropped, see `HomeMode::Ephemeral`
+pub struct EphemeralHomeGuard<'a> {
+    storage: &'a ExecutionStorage,
+}
+
+impl EphemeralHomeGuard<'_> {
+    /// Commits the home back to the context when the context asks for it on success
+    pub fn succeeded(&self) -> anyhow::Result<()> {
+        match self.storage.context.home_mode {
+            HomeMode::Ephemeral(ephemeral_home) if ephemeral_home.commit_on_success => {
+                self.storage.commit_home()
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Drop for EphemeralHomeGuard<'_> {
+    fn drop(&mut self) {
+        if let Err(e) = self.storage.discard_home() {
+            log::warn!("failed to discard ephemeral home: {}", e);
+        }
+    }
+}
+
+/// Deletes the inputs file of an execution when dropped, see `ExecutionStorage::write_inputs`
+pub struct InputsFileGuard {
+    path: PathBuf,
+}
+
+impl InputsFileGuard {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for InputsFileGuard {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_file(&self.path) {
+            log::warn!(
+                "failed to delete inputs file {}: {}",
+                self.path.display(),
+                e
+            );
+        }
+    }
+}
+
 #[cfg(test)]
 #[path = "execution_storage.test.rs"]
 mod tests;
@@ -1,7 +1,13 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::Arc};
 
 use crate::tools::{
-    code_files::CodeFiles, execution_context::ExecutionContext, execution_storage::ExecutionStorage,
+    code_files::CodeFiles,
+    execution_context::{EphemeralHome, ExecutionContext, HomeMode},
+    execution_log::{LogLevel, LogStream},
+    execution_storage::ExecutionStorage,
+    log_retention::LogRetentionPolicy,
+    redaction::{Redactor, RegexRedactionRule},
+    runner_type::RunnerType,
 };
 
 #[tokio::test]
@@ -85,3 +91,347 @@ async fn execution_storage_clean_cache() {
             == 0
     );
 }
+
+#[tokio::test]
+async fn execution_storage_writes_jsonl_log_entries() {
+    let _ = env_logger::builder()
+        .filter_level(log::LevelFilter::Info)
+        .is_test(true)
+        .try_init();
+
+    let test_dir = std::path::PathBuf::from("./shinkai-tools-runner-execution-storage-jsonl");
+    let _ = std::fs::remove_dir_all(&test_dir);
+
+    let redactor = Redactor::new(["my-secret".to_string()]).with_rule(Arc::new(
+        RegexRedactionRule::new(r"Bearer [A-Za-z0-9._-]+").unwrap(),
+    ));
+    let storage = ExecutionStorage::new(
+        CodeFiles {
+            files: HashMap::from([("main.ts".to_string(), "".to_string())]),
+            entrypoint: "main.ts".to_string(),
+        },
+        ExecutionContext {
+            storage: test_dir.clone(),
+            context_id: "ctx".to_string(),
+            execution_id: "exec".to_string(),
+            ..Default::default()
+        },
+    )
+    .with_redactor(redactor);
+    storage.init(None).unwrap();
+
+    storage.append_log("starting, with a comma").unwrap();
+    storage
+        .append_log_entry(LogStream::Stdout, None, "line one\nline two")
+        .unwrap();
+    storage
+        .append_log_entry(
+            LogStream::Stderr,
+            Some(LogLevel::Error),
+            "key my-secret and Authorization: Bearer abc.def",
+        )
+        .unwrap();
+
+    assert_eq!(
+        storage.log_file_path.extension().unwrap().to_str().unwrap(),
+        "jsonl"
+    );
+    let raw = std::fs::read_to_string(&storage.log_file_path).unwrap();
+    assert_eq!(raw.lines().count(), 3);
+    assert!(!raw.contains("my-secret"));
+    assert!(!raw.contains("abc.def"));
+
+    let entries = storage.read_log_entries().unwrap();
+    assert_eq!(entries.len(), 3);
+    assert_eq!(
+        entries.iter().map(|e| e.sequence).collect::<Vec<_>>(),
+        vec![0, 1, 2]
+    );
+    assert_eq!(entries[0].stream, LogStream::System);
+    assert_eq!(entries[0].message, "starting, with a comma");
+    assert_eq!(entries[1].level, LogLevel::Info);
+    assert_eq!(entries[1].message, "line one\nline two");
+    assert_eq!(entries[2].level, LogLevel::Error);
+    assert_eq!(
+        entries[2].message,
+        "key [REDACTED] and Authorization: [REDACTED]"
+    );
+    assert!(entries
+        .iter()
+        .all(|e| e.context_id == "ctx" && e.execution_id == "exec"));
+
+    // A new storage for the same execution continues the sequence
+    let reopened = ExecutionStorage::new(
+        CodeFiles {
+            files: HashMap::from([("main.ts".to_string(), "".to_string())]),
+            entrypoint: "main.ts".to_string(),
+        },
+        ExecutionContext {
+            storage: test_dir.clone(),
+            context_id: "ctx".to_string(),
+            execution_id: "exec".to_string(),
+            ..Default::default()
+        },
+    );
+    reopened.append_log("again").unwrap();
+    assert_eq!(
+        reopened
+            .read_log_entries()
+            .unwrap()
+            .last()
+            .unwrap()
+            .sequence,
+        3
+    );
+
+    let _ = std::fs::remove_dir_all(&test_dir);
+}
+
+#[tokio::test]
+async fn execution_storage_truncates_log_at_size_cap() {
+    let _ = env_logger::builder()
+        .filter_level(log::LevelFilter::Info)
+        .is_test(true)
+        .try_init();
+
+    let test_dir = tempfile::tempdir().unwrap();
+    let storage = ExecutionStorage::new(
+        CodeFiles {
+            files: HashMap::from([("main.ts".to_string(), "".to_string())]),
+            entrypoint: "main.ts".to_string(),
+        },
+        ExecutionContext {
+            storage: test_dir.path().to_path_buf(),
+            log_retention: LogRetentionPolicy {
+                max_bytes_per_execution: Some(1024),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    );
+    storage.init(None).unwrap();
+
+    for i in 0..100 {
+        storage
+            .append_log_entry(LogStream::Stdout, None, &format!("chatty line {}", i))
+            .unwrap();
+    }
+
+    let entries = storage.read_log_entries().unwrap();
+    let last = entries.last().unwrap();
+    assert!(entries.len() < 100);
+    assert_eq!(last.stream, LogStream::System);
+    assert_eq!(last.level, LogLevel::Warn);
+    assert!(last.message.starts_with("log truncated"));
+    assert_eq!(
+        entries
+            .iter()
+            .filter(|e| e.message.starts_with("log truncated"))
+            .count(),
+        1
+    );
+    // Only the truncation notice may go past the limit
+    let raw = std::fs::read_to_string(&storage.log_file_path).unwrap();
+    let notice_line_len = raw.lines().last().unwrap().len() + 1;
+    assert!(raw.len() - notice_line_len <= 1024);
+}
+
+#[tokio::test]
+async fn execution_storage_ephemeral_home() {
+    let _ = env_logger::builder()
+        .filter_level(log::LevelFilter::Info)
+        .is_test(true)
+        .try_init();
+
+    let test_dir = tempfile::tempdir().unwrap();
+    let storage_for = |execution_id: &str, ephemeral_home: EphemeralHome| {
+        let storage = ExecutionStorage::new(
+            CodeFiles {
+                files: HashMap::from([("main.ts".to_string(), "".to_string())]),
+                entrypoint: "main.ts".to_string(),
+            },
+            ExecutionContext {
+                storage: test_dir.path().to_path_buf(),
+                context_id: "ctx".to_string(),
+                execution_id: execution_id.to_string(),
+                home_mode: HomeMode::Ephemeral(ephemeral_home),
+                ..Default::default()
+            },
+        );
+        storage.init(None).unwrap();
+        storage
+    };
+
+    let first = storage_for(
+        "first",
+        EphemeralHome {
+            copy_context_home: true,
+            commit_on_success: false,
+        },
+    );
+    std::fs::write(first.context_home_folder_path.join("shared.txt"), "shared").unwrap();
+    let second = storage_for("second", EphemeralHome::default());
+    assert_ne!(first.home_folder_path, second.home_folder_path);
+    assert_ne!(first.home_folder_path, first.context_home_folder_path);
+
+    {
+        let first_home = first.prepare_home().unwrap();
+        let second_home = second.prepare_home().unwrap();
+        // Copied from the context home or empty
+        assert!(first.home_folder_path.join("shared.txt").exists());
+        assert!(!second.home_folder_path.join("shared.txt").exists());
+
+        std::fs::write(first.home_folder_path.join("out.txt"), "first").unwrap();
+        std::fs::write(second.home_folder_path.join("out.txt"), "second").unwrap();
+        // Not committed on success unless asked to
+        first_home.succeeded().unwrap();
+        drop(second_home);
+        assert!(!second.home_folder_path.exists());
+    }
+    assert!(!first.home_folder_path.exists());
+    assert!(!first.context_home_folder_path.join("out.txt").exists());
+
+    let committed = storage_for(
+        "committed",
+        EphemeralHome {
+            copy_context_home: false,
+            commit_on_success: true,
+        },
+    );
+    {
+        let home = committed.prepare_home().unwrap();
+        std::fs::create_dir_all(committed.home_folder_path.join("nested")).unwrap();
+        std::fs::write(
+            committed.home_folder_path.join("nested").join("out.txt"),
+            "committed",
+        )
+        .unwrap();
+        home.succeeded().unwrap();
+    }
+    assert!(!committed.home_folder_path.exists());
+    assert_eq!(
+        std::fs::read_to_string(
+            committed
+                .context_home_folder_path
+                .join("nested")
+                .join("out.txt")
+        )
+        .unwrap(),
+        "committed"
+    );
+    // Files only in the context home are kept
+    assert!(committed
+        .context_home_folder_path
+        .join("shared.txt")
+        .exists());
+}
+
+#[tokio::test]
+async fn execution_storage_concurrent_init_in_one_context() {
+    let _ = env_logger::builder()
+        .filter_level(log::LevelFilter::Info)
+        .is_test(true)
+        .try_init();
+
+    let test_dir = tempfile::tempdir().unwrap();
+    let storage_for = |code: String| {
+        ExecutionStorage::new(
+            CodeFiles {
+                files: HashMap::from([("main.ts".to_string(), code)]),
+                entrypoint: "main.ts".to_string(),
+            },
+            ExecutionContext {
+                storage: test_dir.path().to_path_buf(),
+                context_id: "ctx".to_string(),
+                code_id: "same-code-id".to_string(),
+                ..Default::default()
+            },
+        )
+    };
+
+    let handles = (0..16)
+        .map(|i| {
+            let storage = storage_for(format!("console.log({});", i % 4));
+            std::thread::spawn(move || {
+                storage.init(None).unwrap();
+                let content = std::fs::read_to_string(&storage.code_entrypoint_file_path).unwrap();
+                (i, content)
+            })
+        })
+        .collect::<Vec<_>>();
+    for handle in handles {
+        let (i, content) = handle.join().unwrap();
+        assert_eq!(content, format!("console.log({});", i % 4));
+    }
+
+    // Different code with the same code id never shares a folder, and no staging folder is left
+    let code_folders = std::fs::read_dir(storage_for(String::new()).root_code_folder_path)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(code_folders.len(), 4);
+    assert!(code_folders
+        .iter()
+        .all(|name| name.starts_with("same-code-id-")));
+}
+
+#[tokio::test]
+async fn execution_storage_pristine_cache_refused_while_in_use() {
+    let _ = env_logger::builder()
+        .filter_level(log::LevelFilter::Info)
+        .is_test(true)
+        .try_init();
+
+    let test_dir = tempfile::tempdir().unwrap();
+    let storage = ExecutionStorage::new(
+        CodeFiles {
+            files: HashMap::from([("main.ts".to_string(), "".to_string())]),
+            entrypoint: "main.ts".to_string(),
+        },
+        ExecutionContext {
+            storage: test_dir.path().to_path_buf(),
+            ..Default::default()
+        },
+    );
+    storage.init(None).unwrap();
+    let cache_file = storage.cache_folder_path.join("in-use.txt");
+    std::fs::write(&cache_file, "used by a live execution").unwrap();
+
+    let lease = storage.acquire_lease().unwrap();
+    assert!(storage.init(Some(true)).is_err());
+    assert!(cache_file.exists());
+
+    drop(lease);
+    storage.init(Some(true)).unwrap();
+    assert!(!cache_file.exists());
+    assert!(storage.cache_folder_path.exists());
+}
+
+#[test]
+fn execution_storage_seeds_deno_cache_without_overwriting() {
+    let test_dir = tempfile::tempdir().unwrap();
+    let seed = test_dir.path().join("seed");
+    std::fs::create_dir_all(seed.join("npm").join("pkg")).unwrap();
+    std::fs::write(seed.join("npm").join("pkg").join("index.js"), "seeded").unwrap();
+    std::fs::write(seed.join("meta.json"), "seeded").unwrap();
+    let storage = ExecutionStorage::new(
+        CodeFiles::default(),
+        ExecutionContext {
+            storage: test_dir.path().join("storage"),
+            ..Default::default()
+        },
+    );
+    let cache = storage.deno_cache_folder_path(RunnerType::Host);
+    std::fs::create_dir_all(&cache).unwrap();
+    std::fs::write(cache.join("meta.json"), "fresher").unwrap();
+
+    storage.seed_deno_cache(RunnerType::Host, &seed).unwrap();
+    assert_eq!(
+        std::fs::read_to_string(cache.join("npm").join("pkg").join("index.js")).unwrap(),
+        "seeded"
+    );
+    assert_eq!(
+        std::fs::read_to_string(cache.join("meta.json")).unwrap(),
+        "fresher"
+    );
+}
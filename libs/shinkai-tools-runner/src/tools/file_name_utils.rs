@@ -52,10 +52,9 @@ pub fn adapt_paths_in_value(
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, target_os = "windows"))]
 mod tests {
     use super::*;
-    #[cfg(target_os = "windows")]
     #[test]
     fn test_normalize_for_docker_path() {
         assert_eq!(
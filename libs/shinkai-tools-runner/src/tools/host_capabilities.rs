@@ -0,0 +1,110 @@
+use std::collections::HashMap;
+
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Identifies the execution a host capability call comes from
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HostCallContext {
+    pub context_id: String,
+    pub execution_id: String,
+    /// Id of the tool making the call when tool calls are enabled
+    pub tool_id: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HttpFetchRequest {
+    pub url: String,
+    #[serde(default = "HttpFetchRequest::default_method")]
+    pub method: String,
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    #[serde(default)]
+    pub body: Option<String>,
+}
+
+impl HttpFetchRequest {
+    fn default_method() -> String {
+        String::from("GET")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HttpFetchResponse {
+    pub status: u16,
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    pub body: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LlmRequest {
+    pub prompt: String,
+    #[serde(default)]
+    pub model: Option<String>,
+    /// Provider specific options (temperature, max tokens...)
+    #[serde(default)]
+    pub options: Value,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LlmResponse {
+    pub content: String,
+    #[serde(default)]
+    pub metadata: Value,
+}
+
+/// Capabilities the embedding application exposes to running tools
+///
+/// Tools reach them through the callback server with the injected client functions
+/// (`shinkaiHost.getSecret(...)` in Deno, `shinkai_get_secret(...)` in Python...), so the host
+/// mediates and can audit every call. Every method defaults to an unsupported error, implementors
+/// override the ones they provide.
+pub trait HostCapabilities: Send + Sync {
+    fn get_secret(
+        &self,
+        context: HostCallContext,
+        name: String,
+    ) -> BoxFuture<'_, anyhow::Result<Option<String>>> {
+        let _ = (context, name);
+        Box::pin(async { Err(anyhow::anyhow!("get_secret is not supported by the host")) })
+    }
+
+    fn kv_get(
+        &self,
+        context: HostCallContext,
+        key: String,
+    ) -> BoxFuture<'_, anyhow::Result<Option<Value>>> {
+        let _ = (context, key);
+        Box::pin(async { Err(anyhow::anyhow!("kv_get is not supported by the host")) })
+    }
+
+    fn kv_set(
+        &self,
+        context: HostCallContext,
+        key: String,
+        value: Value,
+    ) -> BoxFuture<'_, anyhow::Result<()>> {
+        let _ = (context, key, value);
+        Box::pin(async { Err(anyhow::anyhow!("kv_set is not supported by the host")) })
+    }
+
+    fn http_fetch(
+        &self,
+        context: HostCallContext,
+        request: HttpFetchRequest,
+    ) -> BoxFuture<'_, anyhow::Result<HttpFetchResponse>> {
+        let _ = (context, request);
+        Box::pin(async { Err(anyhow::anyhow!("http_fetch is not supported by the host")) })
+    }
+
+    fn call_llm(
+        &self,
+        context: HostCallContext,
+        request: LlmRequest,
+    ) -> BoxFuture<'_, anyhow::Result<LlmResponse>> {
+        let _ = (context, request);
+        Box::pin(async { Err(anyhow::anyhow!("call_llm is not supported by the host")) })
+    }
+}
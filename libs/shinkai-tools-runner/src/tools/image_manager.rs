@@ -0,0 +1,311 @@
+use std::{collections::HashMap, path::Path, process::Stdio, sync::Mutex};
+
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+use super::container_runtime::ContainerRuntime;
+
+/// Image reference to run, by runtime, image and digest, see `ImageManager::prepare`
+static PREPARED_IMAGES: Lazy<Mutex<HashMap<String, String>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Progress message of an image pull
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImagePullProgress {
+    /// Layer the message is about, if any
+    pub id: Option<String>,
+    pub status: String,
+    pub current: Option<u64>,
+    pub total: Option<u64>,
+}
+
+impl ImagePullProgress {
+    /// Progress from a cli output line like `a1b2c3: Downloading`, layer ids are hex
+    fn from_cli_line(line: &str) -> Self {
+        let (id, status) = match line.split_once(": ") {
+            Some((id, status)) if id.chars().all(|c| c.is_ascii_hexdigit()) => {
+                (Some(id.to_string()), status)
+            }
+            _ => (None, line),
+        };
+        ImagePullProgress {
+            id,
+            status: status.trim().to_string(),
+            current: None,
+            total: None,
+        }
+    }
+}
+
+/// Identity of a local image
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImageInfo {
+    /// Image id, `sha256:` prefixed
+    pub id: String,
+    /// Registry references by manifest digest, e.g. `dcspark/shinkai-code-runner@sha256:...`
+    pub repo_digests: Vec<String>,
+}
+
+impl ImageInfo {
+    pub(crate) fn from_inspect(value: &Value) -> Self {
+        let id = value["Id"].as_str().unwrap_or_default();
+        ImageInfo {
+            // Podman reports the bare hex id
+            id: if id.is_empty() || id.contains(':') {
+                id.to_string()
+            } else {
+                format!("sha256:{}", id)
+            },
+            repo_digests: value["RepoDigests"]
+                .as_array()
+                .map(|digests| {
+                    digests
+                        .iter()
+                        .filter_map(|digest| digest.as_str().map(|s| s.to_string()))
+                        .collect()
+                })
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Whether `digest` is the registry manifest digest or the id of this image
+    ///
+    /// Images loaded from a tarball have no manifest digest, they are pinned by id.
+    pub fn matches_digest(&self, digest: &str) -> bool {
+        self.id == digest
+            || self
+                .repo_digests
+                .iter()
+                .any(|repo_digest| repo_digest.ends_with(&format!("@{}", digest)))
+    }
+}
+
+/// Checks, pulls, verifies and loads the code runner image
+pub struct ImageManager {
+    runtime: ContainerRuntime,
+}
+
+impl ImageManager {
+    pub fn new(runtime: ContainerRuntime) -> Self {
+        ImageManager { runtime }
+    }
+
+    pub fn runtime(&self) -> &ContainerRuntime {
+        &self.runtime
+    }
+
+    /// `image` pinned to `digest`, e.g. `dcspark/shinkai-code-runner@sha256:...`
+    pub fn pinned_reference(image: &str, digest: &str) -> String {
+        let name = image.split('@').next().unwrap_or(image);
+        // A tag next to a digest is ignored by the engines, drop it
+        let name = match name.rfind(':') {
+            Some(index) if !name[index..].contains('/') => &name[..index],
+            _ => name,
+        };
+        format!("{}@{}", name, digest)
+    }
+
+    /// None when the image isn't available locally
+    pub async fn inspect(&self, image: &str) -> anyhow::Result<Option<ImageInfo>> {
+        if let Some(client) = self.runtime.engine_client() {
+            return client.inspect_image(image).await;
+        }
+        let output = self
+            .runtime
+            .command()
+            .args(["image", "inspect", "--format", "{{json .}}", image])
+            .output()
+            .await?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            if stderr.contains("No such image") || stderr.contains("image not known") {
+                return Ok(None);
+            }
+            return Err(anyhow::anyhow!(
+                "failed to inspect image {}: {}",
+                image,
+                stderr.trim()
+            ));
+        }
+        let value = serde_json::from_slice::<Value>(&output.stdout)?;
+        // Some versions wrap the result in an array
+        let value = value.get(0).cloned().unwrap_or(value);
+        Ok(Some(ImageInfo::from_inspect(&value)))
+    }
+
+    pub async fn exists(&self, image: &str) -> anyhow::Result<bool> {
+        Ok(self.inspect(image).await?.is_some())
+    }
+
+    pub async fn pull(
+        &self,
+        image: &str,
+        mut on_progress: impl FnMut(ImagePullProgress) + Send,
+    ) -> anyhow::Result<ImageInfo> {
+        log::info!("pulling image {}", image);
+        if let Some(client) = self.runtime.engine_client() {
+            client.pull_image(image, &mut on_progress).await?;
+        } else {
+            let mut child = self
+                .runtime
+                .command()
+                .args(["pull", image])
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .kill_on_drop(true)
+                .spawn()?;
+            // Docker writes progress to stdout and Podman to stderr
+            let mut stdout = BufReader::new(child.stdout.take().unwrap()).lines();
+            let mut stderr = BufReader::new(child.stderr.take().unwrap()).lines();
+            let mut stderr_lines = Vec::new();
+            let (mut stdout_done, mut stderr_done) = (false, false);
+            while !stdout_done || !stderr_done {
+                tokio::select! {
+                    line = stdout.next_line(), if !stdout_done => match line? {
+                        Some(line) => on_progress(ImagePullProgress::from_cli_line(&line)),
+                        None => stdout_done = true,
+                    },
+                    line = stderr.next_line(), if !stderr_done => match line? {
+                        Some(line) => {
+                            on_progress(ImagePullProgress::from_cli_line(&line));
+                            stderr_lines.push(line);
+                        }
+                        None => stderr_done = true,
+                    },
+                }
+            }
+            if !child.wait().await?.success() {
+                return Err(anyhow::anyhow!(
+                    "failed to pull image {}: {}",
+                    image,
+                    stderr_lines.join("\n")
+                ));
+            }
+        }
+        self.inspect(image)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("image {} is missing after pull", image))
+    }
+
+    /// Fails unless the local image exists and matches `digest`
+    pub async fn verify(&self, image: &str, digest: &str) -> anyhow::Result<ImageInfo> {
+        let info = self
+            .inspect(image)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("image {} is not available locally", image))?;
+        if !info.matches_digest(digest) {
+            return Err(anyhow::anyhow!(
+                "image {} does not match digest {}, found id {} and repo digests {:?}",
+                image,
+                digest,
+                info.id,
+                info.repo_digests
+            ));
+        }
+        Ok(info)
+    }
+
+    /// Pulls the image when missing and, when pinned, makes sure it matches `digest`
+    ///
+    /// A pinned image is pulled by digest, so the registry can't serve another build.
+    pub async fn ensure(
+        &self,
+        image: &str,
+        digest: Option<&str>,
+        on_progress: impl FnMut(ImagePullProgress) + Send,
+    ) -> anyhow::Result<ImageInfo> {
+        let Some(digest) = digest else {
+            return match self.inspect(image).await? {
+                Some(info) => Ok(info),
+                None => self.pull(image, on_progress).await,
+            };
+        };
+        let pinned_reference = Self::pinned_reference(image, digest);
+        for reference in [image, pinned_reference.as_str()] {
+            if let Some(info) = self.inspect(reference).await? {
+                if info.matches_digest(digest) {
+                    return Ok(info);
+                }
+            }
+        }
+        self.pull(&pinned_reference, on_progress).await?;
+        self.verify(&pinned_reference, digest).await
+    }
+
+    /// `ensure` once per process and returns the reference to run
+    ///
+    /// Pinned images run by id so a retagged image is never picked up. The cached reference is
+    /// inspected again on every call, the image may have been removed since it was prepared.
+    pub async fn prepare(&self, image: &str, digest: Option<&str>) -> anyhow::Result<String> {
+        let key = format!("{:?}|{}|{:?}", self.runtime, image, digest);
+        let cached = PREPARED_IMAGES.lock().unwrap().get(&key).cloned();
+        if let Some(reference) = cached {
+            if self.inspect(&reference).await?.is_some() {
+                return Ok(reference);
+            }
+            log::warn!("prepared image {} is gone, preparing it again", reference);
+            PREPARED_IMAGES.lock().unwrap().remove(&key);
+        }
+        let info = self
+            .ensure(image, digest, |progress| {
+                log::info!("pulling image {}: {:?}", image, progress);
+            })
+            .await?;
+        let reference = match digest {
+            Some(_) => info.id,
+            None => image.to_string(),
+        };
+        PREPARED_IMAGES
+            .lock()
+            .unwrap()
+            .insert(key, reference.clone());
+        Ok(reference)
+    }
+
+    /// Loads an image tarball, as written by `docker save`, for offline installs
+    pub async fn load(&self, tarball: &Path) -> anyhow::Result<Vec<String>> {
+        log::info!("loading image tarball {}", tarball.display());
+        if let Some(client) = self.runtime.engine_client() {
+            return client.load_image(tarball).await;
+        }
+        let output = self
+            .runtime
+            .command()
+            .arg("load")
+            .arg("-i")
+            .arg(tarball)
+            .output()
+            .await?;
+        if !output.status.success() {
+            return Err(anyhow::anyhow!(
+                "failed to load image tarball {}: {}",
+                tarball.display(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        Ok(parse_loaded_images(&String::from_utf8_lossy(
+            &output.stdout,
+        )))
+    }
+}
+
+/// Image names or ids from `Loaded image: ...` lines
+pub(crate) fn parse_loaded_images(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .filter_map(|line| {
+            ["Loaded image ID: ", "Loaded image(s): ", "Loaded image: "]
+                .iter()
+                .find_map(|prefix| line.trim().strip_prefix(prefix))
+        })
+        .flat_map(|images| images.split(','))
+        .map(|image| image.trim().to_string())
+        .filter(|image| !image.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+#[path = "image_manager.test.rs"]
+mod tests;
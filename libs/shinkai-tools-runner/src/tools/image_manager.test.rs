@@ -0,0 +1,203 @@
+#[cfg(unix)]
+use std::path::{Path, PathBuf};
+
+use serde_json::json;
+
+use crate::tools::image_manager::{parse_loaded_images, ImageInfo, ImageManager};
+
+#[test]
+fn pinned_reference_replaces_tag() {
+    assert_eq!(
+        ImageManager::pinned_reference("dcspark/shinkai-code-runner:0.9.4", "sha256:abc"),
+        "dcspark/shinkai-code-runner@sha256:abc"
+    );
+    assert_eq!(
+        ImageManager::pinned_reference("localhost:5000/runner:1.0", "sha256:abc"),
+        "localhost:5000/runner@sha256:abc"
+    );
+    assert_eq!(
+        ImageManager::pinned_reference("localhost:5000/runner@sha256:old", "sha256:abc"),
+        "localhost:5000/runner@sha256:abc"
+    );
+}
+
+#[test]
+fn image_info_matches_manifest_digest_or_id() {
+    let info = ImageInfo::from_inspect(&json!({
+        "Id": "1111",
+        "RepoDigests": ["dcspark/shinkai-code-runner@sha256:abc"],
+    }));
+    assert_eq!(info.id, "sha256:1111");
+    assert!(info.matches_digest("sha256:abc"));
+    assert!(info.matches_digest("sha256:1111"));
+    assert!(!info.matches_digest("sha256:ab"));
+}
+
+#[test]
+fn parses_loaded_images() {
+    assert_eq!(
+        parse_loaded_images(
+            "Loaded image: dcspark/shinkai-code-runner:0.9.4\nLoaded image ID: sha256:1111\nLoaded image(s): a:1,b:2\nother"
+        ),
+        vec![
+            "dcspark/shinkai-code-runner:0.9.4",
+            "sha256:1111",
+            "a:1",
+            "b:2"
+        ]
+    );
+}
+
+/// Docker cli look-alike keeping its images as files in `folder`, every call is logged to `calls.log`
+#[cfg(unix)]
+fn fake_docker(folder: &Path) -> ImageManager {
+    use crate::tools::{
+        container_runtime::{ContainerRuntime, ContainerRuntimeKind},
+        test_utils::fake_binary,
+    };
+
+    let script = format!(
+        r#"state="{state}"
+echo "$@" >> "$state/calls.log"
+image_file() {{ echo "$state/image-$(echo "$1" | tr '/:@' '___')"; }}
+case "$1" in
+  image)
+    if [ -f "$(image_file "$5")" ]; then cat "$(image_file "$5")"; exit 0; fi
+    echo "Error: No such image: $5" >&2; exit 1 ;;
+  pull)
+    case "$2" in fail/*) echo "Error: pull access denied for $2" >&2; exit 1 ;; esac
+    echo "0.9.4: Pulling from dcspark/shinkai-code-runner"
+    echo "a1b2c3: Downloading" >&2
+    echo "Digest: sha256:abc"
+    echo '{{"Id":"sha256:1111","RepoDigests":["dcspark/shinkai-code-runner@sha256:abc"]}}' > "$(image_file "$2")"
+    exit 0 ;;
+  load)
+    echo '{{"Id":"sha256:2222","RepoDigests":[]}}' > "$(image_file offline/runner:1.0)"
+    echo "Loaded image: offline/runner:1.0"
+    exit 0 ;;
+esac
+exit 1"#,
+        state = folder.display()
+    );
+    let path = fake_binary(folder, "docker", &script);
+    ImageManager::new(ContainerRuntime::new(ContainerRuntimeKind::Docker, path))
+}
+
+#[cfg(unix)]
+fn pulls(folder: &Path) -> usize {
+    std::fs::read_to_string(folder.join("calls.log"))
+        .unwrap_or_default()
+        .lines()
+        .filter(|line| line.starts_with("pull"))
+        .count()
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn ensure_pulls_missing_image_once_with_progress() {
+    let folder = tempfile::tempdir().unwrap();
+    let manager = fake_docker(folder.path());
+    let image = "dcspark/shinkai-code-runner:0.9.4";
+    assert!(!manager.exists(image).await.unwrap());
+
+    let mut progress = Vec::new();
+    let info = manager
+        .ensure(image, None, |event| progress.push(event))
+        .await
+        .unwrap();
+    assert_eq!(info.id, "sha256:1111");
+    assert!(progress
+        .iter()
+        .any(|event| event.id.as_deref() == Some("a1b2c3") && event.status == "Downloading"));
+    assert!(progress
+        .iter()
+        .any(|event| event.id.is_none() && event.status == "Digest: sha256:abc"));
+
+    manager.ensure(image, None, |_| {}).await.unwrap();
+    assert_eq!(pulls(folder.path()), 1);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn pinned_image_is_pulled_by_digest_and_verified() {
+    let folder = tempfile::tempdir().unwrap();
+    let manager = fake_docker(folder.path());
+    let image = "dcspark/shinkai-code-runner:0.9.4";
+
+    let info = manager
+        .ensure(image, Some("sha256:abc"), |_| {})
+        .await
+        .unwrap();
+    assert!(info.matches_digest("sha256:abc"));
+    let calls = std::fs::read_to_string(folder.path().join("calls.log")).unwrap();
+    assert!(calls.contains("pull dcspark/shinkai-code-runner@sha256:abc"));
+
+    // The pinned reference is found locally, no second pull
+    assert_eq!(
+        manager.prepare(image, Some("sha256:abc")).await.unwrap(),
+        "sha256:1111"
+    );
+    assert_eq!(pulls(folder.path()), 1);
+
+    let error = manager
+        .ensure(image, Some("sha256:other"), |_| {})
+        .await
+        .unwrap_err();
+    assert!(error
+        .to_string()
+        .contains("does not match digest sha256:other"));
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn prepare_pulls_again_when_the_prepared_image_is_gone() {
+    let folder = tempfile::tempdir().unwrap();
+    let manager = fake_docker(folder.path());
+    let image = "dcspark/shinkai-code-runner:0.9.4";
+
+    assert_eq!(manager.prepare(image, None).await.unwrap(), image);
+    assert_eq!(manager.prepare(image, None).await.unwrap(), image);
+    assert_eq!(pulls(folder.path()), 1);
+
+    // e.g. `docker image prune` while the process runs
+    std::fs::remove_file(
+        folder
+            .path()
+            .join("image-dcspark_shinkai-code-runner_0.9.4"),
+    )
+    .unwrap();
+    assert_eq!(manager.prepare(image, None).await.unwrap(), image);
+    assert_eq!(pulls(folder.path()), 2);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn loads_tarball_for_offline_installs() {
+    let folder = tempfile::tempdir().unwrap();
+    let manager = fake_docker(folder.path());
+    let tarball: PathBuf = folder.path().join("runner.tar");
+    std::fs::write(&tarball, "tar").unwrap();
+
+    let loaded = manager.load(&tarball).await.unwrap();
+    assert_eq!(loaded, vec!["offline/runner:1.0"]);
+    manager
+        .verify("offline/runner:1.0", "sha256:2222")
+        .await
+        .unwrap();
+    assert!(manager
+        .ensure("offline/runner:1.0", Some("sha256:2222"), |_| {})
+        .await
+        .is_ok());
+    assert_eq!(pulls(folder.path()), 0);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn pull_failure_reports_stderr() {
+    let folder = tempfile::tempdir().unwrap();
+    let manager = fake_docker(folder.path());
+    let error = manager.pull("fail/runner:1.0", |_| {}).await.unwrap_err();
+    assert!(error
+        .to_string()
+        .contains("pull access denied for fail/runner:1.0"));
+}
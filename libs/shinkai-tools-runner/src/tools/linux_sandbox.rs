@@ -0,0 +1,391 @@
+use std::path::{self, Path, PathBuf};
+
+use super::{
+    container_runtime::BindMount, execution_error::InfrastructureError,
+    execution_storage::ExecutionStorage,
+};
+
+/// Runs the tool process in fresh user, mount, pid, ipc and uts namespaces with bubblewrap
+///
+/// Only the system folders and the paths given to `command` are visible, and a seccomp filter
+/// denies syscalls that could escape or tamper with the host, e.g. `mount`, `ptrace` or `bpf`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinuxSandbox {
+    /// bubblewrap binary, a bare name is looked up in PATH
+    pub bwrap_binary_path: PathBuf,
+    /// Gives the tool the host network for http calls, off by default, imports and dependencies
+    /// are fetched ahead by a `fetch_command` step either way
+    pub share_network: bool,
+    /// Host paths mounted read-only so binaries, libraries and certificates keep working,
+    /// missing ones are skipped
+    pub system_paths: Vec<PathBuf>,
+}
+
+impl Default for LinuxSandbox {
+    fn default() -> Self {
+        Self {
+            bwrap_binary_path: PathBuf::from("bwrap"),
+            share_network: false,
+            system_paths: [
+                "/usr",
+                "/bin",
+                "/sbin",
+                "/lib",
+                "/lib32",
+                "/lib64",
+                "/etc/alternatives",
+                "/etc/ca-certificates",
+                "/etc/hosts",
+                "/etc/localtime",
+                "/etc/nsswitch.conf",
+                "/etc/pki",
+                "/etc/resolv.conf",
+                "/etc/ssl",
+            ]
+            .iter()
+            .map(PathBuf::from)
+            .collect(),
+        }
+    }
+}
+
+impl LinuxSandbox {
+    /// File descriptor the seccomp filter is handed to bubblewrap on
+    pub const SECCOMP_FD: i32 = 10;
+
+    /// Whether bubblewrap can be launched, it may still fail when user namespaces are disabled
+    pub fn is_available(&self) -> bool {
+        cfg!(target_os = "linux")
+            && std::process::Command::new(&self.bwrap_binary_path)
+                .arg("--version")
+                .output()
+                .is_ok_and(|output| output.status.success())
+    }
+
+    /// bubblewrap arguments up to and including the `--` before the sandboxed program
+    ///
+    /// Paths are mounted at the same location so the program sees the host paths.
+    pub fn args(&self, program: &Path, mounts: &[BindMount], working_dir: &Path) -> Vec<String> {
+        let mut args = vec![
+            String::from("--die-with-parent"),
+            String::from("--new-session"),
+            String::from("--unshare-all"),
+        ];
+        if self.share_network {
+            args.push(String::from("--share-net"));
+        }
+        for system_path in &self.system_paths {
+            let system_path = system_path.to_string_lossy().to_string();
+            args.extend([
+                String::from("--ro-bind-try"),
+                system_path.clone(),
+                system_path,
+            ]);
+        }
+        args.extend(
+            ["--proc", "/proc", "--dev", "/dev", "--tmpfs", "/tmp"]
+                .iter()
+                .map(|arg| arg.to_string()),
+        );
+        let program = path::absolute(program)
+            .unwrap_or(program.to_path_buf())
+            .to_string_lossy()
+            .to_string();
+        args.extend([String::from("--ro-bind"), program.clone(), program]);
+        for mount in mounts {
+            let flag = if mount.readonly {
+                "--ro-bind-try"
+            } else {
+                "--bind-try"
+            };
+            args.extend([flag.to_string(), mount.source.clone(), mount.target.clone()]);
+        }
+        args.extend([
+            String::from("--chdir"),
+            working_dir.to_string_lossy().to_string(),
+            String::from("--seccomp"),
+            Self::SECCOMP_FD.to_string(),
+            String::from("--"),
+        ]);
+        args
+    }
+
+    /// `command` with the host network shared, for the step fetching dependencies ahead of a
+    /// run that stays offline
+    pub fn fetch_command(
+        &self,
+        program: &Path,
+        mounts: &[BindMount],
+        working_dir: &Path,
+    ) -> anyhow::Result<tokio::process::Command> {
+        LinuxSandbox {
+            share_network: true,
+            ..self.clone()
+        }
+        .command(program, mounts, working_dir)
+    }
+
+    /// Command running `program` in the sandbox, arguments added to it are passed to `program`
+    #[cfg(target_os = "linux")]
+    pub fn command(
+        &self,
+        program: &Path,
+        mounts: &[BindMount],
+        working_dir: &Path,
+    ) -> anyhow::Result<tokio::process::Command> {
+        use std::{
+            io::{Seek, Write},
+            os::fd::AsRawFd,
+        };
+
+        let filter = seccomp_filter().ok_or_else(|| {
+            InfrastructureError(format!(
+                "the sandbox runner type has no seccomp filter for {}",
+                std::env::consts::ARCH
+            ))
+        })?;
+        let mut filter_file = tempfile::tempfile()?;
+        filter_file.write_all(&filter)?;
+        filter_file.rewind()?;
+
+        let mut command = tokio::process::Command::new(&self.bwrap_binary_path);
+        command.args(self.args(program, mounts, working_dir));
+        command.arg(program);
+        // SAFETY: only async-signal-safe calls between fork and exec
+        unsafe {
+            command.pre_exec(move || {
+                let fd = filter_file.as_raw_fd();
+                let result = if fd == Self::SECCOMP_FD {
+                    libc::fcntl(fd, libc::F_SETFD, 0)
+                } else {
+                    libc::dup2(fd, Self::SECCOMP_FD)
+                };
+                if result == -1 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+        Ok(command)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn command(
+        &self,
+        _program: &Path,
+        _mounts: &[BindMount],
+        _working_dir: &Path,
+    ) -> anyhow::Result<tokio::process::Command> {
+        Err(InfrastructureError(String::from(
+            "the sandbox runner type is only supported on linux",
+        ))
+        .into())
+    }
+}
+
+impl ExecutionStorage {
+    /// Paths a sandboxed run can see, code and assets read-only, home, cache and mount files
+    /// writable, plus runtime specific `cache_folders` from the global cache
+    pub fn sandbox_mounts(&self, cache_folders: &[PathBuf]) -> Vec<BindMount> {
+        let bind = |path: &Path, readonly: bool| {
+            let path = path::absolute(path)
+                .unwrap_or(path.to_path_buf())
+                .to_string_lossy()
+                .to_string();
+            BindMount {
+                source: path.clone(),
+                target: path,
+                readonly,
+            }
+        };
+        let mut mounts = vec![
+            bind(&self.code_folder_path, true),
+            bind(&self.root_folder_path.join("deno.json"), true),
+            bind(&self.root_folder_path.join(".config"), true),
+            bind(&self.home_folder_path, false),
+            bind(&self.cache_folder_path, false),
+            bind(&self.assets_folder_path, true),
+        ];
+        mounts.extend(cache_folders.iter().map(|path| bind(path, false)));
+        mounts.extend(
+            self.context
+                .assets_files
+                .iter()
+                .map(|path| bind(path, true)),
+        );
+        mounts.extend(
+            self.context
+                .mount_files
+                .iter()
+                .map(|path| bind(path, false)),
+        );
+        mounts
+    }
+}
+
+/// Syscall numbers the seccomp filter checks, for one architecture
+struct SyscallNumbers {
+    /// AUDIT_ARCH_* value of the architecture
+    arch: u32,
+    clone: u32,
+    clone3: u32,
+    /// Denied with EPERM
+    denied: &'static [u32],
+}
+
+#[cfg(target_arch = "x86_64")]
+const SYSCALL_NUMBERS: Option<SyscallNumbers> = Some(SyscallNumbers {
+    // AUDIT_ARCH_X86_64
+    arch: 0xc000_003e,
+    clone: 56,
+    clone3: 435,
+    denied: &[
+        101, // ptrace
+        103, // syslog
+        135, // personality
+        155, // pivot_root
+        161, // chroot
+        163, // acct
+        165, // mount
+        166, // umount2
+        167, // swapon
+        168, // swapoff
+        169, // reboot
+        172, // iopl
+        173, // ioperm
+        175, // init_module
+        176, // delete_module
+        246, // kexec_load
+        248, // add_key
+        249, // request_key
+        250, // keyctl
+        272, // unshare
+        298, // perf_event_open
+        304, // open_by_handle_at
+        308, // setns
+        310, // process_vm_readv
+        311, // process_vm_writev
+        313, // finit_module
+        320, // kexec_file_load
+        321, // bpf
+        323, // userfaultfd
+        428, // open_tree
+        429, // move_mount
+        430, // fsopen
+        431, // fsconfig
+        432, // fsmount
+    ],
+});
+
+#[cfg(target_arch = "aarch64")]
+const SYSCALL_NUMBERS: Option<SyscallNumbers> = Some(SyscallNumbers {
+    // AUDIT_ARCH_AARCH64
+    arch: 0xc000_00b7,
+    clone: 220,
+    clone3: 435,
+    denied: &[
+        39,  // umount2
+        40,  // mount
+        41,  // pivot_root
+        51,  // chroot
+        89,  // acct
+        92,  // personality
+        97,  // unshare
+        104, // kexec_load
+        105, // init_module
+        106, // delete_module
+        116, // syslog
+        117, // ptrace
+        142, // reboot
+        217, // add_key
+        218, // request_key
+        219, // keyctl
+        224, // swapon
+        225, // swapoff
+        241, // perf_event_open
+        265, // open_by_handle_at
+        268, // setns
+        270, // process_vm_readv
+        271, // process_vm_writev
+        273, // finit_module
+        280, // bpf
+        282, // userfaultfd
+        294, // kexec_file_load
+        428, // open_tree
+        429, // move_mount
+        430, // fsopen
+        431, // fsconfig
+        432, // fsmount
+    ],
+});
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+const SYSCALL_NUMBERS: Option<SyscallNumbers> = None;
+
+/// Classic BPF program for `--seccomp`, as `struct sock_filter` entries in native byte order
+///
+/// Other architectures are killed so the syscall numbers can't be reinterpreted, and x32
+/// syscalls are denied since they would bypass the numbers above. `clone` creating namespaces
+/// is denied like `unshare`, and `clone3` fails with ENOSYS since its flags are behind a pointer
+/// the filter can't read, so libc falls back to `clone`.
+pub fn seccomp_filter() -> Option<Vec<u8>> {
+    const LD_W_ABS: u16 = 0x20;
+    const JEQ_K: u16 = 0x15;
+    const JGE_K: u16 = 0x35;
+    const JSET_K: u16 = 0x45;
+    const RET_K: u16 = 0x06;
+    const RET_KILL_PROCESS: u32 = 0x8000_0000;
+    const RET_ERRNO_EPERM: u32 = 0x0005_0001;
+    const RET_ERRNO_ENOSYS: u32 = 0x0005_0026;
+    const RET_ALLOW: u32 = 0x7fff_0000;
+    const X32_SYSCALL_BIT: u32 = 0x4000_0000;
+    // CLONE_NEWNS | CLONE_NEWCGROUP | CLONE_NEWUTS | CLONE_NEWIPC | CLONE_NEWUSER | CLONE_NEWPID
+    // | CLONE_NEWNET
+    const CLONE_NAMESPACE_FLAGS: u32 = 0x7e02_0000;
+    // Offsets in struct seccomp_data, the low half of the first argument on little endian
+    const NR_OFFSET: u32 = 0;
+    const ARCH_OFFSET: u32 = 4;
+    const FIRST_ARG_OFFSET: u32 = 16;
+
+    let SyscallNumbers {
+        arch,
+        clone,
+        clone3,
+        denied,
+    } = SYSCALL_NUMBERS?;
+    let mut program: Vec<(u16, u8, u8, u32)> = vec![
+        (LD_W_ABS, 0, 0, ARCH_OFFSET),
+        (JEQ_K, 1, 0, arch),
+        (RET_K, 0, 0, RET_KILL_PROCESS),
+        (LD_W_ABS, 0, 0, NR_OFFSET),
+        (JGE_K, 0, 1, X32_SYSCALL_BIT),
+        (RET_K, 0, 0, RET_ERRNO_EPERM),
+        (JEQ_K, 0, 1, clone3),
+        (RET_K, 0, 0, RET_ERRNO_ENOSYS),
+        (JEQ_K, 0, 4, clone),
+        (LD_W_ABS, 0, 0, FIRST_ARG_OFFSET),
+        (JSET_K, 0, 1, CLONE_NAMESPACE_FLAGS),
+        (RET_K, 0, 0, RET_ERRNO_EPERM),
+        (RET_K, 0, 0, RET_ALLOW),
+    ];
+    for syscall in denied {
+        program.push((JEQ_K, 0, 1, *syscall));
+        program.push((RET_K, 0, 0, RET_ERRNO_EPERM));
+    }
+    program.push((RET_K, 0, 0, RET_ALLOW));
+    Some(
+        program
+            .into_iter()
+            .flat_map(|(code, jt, jf, k)| {
+                let mut instruction = code.to_ne_bytes().to_vec();
+                instruction.extend([jt, jf]);
+                instruction.extend(k.to_ne_bytes());
+                instruction
+            })
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+#[path = "linux_sandbox.test.rs"]
+mod tests;
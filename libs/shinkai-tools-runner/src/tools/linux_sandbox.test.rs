@@ -0,0 +1,184 @@
+use std::path::{Path, PathBuf};
+
+use crate::tools::{
+    code_files::CodeFiles,
+    container_runtime::BindMount,
+    execution_context::ExecutionContext,
+    execution_storage::ExecutionStorage,
+    linux_sandbox::{seccomp_filter, LinuxSandbox},
+    runner_type::RunnerType,
+};
+
+fn bind(path: &str, readonly: bool) -> BindMount {
+    BindMount {
+        source: path.to_string(),
+        target: path.to_string(),
+        readonly,
+    }
+}
+
+#[test]
+fn args_bind_paths_in_place_and_end_with_seccomp() {
+    let sandbox = LinuxSandbox {
+        system_paths: vec![PathBuf::from("/usr")],
+        ..Default::default()
+    };
+    let args = sandbox
+        .args(
+            Path::new("/opt/deno"),
+            &[bind("/storage/code", true), bind("/storage/home", false)],
+            Path::new("/storage"),
+        )
+        .join(" ");
+    assert!(args.starts_with("--die-with-parent --new-session --unshare-all --ro-bind"));
+    assert!(args.contains("--ro-bind-try /usr /usr"));
+    assert!(args.contains("--ro-bind /opt/deno /opt/deno"));
+    assert!(args.contains("--ro-bind-try /storage/code /storage/code"));
+    assert!(args.contains("--bind-try /storage/home /storage/home"));
+    assert!(args.ends_with("--chdir /storage --seccomp 10 --"));
+
+    assert!(!args.contains("--share-net"));
+
+    let networked = LinuxSandbox {
+        share_network: true,
+        ..sandbox
+    };
+    assert!(networked
+        .args(Path::new("/opt/deno"), &[], Path::new("/storage"))
+        .contains(&String::from("--share-net")));
+}
+
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+#[test]
+fn seccomp_filter_checks_arch_and_allows_by_default() {
+    let filter = seccomp_filter().unwrap();
+    assert_eq!(filter.len() % 8, 0);
+    let instruction = |index: usize| {
+        let bytes = &filter[index * 8..index * 8 + 8];
+        (
+            u16::from_ne_bytes([bytes[0], bytes[1]]),
+            u32::from_ne_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+        )
+    };
+    // Load the arch, then kill on mismatch
+    assert_eq!(instruction(0), (0x20, 4));
+    assert_eq!(instruction(2), (0x06, 0x8000_0000));
+    // Every denied syscall is a compare and an EPERM return
+    let count = filter.len() / 8;
+    assert!(count > 20);
+    assert_eq!(instruction(count - 2), (0x06, 0x0005_0001));
+    assert_eq!(instruction(count - 1), (0x06, 0x7fff_0000));
+}
+
+#[test]
+fn sandbox_mounts_expose_only_execution_storage_paths() {
+    let folder = tempfile::tempdir().unwrap();
+    let mount_file = folder.path().join("mounted.txt");
+    let asset_file = folder.path().join("asset.txt");
+    let storage = ExecutionStorage::new(
+        CodeFiles {
+            files: Default::default(),
+            entrypoint: String::from("main.ts"),
+        },
+        ExecutionContext {
+            storage: folder.path().join("storage"),
+            mount_files: vec![mount_file.clone()],
+            assets_files: vec![asset_file.clone()],
+            ..Default::default()
+        },
+    );
+    let cache = storage.deno_cache_folder_path(RunnerType::Sandbox);
+    let mounts = storage.sandbox_mounts(std::slice::from_ref(&cache));
+    let readonly = |path: &Path| {
+        mounts
+            .iter()
+            .find(|mount| mount.source == path.to_string_lossy())
+            .map(|mount| mount.readonly)
+    };
+    assert_eq!(readonly(&storage.code_folder_path), Some(true));
+    assert_eq!(readonly(&storage.home_folder_path), Some(false));
+    assert_eq!(readonly(&storage.cache_folder_path), Some(false));
+    assert_eq!(readonly(&cache), Some(false));
+    assert_eq!(readonly(&asset_file), Some(true));
+    assert_eq!(readonly(&mount_file), Some(false));
+    assert_eq!(readonly(&storage.logs_folder_path), None);
+    assert_eq!(readonly(&storage.global_cache_folder_path), None);
+    assert_eq!(
+        readonly(&storage.deno_cache_folder_path(RunnerType::Host)),
+        None
+    );
+    assert!(mounts.iter().all(|mount| mount.source == mount.target));
+}
+
+/// Runs the seccomp filter against a syscall of the current architecture, returning its action
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+fn seccomp_action(nr: u32, first_arg: u64) -> u32 {
+    #[cfg(target_arch = "x86_64")]
+    const ARCH: u32 = 0xc000_003e;
+    #[cfg(target_arch = "aarch64")]
+    const ARCH: u32 = 0xc000_00b7;
+
+    let mut data = [0u8; 64];
+    data[0..4].copy_from_slice(&nr.to_ne_bytes());
+    data[4..8].copy_from_slice(&ARCH.to_ne_bytes());
+    data[16..24].copy_from_slice(&first_arg.to_ne_bytes());
+    let filter = seccomp_filter().unwrap();
+    let (mut pc, mut accumulator) = (0, 0u32);
+    loop {
+        let bytes = &filter[pc * 8..pc * 8 + 8];
+        let code = u16::from_ne_bytes([bytes[0], bytes[1]]);
+        let (jt, jf) = (bytes[2] as usize, bytes[3] as usize);
+        let k = u32::from_ne_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+        let jump = |condition: bool| pc + 1 + if condition { jt } else { jf };
+        pc = match code {
+            0x20 => {
+                let offset = k as usize;
+                accumulator = u32::from_ne_bytes(data[offset..offset + 4].try_into().unwrap());
+                pc + 1
+            }
+            0x15 => jump(accumulator == k),
+            0x35 => jump(accumulator >= k),
+            0x45 => jump(accumulator & k != 0),
+            0x06 => return k,
+            _ => panic!("unexpected instruction {:#x}", code),
+        };
+    }
+}
+
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+#[test]
+fn seccomp_filter_denies_namespaces_through_clone() {
+    #[cfg(target_arch = "x86_64")]
+    const CLONE: u32 = 56;
+    #[cfg(target_arch = "aarch64")]
+    const CLONE: u32 = 220;
+    const CLONE3: u32 = 435;
+    const EPERM: u32 = 0x0005_0001;
+    const ENOSYS: u32 = 0x0005_0026;
+    const ALLOW: u32 = 0x7fff_0000;
+    // CLONE_VM | CLONE_FS | CLONE_FILES | CLONE_SIGHAND | CLONE_THREAD, as used for threads
+    const THREAD_FLAGS: u64 = 0x0001_0f00;
+
+    assert_eq!(seccomp_action(CLONE, THREAD_FLAGS), ALLOW);
+    // SIGCHLD, as used by fork
+    assert_eq!(seccomp_action(CLONE, 17), ALLOW);
+    for namespace_flag in [
+        0x0002_0000u64, // CLONE_NEWNS
+        0x0200_0000,    // CLONE_NEWCGROUP
+        0x0400_0000,    // CLONE_NEWUTS
+        0x0800_0000,    // CLONE_NEWIPC
+        0x1000_0000,    // CLONE_NEWUSER
+        0x2000_0000,    // CLONE_NEWPID
+        0x4000_0000,    // CLONE_NEWNET
+    ] {
+        assert_eq!(
+            seccomp_action(CLONE, 17 | namespace_flag),
+            EPERM,
+            "{:#x}",
+            namespace_flag
+        );
+    }
+    assert_eq!(seccomp_action(CLONE3, 0), ENOSYS);
+    // Not a namespace syscall, it's allowed whatever its arguments
+    assert_eq!(seccomp_action(0, 0x1000_0000), ALLOW);
+}
@@ -0,0 +1,152 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use super::storage_manager::StorageManager;
+
+/// Limits applied to execution logs of a context
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogRetentionPolicy {
+    /// Maximum size of a single execution log, further lines are dropped after a truncation notice
+    pub max_bytes_per_execution: Option<u64>,
+    /// Log files older than this are deleted on cleanup
+    pub max_age: Option<Duration>,
+    /// Only the newest log files of a context are kept on cleanup
+    pub max_files_per_context: Option<usize>,
+}
+
+impl Default for LogRetentionPolicy {
+    fn default() -> Self {
+        Self {
+            max_bytes_per_execution: Some(Self::DEFAULT_MAX_BYTES_PER_EXECUTION),
+            max_age: None,
+            max_files_per_context: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LogCleanupReport {
+    pub removed_files: Vec<PathBuf>,
+    pub freed_bytes: u64,
+    /// Context folders skipped because a live execution uses them
+    pub skipped_in_use: Vec<PathBuf>,
+}
+
+impl LogCleanupReport {
+    fn merge(&mut self, other: LogCleanupReport) {
+        self.removed_files.extend(other.removed_files);
+        self.freed_bytes += other.freed_bytes;
+        self.skipped_in_use.extend(other.skipped_in_use);
+    }
+}
+
+impl LogRetentionPolicy {
+    pub const DEFAULT_MAX_BYTES_PER_EXECUTION: u64 = 10 * 1024 * 1024;
+
+    /// Policy that never truncates nor deletes logs
+    pub fn unlimited() -> Self {
+        Self {
+            max_bytes_per_execution: None,
+            max_age: None,
+            max_files_per_context: None,
+        }
+    }
+
+    /// Deletes the log files of `logs_folder` outside the retention limits, never touching `keep`
+    pub fn cleanup_logs_folder(
+        &self,
+        logs_folder: &Path,
+        keep: &[PathBuf],
+    ) -> anyhow::Result<LogCleanupReport> {
+        let mut report = LogCleanupReport::default();
+        if (self.max_age.is_none() && self.max_files_per_context.is_none()) || !logs_folder.exists()
+        {
+            return Ok(report);
+        }
+
+        let mut files = Vec::new();
+        for entry in std::fs::read_dir(logs_folder)? {
+            let entry = entry?;
+            let path = entry.path();
+            let is_log = path
+                .extension()
+                .is_some_and(|extension| extension == "jsonl" || extension == "log");
+            if !is_log || !entry.file_type()?.is_file() {
+                continue;
+            }
+            let metadata = entry.metadata()?;
+            let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+            files.push((path, modified, metadata.len()));
+        }
+        // Newest first, so the count limit keeps the most recent executions
+        files.sort_by_key(|(_, modified, _)| std::cmp::Reverse(*modified));
+
+        let now = SystemTime::now();
+        let mut kept = 0;
+        for (path, modified, size) in files {
+            let is_protected = keep.iter().any(|keep_path| keep_path == &path);
+            let too_old = self
+                .max_age
+                .is_some_and(|max_age| now.duration_since(modified).unwrap_or_default() > max_age);
+            let too_many = self
+                .max_files_per_context
+                .is_some_and(|max_files| kept >= max_files);
+            if is_protected || (!too_old && !too_many) {
+                kept += 1;
+                continue;
+            }
+            log::info!("removing expired log file {}", path.display());
+            std::fs::remove_file(&path).map_err(|e| {
+                log::error!("failed to remove log file {}: {}", path.display(), e);
+                e
+            })?;
+            report.freed_bytes += size;
+            report.removed_files.push(path);
+        }
+        Ok(report)
+    }
+
+    /// Applies the retention limits to the logs of a context folder, never touching `keep`
+    ///
+    /// The context is skipped while a live execution holds a lease on it, since its log may still
+    /// be written.
+    pub fn cleanup_context_logs(
+        &self,
+        context_folder: &Path,
+        keep: &[PathBuf],
+    ) -> anyhow::Result<LogCleanupReport> {
+        let logs_folder = context_folder.join("logs");
+        if !logs_folder.is_dir() {
+            return Ok(LogCleanupReport::default());
+        }
+        let Some(_lock) = StorageManager::try_lock_context(context_folder)? else {
+            log::info!(
+                "skipping log cleanup of {}, it is in use",
+                context_folder.display()
+            );
+            return Ok(LogCleanupReport {
+                skipped_in_use: vec![context_folder.to_path_buf()],
+                ..Default::default()
+            });
+        };
+        self.cleanup_logs_folder(&logs_folder, keep)
+    }
+
+    /// Applies the retention limits to the logs of every context under an execution storage root
+    pub fn cleanup_storage(&self, storage: &Path) -> anyhow::Result<LogCleanupReport> {
+        let mut report = LogCleanupReport::default();
+        if !storage.exists() {
+            return Ok(report);
+        }
+        for entry in std::fs::read_dir(storage)? {
+            report.merge(self.cleanup_context_logs(&entry?.path(), &[])?);
+        }
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+#[path = "log_retention.test.rs"]
+mod tests;
@@ -0,0 +1,100 @@
+use std::{
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+
+use crate::tools::{log_retention::LogRetentionPolicy, storage_manager::StorageManager};
+
+fn write_log(folder: &std::path::Path, name: &str, age: Duration) -> PathBuf {
+    let path = folder.join(name);
+    std::fs::write(&path, "{}\n").unwrap();
+    let file = std::fs::File::options().write(true).open(&path).unwrap();
+    file.set_modified(SystemTime::now() - age).unwrap();
+    path
+}
+
+#[test]
+fn cleanup_removes_expired_and_extra_logs() {
+    let storage = tempfile::tempdir().unwrap();
+    let logs_folder = storage.path().join("context").join("logs");
+    std::fs::create_dir_all(&logs_folder).unwrap();
+
+    let newest = write_log(&logs_folder, "log_context_a.jsonl", Duration::from_secs(10));
+    let second = write_log(&logs_folder, "log_context_b.jsonl", Duration::from_secs(20));
+    let third = write_log(&logs_folder, "log_context_c.jsonl", Duration::from_secs(30));
+    let expired = write_log(&logs_folder, "log_context_d.log", Duration::from_secs(7200));
+    let other = logs_folder.join("notes.txt");
+    std::fs::write(&other, "not a log").unwrap();
+
+    let policy = LogRetentionPolicy {
+        max_age: Some(Duration::from_secs(3600)),
+        max_files_per_context: Some(2),
+        ..Default::default()
+    };
+    let report = policy.cleanup_storage(storage.path()).unwrap();
+
+    assert!(newest.exists());
+    assert!(second.exists());
+    assert!(!third.exists());
+    assert!(!expired.exists());
+    assert!(other.exists());
+    assert_eq!(report.removed_files.len(), 2);
+    assert_eq!(report.freed_bytes, 6);
+}
+
+#[test]
+fn cleanup_keeps_protected_logs() {
+    let storage = tempfile::tempdir().unwrap();
+    let logs_folder = storage.path().join("logs");
+    std::fs::create_dir_all(&logs_folder).unwrap();
+    let current = write_log(
+        &logs_folder,
+        "log_context_old.jsonl",
+        Duration::from_secs(7200),
+    );
+
+    let policy = LogRetentionPolicy {
+        max_age: Some(Duration::from_secs(60)),
+        ..Default::default()
+    };
+    let report = policy
+        .cleanup_logs_folder(&logs_folder, std::slice::from_ref(&current))
+        .unwrap();
+    assert!(current.exists());
+    assert!(report.removed_files.is_empty());
+
+    let report = LogRetentionPolicy::unlimited()
+        .cleanup_logs_folder(&logs_folder, &[])
+        .unwrap();
+    assert!(current.exists());
+    assert!(report.removed_files.is_empty());
+}
+
+#[test]
+fn cleanup_skips_contexts_in_use() {
+    let storage = tempfile::tempdir().unwrap();
+    let context_folder = storage.path().join("context");
+    let logs_folder = context_folder.join("logs");
+    std::fs::create_dir_all(&logs_folder).unwrap();
+    let running = write_log(
+        &logs_folder,
+        "log_context_running.jsonl",
+        Duration::from_secs(7200),
+    );
+    let policy = LogRetentionPolicy {
+        max_age: Some(Duration::from_secs(60)),
+        ..Default::default()
+    };
+
+    let lease = StorageManager::try_lock_context(&context_folder)
+        .unwrap()
+        .unwrap();
+    let report = policy.cleanup_storage(storage.path()).unwrap();
+    assert!(running.exists());
+    assert_eq!(report.skipped_in_use, vec![context_folder]);
+
+    drop(lease);
+    let report = policy.cleanup_storage(storage.path()).unwrap();
+    assert!(!running.exists());
+    assert!(report.skipped_in_use.is_empty());
+}
@@ -1,18 +1,51 @@
+pub mod bundle;
+pub mod callback_server;
 pub mod check_utils;
 pub mod code_files;
+pub mod container_runtime;
 pub mod container_utils;
+pub mod context_snapshot;
 pub mod deno_execution_storage;
 pub mod deno_runner;
 pub mod deno_runner_options;
+pub mod diagnostics;
+pub mod docker_engine_api;
 pub mod execution_context;
 pub mod execution_error;
+pub mod execution_log;
 pub mod execution_storage;
 mod file_name_utils;
+pub mod host_capabilities;
+pub mod image_manager;
+pub mod linux_sandbox;
+pub mod log_retention;
+pub mod node_execution_storage;
+pub mod node_runner;
+pub mod node_runner_options;
 mod path_buf_ext;
+mod process_output;
 pub mod python_execution_storage;
+pub mod python_file_access;
 pub mod python_runner;
 pub mod python_runner_options;
+pub mod redaction;
 pub mod run_result;
 pub mod runner_type;
+pub mod secret_configurations;
+pub mod shell_runner;
+pub mod shell_runner_options;
 pub mod shinkai_node_location;
+pub mod storage_manager;
+#[cfg(test)]
+mod test_utils;
+pub mod tool_call;
 pub mod tool_definition;
+pub mod tool_package;
+pub mod tool_registry;
+pub mod tool_runner;
+pub mod tool_search_index;
+pub mod tool_signature;
+#[cfg(feature = "wasm")]
+pub mod wasm_runner;
+#[cfg(feature = "wasm")]
+pub mod wasm_runner_options;
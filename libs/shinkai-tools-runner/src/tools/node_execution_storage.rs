@@ -0,0 +1,104 @@
+use std::path::{Path, PathBuf};
+
+use super::{execution_storage::ExecutionStorage, runner_type::RunnerType};
+
+impl ExecutionStorage {
+    pub const NODE_PACKAGE_JSON_FILE_NAME: &'static str = "package.json";
+
+    fn node_cache_folder_path_host(&self) -> PathBuf {
+        self.global_cache_folder_path.join("node-cache-host")
+    }
+    /// The sandbox installs into its own cache, host installs never read what a sandboxed run
+    /// wrote
+    fn node_cache_folder_path_sandbox(&self) -> PathBuf {
+        self.global_cache_folder_path.join("node-cache-sandbox")
+    }
+    fn node_cache_folder_path_docker(&self) -> PathBuf {
+        self.global_cache_folder_path.join("node-cache-docker")
+    }
+    /// Package manager cache, shared by every context
+    pub fn node_cache_folder_path(&self, runner_type: RunnerType) -> PathBuf {
+        match runner_type {
+            RunnerType::Host => self.node_cache_folder_path_host(),
+            RunnerType::Sandbox => self.node_cache_folder_path_sandbox(),
+            RunnerType::Docker => self.node_cache_folder_path_docker(),
+        }
+    }
+    /// Where the `package.json` dependencies are installed, next to the code folder so the
+    /// storage manager evicts it like one
+    pub fn node_dependencies_folder_path(&self) -> PathBuf {
+        let mut folder_name = self
+            .code_folder_path
+            .file_name()
+            .unwrap_or_default()
+            .to_os_string();
+        folder_name.push(".node-dependencies");
+        self.root_code_folder_path.join(folder_name)
+    }
+    /// Written in the dependencies folder once its `node_modules` are installed
+    pub fn node_dependencies_marker_path(&self) -> PathBuf {
+        self.node_dependencies_folder_path()
+            .join(".shinkai-dependencies-installed")
+    }
+    /// Copies the code into the dependencies folder for the package manager and links its
+    /// `node_modules` from the code folder
+    ///
+    /// The code folder is never written by an install, so the code that passed the signature
+    /// check is the code every later run with the same hash gets. The link is relative so it
+    /// resolves inside containers too.
+    pub fn prepare_node_dependencies(&self) -> anyhow::Result<()> {
+        let dependencies_folder_path = self.node_dependencies_folder_path();
+        if !self.node_dependencies_marker_path().exists() {
+            for (path, content) in &self.code_files.files {
+                Self::write_file_atomic(&dependencies_folder_path.join(path), content.as_bytes())?;
+            }
+        }
+        std::fs::create_dir_all(dependencies_folder_path.join("node_modules"))?;
+
+        let link_path = self.code_folder_path.join("node_modules");
+        if link_path.symlink_metadata().is_ok() {
+            return Ok(());
+        }
+        let target = Path::new("..")
+            .join(dependencies_folder_path.file_name().unwrap_or_default())
+            .join("node_modules");
+        #[cfg(unix)]
+        let result = std::os::unix::fs::symlink(&target, &link_path);
+        #[cfg(windows)]
+        let result = std::os::windows::fs::symlink_dir(&target, &link_path);
+        match result {
+            // Linked by a concurrent run
+            Err(e) if e.kind() != std::io::ErrorKind::AlreadyExists => {
+                log::error!("failed to link {}: {}", link_path.display(), e);
+                Err(e.into())
+            }
+            _ => Ok(()),
+        }
+    }
+    pub fn init_for_node(
+        &self,
+        pristine_cache: Option<bool>,
+        runner_type: RunnerType,
+    ) -> anyhow::Result<()> {
+        // Marks the code as ES modules unless the tool brings its own package.json
+        if self
+            .code_files
+            .files
+            .contains_key(Self::NODE_PACKAGE_JSON_FILE_NAME)
+        {
+            self.init(pristine_cache)?;
+        } else {
+            self.init_with_generated_files(
+                pristine_cache,
+                &[(Self::NODE_PACKAGE_JSON_FILE_NAME, r#"{"type":"module"}"#)],
+            )?;
+        }
+
+        log::info!("creating node cache directory");
+        std::fs::create_dir_all(self.node_cache_folder_path(runner_type)).map_err(|e| {
+            log::error!("failed to create node cache directory: {}", e);
+            e
+        })?;
+        Ok(())
+    }
+}
@@ -0,0 +1,807 @@
+use serde_json::Value;
+use std::{
+    collections::{HashMap, HashSet},
+    path::{self, Path, PathBuf},
+    time::Duration,
+};
+
+use crate::tools::{
+    container_runtime::{BindMount, ContainerRuntime},
+    docker_engine_api::{run_in_engine, ContainerSpec},
+    file_name_utils::{adapt_paths_in_value, normalize_for_docker_path},
+    image_manager::ImageManager,
+    path_buf_ext::PathBufExt,
+    process_output::{parse_code_result, run_step, wait_for_output},
+    runner_type::resolve_runner_type_for_policy,
+    secret_configurations::SecretConfigurations,
+};
+
+use super::{
+    code_files::CodeFiles,
+    deno_runner::BROWSER_EXECUTABLE_PATHS,
+    execution_error::{ExecutionError, InfrastructureError},
+    execution_storage::ExecutionStorage,
+    node_runner_options::{JsRuntime, NodeRunnerOptions},
+    run_result::RunResult,
+    runner_type::RunnerType,
+};
+
+/// Runs JavaScript tools with Node.js or Bun instead of Deno
+///
+/// The entrypoint is an ES module exporting `run(configurations, parameters)`, a `package.json`
+/// in the code files is honored and its dependencies are installed once per code folder.
+#[derive(Default)]
+pub struct NodeRunner {
+    code: CodeFiles,
+    configurations: Value,
+    options: NodeRunnerOptions,
+}
+
+impl NodeRunner {
+    pub const ENTRYPOINT_WRAPPER_FILE_NAME: &'static str = "__shinkai_entrypoint.mjs";
+
+    pub fn new(
+        code_files: CodeFiles,
+        configurations: Value,
+        options: Option<NodeRunnerOptions>,
+    ) -> Self {
+        let options = options.unwrap_or_default();
+        NodeRunner {
+            code: code_files,
+            configurations,
+            options,
+        }
+    }
+
+    pub async fn run(
+        &self,
+        envs: Option<HashMap<String, String>>,
+        parameters: Value,
+        max_execution_timeout: Option<Duration>,
+    ) -> Result<RunResult, ExecutionError> {
+        log::info!("preparing to run tool with {:?}", self.options.js_runtime);
+        self.options
+            .trust_store
+            .verify_code_files(&self.code, self.options.code_signature.as_ref())?;
+        if !self.code.files.contains_key(&self.code.entrypoint) {
+            return Err(ExecutionError::new(
+                format!("no entrypoint found {}", self.code.entrypoint),
+                None,
+            ));
+        }
+        let (public_configurations, secrets) = SecretConfigurations::split(
+            &self.configurations,
+            &self.options.secret_configuration_keys,
+        );
+        let secrets = secrets.with_redaction_rules(&self.options.redaction_rules);
+        log::info!("configurations: {}", secrets.masked(&self.configurations));
+        log::info!(
+            "parameters: {}",
+            secrets.redactor().redact(&parameters.to_string())
+        );
+
+        let resolved_runner_type = resolve_runner_type_for_policy(
+            self.options.force_runner_type.clone(),
+            &self.options.runner_policy,
+            self.options.container_runtime.as_ref(),
+        );
+        let (output, metadata) = self
+            .options
+            .runner_policy
+            .run_with_fallback(
+                self.options.force_runner_type.as_ref(),
+                resolved_runner_type,
+                |runner_type| {
+                    let envs = envs.clone();
+                    let (parameters, public_configurations, secrets) =
+                        (&parameters, &public_configurations, &secrets);
+                    async move {
+                        self.run_with_runner_type(
+                            &runner_type,
+                            envs,
+                            parameters,
+                            public_configurations,
+                            secrets,
+                            max_execution_timeout,
+                        )
+                        .await
+                    }
+                },
+            )
+            .await?;
+        Ok(RunResult {
+            data: parse_code_result(&output, secrets.redactor())?,
+            metadata,
+        })
+    }
+
+    /// The code files plus a wrapper module that reads the inputs file, calls `run` and prints
+    /// the result between the result markers
+    ///
+    /// The wrapper doesn't embed the inputs, so the code folder and its `node_modules` are
+    /// reused across executions.
+    fn wrapped_code(&self, secrets: &SecretConfigurations) -> CodeFiles {
+        let mut code = self.code.clone();
+        code.files.insert(
+            Self::ENTRYPOINT_WRAPPER_FILE_NAME.to_string(),
+            format!(
+                r#"
+import {{ readFileSync }} from "node:fs";
+import * as tool from "./{entrypoint}";
+
+const inputs = JSON.parse(readFileSync(process.env.{inputs_file_env}, "utf8"));
+const configurations = inputs.configurations;
+{merge_code}
+const parameters = inputs.parameters;
+
+const run = tool.run ?? tool.default?.run;
+if (typeof run !== "function") {{
+    throw new Error("{entrypoint} doesn't export a run function");
+}}
+const result = await run(configurations, parameters);
+const adaptedResult = result === undefined ? null : result;
+console.log("<shinkai-code-result>");
+console.log(JSON.stringify(adaptedResult));
+console.log("</shinkai-code-result>");
+process.exit(0);
+"#,
+                entrypoint = self.code.entrypoint.replace("\\", "/"),
+                inputs_file_env = ExecutionStorage::INPUTS_FILE_ENV,
+                merge_code = secrets.node_merge_code(),
+            ),
+        );
+        code.entrypoint = Self::ENTRYPOINT_WRAPPER_FILE_NAME.to_string();
+        code
+    }
+
+    /// Whether the code brings a `package.json` declaring dependencies
+    fn has_dependencies(&self) -> bool {
+        self.code
+            .files
+            .get(ExecutionStorage::NODE_PACKAGE_JSON_FILE_NAME)
+            .and_then(|package_json| serde_json::from_str::<Value>(package_json).ok())
+            .and_then(|package_json| {
+                package_json["dependencies"]
+                    .as_object()
+                    .map(|dependencies| !dependencies.is_empty())
+            })
+            .unwrap_or(false)
+    }
+
+    /// Runtime arguments before the wrapper module
+    fn runtime_args(&self) -> Vec<String> {
+        match self.options.js_runtime {
+            JsRuntime::Node
+                if [".ts", ".mts", ".cts"]
+                    .iter()
+                    .any(|extension| self.code.entrypoint.ends_with(extension)) =>
+            {
+                vec![String::from("--experimental-strip-types")]
+            }
+            JsRuntime::Node => vec![],
+            JsRuntime::Bun => vec![String::from("run")],
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn run_with_runner_type(
+        &self,
+        resolved_runner_type: &RunnerType,
+        envs: Option<HashMap<String, String>>,
+        parameters: &Value,
+        public_configurations: &Value,
+        secrets: &SecretConfigurations,
+        max_execution_timeout: Option<Duration>,
+    ) -> Result<Vec<String>, ExecutionError> {
+        let mut adapted_configurations = public_configurations.clone();
+        let mut adapted_parameters = parameters.clone();
+        if !self.options.context.mount_files.is_empty()
+            && matches!(resolved_runner_type, RunnerType::Docker)
+        {
+            let mount_files = self
+                .options
+                .context
+                .mount_files
+                .iter()
+                .map(|p| path::absolute(p).unwrap().to_string_lossy().to_string())
+                .collect::<HashSet<String>>();
+            adapted_configurations = adapt_paths_in_value(&adapted_configurations, &mount_files);
+            adapted_parameters = adapt_paths_in_value(&adapted_parameters, &mount_files);
+        }
+
+        let code = self.wrapped_code(secrets);
+        match resolved_runner_type {
+            RunnerType::Host | RunnerType::Sandbox => {
+                self.run_in_host(
+                    resolved_runner_type,
+                    code,
+                    envs,
+                    (&adapted_configurations, &adapted_parameters),
+                    secrets,
+                    max_execution_timeout,
+                )
+                .await
+            }
+            RunnerType::Docker => {
+                self.run_in_docker(
+                    code,
+                    envs,
+                    (&adapted_configurations, &adapted_parameters),
+                    secrets,
+                    max_execution_timeout,
+                )
+                .await
+            }
+        }
+        .map_err(|e| {
+            ExecutionError::from_runner_error(secrets.redactor().redact(&e.to_string()), &e)
+        })
+    }
+
+    async fn run_in_docker(
+        &self,
+        code_files: CodeFiles,
+        envs: Option<HashMap<String, String>>,
+        (configurations, parameters): (&Value, &Value),
+        secrets: &SecretConfigurations,
+        max_execution_timeout: Option<Duration>,
+    ) -> anyhow::Result<Vec<String>> {
+        let image_name = self.options.docker_image_name();
+        log::info!(
+            "using {:?} from container image: {}",
+            self.options.js_runtime,
+            image_name
+        );
+
+        let execution_storage = ExecutionStorage::new(code_files, self.options.context.clone())
+            .with_redactor(secrets.redactor().clone());
+        let _lease = execution_storage.acquire_lease()?;
+        execution_storage.init_for_node(None, RunnerType::Docker)?;
+        if self.has_dependencies() {
+            execution_storage.prepare_node_dependencies()?;
+        }
+        let home = execution_storage.prepare_home()?;
+        let inputs = execution_storage.write_inputs(configurations, parameters)?;
+
+        let container_runtime = self
+            .options
+            .container_runtime
+            .clone()
+            .or_else(ContainerRuntime::detect)
+            .unwrap_or_default();
+        log::info!("using container runtime: {:?}", container_runtime);
+        // Pulled here so a missing image doesn't eat the execution timeout
+        let image = ImageManager::new(container_runtime.clone())
+            .prepare(&image_name, self.options.docker_image_digest.as_deref())
+            .await
+            .map_err(|e| InfrastructureError(format!("failed to prepare image: {}", e)))?;
+
+        let node_cache_folder_path = execution_storage.node_cache_folder_path(RunnerType::Docker);
+        let inputs_file_path = format!(
+            "/app/{}",
+            execution_storage.relative_to_root(inputs.path().to_path_buf())
+        );
+        let mut mounts = vec![
+            BindMount {
+                source: execution_storage.code_folder_path.as_normalized_string(),
+                target: format!(
+                    "/app/{}",
+                    execution_storage.relative_to_root(execution_storage.code_folder_path.clone())
+                ),
+                readonly: true,
+            },
+            BindMount {
+                source: node_cache_folder_path.as_normalized_string(),
+                target: format!(
+                    "/app/{}",
+                    execution_storage.relative_to_global_cache(node_cache_folder_path.clone())
+                ),
+                readonly: false,
+            },
+            BindMount {
+                source: execution_storage.home_folder_path.as_normalized_string(),
+                target: String::from("/app/home"),
+                readonly: false,
+            },
+            BindMount {
+                source: inputs.path().to_path_buf().as_normalized_string(),
+                target: inputs_file_path.clone(),
+                readonly: true,
+            },
+        ];
+
+        let needs_install =
+            self.has_dependencies() && !execution_storage.node_dependencies_marker_path().exists();
+        if self.has_dependencies() {
+            let dependencies_folder_path = execution_storage.node_dependencies_folder_path();
+            mounts.push(BindMount {
+                source: dependencies_folder_path.as_normalized_string(),
+                target: format!(
+                    "/app/{}",
+                    execution_storage.relative_to_root(dependencies_folder_path.clone())
+                ),
+                // Only the run installing the dependencies writes them
+                readonly: !needs_install,
+            });
+        }
+
+        let mut mount_env = String::from("");
+        log::info!("mount files: {:?}", self.options.context.mount_files);
+        // Mounted at the same path so the code sees the same paths as in the host
+        for file in &self.options.context.mount_files {
+            let path = normalize_for_docker_path(file.to_path_buf());
+            mount_env += &format!("{},", path);
+            mounts.push(BindMount {
+                source: path.clone(),
+                target: path,
+                readonly: false,
+            });
+        }
+
+        let mut mount_assets_env = String::from("");
+        for file in &self.options.context.assets_files {
+            let target_path = format!(
+                "/app/{}/{}",
+                execution_storage.relative_to_root(execution_storage.assets_folder_path.clone()),
+                file.file_name().unwrap().to_str().unwrap()
+            );
+            mount_assets_env += &format!("{},", target_path);
+            mounts.push(BindMount {
+                source: path::absolute(file).unwrap().as_normalized_string(),
+                target: target_path,
+                readonly: true,
+            });
+        }
+
+        let node_cache_env = format!(
+            "/app/{}",
+            execution_storage.relative_to_global_cache(node_cache_folder_path.clone())
+        );
+        let mut container_envs = vec![
+            String::from("NO_COLOR=true"),
+            format!("npm_config_cache={}", node_cache_env),
+            format!("BUN_INSTALL_CACHE_DIR={}", node_cache_env),
+            format!(
+                "SHINKAI_NODE_LOCATION={}://host.docker.internal:{}",
+                self.options.shinkai_node_location.protocol,
+                self.options.shinkai_node_location.port
+            ),
+            String::from("SHINKAI_HOME=/app/home"),
+            format!("SHINKAI_ASSETS={}", mount_assets_env),
+            format!("SHINKAI_MOUNT={}", mount_env),
+            format!("SHINKAI_CONTEXT_ID={}", self.options.context.context_id),
+            format!("SHINKAI_EXECUTION_ID={}", self.options.context.execution_id),
+            format!("{}={}", ExecutionStorage::INPUTS_FILE_ENV, inputs_file_path),
+        ];
+        if let Some(envs) = envs {
+            for (key, value) in envs {
+                container_envs.push(format!("{}={}", key, value));
+            }
+        }
+
+        let (binary, install) = match self.options.js_runtime {
+            JsRuntime::Node => (
+                "node",
+                "npm install --omit=dev --ignore-scripts --no-audit --no-fund",
+            ),
+            JsRuntime::Bun => ("bun", "bun install --production --ignore-scripts"),
+        };
+        let mut start_script = String::new();
+        if needs_install {
+            let dependencies_folder = shell_quote(&format!(
+                "/app/{}",
+                execution_storage
+                    .relative_to_root(execution_storage.node_dependencies_folder_path())
+            ));
+            let marker = shell_quote(&format!(
+                "/app/{}",
+                execution_storage
+                    .relative_to_root(execution_storage.node_dependencies_marker_path())
+            ));
+            start_script += &format!(
+                "[ -f {marker} ] || (cd {dependencies_folder} && {install} >&2 && touch {marker}) || exit 1; ",
+            );
+        }
+        start_script += &format!(
+            "exec {} {}",
+            binary,
+            self.runtime_args()
+                .into_iter()
+                .chain([execution_storage
+                    .relative_to_root(execution_storage.code_entrypoint_file_path.clone())])
+                .map(|arg| shell_quote(&arg))
+                .collect::<Vec<_>>()
+                .join(" ")
+        );
+        let container_command = vec![String::from("sh"), String::from("-c"), start_script];
+
+        if let Some(engine_client) = container_runtime.engine_client() {
+            let mut env = container_envs;
+            // Sent over the engine socket, it never shows up in a process command line
+            if !secrets.is_empty() {
+                env.push(format!(
+                    "{}={}",
+                    SecretConfigurations::ENV,
+                    secrets.env_value()
+                ));
+            }
+            let spec = ContainerSpec {
+                image,
+                cmd: container_command,
+                env,
+                working_dir: Some(String::from("/app")),
+                mounts,
+            };
+            let stdout = run_in_engine(
+                &engine_client,
+                &spec,
+                &execution_storage,
+                binary,
+                max_execution_timeout,
+            )
+            .await?;
+            home.succeeded()?;
+            return Ok(stdout);
+        }
+
+        let mut command = container_runtime.command();
+        let mut args = vec![String::from("run"), String::from("--rm")];
+        args.extend(container_runtime.run_args());
+        for mount in &mounts {
+            args.extend([String::from("--mount"), container_runtime.bind_mount(mount)]);
+        }
+        for env in container_envs {
+            args.extend([String::from("-e"), env]);
+        }
+        // Passed by name so the value is read from the docker cli environment and never shows up in its arguments
+        if !secrets.is_empty() {
+            args.extend([String::from("-e"), SecretConfigurations::ENV.to_string()]);
+        }
+        args.extend([String::from("--workdir"), String::from("/app"), image]);
+        args.extend(container_command);
+        let command = command
+            .args(args)
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .kill_on_drop(true);
+        if !secrets.is_empty() {
+            command.env(SecretConfigurations::ENV, secrets.env_value());
+        }
+
+        log::info!(
+            "spawning docker command: {}",
+            execution_storage.redactor.redact(&format!("{:?}", command))
+        );
+        let child = command.spawn().map_err(|e| {
+            let error_msg = execution_storage.redactor.redact(&format!(
+                "failed to spawn command: {:?}, error: {}",
+                command, e
+            ));
+            log::error!("{}", error_msg);
+            InfrastructureError(error_msg)
+        })?;
+        let (status, stdout, stderr) =
+            wait_for_output(child, &execution_storage, binary, max_execution_timeout).await?;
+        if !status.success() {
+            let stderr = execution_storage.redactor.redact(&stderr.join("\n"));
+            log::error!("command execution failed: {}", stderr);
+            // The container never ran the tool, e.g. a rejected mount or a missing runtime
+            if InfrastructureError::is_infrastructure_exit_code(status.code()) {
+                return Err(InfrastructureError(stderr).into());
+            }
+            return Err(anyhow::Error::new(std::io::Error::other(stderr)));
+        }
+        home.succeeded()?;
+        Ok(stdout)
+    }
+
+    /// Runs with the host binary, inside the Linux sandbox for `RunnerType::Sandbox`
+    async fn run_in_host(
+        &self,
+        runner_type: &RunnerType,
+        code_files: CodeFiles,
+        envs: Option<HashMap<String, String>>,
+        (configurations, parameters): (&Value, &Value),
+        secrets: &SecretConfigurations,
+        max_execution_timeout: Option<Duration>,
+    ) -> anyhow::Result<Vec<String>> {
+        let execution_storage = ExecutionStorage::new(code_files, self.options.context.clone())
+            .with_redactor(secrets.redactor().clone());
+        let _lease = execution_storage.acquire_lease()?;
+        execution_storage.init_for_node(None, runner_type.clone())?;
+        if self.has_dependencies() {
+            execution_storage.prepare_node_dependencies()?;
+        }
+        let home = execution_storage.prepare_home()?;
+        let inputs = execution_storage.write_inputs(configurations, parameters)?;
+
+        let binary_path = path::absolute(self.options.binary_path())?;
+        log::info!(
+            "using {:?} from host at path: {}",
+            self.options.js_runtime,
+            binary_path.display()
+        );
+        let node_cache_folder_path = execution_storage.node_cache_folder_path(runner_type.clone());
+        if self.has_dependencies() && !execution_storage.node_dependencies_marker_path().exists() {
+            self.install_dependencies(
+                runner_type,
+                &binary_path,
+                &execution_storage,
+                &node_cache_folder_path,
+            )
+            .await?;
+        }
+
+        let mut command = match runner_type {
+            RunnerType::Sandbox => {
+                let mut mounts =
+                    execution_storage.sandbox_mounts(std::slice::from_ref(&node_cache_folder_path));
+                if self.has_dependencies() {
+                    let dependencies_folder_path = execution_storage
+                        .node_dependencies_folder_path()
+                        .to_string_lossy()
+                        .to_string();
+                    mounts.push(BindMount {
+                        source: dependencies_folder_path.clone(),
+                        target: dependencies_folder_path,
+                        readonly: true,
+                    });
+                }
+                let inputs_file_path = inputs.path().to_string_lossy().to_string();
+                mounts.push(BindMount {
+                    source: inputs_file_path.clone(),
+                    target: inputs_file_path,
+                    readonly: true,
+                });
+                self.options.sandbox.command(
+                    &binary_path,
+                    &mounts,
+                    &execution_storage.root_folder_path,
+                )?
+            }
+            _ => tokio::process::Command::new(&binary_path),
+        };
+        if matches!(runner_type, RunnerType::Host)
+            && matches!(self.options.js_runtime, JsRuntime::Node)
+            && self.options.restrict_host_file_access
+        {
+            command.args(self.host_permission_args(&execution_storage, &binary_path));
+        }
+        let command = command
+            .args(self.runtime_args())
+            .arg(execution_storage.code_entrypoint_file_path.clone())
+            .current_dir(execution_storage.root_folder_path.clone())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .kill_on_drop(true);
+
+        command.env("NO_COLOR", "true");
+        command.env("npm_config_cache", &node_cache_folder_path);
+        command.env("BUN_INSTALL_CACHE_DIR", &node_cache_folder_path);
+        // The host home isn't visible in the sandbox
+        if matches!(runner_type, RunnerType::Sandbox) {
+            command.env("HOME", execution_storage.home_folder_path.clone());
+        }
+        command.env(
+            "SHINKAI_NODE_LOCATION",
+            format!(
+                "{}://{}:{}",
+                self.options.shinkai_node_location.protocol,
+                self.options.shinkai_node_location.host,
+                self.options.shinkai_node_location.port
+            ),
+        );
+        command.env("SHINKAI_HOME", execution_storage.home_folder_path.clone());
+        command.env(
+            "SHINKAI_ASSETS",
+            self.options
+                .context
+                .assets_files
+                .iter()
+                .map(|p| path::absolute(p).unwrap().to_string_lossy().to_string())
+                .collect::<Vec<_>>()
+                .join(","),
+        );
+        command.env(
+            "SHINKAI_MOUNT",
+            self.options
+                .context
+                .mount_files
+                .iter()
+                .map(|p| path::absolute(p).unwrap().to_string_lossy().to_string())
+                .collect::<Vec<_>>()
+                .join(","),
+        );
+        command.env(
+            "SHINKAI_CONTEXT_ID",
+            self.options.context.context_id.clone(),
+        );
+        command.env(
+            "SHINKAI_EXECUTION_ID",
+            self.options.context.execution_id.clone(),
+        );
+        command.env(ExecutionStorage::INPUTS_FILE_ENV, inputs.path());
+        if let Some(envs) = envs {
+            command.envs(envs);
+        }
+        if !secrets.is_empty() {
+            command.env(SecretConfigurations::ENV, secrets.env_value());
+        }
+        log::info!(
+            "prepared command with arguments: {}",
+            execution_storage.redactor.redact(&format!("{:?}", command))
+        );
+        let child = command.spawn().map_err(|e| {
+            let error_msg = execution_storage.redactor.redact(&format!(
+                "failed to spawn command: {:?} error: {}",
+                command, e
+            ));
+            log::error!("{}", error_msg);
+            anyhow::anyhow!("{}", error_msg)
+        })?;
+        let (status, stdout, stderr) = wait_for_output(
+            child,
+            &execution_storage,
+            &format!("{:?}", self.options.js_runtime).to_lowercase(),
+            max_execution_timeout,
+        )
+        .await?;
+        if !status.success() {
+            let stderr = execution_storage.redactor.redact(&stderr.join("\n"));
+            log::error!("command execution failed: {}", stderr);
+            return Err(anyhow::Error::new(std::io::Error::other(stderr)));
+        }
+        home.succeeded()?;
+        Ok(stdout)
+    }
+
+    /// Node permission model flags, the same paths Deno host runs get
+    fn host_permission_args(
+        &self,
+        execution_storage: &ExecutionStorage,
+        binary_path: &Path,
+    ) -> Vec<String> {
+        let absolute = |path: &PathBuf| path::absolute(path).unwrap_or(path.clone());
+        let temp_dir = std::env::temp_dir();
+        let mut read = vec![
+            absolute(&execution_storage.root_folder_path),
+            binary_path.to_path_buf(),
+            PathBuf::from("/tmp"),
+            PathBuf::from("/var/folders"),
+            temp_dir.clone(),
+        ];
+        read.extend(BROWSER_EXECUTABLE_PATHS.iter().map(PathBuf::from));
+        read.extend(self.options.context.assets_files.iter().map(absolute));
+        read.extend(self.options.context.mount_files.iter().map(absolute));
+        let mut write = vec![
+            absolute(&execution_storage.home_folder_path),
+            PathBuf::from("/tmp"),
+            PathBuf::from("/var/folders"),
+            temp_dir,
+        ];
+        write.extend(self.options.context.mount_files.iter().map(absolute));
+        // Node aborts on a path given twice, e.g. when the temp dir is /tmp
+        for paths in [&mut read, &mut write] {
+            paths.sort();
+            paths.dedup();
+        }
+
+        let mut args = [
+            "--experimental-permission",
+            "--allow-child-process",
+            "--allow-worker",
+            "--allow-addons",
+            "--allow-wasi",
+        ]
+        .map(String::from)
+        .to_vec();
+        args.extend(
+            read.iter()
+                .map(|path| format!("--allow-fs-read={}", path.to_string_lossy())),
+        );
+        args.extend(
+            write
+                .iter()
+                .map(|path| format!("--allow-fs-write={}", path.to_string_lossy())),
+        );
+        log::info!("node permissions: {}", args.join(" "));
+        args
+    }
+
+    /// Installs the `package.json` dependencies into the dependencies folder
+    ///
+    /// Lifecycle scripts never run, and Sandbox runs install inside the sandbox with network
+    /// access to reach the registry. The code folder isn't reachable from the install.
+    async fn install_dependencies(
+        &self,
+        runner_type: &RunnerType,
+        binary_path: &Path,
+        execution_storage: &ExecutionStorage,
+        cache_folder_path: &Path,
+    ) -> anyhow::Result<()> {
+        let npm_cli_path = npm_cli_path(binary_path);
+        let dependencies_folder_path = execution_storage.node_dependencies_folder_path();
+        let mut command = match runner_type {
+            RunnerType::Sandbox => {
+                let bind = |path: &Path, readonly: bool| {
+                    let path = path::absolute(path)
+                        .unwrap_or(path.to_path_buf())
+                        .to_string_lossy()
+                        .to_string();
+                    BindMount {
+                        source: path.clone(),
+                        target: path,
+                        readonly,
+                    }
+                };
+                let mut mounts = vec![
+                    bind(&dependencies_folder_path, false),
+                    bind(cache_folder_path, false),
+                    bind(&execution_storage.home_folder_path, false),
+                ];
+                if let Some(npm_folder_path) = npm_cli_path.parent().and_then(Path::parent) {
+                    mounts.push(bind(npm_folder_path, true));
+                }
+                let mut command = self.options.sandbox.fetch_command(
+                    binary_path,
+                    &mounts,
+                    &dependencies_folder_path,
+                )?;
+                // The host home isn't visible in the sandbox
+                command.env("HOME", execution_storage.home_folder_path.clone());
+                command
+            }
+            _ => tokio::process::Command::new(binary_path),
+        };
+        match self.options.js_runtime {
+            JsRuntime::Node => command
+                .arg(&npm_cli_path)
+                .args([
+                    "install",
+                    "--omit=dev",
+                    "--ignore-scripts",
+                    "--no-audit",
+                    "--no-fund",
+                ])
+                .env("npm_config_cache", cache_folder_path),
+            JsRuntime::Bun => command
+                .args(["install", "--production", "--ignore-scripts"])
+                .env("BUN_INSTALL_CACHE_DIR", cache_folder_path),
+        };
+        log::info!(
+            "installing dependencies in {}",
+            dependencies_folder_path.display()
+        );
+        run_step(
+            command.current_dir(&dependencies_folder_path),
+            "install dependencies",
+        )
+        .await?;
+        std::fs::write(execution_storage.node_dependencies_marker_path(), "")?;
+        Ok(())
+    }
+}
+
+/// Single quotes `value` for `sh`
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// npm bundled with the node binary, `bin/node` and `lib/node_modules` on unix, side by side on windows
+fn npm_cli_path(node_binary_path: &Path) -> PathBuf {
+    let folder = node_binary_path.parent().unwrap_or(Path::new("."));
+    let modules_folder = if folder.ends_with("bin") {
+        folder
+            .parent()
+            .unwrap_or(folder)
+            .join("lib")
+            .join("node_modules")
+    } else {
+        folder.join("node_modules")
+    };
+    modules_folder.join("npm").join("bin").join("npm-cli.js")
+}
+
+#[cfg(test)]
+#[path = "node_runner.test.rs"]
+mod tests;
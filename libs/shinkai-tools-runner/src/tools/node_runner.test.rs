@@ -0,0 +1,476 @@
+use rstest::rstest;
+use serde_json::json;
+
+#[cfg(unix)]
+use crate::tools::test_utils::{fake_binary, fake_docker};
+use crate::tools::{
+    code_files::CodeFiles,
+    execution_context::ExecutionContext,
+    execution_storage::ExecutionStorage,
+    node_runner::NodeRunner,
+    node_runner_options::{JsRuntime, NodeRunnerOptions},
+    runner_type::RunnerType,
+};
+
+use std::{collections::HashMap, path::PathBuf};
+
+/// The system node, the managed one is only downloaded with the `node` feature
+fn system_node() -> Option<PathBuf> {
+    let output = std::process::Command::new("node")
+        .args(["-p", "process.execPath"])
+        .output()
+        .ok()?;
+    output
+        .status
+        .success()
+        .then(|| PathBuf::from(String::from_utf8_lossy(&output.stdout).trim()))
+}
+
+#[tokio::test]
+async fn run_tool_with_host_node() {
+    let _ = env_logger::builder()
+        .filter_level(log::LevelFilter::Info)
+        .is_test(true)
+        .try_init();
+    let Some(node_binary_path) = system_node() else {
+        log::warn!("node not found, skipping");
+        return;
+    };
+    let folder = tempfile::tempdir().unwrap();
+    let code_files = CodeFiles {
+        files: HashMap::from([(
+            "main.js".to_string(),
+            r#"
+                import { join } from "node:path";
+                export async function run(configurations, parameters) {
+                    return {
+                        message: `hello ${parameters.name}`,
+                        key: configurations.key,
+                        secret: configurations.secret,
+                        home: process.env.SHINKAI_HOME,
+                        joined: join("a", "b"),
+                    };
+                }
+            "#
+            .to_string(),
+        )]),
+        entrypoint: "main.js".to_string(),
+    };
+    let context = ExecutionContext {
+        storage: folder.path().join("storage"),
+        ..Default::default()
+    };
+    let node_runner = NodeRunner::new(
+        code_files,
+        json!({ "key": "public", "secret": "s3cr3t" }),
+        Some(NodeRunnerOptions {
+            context: context.clone(),
+            binary_path: Some(node_binary_path),
+            force_runner_type: Some(RunnerType::Host),
+            secret_configuration_keys: vec![String::from("secret")],
+            ..Default::default()
+        }),
+    );
+
+    let result = node_runner
+        .run(None, json!({ "name": "world" }), None)
+        .await
+        .unwrap();
+    let storage = ExecutionStorage::new(Default::default(), context);
+    assert_eq!(result.data["message"], "hello world");
+    assert_eq!(result.data["key"], "public");
+    assert_eq!(result.data["secret"], "s3cr3t");
+    assert_eq!(result.data["joined"], "a/b");
+    assert_eq!(
+        result.data["home"],
+        storage.home_folder_path.to_string_lossy().to_string()
+    );
+    assert_eq!(result.metadata.runner_type, Some(RunnerType::Host));
+    assert!(!storage.inputs_file_path().exists());
+}
+
+#[tokio::test]
+async fn installs_package_json_dependencies_once() {
+    let Some(node_binary_path) = system_node() else {
+        log::warn!("node not found, skipping");
+        return;
+    };
+    let folder = tempfile::tempdir().unwrap();
+    // A local dependency so npm doesn't need the registry
+    let code_files = CodeFiles {
+        files: HashMap::from([
+            (
+                "package.json".to_string(),
+                r#"{
+                    "type": "module",
+                    "dependencies": { "greeter": "file:./greeter" },
+                    "scripts": { "postinstall": "echo ran > postinstall-ran" }
+                }"#
+                .to_string(),
+            ),
+            (
+                "greeter/package.json".to_string(),
+                r#"{ "name": "greeter", "version": "1.0.0", "type": "module", "main": "index.js" }"#
+                    .to_string(),
+            ),
+            (
+                "greeter/index.js".to_string(),
+                r#"export const greet = (name) => `hi ${name}`;"#.to_string(),
+            ),
+            (
+                "main.js".to_string(),
+                r#"
+                    import { greet } from "greeter";
+                    export const run = (_, parameters) => ({ message: greet(parameters.name) });
+                "#
+                .to_string(),
+            ),
+        ]),
+        entrypoint: "main.js".to_string(),
+    };
+    let context = ExecutionContext {
+        storage: folder.path().join("storage"),
+        ..Default::default()
+    };
+    let node_runner = NodeRunner::new(
+        code_files,
+        json!({}),
+        Some(NodeRunnerOptions {
+            context: context.clone(),
+            binary_path: Some(node_binary_path),
+            force_runner_type: Some(RunnerType::Host),
+            ..Default::default()
+        }),
+    );
+
+    let result = node_runner
+        .run(None, json!({ "name": "node" }), None)
+        .await
+        .unwrap();
+    assert_eq!(result.data["message"], "hi node");
+
+    let marker = std::fs::read_dir(folder.path().join("storage"))
+        .unwrap()
+        .flat_map(|context_folder| {
+            std::fs::read_dir(context_folder.unwrap().path().join("code"))
+                .into_iter()
+                .flatten()
+        })
+        .map(|code_folder| {
+            code_folder
+                .unwrap()
+                .path()
+                .join(".shinkai-dependencies-installed")
+        })
+        .find(|marker| marker.exists())
+        .expect("dependencies marker");
+    // Lifecycle scripts never run
+    assert!(!marker.with_file_name("postinstall-ran").exists());
+    // The install never writes into the code folder, it links the installed modules
+    let code_folder = marker.parent().unwrap().with_extension("");
+    assert!(code_folder
+        .join("node_modules")
+        .symlink_metadata()
+        .unwrap()
+        .file_type()
+        .is_symlink());
+    assert!(!code_folder.join("package-lock.json").exists());
+    let installed_at = std::fs::metadata(&marker).unwrap().modified().unwrap();
+    node_runner
+        .run(None, json!({ "name": "again" }), None)
+        .await
+        .unwrap();
+    assert_eq!(
+        std::fs::metadata(&marker).unwrap().modified().unwrap(),
+        installed_at
+    );
+}
+
+#[tokio::test]
+async fn host_node_only_reaches_execution_paths() {
+    let Some(node_binary_path) = system_node() else {
+        log::warn!("node not found, skipping");
+        return;
+    };
+    let folder = tempfile::tempdir().unwrap();
+    // Outside of the temp dir, which host runs can read
+    let outside_folder = tempfile::tempdir_in(".").unwrap();
+    let outside_file = outside_folder.path().join("outside.txt");
+    std::fs::write(&outside_file, "private").unwrap();
+    let mount_file = folder.path().join("mounted.txt");
+    std::fs::write(&mount_file, "mounted").unwrap();
+    let code_files = CodeFiles {
+        files: HashMap::from([(
+            "main.js".to_string(),
+            r#"
+                import { readFileSync, writeFileSync } from "node:fs";
+                export async function run(_, parameters) {
+                    writeFileSync(`${process.env.SHINKAI_HOME}/out.txt`, "written");
+                    writeFileSync(parameters.mount, "changed");
+                    let outside;
+                    try {
+                        outside = readFileSync(parameters.outside, "utf8");
+                    } catch (e) {
+                        outside = e.code;
+                    }
+                    return { outside };
+                }
+            "#
+            .to_string(),
+        )]),
+        entrypoint: "main.js".to_string(),
+    };
+    let context = ExecutionContext {
+        storage: folder.path().join("storage"),
+        mount_files: vec![mount_file.clone()],
+        ..Default::default()
+    };
+    let node_runner = NodeRunner::new(
+        code_files,
+        json!({}),
+        Some(NodeRunnerOptions {
+            context: context.clone(),
+            binary_path: Some(node_binary_path),
+            force_runner_type: Some(RunnerType::Host),
+            ..Default::default()
+        }),
+    );
+
+    let result = node_runner
+        .run(
+            None,
+            json!({ "outside": outside_file, "mount": mount_file }),
+            None,
+        )
+        .await
+        .unwrap();
+    assert_eq!(result.data["outside"], "ERR_ACCESS_DENIED");
+    assert_eq!(std::fs::read_to_string(&mount_file).unwrap(), "changed");
+    let storage = ExecutionStorage::new(Default::default(), context);
+    assert!(storage.home_folder_path.join("out.txt").exists());
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn sandbox_installs_dependencies_inside_the_sandbox() {
+    use crate::tools::linux_sandbox::LinuxSandbox;
+
+    let Some(node_binary_path) = system_node() else {
+        log::warn!("node not found, skipping");
+        return;
+    };
+    let folder = tempfile::tempdir().unwrap();
+    // Records each call on a line, then runs the program after `--`
+    let bwrap_binary_path = fake_binary(
+        folder.path(),
+        "bwrap",
+        &format!(
+            r#"echo "$@" >> "{folder}/bwrap-args"
+while [ "$1" != "--" ]; do shift; done
+shift
+exec "$@""#,
+            folder = folder.path().display()
+        ),
+    );
+    let code_files = CodeFiles {
+        files: HashMap::from([
+            (
+                "package.json".to_string(),
+                r#"{ "type": "module", "dependencies": { "greeter": "file:./greeter" } }"#
+                    .to_string(),
+            ),
+            (
+                "greeter/package.json".to_string(),
+                r#"{ "name": "greeter", "version": "1.0.0", "type": "module", "main": "index.js" }"#
+                    .to_string(),
+            ),
+            (
+                "greeter/index.js".to_string(),
+                r#"export const greet = (name) => `hi ${name}`;"#.to_string(),
+            ),
+            (
+                "main.js".to_string(),
+                r#"
+                    import { greet } from "greeter";
+                    export const run = (_, parameters) => ({ message: greet(parameters.name) });
+                "#
+                .to_string(),
+            ),
+        ]),
+        entrypoint: "main.js".to_string(),
+    };
+    let node_runner = NodeRunner::new(
+        code_files,
+        json!({}),
+        Some(NodeRunnerOptions {
+            context: ExecutionContext {
+                storage: folder.path().join("storage"),
+                ..Default::default()
+            },
+            binary_path: Some(node_binary_path),
+            force_runner_type: Some(RunnerType::Sandbox),
+            sandbox: LinuxSandbox {
+                bwrap_binary_path,
+                ..Default::default()
+            },
+            ..Default::default()
+        }),
+    );
+
+    let result = node_runner
+        .run(None, json!({ "name": "sandbox" }), None)
+        .await
+        .unwrap();
+    assert_eq!(result.data["message"], "hi sandbox");
+    let calls = std::fs::read_to_string(folder.path().join("bwrap-args")).unwrap();
+    let calls = calls.lines().collect::<Vec<_>>();
+    assert_eq!(calls.len(), 2);
+    // The install reaches the registry, the tool stays off the network
+    assert!(calls[0].contains("--share-net"), "{}", calls[0]);
+    assert!(calls[0].contains("--ignore-scripts"), "{}", calls[0]);
+    // The dependencies folder is the only writable code path of the install
+    let install_args = calls[0].split_whitespace().collect::<Vec<_>>();
+    let writable = install_args
+        .windows(2)
+        .filter(|pair| pair[0] == "--bind-try")
+        .map(|pair| pair[1])
+        .collect::<Vec<_>>();
+    assert!(writable
+        .iter()
+        .any(|path| path.ends_with(".node-dependencies")));
+    assert!(writable
+        .iter()
+        .all(|path| !path.contains("/code/") || path.ends_with(".node-dependencies")));
+    assert!(!calls[1].contains("--share-net"), "{}", calls[1]);
+    assert!(calls[1].ends_with(NodeRunner::ENTRYPOINT_WRAPPER_FILE_NAME));
+}
+
+#[tokio::test]
+async fn tool_errors_are_reported() {
+    let Some(node_binary_path) = system_node() else {
+        log::warn!("node not found, skipping");
+        return;
+    };
+    let folder = tempfile::tempdir().unwrap();
+    let code_files = CodeFiles {
+        files: HashMap::from([(
+            "main.mjs".to_string(),
+            r#"export async function run() { throw new Error("tool exploded"); }"#.to_string(),
+        )]),
+        entrypoint: "main.mjs".to_string(),
+    };
+    let node_runner = NodeRunner::new(
+        code_files,
+        json!({}),
+        Some(NodeRunnerOptions {
+            context: ExecutionContext {
+                storage: folder.path().join("storage"),
+                ..Default::default()
+            },
+            binary_path: Some(node_binary_path),
+            force_runner_type: Some(RunnerType::Host),
+            ..Default::default()
+        }),
+    );
+    let error = node_runner.run(None, json!({}), None).await.unwrap_err();
+    assert!(error.message().contains("tool exploded"), "{}", error);
+}
+
+/// Docker cli look-alike recording the `run` arguments and printing a fixed result
+#[cfg(unix)]
+fn recording_docker(folder: &std::path::Path) -> crate::tools::container_runtime::ContainerRuntime {
+    fake_docker(
+        folder,
+        &format!(
+            r#"    printf '%s\n' "$@" > "{folder}/run-args.txt"
+    echo "<shinkai-code-result>"
+    echo '{{"from":"docker"}}'
+    echo "</shinkai-code-result>"
+    exit 0"#,
+            folder = folder.display()
+        ),
+    )
+}
+
+#[cfg(unix)]
+#[rstest]
+#[case::node(
+    JsRuntime::Node,
+    "node:22.12.0-slim",
+    "npm install --omit=dev --ignore-scripts",
+    "exec node 'code/"
+)]
+#[case::bun(
+    JsRuntime::Bun,
+    "oven/bun:1.1.38-slim",
+    "bun install --production --ignore-scripts",
+    "exec bun 'run' 'code/"
+)]
+#[tokio::test]
+async fn docker_runs_wrapper_in_runtime_image(
+    #[case] js_runtime: JsRuntime,
+    #[case] image: &str,
+    #[case] install: &str,
+    #[case] exec: &str,
+) {
+    let folder = tempfile::tempdir().unwrap();
+    let code_files = CodeFiles {
+        files: HashMap::from([
+            (
+                "package.json".to_string(),
+                r#"{ "type": "module", "dependencies": { "left-pad": "1.3.0" } }"#.to_string(),
+            ),
+            (
+                "main.js".to_string(),
+                "export const run = () => ({});".to_string(),
+            ),
+        ]),
+        entrypoint: "main.js".to_string(),
+    };
+    let node_runner = NodeRunner::new(
+        code_files,
+        json!({}),
+        Some(NodeRunnerOptions {
+            context: ExecutionContext {
+                storage: folder.path().join("storage"),
+                ..Default::default()
+            },
+            js_runtime,
+            container_runtime: Some(recording_docker(folder.path())),
+            force_runner_type: Some(RunnerType::Docker),
+            ..Default::default()
+        }),
+    );
+
+    let result = node_runner.run(None, json!({}), None).await.unwrap();
+    assert_eq!(result.data, json!({ "from": "docker" }));
+    assert_eq!(result.metadata.runner_type, Some(RunnerType::Docker));
+
+    let args = std::fs::read_to_string(folder.path().join("run-args.txt")).unwrap();
+    let args = args.lines().collect::<Vec<_>>();
+    let image_index = args.iter().position(|arg| *arg == image).unwrap();
+    assert_eq!(args[image_index + 1..image_index + 3], ["sh", "-c"]);
+    let script = args[image_index + 3];
+    assert!(script.contains(install), "{}", script);
+    assert!(script.contains(exec), "{}", script);
+    assert!(script.ends_with(&format!("{}'", NodeRunner::ENTRYPOINT_WRAPPER_FILE_NAME)));
+    // The install writes into its own folder, the code stays read-only
+    let code_mounts = args
+        .iter()
+        .filter(|arg| arg.contains(",target=/app/code/"))
+        .collect::<Vec<_>>();
+    assert_eq!(code_mounts.len(), 2, "{:?}", code_mounts);
+    for mount in code_mounts {
+        let is_dependencies = mount.ends_with(".node-dependencies");
+        assert_eq!(
+            mount.contains("readonly=true"),
+            !is_dependencies,
+            "{}",
+            mount
+        );
+    }
+    assert!(args.contains(&"SHINKAI_HOME=/app/home"));
+    assert!(args
+        .iter()
+        .any(|arg| arg.starts_with("SHINKAI_INPUTS_FILE=/app/inputs/")));
+}
@@ -0,0 +1,113 @@
+use std::{path::PathBuf, sync::Arc};
+
+use super::{
+    container_runtime::ContainerRuntime,
+    execution_context::ExecutionContext,
+    linux_sandbox::LinuxSandbox,
+    redaction::RedactionRule,
+    runner_type::{RunnerPolicy, RunnerType},
+    shinkai_node_location::ShinkaiNodeLocation,
+    tool_signature::{ToolSignature, TrustStore},
+};
+
+/// JavaScript runtime executing the tool
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum JsRuntime {
+    #[default]
+    Node,
+    Bun,
+}
+
+impl JsRuntime {
+    /// Managed binary copied by `copy_assets::copy_node` or `copy_assets::copy_bun`
+    pub fn default_binary_path(&self) -> PathBuf {
+        let resources = PathBuf::from("./shinkai-tools-runner-resources");
+        match self {
+            JsRuntime::Node => resources.join(crate::copy_assets::node_binary_relative_path()),
+            JsRuntime::Bun => resources.join(crate::copy_assets::bun_binary_name()),
+        }
+    }
+
+    /// Official image with the same runtime version as the managed binary
+    pub fn default_docker_image_name(&self) -> String {
+        match self {
+            JsRuntime::Node => format!(
+                "node:{}-slim",
+                crate::copy_assets::NODE_VERSION.trim_start_matches('v')
+            ),
+            JsRuntime::Bun => format!(
+                "oven/bun:{}-slim",
+                crate::copy_assets::BUN_VERSION.trim_start_matches('v')
+            ),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct NodeRunnerOptions {
+    pub context: ExecutionContext,
+    pub js_runtime: JsRuntime,
+    /// Runtime binary for the Host and Sandbox runner types, the managed one when None
+    pub binary_path: Option<PathBuf>,
+    /// Image for the Docker runner type, the official one of `js_runtime` when None
+    pub docker_image_name: Option<String>,
+    /// Pins the image, it is pulled by digest and verified before running
+    pub docker_image_digest: Option<String>,
+    pub force_runner_type: Option<RunnerType>,
+    /// Runner types allowed when `force_runner_type` isn't set, and whether Docker falls back to Host
+    pub runner_policy: RunnerPolicy,
+    /// Container runtime used by the Docker runner type, detected when None
+    pub container_runtime: Option<ContainerRuntime>,
+    /// bubblewrap sandbox used by the Sandbox runner type
+    pub sandbox: LinuxSandbox,
+    /// Runs Node host runs under its permission model, reading the code, assets and mounts and
+    /// writing home and mounts like Deno does, Bun has no permission model
+    pub restrict_host_file_access: bool,
+    pub shinkai_node_location: ShinkaiNodeLocation,
+    pub trust_store: TrustStore,
+    pub code_signature: Option<ToolSignature>,
+    /// Top level configurations keys delivered out-of-band and redacted from logs
+    pub secret_configuration_keys: Vec<String>,
+    /// Extra redaction applied to logs and execution log entries
+    pub redaction_rules: Vec<Arc<dyn RedactionRule>>,
+}
+
+impl NodeRunnerOptions {
+    pub fn binary_path(&self) -> PathBuf {
+        self.binary_path
+            .clone()
+            .unwrap_or_else(|| self.js_runtime.default_binary_path())
+    }
+
+    pub fn docker_image_name(&self) -> String {
+        self.docker_image_name
+            .clone()
+            .unwrap_or_else(|| self.js_runtime.default_docker_image_name())
+    }
+}
+
+impl Default for NodeRunnerOptions {
+    fn default() -> Self {
+        Self {
+            context: ExecutionContext::default(),
+            js_runtime: JsRuntime::default(),
+            binary_path: None,
+            docker_image_name: None,
+            docker_image_digest: None,
+            force_runner_type: None,
+            runner_policy: RunnerPolicy::default(),
+            container_runtime: None,
+            sandbox: LinuxSandbox::default(),
+            restrict_host_file_access: true,
+            shinkai_node_location: ShinkaiNodeLocation {
+                protocol: String::from("http"),
+                host: String::from("127.0.0.1"),
+                port: 9550,
+            },
+            trust_store: TrustStore::default(),
+            code_signature: None,
+            secret_configuration_keys: Vec::new(),
+            redaction_rules: Vec::new(),
+        }
+    }
+}
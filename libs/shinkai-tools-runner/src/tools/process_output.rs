@@ -0,0 +1,113 @@
+use std::{process::ExitStatus, time::Duration};
+
+use serde_json::Value;
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+
+use super::{
+    execution_error::ExecutionError, execution_log::LogStream, execution_storage::ExecutionStorage,
+    redaction::Redactor,
+};
+
+/// Runs a preparation step, e.g. installing dependencies, failing with its stderr
+pub async fn run_step(command: &mut tokio::process::Command, step: &str) -> anyhow::Result<()> {
+    let output = command
+        .kill_on_drop(true)
+        .output()
+        .await
+        .map_err(|e| anyhow::anyhow!("failed to {}: {}", step, e))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        log::error!("failed to {}: {}", step, stderr);
+        return Err(anyhow::anyhow!("failed to {}: {}", step, stderr));
+    }
+    Ok(())
+}
+
+/// Waits for the child, appending its output to the execution log, and returns its status
+/// with the stdout and stderr lines
+pub async fn wait_for_output(
+    mut child: tokio::process::Child,
+    execution_storage: &ExecutionStorage,
+    source: &str,
+    max_execution_timeout: Option<Duration>,
+) -> anyhow::Result<(ExitStatus, Vec<String>, Vec<String>)> {
+    fn read_lines(
+        stream: impl AsyncRead + Unpin + Send + 'static,
+        log_stream: LogStream,
+        execution_storage: ExecutionStorage,
+        source: String,
+    ) -> tokio::task::JoinHandle<Vec<String>> {
+        tokio::spawn(async move {
+            let mut lines = Vec::new();
+            let mut stream = BufReader::new(stream).lines();
+            while let Ok(Some(line)) = stream.next_line().await {
+                log::info!(
+                    "from {}: {}",
+                    source,
+                    execution_storage.redactor.redact(&line)
+                );
+                let _ = execution_storage.append_log_entry(log_stream, None, &line);
+                lines.push(line);
+            }
+            lines
+        })
+    }
+    let stdout_task = read_lines(
+        child.stdout.take().expect("Failed to get stdout"),
+        LogStream::Stdout,
+        execution_storage.clone(),
+        source.to_string(),
+    );
+    let stderr_task = read_lines(
+        child.stderr.take().expect("Failed to get stderr"),
+        LogStream::Stderr,
+        execution_storage.clone(),
+        source.to_string(),
+    );
+
+    let status = if let Some(timeout) = max_execution_timeout {
+        log::info!("executing command with {}[s] timeout", timeout.as_secs());
+        match tokio::time::timeout(timeout, child.wait()).await {
+            Ok(result) => result?,
+            Err(_) => {
+                log::error!("command execution timed out after {}[s]", timeout.as_secs());
+                return Err(anyhow::anyhow!(
+                    "process timed out after {}[s]",
+                    timeout.as_secs()
+                ));
+            }
+        }
+    } else {
+        log::info!("executing command without timeout");
+        child.wait().await?
+    };
+    Ok((status, stdout_task.await?, stderr_task.await?))
+}
+
+/// Text the tool wrapper printed between the `<shinkai-code-result>` and
+/// `</shinkai-code-result>` lines
+pub fn code_result_text(lines: &[String]) -> String {
+    lines
+        .iter()
+        .skip_while(|line| !line.contains("<shinkai-code-result>"))
+        .skip(1)
+        .take_while(|line| !line.contains("</shinkai-code-result>"))
+        .map(|s| s.to_string())
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Parses the JSON result the tool wrapper printed, see `code_result_text`
+pub fn parse_code_result(lines: &[String], redactor: &Redactor) -> Result<Value, ExecutionError> {
+    let result_text = code_result_text(lines);
+    log::info!("result text: {}", redactor.redact(&result_text));
+    let result: Value = serde_json::from_str(&result_text).map_err(|e| {
+        log::info!("failed to parse result: {}", e);
+        ExecutionError::new(format!("failed to parse result: {}", e), None)
+    })?;
+    log::info!(
+        "successfully parsed run result: {}",
+        redactor.redact(&format!("{:?}", result))
+    );
+    Ok(result)
+}
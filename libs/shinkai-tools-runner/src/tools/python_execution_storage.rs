@@ -10,6 +10,16 @@ impl ExecutionStorage {
     pub fn python_run_docker_uv_cache_folder_path(&self) -> std::path::PathBuf {
         self.global_cache_folder_path.join("uv-cache-docker")
     }
+    /// The sandbox can't see the host venv interpreter, it gets its own venv and python installs
+    pub fn python_run_sandbox_venv_folder_path(&self) -> std::path::PathBuf {
+        self.cache_folder_path.join("python-run-sandbox-venv")
+    }
+    pub fn python_run_sandbox_uv_cache_folder_path(&self) -> std::path::PathBuf {
+        self.global_cache_folder_path.join("uv-cache-sandbox")
+    }
+    pub fn python_run_sandbox_python_install_folder_path(&self) -> std::path::PathBuf {
+        self.global_cache_folder_path.join("uv-python-sandbox")
+    }
 
     pub fn python_check_venv_folder_path(&self) -> std::path::PathBuf {
         self.cache_folder_path.join("python-check-venv")
@@ -34,6 +44,16 @@ impl ExecutionStorage {
             log::error!("failed to create uv cache directory: {}", e);
             e
         })?;
+        for sandbox_folder in [
+            self.python_run_sandbox_venv_folder_path(),
+            self.python_run_sandbox_uv_cache_folder_path(),
+            self.python_run_sandbox_python_install_folder_path(),
+        ] {
+            std::fs::create_dir_all(sandbox_folder).map_err(|e| {
+                log::error!("failed to create python sandbox directory: {}", e);
+                e
+            })?;
+        }
         Ok(())
     }
 }
@@ -0,0 +1,120 @@
+use std::path::{self, Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use super::execution_storage::ExecutionStorage;
+
+/// Paths a host mode Python run may read and write, the parity of Deno's `--allow-read` and
+/// `--allow-write` flags
+///
+/// Enforced by `LAUNCHER_CODE`, which installs an audit hook before running the entrypoint. The
+/// hook can't stop native code, e.g. ctypes or subprocesses, it keeps well behaved tools and
+/// their libraries away from the rest of the user files.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PythonFileAccessPolicy {
+    pub read: Vec<PathBuf>,
+    /// Also readable
+    pub write: Vec<PathBuf>,
+}
+
+impl PythonFileAccessPolicy {
+    pub const ENV: &'static str = "SHINKAI_FILE_ACCESS_POLICY";
+    pub const LAUNCHER_FILE_NAME: &'static str = "shinkai_file_access_launcher.py";
+
+    /// Runs the script in `sys.argv[1]` as `__main__` once the audit hook is installed
+    ///
+    /// Besides the policy paths it allows the interpreter, the import path, the temp dir like
+    /// Deno does, and a few system files the standard library reads.
+    pub const LAUNCHER_CODE: &'static str = r#"
+import errno
+import json
+import os
+import runpy
+import sys
+import tempfile
+
+
+def _shinkai_install_file_access_hook():
+    policy = json.loads(os.environ.pop("SHINKAI_FILE_ACCESS_POLICY", '{"read": [], "write": []}'))
+
+    def resolve(path):
+        return os.path.normcase(os.path.realpath(os.path.abspath(path)))
+
+    def roots(paths):
+        return tuple(resolve(path) for path in paths if path and os.path.exists(path))
+
+    temp_dir = tempfile.gettempdir()
+    write_roots = roots(policy["write"] + [temp_dir])
+    read_roots = write_roots + roots(
+        policy["read"]
+        + [p for p in sys.path if p and os.path.dirname(p) != p]
+        + [sys.prefix, sys.base_prefix, sys.exec_prefix, sys.base_exec_prefix]
+        + [os.devnull, "/dev", "/proc/self", "/etc/localtime", "/usr/share/zoneinfo", "/etc/mime.types", "/etc/ssl", "/etc/pki", "/etc/ca-certificates", "/etc/os-release"]
+    )
+
+    def allowed(path, allowed_roots):
+        return any(path == root or path.startswith(root.rstrip(os.sep) + os.sep) for root in allowed_roots)
+
+    def check(path, write):
+        if isinstance(path, int) or path is None:
+            return
+        try:
+            resolved = resolve(os.fsdecode(path))
+        except (TypeError, ValueError):
+            return
+        if not allowed(resolved, write_roots if write else read_roots):
+            raise PermissionError(errno.EACCES, f"shinkai: {'write' if write else 'read'} access denied", os.fsdecode(path))
+
+    write_flags = os.O_WRONLY | os.O_RDWR | os.O_CREAT | os.O_APPEND | os.O_TRUNC
+    write_events = {
+        "os.mkdir": 1, "os.remove": 1, "os.rmdir": 1, "os.rename": 2, "os.link": 2, "os.symlink": 2,
+        "os.truncate": 1, "os.chmod": 1, "os.chown": 1, "os.utime": 1, "shutil.rmtree": 1, "os.mkfifo": 1,
+    }
+    read_events = {"os.listdir", "os.scandir"}
+
+    def hook(event, args):
+        if event == "open":
+            path, mode, flags = args
+            write = any(c in (mode or "") for c in "wax+") or bool((flags or 0) & write_flags)
+            check(path, write)
+        elif event in write_events:
+            for path in args[: write_events[event]]:
+                check(path, True)
+        elif event in read_events:
+            check(args[0] if args else ".", False)
+
+    sys.addaudithook(hook)
+
+
+_shinkai_install_file_access_hook()
+del _shinkai_install_file_access_hook
+sys.dont_write_bytecode = True
+sys.argv = sys.argv[1:]
+runpy.run_path(sys.argv[0], run_name="__main__")
+"#;
+
+    /// Reads of the code, venv, assets and mounts, writes to home and mounts
+    pub fn for_execution(execution_storage: &ExecutionStorage, venv_folder_path: &Path) -> Self {
+        let absolute = |path: &PathBuf| path::absolute(path).unwrap_or(path.clone());
+        let context = &execution_storage.context;
+        let mut read = vec![
+            execution_storage.code_folder_path.clone(),
+            venv_folder_path.to_path_buf(),
+            execution_storage.assets_folder_path.clone(),
+            execution_storage.inputs_file_path(),
+        ];
+        read.extend(context.assets_files.iter().map(absolute));
+        read.extend(context.mount_files.iter().map(absolute));
+        let mut write = vec![execution_storage.home_folder_path.clone()];
+        write.extend(context.mount_files.iter().map(absolute));
+        PythonFileAccessPolicy { read, write }
+    }
+
+    pub fn env_value(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+#[path = "python_file_access.test.rs"]
+mod tests;
@@ -0,0 +1,136 @@
+use std::path::PathBuf;
+
+use serde_json::Value;
+
+use crate::tools::{
+    code_files::CodeFiles, execution_context::ExecutionContext,
+    execution_storage::ExecutionStorage, python_file_access::PythonFileAccessPolicy,
+};
+
+#[test]
+fn policy_reads_code_venv_assets_and_mounts_and_writes_home_and_mounts() {
+    let folder = tempfile::tempdir().unwrap();
+    let mount_file = folder.path().join("mounted.txt");
+    let asset_file = folder.path().join("asset.txt");
+    let storage = ExecutionStorage::new(
+        CodeFiles {
+            files: Default::default(),
+            entrypoint: String::from("main.py"),
+        },
+        ExecutionContext {
+            storage: folder.path().join("storage"),
+            mount_files: vec![mount_file.clone()],
+            assets_files: vec![asset_file.clone()],
+            ..Default::default()
+        },
+    );
+    let venv = storage.python_run_host_venv_folder_path();
+    let policy = PythonFileAccessPolicy::for_execution(&storage, &venv);
+
+    assert_eq!(
+        policy.read,
+        vec![
+            storage.code_folder_path.clone(),
+            venv,
+            storage.assets_folder_path.clone(),
+            storage.inputs_file_path(),
+            asset_file,
+            mount_file.clone(),
+        ]
+    );
+    assert_eq!(
+        policy.write,
+        vec![storage.home_folder_path.clone(), mount_file]
+    );
+    let value: Value = serde_json::from_str(&policy.env_value()).unwrap();
+    assert!(value["read"].is_array() && value["write"].is_array());
+}
+
+/// Runs the launcher with the system python, these checks don't need uv or a venv
+#[cfg(unix)]
+#[test]
+fn launcher_denies_access_outside_the_policy() {
+    if std::process::Command::new("python3")
+        .arg("--version")
+        .output()
+        .is_err()
+    {
+        log::warn!("python3 not found, skipping");
+        return;
+    }
+    let folder = tempfile::tempdir().unwrap();
+    let path = |name: &str| -> PathBuf {
+        let path = folder.path().join(name);
+        std::fs::create_dir_all(&path).unwrap();
+        path
+    };
+    let (code, home, forbidden, temp) =
+        (path("code"), path("home"), path("forbidden"), path("tmp"));
+    let mount_file = folder.path().join("mounted.txt");
+    std::fs::write(&mount_file, "mounted").unwrap();
+    std::fs::write(forbidden.join("secret.txt"), "secret").unwrap();
+    std::fs::write(
+        code.join(PythonFileAccessPolicy::LAUNCHER_FILE_NAME),
+        PythonFileAccessPolicy::LAUNCHER_CODE,
+    )
+    .unwrap();
+    std::fs::write(
+        code.join("main.py"),
+        r#"
+import json, os, sys
+
+def attempt(action):
+    try:
+        action()
+        return "ok"
+    except PermissionError:
+        return "denied"
+
+home, forbidden, mount_file = sys.argv[1:4]
+results = {
+    "write_home": attempt(lambda: open(os.path.join(home, "out.txt"), "w").write("ok")),
+    "read_mount": attempt(lambda: open(mount_file).read()),
+    "append_mount": attempt(lambda: open(mount_file, "a").write("!")),
+    "read_forbidden": attempt(lambda: open(os.path.join(forbidden, "secret.txt")).read()),
+    "write_forbidden": attempt(lambda: open(os.path.join(forbidden, "test.txt"), "w").write("no")),
+    "list_forbidden": attempt(lambda: os.listdir(forbidden)),
+    "remove_forbidden": attempt(lambda: os.remove(os.path.join(forbidden, "secret.txt"))),
+    "write_temp": attempt(lambda: open(os.path.join(os.environ["TMPDIR"], "t.txt"), "w").write("ok")),
+}
+print(json.dumps(results))
+"#,
+    )
+    .unwrap();
+    let policy = PythonFileAccessPolicy {
+        read: vec![code.clone(), mount_file.clone()],
+        write: vec![home.clone(), mount_file.clone()],
+    };
+
+    let output = std::process::Command::new("python3")
+        .arg(code.join(PythonFileAccessPolicy::LAUNCHER_FILE_NAME))
+        .arg(code.join("main.py"))
+        .args([&home, &forbidden, &mount_file])
+        .env(PythonFileAccessPolicy::ENV, policy.env_value())
+        .env("TMPDIR", &temp)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let results: Value = serde_json::from_slice(&output.stdout).unwrap();
+    for allowed in ["write_home", "read_mount", "append_mount", "write_temp"] {
+        assert_eq!(results[allowed], "ok", "{}", allowed);
+    }
+    for denied in [
+        "read_forbidden",
+        "write_forbidden",
+        "list_forbidden",
+        "remove_forbidden",
+    ] {
+        assert_eq!(results[denied], "denied", "{}", denied);
+    }
+    assert!(forbidden.join("secret.txt").exists());
+    assert!(!forbidden.join("test.txt").exists());
+}
@@ -1,7 +1,7 @@
 use serde_json::Value;
 use std::{
     collections::{HashMap, HashSet},
-    path::{self, PathBuf},
+    path::{self, Path, PathBuf},
     sync::Arc,
     time::Duration,
 };
@@ -12,17 +12,27 @@ use tokio::{
 use toml_edit::DocumentMut;
 
 use crate::tools::{
+    callback_server::{CallbackHandlers, CallbackServer},
     check_utils::normalize_error_message,
-    execution_error::ExecutionError,
+    container_runtime::{BindMount, ContainerRuntime},
+    docker_engine_api::{run_in_engine, ContainerSpec},
+    execution_error::{ExecutionError, InfrastructureError},
+    execution_log::LogStream,
     file_name_utils::{adapt_paths_in_value, normalize_for_docker_path},
+    host_capabilities::HostCallContext,
+    image_manager::ImageManager,
     path_buf_ext::PathBufExt,
+    process_output::{code_result_text, parse_code_result, run_step},
     run_result::RunResult,
-    runner_type::resolve_runner_type,
+    runner_type::resolve_runner_type_for_policy,
+    secret_configurations::SecretConfigurations,
+    tool_definition::ToolDefinition,
 };
 
 use super::{
     code_files::CodeFiles, execution_storage::ExecutionStorage,
-    python_runner_options::PythonRunnerOptions, runner_type::RunnerType,
+    python_file_access::PythonFileAccessPolicy, python_runner_options::PythonRunnerOptions,
+    runner_type::RunnerType,
 };
 
 pub struct PythonRunner {
@@ -34,6 +44,7 @@ pub struct PythonRunner {
 impl PythonRunner {
     pub const MAX_EXECUTION_TIME_MS_INTERNAL_OPS: u64 = 1000;
     pub const PYPROJECT_TOML_FILE_NAME: &'static str = "pyproject.toml";
+    pub const DEFINITION_ENTRYPOINT_FILE_NAME: &'static str = "__shinkai_definition.py";
 
     pub fn new(
         code_files: CodeFiles,
@@ -233,10 +244,7 @@ requires-python = ">=3.10"
             }
         }
 
-        log::info!(
-            "autogenerated pyproject_toml: {}",
-            pyproject_toml.to_string()
-        );
+        log::info!("autogenerated pyproject_toml: {}", pyproject_toml);
         code_files.files.insert(
             Self::PYPROJECT_TOML_FILE_NAME.to_string(),
             pyproject_toml.to_string(),
@@ -248,6 +256,7 @@ requires-python = ">=3.10"
         let code = Self::extend_with_pyproject_toml(self.code.clone())
             .map_err(|e| anyhow::anyhow!("failed to create pyproject.toml: {}", e))?;
         let execution_storage = ExecutionStorage::new(code.clone(), self.options.context.clone());
+        let _lease = execution_storage.acquire_lease()?;
         execution_storage.init_for_python(None)?;
 
         let uv_binary_path = path::absolute(self.options.uv_binary_path.clone())
@@ -383,6 +392,191 @@ requires-python = ">=3.10"
         Ok(vec![])
     }
 
+    /// Extracts the tool definition from the code without executing `run`
+    ///
+    /// The entrypoint is loaded as a module from a generated introspection script that reads the
+    /// module level `metadata` (or `definition`) dict and builds JSON Schemas from the type hints
+    /// of the `CONFIG`, `INPUTS` and `OUTPUT` classes.
+    ///
+    /// # Returns
+    ///
+    /// Returns a Result containing:
+    /// - Ok(ToolDefinition): The definition with configurations, parameters and result schemas
+    /// - Err(ExecutionError): Any errors that occurred while loading the code or building schemas
+    pub async fn definition(&self) -> Result<ToolDefinition, ExecutionError> {
+        log::info!("preparing to extract tool definition");
+        self.options
+            .trust_store
+            .verify_code_files(&self.code, self.options.code_signature.as_ref())?;
+        let entrypoint_code = match self.code.files.get(&self.code.entrypoint) {
+            Some(entrypoint_code) => entrypoint_code.clone(),
+            None => {
+                return Err(ExecutionError::new(
+                    format!("no entrypoint found {}", self.code.entrypoint),
+                    None,
+                ))
+            }
+        };
+        let mut code = Self::extend_with_pyproject_toml(self.code.clone()).map_err(|e| {
+            ExecutionError::new(format!("failed to create pyproject.toml: {}", e), None)
+        })?;
+
+        code.files.insert(
+            Self::DEFINITION_ENTRYPOINT_FILE_NAME.to_string(),
+            format!(
+                r#"
+import enum
+import importlib.util
+import json
+import os
+import sys
+import types
+import typing
+
+entrypoint_path = os.path.join(os.path.dirname(os.path.abspath(__file__)), "{entrypoint}")
+spec = importlib.util.spec_from_file_location("shinkai_tool", entrypoint_path)
+tool = importlib.util.module_from_spec(spec)
+sys.modules["shinkai_tool"] = tool
+spec.loader.exec_module(tool)
+
+PRIMITIVE_SCHEMAS = {{
+    str: {{"type": "string"}},
+    int: {{"type": "integer"}},
+    float: {{"type": "number"}},
+    bool: {{"type": "boolean"}},
+    type(None): {{"type": "null"}},
+    bytes: {{"type": "string"}},
+}}
+
+def schema_for_type(tp, seen):
+    if tp in PRIMITIVE_SCHEMAS:
+        return dict(PRIMITIVE_SCHEMAS[tp])
+    if tp is typing.Any:
+        return {{}}
+    origin = typing.get_origin(tp)
+    args = typing.get_args(tp)
+    if origin is typing.Union or (hasattr(types, "UnionType") and origin is types.UnionType):
+        not_none = [arg for arg in args if arg is not type(None)]
+        if len(not_none) == 1:
+            return schema_for_type(not_none[0], seen)
+        return {{"anyOf": [schema_for_type(arg, seen) for arg in args]}}
+    if origin is typing.Literal:
+        return {{"enum": list(args)}}
+    if origin in (list, set, frozenset, tuple) or tp in (list, set, frozenset, tuple):
+        items = schema_for_type(args[0], seen) if args else {{}}
+        return {{"type": "array", "items": items}}
+    if origin is dict or tp is dict:
+        values = schema_for_type(args[1], seen) if len(args) == 2 else {{}}
+        return {{"type": "object", "additionalProperties": values}}
+    if isinstance(tp, type) and issubclass(tp, enum.Enum):
+        return {{"enum": [member.value for member in tp]}}
+    if isinstance(tp, type):
+        return schema_for_class(tp, seen)
+    return {{}}
+
+def schema_for_class(cls, seen):
+    if cls in seen:
+        return {{"type": "object"}}
+    seen = seen | {{cls}}
+    try:
+        hints = typing.get_type_hints(cls)
+    except Exception:
+        hints = getattr(cls, "__annotations__", {{}})
+    properties = {{}}
+    required = []
+    for name, hint in hints.items():
+        if name.startswith("_"):
+            continue
+        properties[name] = schema_for_type(hint, seen)
+        is_optional = type(None) in typing.get_args(hint)
+        if not hasattr(cls, name) and not is_optional:
+            required.append(name)
+    schema = {{"type": "object", "properties": properties}}
+    if required:
+        schema["required"] = required
+    return schema
+
+def schema_for(name):
+    cls = getattr(tool, name, None)
+    if cls is None:
+        return {{"type": "object", "properties": {{}}}}
+    return schema_for_class(cls, frozenset())
+
+metadata = getattr(tool, "metadata", None) or getattr(tool, "definition", None) or {{}}
+definition = {{
+    "id": metadata.get("id", ""),
+    "name": metadata.get("name", ""),
+    "description": metadata.get("description", ""),
+    "author": metadata.get("author", ""),
+    "keywords": metadata.get("keywords", []),
+    "configurations": metadata.get("configurations") or schema_for("CONFIG"),
+    "parameters": metadata.get("parameters") or schema_for("INPUTS"),
+    "result": metadata.get("result") or schema_for("OUTPUT"),
+}}
+
+print("<shinkai-code-result>")
+print(json.dumps(definition))
+print("</shinkai-code-result>")
+        "#,
+                entrypoint = self.code.entrypoint.replace("\\", "/"),
+            ),
+        );
+        code.entrypoint = Self::DEFINITION_ENTRYPOINT_FILE_NAME.to_string();
+
+        let resolved_runner_type = resolve_runner_type_for_policy(
+            self.options.force_runner_type.clone(),
+            &self.options.runner_policy,
+            self.options.container_runtime.as_ref(),
+        );
+        let no_secrets =
+            SecretConfigurations::default().with_redaction_rules(&self.options.redaction_rules);
+        let no_inputs = (&Value::Null, &Value::Null);
+        let (output, _) = self
+            .options
+            .runner_policy
+            .run_with_fallback(
+                self.options.force_runner_type.as_ref(),
+                resolved_runner_type,
+                |runner_type| {
+                    let code = code.clone();
+                    let no_secrets = &no_secrets;
+                    async move {
+                        match runner_type {
+                            RunnerType::Host | RunnerType::Sandbox => {
+                                self.run_in_host(
+                                    &runner_type,
+                                    code,
+                                    None,
+                                    no_inputs,
+                                    no_secrets,
+                                    None,
+                                )
+                                .await
+                            }
+                            RunnerType::Docker => {
+                                self.run_in_docker(code, None, no_inputs, no_secrets, None)
+                                    .await
+                            }
+                        }
+                        .map_err(|e| ExecutionError::from_runner_error(e.to_string(), &e))
+                    }
+                },
+            )
+            .await?;
+        let result_text = code_result_text(&output);
+
+        let mut definition: ToolDefinition = serde_json::from_str(&result_text).map_err(|e| {
+            log::info!("failed to parse tool definition: {}", e);
+            ExecutionError::new(format!("failed to parse tool definition: {}", e), None)
+        })?;
+        definition.code = Some(entrypoint_code);
+        log::info!(
+            "successfully extracted tool definition: {}",
+            definition.name
+        );
+        Ok(definition)
+    }
+
     pub async fn run(
         &self,
         envs: Option<HashMap<String, String>>,
@@ -390,8 +584,19 @@ requires-python = ">=3.10"
         max_execution_timeout: Option<Duration>,
     ) -> Result<RunResult, ExecutionError> {
         log::info!("preparing to run tool");
-        log::info!("configurations: {}", self.configurations.to_string());
-        log::info!("parameters: {}", parameters.to_string());
+        self.options
+            .trust_store
+            .verify_code_files(&self.code, self.options.code_signature.as_ref())?;
+        let (public_configurations, secrets) = SecretConfigurations::split(
+            &self.configurations,
+            &self.options.secret_configuration_keys,
+        );
+        let secrets = secrets.with_redaction_rules(&self.options.redaction_rules);
+        log::info!("configurations: {}", secrets.masked(&self.configurations));
+        log::info!(
+            "parameters: {}",
+            secrets.redactor().redact(&parameters.to_string())
+        );
 
         let entrypoint_code = self.code.files.get(&self.code.entrypoint.clone());
         if entrypoint_code.is_none() {
@@ -400,7 +605,52 @@ requires-python = ">=3.10"
                 None,
             ));
         }
-        let resolved_runner_type = resolve_runner_type(self.options.force_runner_type.clone());
+        let resolved_runner_type = resolve_runner_type_for_policy(
+            self.options.force_runner_type.clone(),
+            &self.options.runner_policy,
+            self.options.container_runtime.as_ref(),
+        );
+        let (output, metadata) = self
+            .options
+            .runner_policy
+            .run_with_fallback(
+                self.options.force_runner_type.as_ref(),
+                resolved_runner_type,
+                |runner_type| {
+                    let envs = envs.clone();
+                    let (parameters, public_configurations, secrets) =
+                        (&parameters, &public_configurations, &secrets);
+                    async move {
+                        self.run_with_runner_type(
+                            &runner_type,
+                            envs,
+                            parameters,
+                            public_configurations,
+                            secrets,
+                            max_execution_timeout,
+                        )
+                        .await
+                    }
+                },
+            )
+            .await?;
+        Ok(RunResult {
+            data: parse_code_result(&output, secrets.redactor())?,
+            metadata,
+        })
+    }
+
+    /// Wraps the entrypoint for the runner type and runs it, returning the output lines
+    #[allow(clippy::too_many_arguments)]
+    async fn run_with_runner_type(
+        &self,
+        resolved_runner_type: &RunnerType,
+        envs: Option<HashMap<String, String>>,
+        parameters: &Value,
+        public_configurations: &Value,
+        secrets: &SecretConfigurations,
+        max_execution_timeout: Option<Duration>,
+    ) -> Result<Vec<String>, ExecutionError> {
         let mut code = Self::extend_with_pyproject_toml(self.code.clone()).map_err(|e| {
             ExecutionError::new(format!("failed to create pyproject.toml: {}", e), None)
         })?;
@@ -411,7 +661,7 @@ requires-python = ">=3.10"
             "Extended pyproject.toml {:?}",
             code.files.get(Self::PYPROJECT_TOML_FILE_NAME).unwrap()
         );
-        let mut adapted_configurations = self.configurations.clone();
+        let mut adapted_configurations = public_configurations.clone();
         if let Some(object) = adapted_configurations.as_object_mut() {
             object.insert(
                 "py/object".to_string(),
@@ -457,12 +707,15 @@ requires-python = ">=3.10"
             );
         }
 
+        // The inputs are read from the inputs file so the code folder, its hash and its venv only
+        // depend on the tool code
         let adapted_entrypoint_code = format!(
             r#"
 {}
 import asyncio
 import jsonpickle
 import json
+import os
 
 class TrickyJsonEncoder(json.JSONEncoder):
     def default(self, obj):
@@ -491,9 +744,11 @@ def tricky_json_dump(obj):
     jsonpickle_decoded = jsonpickle.decode(jsonpickle_encoded, reset=True)
     custom_json_dump = json.dumps(jsonpickle_decoded, indent=4, cls=TrickyJsonEncoder)
     return custom_json_dump
-
-configurations = jsonpickle.decode('{}')
-parameters = jsonpickle.decode('{}')
+{}
+with open(os.environ["{}"], encoding="utf-8") as shinkai_inputs_file:
+    shinkai_inputs = json.load(shinkai_inputs_file)
+configurations = jsonpickle.decode(json.dumps(shinkai_inputs["configurations"])){}
+parameters = jsonpickle.decode(json.dumps(shinkai_inputs["parameters"]))
 
 result = run(configurations, parameters)
 if asyncio.iscoroutine(result):
@@ -506,50 +761,81 @@ print(serialized_result)
 print("</shinkai-code-result>")
         "#,
             &entrypoint_code,
-            serde_json::to_string(&adapted_configurations)
-                .unwrap()
-                .replace("\\", "\\\\")
-                .replace("'", "\\'")
-                .replace("\"", "\\\""),
-            serde_json::to_string(&adapted_parameters)
-                .unwrap()
-                .replace("\\", "\\\\")
-                .replace("'", "\\'")
-                .replace("\"", "\\\"")
+            CallbackServer::PYTHON_CLIENT_CODE,
+            ExecutionStorage::INPUTS_FILE_ENV,
+            secrets.python_merge_code(),
         );
         code.files
             .insert(self.code.entrypoint.clone(), adapted_entrypoint_code);
 
-        let result = match resolved_runner_type {
-            RunnerType::Host => self.run_in_host(code, envs, max_execution_timeout).await,
-            RunnerType::Docker => self.run_in_docker(code, envs, max_execution_timeout).await,
-        }
-        .map_err(|e| ExecutionError::new(e.to_string(), None))?;
-
-        let result_text = result
-            .iter()
-            .skip_while(|line| !line.contains("<shinkai-code-result>"))
-            .skip(1)
-            .take_while(|line| !line.contains("</shinkai-code-result>"))
-            .map(|s| s.to_string())
-            .collect::<Vec<String>>()
-            .join("\n");
-
-        log::info!("result : {:?}", result);
-        log::info!("result text: {:?}", result_text);
+        // Kept alive until the process exits so the tool can call back into the host
+        let mut envs = envs;
+        let callback_handlers = CallbackHandlers {
+            context: HostCallContext {
+                context_id: self.options.context.context_id.clone(),
+                execution_id: self.options.context.execution_id.clone(),
+                tool_id: self
+                    .options
+                    .tool_calls
+                    .as_ref()
+                    .and_then(|tool_calls| tool_calls.call_chain.last().cloned()),
+            },
+            tool_calls: self
+                .options
+                .tool_calls
+                .clone()
+                .map(|tool_calls| tool_calls.with_timeout(max_execution_timeout)),
+            host_capabilities: self.options.host_capabilities.clone(),
+        };
+        let _callback_server = if callback_handlers.is_empty() {
+            None
+        } else {
+            let callback_server = CallbackServer::start(
+                resolved_runner_type,
+                self.options.container_runtime.as_ref(),
+                callback_handlers,
+            )
+            .await
+            .map_err(|e| {
+                ExecutionError::from_runner_error(
+                    format!("failed to start callback server: {}", e),
+                    &e,
+                )
+            })?;
+            envs.get_or_insert_with(HashMap::new)
+                .extend(callback_server.envs(resolved_runner_type));
+            Some(callback_server)
+        };
 
-        let result: Value = serde_json::from_str(&result_text).map_err(|e| {
-            log::info!("failed to parse result: {}", e);
-            ExecutionError::new(format!("failed to parse result: {}", e), None)
-        })?;
-        log::info!("successfully parsed run result: {:?}", result);
-        Ok(RunResult { data: result })
+        let inputs = (&adapted_configurations, &adapted_parameters);
+        match resolved_runner_type {
+            RunnerType::Host | RunnerType::Sandbox => {
+                self.run_in_host(
+                    resolved_runner_type,
+                    code,
+                    envs,
+                    inputs,
+                    secrets,
+                    max_execution_timeout,
+                )
+                .await
+            }
+            RunnerType::Docker => {
+                self.run_in_docker(code, envs, inputs, secrets, max_execution_timeout)
+                    .await
+            }
+        }
+        .map_err(|e| {
+            ExecutionError::from_runner_error(secrets.redactor().redact(&e.to_string()), &e)
+        })
     }
 
     async fn run_in_docker(
         &self,
         code_files: CodeFiles,
         envs: Option<HashMap<String, String>>,
+        (configurations, parameters): (&Value, &Value),
+        secrets: &SecretConfigurations,
         max_execution_timeout: Option<Duration>,
     ) -> anyhow::Result<Vec<String>> {
         log::info!(
@@ -558,10 +844,30 @@ print("</shinkai-code-result>")
         );
 
         log::info!("code files: {:?}", code_files.files.get("main.py"));
-        let execution_storage = ExecutionStorage::new(code_files, self.options.context.clone());
+        let execution_storage = ExecutionStorage::new(code_files, self.options.context.clone())
+            .with_redactor(secrets.redactor().clone());
+        let _lease = execution_storage.acquire_lease()?;
         execution_storage.init_for_python(None)?;
+        let home = execution_storage.prepare_home()?;
+        let inputs = execution_storage.write_inputs(configurations, parameters)?;
+
+        let container_runtime = self
+            .options
+            .container_runtime
+            .clone()
+            .or_else(ContainerRuntime::detect)
+            .unwrap_or_default();
+        log::info!("using container runtime: {:?}", container_runtime);
+        // Pulled here so a missing image doesn't eat the execution timeout
+        let image = ImageManager::new(container_runtime.clone())
+            .prepare(
+                &self.options.code_runner_docker_image_name,
+                self.options.code_runner_docker_image_digest.as_deref(),
+            )
+            .await
+            .map_err(|e| InfrastructureError(format!("failed to prepare image: {}", e)))?;
 
-        let mut mount_params = Vec::<String>::new();
+        let mut mounts = Vec::<BindMount>::new();
 
         let mount_dirs = [
             (
@@ -570,7 +876,7 @@ print("</shinkai-code-result>")
             ),
             (
                 execution_storage.home_folder_path.as_normalized_string(),
-                execution_storage.relative_to_root(execution_storage.home_folder_path.clone()),
+                String::from("home"),
             ),
             (
                 execution_storage
@@ -589,10 +895,21 @@ print("</shinkai-code-result>")
             ),
         ];
         for (dir, relative_path) in mount_dirs {
-            let mount_param = format!(r#"type=bind,source={},target=/app/{}"#, dir, relative_path);
-            log::info!("mount parameter created: {}", mount_param);
-            mount_params.extend([String::from("--mount"), mount_param]);
+            mounts.push(BindMount {
+                source: dir,
+                target: format!("/app/{}", relative_path),
+                readonly: false,
+            });
         }
+        let inputs_file_path = format!(
+            "/app/{}",
+            execution_storage.relative_to_root(inputs.path().to_path_buf())
+        );
+        mounts.push(BindMount {
+            source: inputs.path().to_path_buf().as_normalized_string(),
+            target: inputs_file_path.clone(),
+            readonly: true,
+        });
 
         let mut mount_env = String::from("");
         log::info!("mount files: {:?}", self.options.context.mount_files);
@@ -601,10 +918,12 @@ print("</shinkai-code-result>")
             // Copy the files to the exact same path in the volume.
             // This will allow to run the same code in the host and in the container.
             let path = normalize_for_docker_path(file.to_path_buf());
-            let mount_param = format!(r#"type=bind,source={},target={}"#, path, path);
-            log::info!("mount parameter created: {}", mount_param);
             mount_env += &format!("{},", path);
-            mount_params.extend([String::from("--mount"), mount_param]);
+            mounts.push(BindMount {
+                source: path.clone(),
+                target: path,
+                readonly: false,
+            });
         }
 
         let mut mount_assets_env = String::from("");
@@ -615,53 +934,47 @@ print("</shinkai-code-result>")
                 execution_storage.relative_to_root(execution_storage.assets_folder_path.clone()),
                 file.file_name().unwrap().to_str().unwrap()
             );
-            let mount_param = format!(
-                r#"type=bind,readonly=true,source={},target={}"#,
-                path::absolute(file).unwrap().as_normalized_string(),
-                target_path,
-            );
-            log::debug!("mount parameter created: {}", mount_param);
             mount_assets_env += &format!("{},", target_path);
-            mount_params.extend([String::from("--mount"), mount_param]);
+            mounts.push(BindMount {
+                source: path::absolute(file).unwrap().as_normalized_string(),
+                target: target_path,
+                readonly: true,
+            });
         }
 
         let mut container_envs = Vec::<String>::new();
 
-        container_envs.push(String::from("-e"));
         container_envs.push(format!(
             "SHINKAI_NODE_LOCATION={}://host.docker.internal:{}",
             self.options.shinkai_node_location.protocol, self.options.shinkai_node_location.port
         ));
 
-        container_envs.push(String::from("-e"));
         container_envs.push(String::from("SHINKAI_HOME=/app/home"));
-        container_envs.push(String::from("-e"));
         container_envs.push(format!("SHINKAI_ASSETS={}", mount_assets_env));
-        container_envs.push(String::from("-e"));
         container_envs.push(format!("SHINKAI_MOUNT={}", mount_env));
-        container_envs.push(String::from("-e"));
         container_envs.push(format!(
             "SHINKAI_CONTEXT_ID={}",
             self.options.context.context_id
         ));
-        container_envs.push(String::from("-e"));
         container_envs.push(format!(
             "SHINKAI_EXECUTION_ID={}",
             self.options.context.execution_id
         ));
-        container_envs.push(String::from("-e"));
+        container_envs.push(format!(
+            "{}={}",
+            ExecutionStorage::INPUTS_FILE_ENV,
+            inputs_file_path
+        ));
         container_envs.push(format!(
             "VIRTUAL_ENV=/app/{}",
             execution_storage
                 .relative_to_root(execution_storage.python_run_docker_venv_folder_path())
         ));
-        container_envs.push(String::from("-e"));
         container_envs.push(format!(
             "UV_PROJECT_ENVIRONMENT=/app/{}",
             execution_storage
                 .relative_to_root(execution_storage.python_run_docker_venv_folder_path())
         ));
-        container_envs.push(String::from("-e"));
         container_envs.push(format!(
             "UV_CACHE_DIR=/app/{}",
             execution_storage.relative_to_global_cache(
@@ -669,22 +982,24 @@ print("</shinkai-code-result>")
             )
         ));
 
-        if let Some(envs) = envs {
+        // Passed by name on the docker cli so their values never show up in its arguments
+        let mut named_envs = Vec::new();
+        if let Some(mut envs) = envs {
+            if let Some(token) = envs.remove(CallbackServer::TOKEN_ENV) {
+                named_envs.push((CallbackServer::TOKEN_ENV.to_string(), token));
+            }
             for (key, value) in envs {
                 let env = format!("{}={}", key, value);
-                container_envs.push(String::from("-e"));
                 container_envs.push(env);
             }
         }
+        if !secrets.is_empty() {
+            named_envs.push((SecretConfigurations::ENV.to_string(), secrets.env_value()));
+        }
 
         let code_entrypoint =
             execution_storage.relative_to_root(execution_storage.code_entrypoint_file_path.clone());
 
-        let mut command = tokio::process::Command::new("docker");
-        let mut args = vec!["run", "--rm"];
-        args.extend(mount_params.iter().map(|s| s.as_str()));
-        args.extend(container_envs.iter().map(|s| s.as_str()));
-
         let pyproject_toml_path = execution_storage
             .relative_to_root(
                 execution_storage
@@ -700,26 +1015,74 @@ print("</shinkai-code-result>")
             code_entrypoint.clone().as_str(),
         );
 
-        args.extend([
-            "--workdir",
-            "/app",
-            self.options.code_runner_docker_image_name.as_str(),
-            "/bin/bash",
-            "-c",
-            python_start_script.as_str(),
-        ]);
+        let container_command = vec![
+            String::from("/bin/bash"),
+            String::from("-c"),
+            python_start_script,
+        ];
+
+        if let Some(engine_client) = container_runtime.engine_client() {
+            let mut env = container_envs;
+            // Sent over the engine socket, they never show up in a process command line
+            env.extend(
+                named_envs
+                    .iter()
+                    .map(|(key, value)| format!("{}={}", key, value)),
+            );
+            let spec = ContainerSpec {
+                image,
+                cmd: container_command,
+                env,
+                working_dir: Some(String::from("/app")),
+                mounts,
+            };
+            let stdout = run_in_engine(
+                &engine_client,
+                &spec,
+                &execution_storage,
+                "python",
+                max_execution_timeout,
+            )
+            .await?;
+            home.succeeded()?;
+            return Ok(stdout);
+        }
+
+        let mut command = container_runtime.command();
+        let mut args = vec![String::from("run"), String::from("--rm")];
+        args.extend(container_runtime.run_args());
+        for mount in &mounts {
+            let mount_param = container_runtime.bind_mount(mount);
+            log::info!("mount parameter created: {}", mount_param);
+            args.extend([String::from("--mount"), mount_param]);
+        }
+        for env in container_envs {
+            args.extend([String::from("-e"), env]);
+        }
+        for (key, _) in &named_envs {
+            args.extend([String::from("-e"), key.clone()]);
+        }
+        args.extend([String::from("--workdir"), String::from("/app"), image]);
+        args.extend(container_command);
 
         let command = command
             .args(args)
             .stdout(std::process::Stdio::piped())
             .stderr(std::process::Stdio::piped())
             .kill_on_drop(true);
+        command.envs(named_envs);
 
-        log::info!("spawning docker command: {:?}", command);
+        log::info!(
+            "spawning docker command: {}",
+            execution_storage.redactor.redact(&format!("{:?}", command))
+        );
         let mut child = command.spawn().map_err(|e| {
-            let error_msg = format!("failed to spawn command: {:?} error: {}", command, e);
+            let error_msg = execution_storage.redactor.redact(&format!(
+                "failed to spawn command: {:?} error: {}",
+                command, e
+            ));
             log::error!("{}", error_msg);
-            anyhow::anyhow!("{}", error_msg)
+            InfrastructureError(error_msg)
         })?;
 
         let stdout = child.stdout.take().expect("Failed to get stdout");
@@ -739,9 +1102,16 @@ print("</shinkai-code-result>")
         let stdout_task = tokio::task::spawn_blocking(move || {
             tokio::runtime::Runtime::new().unwrap().block_on(async {
                 while let Ok(Some(line)) = stdout_stream.next_line().await {
-                    log::info!("from python: {}", line);
+                    log::info!(
+                        "from python: {}",
+                        execution_storage_clone.redactor.redact(&line)
+                    );
                     stdout_lines_clone.lock().await.push(line.clone());
-                    let _ = execution_storage_clone.append_log(line.as_str());
+                    let _ = execution_storage_clone.append_log_entry(
+                        LogStream::Stdout,
+                        None,
+                        line.as_str(),
+                    );
                 }
             });
         });
@@ -749,9 +1119,16 @@ print("</shinkai-code-result>")
         let stderr_task = tokio::task::spawn_blocking(move || {
             tokio::runtime::Runtime::new().unwrap().block_on(async {
                 while let Ok(Some(line)) = stderr_stream.next_line().await {
-                    log::info!("from python: {}", line);
+                    log::info!(
+                        "from python: {}",
+                        execution_storage_clone2.redactor.redact(&line)
+                    );
                     stderr_lines_clone.lock().await.push(line.clone());
-                    let _ = execution_storage_clone2.append_log(line.as_str());
+                    let _ = execution_storage_clone2.append_log_entry(
+                        LogStream::Stderr,
+                        None,
+                        line.as_str(),
+                    );
                 }
             });
         });
@@ -779,27 +1156,122 @@ print("</shinkai-code-result>")
         };
         let _ = std_tasks.await;
         if !output.status.success() {
-            let stderr = stderr_lines.lock().await.to_vec().join("\n");
+            let stderr = execution_storage
+                .redactor
+                .redact(&stderr_lines.lock().await.to_vec().join("\n"));
             log::error!("command execution failed: {}", stderr);
-            return Err(anyhow::Error::new(std::io::Error::new(
-                std::io::ErrorKind::Other,
+            // The container never ran the tool, e.g. a rejected mount or a missing interpreter
+            if InfrastructureError::is_infrastructure_exit_code(output.status.code()) {
+                return Err(InfrastructureError(stderr).into());
+            }
+            return Err(anyhow::Error::new(std::io::Error::other(
                 stderr.to_string(),
             )));
         }
 
         let stdout: Vec<String> = stdout_lines.lock().await.to_vec();
-        log::info!("command completed successfully with output: {:?}", stdout);
+        log::info!(
+            "command completed successfully with output: {}",
+            execution_storage.redactor.redact(&format!("{:?}", stdout))
+        );
+        home.succeeded()?;
         Ok(stdout)
     }
 
+    /// Installs python and the `pyproject.toml` dependencies into the sandbox venv, inside the
+    /// sandbox with the host network shared
+    ///
+    /// `uv run` would lock the project first, writing into the read-only code folder, so the
+    /// venv is filled with `uv pip` and the run itself stays offline.
+    async fn install_sandbox_dependencies(
+        &self,
+        execution_storage: &ExecutionStorage,
+        uv_binary_path: &Path,
+        venv_folder_path: &Path,
+    ) -> anyhow::Result<()> {
+        let bind = |path: &Path, readonly: bool| {
+            let path = path.to_string_lossy().to_string();
+            BindMount {
+                source: path.clone(),
+                target: path,
+                readonly,
+            }
+        };
+        let uv_cache_folder_path = execution_storage.python_run_sandbox_uv_cache_folder_path();
+        let python_install_folder_path =
+            execution_storage.python_run_sandbox_python_install_folder_path();
+        let mounts = [
+            bind(&execution_storage.code_folder_path, true),
+            bind(venv_folder_path, false),
+            bind(&uv_cache_folder_path, false),
+            bind(&python_install_folder_path, false),
+            bind(&execution_storage.home_folder_path, false),
+        ];
+        let venv = venv_folder_path.to_string_lossy().to_string();
+        let pyproject_toml = execution_storage
+            .code_folder_path
+            .join(Self::PYPROJECT_TOML_FILE_NAME)
+            .to_string_lossy()
+            .to_string();
+        for (args, step) in [
+            (
+                vec!["venv", "--allow-existing", venv.as_str()],
+                "create the sandbox venv",
+            ),
+            (
+                vec![
+                    "pip",
+                    "install",
+                    "--python",
+                    venv.as_str(),
+                    "-r",
+                    pyproject_toml.as_str(),
+                ],
+                "install dependencies",
+            ),
+        ] {
+            let mut command = self.options.sandbox.fetch_command(
+                uv_binary_path,
+                &mounts,
+                &execution_storage.code_folder_path,
+            )?;
+            command
+                .args(args)
+                .current_dir(&execution_storage.code_folder_path)
+                .env("HOME", &execution_storage.home_folder_path)
+                .env("UV_CACHE_DIR", &uv_cache_folder_path)
+                .env("UV_PYTHON_INSTALL_DIR", &python_install_folder_path);
+            run_step(&mut command, step).await?;
+        }
+        Ok(())
+    }
+
+    /// Runs with the host binary, inside the Linux sandbox for `RunnerType::Sandbox`
     async fn run_in_host(
         &self,
+        runner_type: &RunnerType,
         code_files: CodeFiles,
         envs: Option<HashMap<String, String>>,
+        (configurations, parameters): (&Value, &Value),
+        secrets: &SecretConfigurations,
         max_execution_timeout: Option<Duration>,
     ) -> anyhow::Result<Vec<String>> {
-        let execution_storage = ExecutionStorage::new(code_files, self.options.context.clone());
+        // The sandbox and containers restrict the filesystem themselves
+        let restrict_file_access =
+            self.options.restrict_host_file_access && matches!(runner_type, RunnerType::Host);
+        let mut code_files = code_files;
+        if restrict_file_access {
+            code_files.files.insert(
+                PythonFileAccessPolicy::LAUNCHER_FILE_NAME.to_string(),
+                PythonFileAccessPolicy::LAUNCHER_CODE.to_string(),
+            );
+        }
+        let execution_storage = ExecutionStorage::new(code_files, self.options.context.clone())
+            .with_redactor(secrets.redactor().clone());
+        let _lease = execution_storage.acquire_lease()?;
         execution_storage.init_for_python(None)?;
+        let home = execution_storage.prepare_home()?;
+        let inputs = execution_storage.write_inputs(configurations, parameters)?;
 
         let uv_binary_path = path::absolute(self.options.uv_binary_path.clone())
             .unwrap()
@@ -809,18 +1281,61 @@ print("</shinkai-code-result>")
 
         log::info!("using uv from host at path: {:?}", uv_binary_path.clone());
 
-        let mut command = tokio::process::Command::new(uv_binary_path);
+        let venv_folder_path = match runner_type {
+            RunnerType::Sandbox => execution_storage.python_run_sandbox_venv_folder_path(),
+            _ => execution_storage.python_run_host_venv_folder_path(),
+        };
+        if matches!(runner_type, RunnerType::Sandbox) {
+            self.install_sandbox_dependencies(
+                &execution_storage,
+                Path::new(&uv_binary_path),
+                &venv_folder_path,
+            )
+            .await?;
+        }
+        let mut command = match runner_type {
+            RunnerType::Sandbox => {
+                let mut mounts = execution_storage.sandbox_mounts(&[
+                    execution_storage.python_run_sandbox_uv_cache_folder_path(),
+                    execution_storage.python_run_sandbox_python_install_folder_path(),
+                ]);
+                let inputs_file_path = inputs.path().to_string_lossy().to_string();
+                mounts.push(BindMount {
+                    source: inputs_file_path.clone(),
+                    target: inputs_file_path,
+                    readonly: true,
+                });
+                mounts.extend(CallbackServer::sandbox_mounts(envs.as_ref()));
+                self.options.sandbox.command(
+                    Path::new(&uv_binary_path),
+                    &mounts,
+                    &execution_storage.root_folder_path,
+                )?
+            }
+            _ => tokio::process::Command::new(uv_binary_path),
+        };
 
-        let command = command
-            .arg("run")
-            .args([
+        command.arg("run");
+        match runner_type {
+            // The dependencies are in the venv already, the tool stays off the network
+            RunnerType::Sandbox => command
+                .args(["--offline", "--no-project", "--python"])
+                .arg(venv_folder_path.join("bin").join("python")),
+            _ => command.args([
                 "--project",
                 execution_storage
                     .code_folder_path
                     .join(Self::PYPROJECT_TOML_FILE_NAME)
                     .to_str()
                     .unwrap(),
-            ])
+            ]),
+        };
+        let command = command
+            .args(restrict_file_access.then(|| {
+                execution_storage
+                    .code_folder_path
+                    .join(PythonFileAccessPolicy::LAUNCHER_FILE_NAME)
+            }))
             .arg(execution_storage.code_entrypoint_file_path.clone())
             .current_dir(execution_storage.root_folder_path.clone())
             .stdout(std::process::Stdio::piped())
@@ -829,20 +1344,31 @@ print("</shinkai-code-result>")
 
         command.env(
             "VIRTUAL_ENV",
-            execution_storage
-                .python_run_host_venv_folder_path()
-                .to_string_lossy()
-                .to_string()
-                .as_str(),
+            venv_folder_path.to_string_lossy().to_string().as_str(),
         );
         command.env(
             "UV_PROJECT_ENVIRONMENT",
-            execution_storage
-                .python_run_host_venv_folder_path()
-                .to_string_lossy()
-                .to_string()
-                .as_str(),
+            venv_folder_path.to_string_lossy().to_string().as_str(),
         );
+        if restrict_file_access {
+            command.env(
+                PythonFileAccessPolicy::ENV,
+                PythonFileAccessPolicy::for_execution(&execution_storage, &venv_folder_path)
+                    .env_value(),
+            );
+        }
+        // The host home, uv cache and python installs aren't visible in the sandbox
+        if matches!(runner_type, RunnerType::Sandbox) {
+            command.env("HOME", execution_storage.home_folder_path.clone());
+            command.env(
+                "UV_CACHE_DIR",
+                execution_storage.python_run_sandbox_uv_cache_folder_path(),
+            );
+            command.env(
+                "UV_PYTHON_INSTALL_DIR",
+                execution_storage.python_run_sandbox_python_install_folder_path(),
+            );
+        }
 
         command.env(
             "SHINKAI_NODE_LOCATION",
@@ -883,13 +1409,23 @@ print("</shinkai-code-result>")
             "SHINKAI_EXECUTION_ID",
             self.options.context.execution_id.clone(),
         );
+        command.env(ExecutionStorage::INPUTS_FILE_ENV, inputs.path());
 
         if let Some(envs) = envs {
             command.envs(envs);
         }
-        log::info!("prepared command with arguments: {:?}", command);
+        if !secrets.is_empty() {
+            command.env(SecretConfigurations::ENV, secrets.env_value());
+        }
+        log::info!(
+            "prepared command with arguments: {}",
+            execution_storage.redactor.redact(&format!("{:?}", command))
+        );
         let mut child = command.spawn().map_err(|e| {
-            let error_msg = format!("failed to spawn command: {:?} error: {}", command, e);
+            let error_msg = execution_storage.redactor.redact(&format!(
+                "failed to spawn command: {:?} error: {}",
+                command, e
+            ));
             log::error!("{}", error_msg);
             anyhow::anyhow!("{}", error_msg)
         })?;
@@ -911,9 +1447,16 @@ print("</shinkai-code-result>")
         let stdout_task = tokio::task::spawn_blocking(move || {
             tokio::runtime::Runtime::new().unwrap().block_on(async {
                 while let Ok(Some(line)) = stdout_stream.next_line().await {
-                    log::info!("from python: {}", line);
+                    log::info!(
+                        "from python: {}",
+                        execution_storage_clone.redactor.redact(&line)
+                    );
                     stdout_lines_clone.lock().await.push(line.clone());
-                    let _ = execution_storage_clone.append_log(line.as_str());
+                    let _ = execution_storage_clone.append_log_entry(
+                        LogStream::Stdout,
+                        None,
+                        line.as_str(),
+                    );
                 }
             });
         });
@@ -921,9 +1464,16 @@ print("</shinkai-code-result>")
         let stderr_task = tokio::task::spawn_blocking(move || {
             tokio::runtime::Runtime::new().unwrap().block_on(async {
                 while let Ok(Some(line)) = stderr_stream.next_line().await {
-                    log::info!("from python: {}", line);
+                    log::info!(
+                        "from python: {}",
+                        execution_storage_clone2.redactor.redact(&line)
+                    );
                     stderr_lines_clone.lock().await.push(line.clone());
-                    let _ = execution_storage_clone2.append_log(line.as_str());
+                    let _ = execution_storage_clone2.append_log_entry(
+                        LogStream::Stderr,
+                        None,
+                        line.as_str(),
+                    );
                 }
             });
         });
@@ -951,15 +1501,20 @@ print("</shinkai-code-result>")
         };
         let _ = std_tasks.await;
         if !output.status.success() {
-            let stderr = stderr_lines.lock().await.to_vec().join("\n");
+            let stderr = execution_storage
+                .redactor
+                .redact(&stderr_lines.lock().await.to_vec().join("\n"));
             log::error!("command execution failed: {}", stderr);
-            return Err(anyhow::Error::new(std::io::Error::new(
-                std::io::ErrorKind::Other,
+            return Err(anyhow::Error::new(std::io::Error::other(
                 stderr.to_string(),
             )));
         }
         let stdout: Vec<String> = stdout_lines.lock().await.to_vec();
-        log::info!("command completed successfully with output: {:?}", stdout);
+        log::info!(
+            "command completed successfully with output: {}",
+            execution_storage.redactor.redact(&format!("{:?}", stdout))
+        );
+        home.succeeded()?;
         Ok(stdout)
     }
 
@@ -1,12 +1,18 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::Arc};
 
+use crate::tools::execution_error::ExecutionError;
 use crate::tools::runner_type::RunnerType;
+use crate::tools::tool_call::{ToolCallConfig, ToolResolver};
+use futures::future::BoxFuture;
 use rstest::rstest;
 use serde_json::{json, Value};
 
 use crate::tools::execution_context::ExecutionContext;
+use crate::tools::execution_storage::ExecutionStorage;
 use crate::tools::python_runner_options::PythonRunnerOptions;
 use crate::tools::shinkai_node_location::ShinkaiNodeLocation;
+#[cfg(unix)]
+use crate::tools::test_utils::fake_binary;
 use crate::tools::{code_files::CodeFiles, python_runner::PythonRunner};
 
 #[rstest]
@@ -54,6 +60,59 @@ def run(configurations, parameters):
     assert_eq!(result.data.get("message").unwrap(), "hello world");
 }
 
+#[rstest]
+#[case::host(RunnerType::Host)]
+#[case::docker(RunnerType::Docker)]
+#[tokio::test]
+async fn code_folder_is_reused_across_inputs(#[case] runner_type: RunnerType) {
+    let _ = env_logger::builder()
+        .filter_level(log::LevelFilter::Info)
+        .is_test(true)
+        .try_init();
+
+    let code_files = CodeFiles {
+        files: HashMap::from([(
+            "main.py".to_string(),
+            r#"
+class CONFIG:
+    greeting: str
+
+class INPUTS:
+    name: str
+
+def run(configurations: CONFIG, parameters: INPUTS):
+    return { 'message': configurations.greeting + ' ' + parameters.name }
+            "#
+            .to_string(),
+        )]),
+        entrypoint: "main.py".to_string(),
+    };
+    let context = ExecutionContext {
+        context_id: nanoid::nanoid!(),
+        ..Default::default()
+    };
+    for name in ["python", "again"] {
+        let python_runner = PythonRunner::new(
+            code_files.clone(),
+            json!({ "greeting": "hi" }),
+            Some(PythonRunnerOptions {
+                context: context.clone(),
+                force_runner_type: Some(runner_type.clone()),
+                ..Default::default()
+            }),
+        );
+        let result = python_runner
+            .run(None, json!({ "name": name }), None)
+            .await
+            .unwrap();
+        assert_eq!(result.data["message"], format!("hi {}", name));
+    }
+
+    let storage = ExecutionStorage::new(code_files, context);
+    let code_folders = std::fs::read_dir(storage.root_folder_path.join("code")).unwrap();
+    assert_eq!(code_folders.count(), 1);
+}
+
 #[rstest]
 #[case::host(RunnerType::Host)]
 #[case::docker(RunnerType::Docker)]
@@ -1731,12 +1790,18 @@ async def run(c: CONFIG, p: INPUTS) -> OUTPUT:
         });
     assert!(result.is_ok());
 
-    let pyproject_toml_path = context
-        .storage
-        .join(context_id)
-        .join("code")
-        .join(code_id)
-        .join("pyproject.toml");
+    // Code folders are named after the code id and the code content hash
+    let code_folder = std::fs::read_dir(context.storage.join(context_id).join("code"))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .find(|path| {
+            path.file_name()
+                .unwrap()
+                .to_string_lossy()
+                .starts_with(&code_id)
+        })
+        .unwrap();
+    let pyproject_toml_path = code_folder.join("pyproject.toml");
     println!("pyproject_toml_path: {:?}", pyproject_toml_path);
     assert!(pyproject_toml_path.exists());
     let pyproject_toml_content = std::fs::read_to_string(pyproject_toml_path).unwrap();
@@ -1905,3 +1970,315 @@ async def run(config: CONFIG, inputs: INPUTS) -> OUTPUT:
         .unwrap()
         .contains("Hello, world parameters!"));
 }
+
+#[rstest]
+#[case::host(RunnerType::Host)]
+#[case::docker(RunnerType::Docker)]
+#[tokio::test]
+async fn definition_from_code(#[case] runner_type: RunnerType) {
+    let _ = env_logger::builder()
+        .filter_level(log::LevelFilter::Info)
+        .is_test(true)
+        .try_init();
+
+    let code_files = CodeFiles {
+        files: HashMap::from([(
+            "main.py".to_string(),
+            r#"
+from typing import List, Optional
+
+metadata = {
+    "id": "shinkai-tool-echo",
+    "name": "Shinkai: Echo",
+    "description": "Echoes the message",
+    "author": "Shinkai",
+    "keywords": ["echo"],
+}
+
+class CONFIG:
+    prefix: Optional[str]
+
+class INPUTS:
+    message: str
+    repeat: int = 1
+
+class OUTPUT:
+    messages: List[str]
+
+async def run(c: CONFIG, p: INPUTS) -> OUTPUT:
+    raise Exception("run should not be executed")
+            "#
+            .to_string(),
+        )]),
+        entrypoint: "main.py".to_string(),
+    };
+
+    let python_runner = PythonRunner::new(
+        code_files,
+        Value::Null,
+        Some(PythonRunnerOptions {
+            force_runner_type: Some(runner_type),
+            ..Default::default()
+        }),
+    );
+
+    let definition = python_runner.definition().await.unwrap();
+    assert_eq!(definition.id, "shinkai-tool-echo");
+    assert_eq!(definition.name, "Shinkai: Echo");
+    assert_eq!(definition.keywords, vec!["echo".to_string()]);
+    assert_eq!(
+        definition.parameters["properties"]["message"]["type"],
+        "string"
+    );
+    assert_eq!(
+        definition.parameters["properties"]["repeat"]["type"],
+        "integer"
+    );
+    assert_eq!(definition.parameters["required"], json!(["message"]));
+    assert_eq!(
+        definition.configurations["properties"]["prefix"]["type"],
+        "string"
+    );
+    assert_eq!(
+        definition.result["properties"]["messages"]["items"]["type"],
+        "string"
+    );
+    assert!(definition
+        .code
+        .unwrap()
+        .contains("run should not be executed"));
+}
+
+struct EchoResolver;
+
+impl ToolResolver for EchoResolver {
+    fn call_tool(
+        &self,
+        tool_id: String,
+        parameters: Value,
+        tool_calls: ToolCallConfig,
+    ) -> BoxFuture<'_, Result<Value, ExecutionError>> {
+        Box::pin(async move {
+            Ok(json!({
+                "tool_id": tool_id,
+                "parameters": parameters,
+                "call_chain": tool_calls.call_chain,
+            }))
+        })
+    }
+}
+
+#[rstest]
+#[case::host(RunnerType::Host)]
+#[case::docker(RunnerType::Docker)]
+#[tokio::test]
+async fn call_other_tool(#[case] runner_type: RunnerType) {
+    let _ = env_logger::builder()
+        .filter_level(log::LevelFilter::Info)
+        .is_test(true)
+        .try_init();
+
+    let code_files = CodeFiles {
+        files: HashMap::from([(
+            "main.py".to_string(),
+            r#"
+async def run(c, p):
+    child = shinkai_call_tool("child", {"message": p.message})
+    cycle_error = ""
+    try:
+        shinkai_call_tool("parent", {})
+    except RuntimeError as e:
+        cycle_error = str(e)
+    return {"child": child, "cycle_error": cycle_error}
+            "#
+            .to_string(),
+        )]),
+        entrypoint: "main.py".to_string(),
+    };
+
+    let python_runner = PythonRunner::new(
+        code_files,
+        json!({}),
+        Some(PythonRunnerOptions {
+            force_runner_type: Some(runner_type),
+            tool_calls: Some(ToolCallConfig::new(Arc::new(EchoResolver), "parent")),
+            ..Default::default()
+        }),
+    );
+
+    let result = python_runner
+        .run(None, json!({ "message": "hi" }), None)
+        .await
+        .unwrap();
+
+    assert_eq!(result.data["child"]["tool_id"], "child");
+    assert_eq!(result.data["child"]["parameters"]["message"], "hi");
+    assert_eq!(
+        result.data["child"]["call_chain"],
+        json!(["parent", "child"])
+    );
+    assert!(result.data["cycle_error"]
+        .as_str()
+        .unwrap()
+        .contains("cycle detected"));
+}
+
+#[rstest]
+#[case::host(RunnerType::Host)]
+#[tokio::test]
+async fn write_forbidden_folder(#[case] runner_type: RunnerType) {
+    let _ = env_logger::builder()
+        .filter_level(log::LevelFilter::Info)
+        .is_test(true)
+        .try_init();
+
+    let forbidden_folder = tempfile::tempdir_in(".").unwrap();
+    let code_files = CodeFiles {
+        files: HashMap::from([(
+            "main.py".to_string(),
+            format!(
+                r#"
+def run(configurations, parameters):
+    with open("{}/test.txt", "w") as file:
+        file.write("This should fail")
+    return "write succeeded"
+            "#,
+                forbidden_folder.path().to_string_lossy()
+            ),
+        )]),
+        entrypoint: "main.py".to_string(),
+    };
+    let python_runner = PythonRunner::new(
+        code_files,
+        json!({}),
+        Some(PythonRunnerOptions {
+            force_runner_type: Some(runner_type),
+            ..Default::default()
+        }),
+    );
+
+    let result = python_runner.run(None, json!({}), None).await;
+    assert!(result.is_err());
+    assert!(result.unwrap_err().message().contains("PermissionError"));
+    assert!(!forbidden_folder.path().join("test.txt").exists());
+}
+
+#[rstest]
+#[case::host(RunnerType::Host)]
+#[tokio::test]
+async fn read_forbidden_folder(#[case] runner_type: RunnerType) {
+    let _ = env_logger::builder()
+        .filter_level(log::LevelFilter::Info)
+        .is_test(true)
+        .try_init();
+
+    let forbidden_folder = tempfile::tempdir_in(".").unwrap();
+    std::fs::write(forbidden_folder.path().join("secret.txt"), "secret").unwrap();
+    let code_files = CodeFiles {
+        files: HashMap::from([(
+            "main.py".to_string(),
+            format!(
+                r#"
+def run(configurations, parameters):
+    with open("{}/secret.txt") as file:
+        return file.read()
+            "#,
+                forbidden_folder.path().to_string_lossy()
+            ),
+        )]),
+        entrypoint: "main.py".to_string(),
+    };
+    let python_runner = PythonRunner::new(
+        code_files,
+        json!({}),
+        Some(PythonRunnerOptions {
+            force_runner_type: Some(runner_type),
+            ..Default::default()
+        }),
+    );
+
+    let result = python_runner.run(None, json!({}), None).await;
+    assert!(result.is_err());
+    assert!(result.unwrap_err().message().contains("PermissionError"));
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn sandbox_installs_dependencies_before_an_offline_run() {
+    use crate::tools::linux_sandbox::LinuxSandbox;
+
+    let folder = tempfile::tempdir().unwrap();
+    // Records each call on a line, then runs the program after `--`
+    let bwrap_binary_path = fake_binary(
+        folder.path(),
+        "bwrap",
+        &format!(
+            r#"echo "$@" >> "{folder}/bwrap-args"
+while [ "$1" != "--" ]; do shift; done
+shift
+exec "$@""#,
+            folder = folder.path().display()
+        ),
+    );
+    let uv_binary_path = fake_binary(
+        folder.path(),
+        "uv",
+        r#"if [ "$1" = "run" ]; then
+  echo "<shinkai-code-result>"
+  echo '{"message":"hello from the sandbox"}'
+  echo "</shinkai-code-result>"
+fi"#,
+    );
+    let code_files = CodeFiles {
+        files: HashMap::from([(
+            "main.py".to_string(),
+            r#"
+# /// script
+# dependencies = [
+#   "requests",
+# ]
+# ///
+def run(configurations, parameters):
+    return { 'message': 'hello from the sandbox' }
+            "#
+            .to_string(),
+        )]),
+        entrypoint: "main.py".to_string(),
+    };
+    let python_runner = PythonRunner::new(
+        code_files,
+        json!({}),
+        Some(PythonRunnerOptions {
+            context: ExecutionContext {
+                storage: folder.path().join("storage"),
+                ..Default::default()
+            },
+            uv_binary_path,
+            force_runner_type: Some(RunnerType::Sandbox),
+            sandbox: LinuxSandbox {
+                bwrap_binary_path,
+                ..Default::default()
+            },
+            ..Default::default()
+        }),
+    );
+
+    let result = python_runner.run(None, json!({}), None).await.unwrap();
+    assert_eq!(result.data["message"], "hello from the sandbox");
+    let calls = std::fs::read_to_string(folder.path().join("bwrap-args")).unwrap();
+    let calls = calls.lines().collect::<Vec<_>>();
+    assert_eq!(calls.len(), 3);
+    // Python and the dependencies are installed with the network shared
+    assert!(calls[0].contains("--share-net"), "{}", calls[0]);
+    assert!(calls[0].contains(" venv --allow-existing "), "{}", calls[0]);
+    assert!(calls[1].contains("--share-net"), "{}", calls[1]);
+    assert!(calls[1].contains(" pip install --python "), "{}", calls[1]);
+    assert!(calls[1].ends_with("pyproject.toml"), "{}", calls[1]);
+    // The tool runs offline on the installed venv
+    assert!(!calls[2].contains("--share-net"), "{}", calls[2]);
+    assert!(
+        calls[2].contains(" run --offline --no-project --python "),
+        "{}",
+        calls[2]
+    );
+}
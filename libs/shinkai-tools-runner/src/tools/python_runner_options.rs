@@ -1,8 +1,15 @@
-use std::path::PathBuf;
+use std::{path::PathBuf, sync::Arc};
 
 use super::{
-    execution_context::ExecutionContext, runner_type::RunnerType,
+    container_runtime::ContainerRuntime,
+    execution_context::ExecutionContext,
+    host_capabilities::HostCapabilities,
+    linux_sandbox::LinuxSandbox,
+    redaction::RedactionRule,
+    runner_type::{RunnerPolicy, RunnerType},
     shinkai_node_location::ShinkaiNodeLocation,
+    tool_call::ToolCallConfig,
+    tool_signature::{ToolSignature, TrustStore},
 };
 
 #[derive(Clone)]
@@ -10,8 +17,29 @@ pub struct PythonRunnerOptions {
     pub context: ExecutionContext,
     pub uv_binary_path: PathBuf,
     pub code_runner_docker_image_name: String,
+    /// Pins the code runner image, it is pulled by digest and verified before running
+    pub code_runner_docker_image_digest: Option<String>,
     pub force_runner_type: Option<RunnerType>,
+    /// Runner types allowed when `force_runner_type` isn't set, and whether Docker falls back to Host
+    pub runner_policy: RunnerPolicy,
+    /// Container runtime used by the Docker runner type, detected when None
+    pub container_runtime: Option<ContainerRuntime>,
+    /// bubblewrap sandbox used by the Sandbox runner type
+    pub sandbox: LinuxSandbox,
+    /// Limits host runs to reading the code, venv, assets and mounts and to writing home and
+    /// mounts, see `PythonFileAccessPolicy`
+    pub restrict_host_file_access: bool,
     pub shinkai_node_location: ShinkaiNodeLocation,
+    pub trust_store: TrustStore,
+    pub code_signature: Option<ToolSignature>,
+    /// Lets the tool call other tools through the callback server
+    pub tool_calls: Option<ToolCallConfig>,
+    /// Secrets, key-value store, http and llm access mediated by the embedding application
+    pub host_capabilities: Option<Arc<dyn HostCapabilities>>,
+    /// Top level configurations keys delivered out-of-band and redacted from logs
+    pub secret_configuration_keys: Vec<String>,
+    /// Extra redaction applied to logs and execution log entries
+    pub redaction_rules: Vec<Arc<dyn RedactionRule>>,
 }
 
 impl Default for PythonRunnerOptions {
@@ -19,17 +47,28 @@ impl Default for PythonRunnerOptions {
         Self {
             context: ExecutionContext::default(),
             code_runner_docker_image_name: String::from("dcspark/shinkai-code-runner:0.9.4"),
+            code_runner_docker_image_digest: None,
             uv_binary_path: PathBuf::from(if cfg!(windows) {
                 "./shinkai-tools-runner-resources/uv.exe"
             } else {
                 "./shinkai-tools-runner-resources/uv"
             }),
             force_runner_type: None,
+            runner_policy: RunnerPolicy::default(),
+            container_runtime: None,
+            sandbox: LinuxSandbox::default(),
+            restrict_host_file_access: true,
             shinkai_node_location: ShinkaiNodeLocation {
                 protocol: String::from("http"),
                 host: String::from("127.0.0.1"),
                 port: 9550,
             },
+            trust_store: TrustStore::default(),
+            code_signature: None,
+            tool_calls: None,
+            host_capabilities: None,
+            secret_configuration_keys: Vec::new(),
+            redaction_rules: Vec::new(),
         }
     }
 }
@@ -0,0 +1,182 @@
+use std::sync::Arc;
+
+use regex::Regex;
+use serde_json::Value;
+
+/// Custom redaction applied to log text after known secrets are replaced
+pub trait RedactionRule: Send + Sync {
+    fn redact(&self, text: &str) -> String;
+}
+
+/// Replaces every match of a regex, e.g. `Bearer [A-Za-z0-9._-]+`
+#[derive(Debug, Clone)]
+pub struct RegexRedactionRule {
+    regex: Regex,
+    replacement: String,
+}
+
+impl RegexRedactionRule {
+    pub fn new(pattern: &str) -> anyhow::Result<Self> {
+        Ok(RegexRedactionRule {
+            regex: Regex::new(pattern)?,
+            replacement: Redactor::PLACEHOLDER.to_string(),
+        })
+    }
+
+    pub fn with_replacement(mut self, replacement: &str) -> Self {
+        self.replacement = replacement.to_string();
+        self
+    }
+}
+
+impl RedactionRule for RegexRedactionRule {
+    fn redact(&self, text: &str) -> String {
+        self.regex
+            .replace_all(text, self.replacement.as_str())
+            .to_string()
+    }
+}
+
+/// Replaces known secret values in text before it reaches logs, then applies the redaction rules
+#[derive(Clone, Default)]
+pub struct Redactor {
+    secrets: Vec<String>,
+    rules: Vec<Arc<dyn RedactionRule>>,
+}
+
+impl std::fmt::Debug for Redactor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Never print the secrets themselves
+        f.debug_struct("Redactor")
+            .field("secrets", &self.secrets.len())
+            .field("rules", &self.rules.len())
+            .finish()
+    }
+}
+
+impl Redactor {
+    pub const PLACEHOLDER: &'static str = "[REDACTED]";
+    pub const MIN_VALUE_SECRET_LENGTH: usize = 4;
+
+    pub fn new(secrets: impl IntoIterator<Item = String>) -> Self {
+        let mut redactor = Redactor::default();
+        for secret in secrets {
+            redactor.add_secret(&secret);
+        }
+        redactor
+    }
+
+    /// Redacts the string leaves of `value`, see `Redactor::add_value`
+    pub fn from_value(value: &Value) -> Self {
+        let mut redactor = Redactor::default();
+        redactor.add_value(value);
+        redactor
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.secrets.is_empty() && self.rules.is_empty()
+    }
+
+    pub fn with_rule(mut self, rule: Arc<dyn RedactionRule>) -> Self {
+        self.add_rule(rule);
+        self
+    }
+
+    pub fn add_rule(&mut self, rule: Arc<dyn RedactionRule>) {
+        self.rules.push(rule);
+    }
+
+    pub fn add_secret(&mut self, secret: &str) {
+        if secret.is_empty() {
+            return;
+        }
+        // Secrets also show up JSON escaped when logged inside serialized values
+        let escaped = serde_json::to_string(secret).unwrap_or_default();
+        let escaped = escaped.trim_matches('"');
+        for candidate in [secret, escaped] {
+            if !candidate.is_empty() && !self.secrets.iter().any(|s| s == candidate) {
+                self.secrets.push(candidate.to_string());
+            }
+        }
+        // Longest first so a secret containing another one is fully redacted
+        self.secrets.sort_by_key(|s| std::cmp::Reverse(s.len()));
+    }
+
+    /// Registers the string leaves of `value` as secrets
+    ///
+    /// Numbers and strings shorter than `MIN_VALUE_SECRET_LENGTH` are skipped, values like `1`
+    /// or `30` would blank unrelated text in every log line.
+    pub fn add_value(&mut self, value: &Value) {
+        match value {
+            Value::String(secret) if secret.chars().count() >= Self::MIN_VALUE_SECRET_LENGTH => {
+                self.add_secret(secret)
+            }
+            Value::String(_) | Value::Number(_) => {}
+            Value::Array(values) => values.iter().for_each(|value| self.add_value(value)),
+            Value::Object(map) => map.values().for_each(|value| self.add_value(value)),
+            Value::Bool(_) | Value::Null => {}
+        }
+    }
+
+    pub fn redact(&self, text: &str) -> String {
+        let mut redacted = text.to_string();
+        for secret in self.secrets.iter() {
+            if redacted.contains(secret.as_str()) {
+                redacted = redacted.replace(secret.as_str(), Self::PLACEHOLDER);
+            }
+        }
+        for rule in self.rules.iter() {
+            redacted = rule.redact(&redacted);
+        }
+        redacted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn redacts_secrets_and_their_json_escaped_form() {
+        let redactor = Redactor::from_value(&json!({
+            "api_key": "sk-123",
+            "nested": { "password": "p\"ss" },
+            "enabled": true,
+        }));
+        assert_eq!(
+            redactor.redact("key=sk-123 password={\"value\":\"p\\\"ss\"} other=p\"ss"),
+            "key=[REDACTED] password={\"value\":\"[REDACTED]\"} other=[REDACTED]"
+        );
+        assert_eq!(redactor.redact("true"), "true");
+        assert!(Redactor::new([String::new()]).is_empty());
+    }
+
+    #[test]
+    fn skips_numbers_and_short_values() {
+        let redactor = Redactor::from_value(&json!({
+            "retries": 30,
+            "region": "eu",
+            "token": "t0ken",
+        }));
+        assert_eq!(
+            redactor.redact("retried 30 times in eu with t0ken"),
+            "retried 30 times in eu with [REDACTED]"
+        );
+    }
+
+    #[test]
+    fn applies_redaction_rules_after_secrets() {
+        let redactor = Redactor::new(["sk-123".to_string()]).with_rule(Arc::new(
+            RegexRedactionRule::new(r"Bearer [A-Za-z0-9._-]+")
+                .unwrap()
+                .with_replacement("Bearer ***"),
+        ));
+        assert_eq!(
+            redactor.redact("key sk-123, header Authorization: Bearer abc.def"),
+            "key [REDACTED], header Authorization: Bearer ***"
+        );
+        assert!(!format!("{:?}", redactor).contains("sk-123"));
+    }
+}
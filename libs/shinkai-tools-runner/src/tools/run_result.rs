@@ -1,7 +1,20 @@
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
+use super::runner_type::RunnerType;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RunResult {
     pub data: Value,
+    #[serde(default)]
+    pub metadata: RunMetadata,
+}
+
+/// How the tool was executed
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RunMetadata {
+    /// Runner type that produced the result
+    pub runner_type: Option<RunnerType>,
+    /// Infrastructure error of the preferred runner type when it fell back to Host
+    pub fallback_reason: Option<String>,
 }
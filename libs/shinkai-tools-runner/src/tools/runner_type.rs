@@ -1,25 +1,137 @@
-use super::container_utils::{is_docker_available, DockerStatus};
+use std::future::Future;
 
-#[derive(Clone)]
+use serde::{Deserialize, Serialize};
+
+use super::{
+    container_runtime::ContainerRuntime,
+    container_utils::DockerStatus,
+    execution_error::{ExecutionError, ExecutionErrorKind},
+    run_result::RunMetadata,
+};
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum RunnerType {
     Host,
     Docker,
+    /// Host binaries inside a bubblewrap sandbox, Linux only, see `LinuxSandbox`
+    Sandbox,
+}
+
+/// Which runner types a tool may run with when `force_runner_type` isn't set
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum RunnerPolicy {
+    /// Always Docker, infrastructure failures are returned as errors
+    DockerOnly,
+    /// Always Host
+    HostOnly,
+    /// Docker when a container runtime is running, Host otherwise or when Docker fails before the tool starts
+    #[default]
+    DockerPreferred,
+}
+
+impl RunnerPolicy {
+    /// Whether a failed run should be retried with the Host runner type
+    ///
+    /// Only infrastructure failures fall back, a tool failing in Docker would fail on Host too.
+    pub fn should_fall_back_to_host(
+        &self,
+        force_runner_type: Option<&RunnerType>,
+        runner_type: &RunnerType,
+        error: &ExecutionError,
+    ) -> bool {
+        *self == RunnerPolicy::DockerPreferred
+            && force_runner_type.is_none()
+            && *runner_type == RunnerType::Docker
+            && *error.kind() == ExecutionErrorKind::Infrastructure
+    }
+
+    /// Runs `run` with `runner_type`, then with Host when `should_fall_back_to_host` allows it
+    ///
+    /// Returns the output with how it was produced.
+    pub async fn run_with_fallback<T, Fut>(
+        &self,
+        force_runner_type: Option<&RunnerType>,
+        runner_type: RunnerType,
+        run: impl Fn(RunnerType) -> Fut,
+    ) -> Result<(T, RunMetadata), ExecutionError>
+    where
+        Fut: Future<Output = Result<T, ExecutionError>>,
+    {
+        match run(runner_type.clone()).await {
+            Err(e) if self.should_fall_back_to_host(force_runner_type, &runner_type, &e) => {
+                log::warn!(
+                    "docker runner failed before the tool started, falling back to host: {}",
+                    e.message()
+                );
+                let output = run(RunnerType::Host).await?;
+                let metadata = RunMetadata {
+                    runner_type: Some(RunnerType::Host),
+                    fallback_reason: Some(e.message().to_string()),
+                };
+                Ok((output, metadata))
+            }
+            result => {
+                let metadata = RunMetadata {
+                    runner_type: Some(runner_type),
+                    fallback_reason: None,
+                };
+                Ok((result?, metadata))
+            }
+        }
+    }
 }
 
 pub fn resolve_runner_type(force_runner_type: Option<RunnerType>) -> RunnerType {
     if let Some(force_runner_type) = force_runner_type {
         return force_runner_type.clone();
     }
-    if is_docker_available() == DockerStatus::Running {
+    if ContainerRuntime::docker().cached_status() == DockerStatus::Running {
+        RunnerType::Docker
+    } else {
+        RunnerType::Host
+    }
+}
+
+/// Picks Docker when the configured container runtime, or any detected one, is running
+pub fn resolve_runner_type_for_runtime(
+    force_runner_type: Option<RunnerType>,
+    container_runtime: Option<&ContainerRuntime>,
+) -> RunnerType {
+    if let Some(force_runner_type) = force_runner_type {
+        return force_runner_type;
+    }
+    let is_running = match container_runtime {
+        Some(container_runtime) => container_runtime.cached_status() == DockerStatus::Running,
+        None => ContainerRuntime::detect().is_some(),
+    };
+    if is_running {
         RunnerType::Docker
     } else {
         RunnerType::Host
     }
 }
 
+/// Runner type for the policy, `force_runner_type` takes precedence
+pub fn resolve_runner_type_for_policy(
+    force_runner_type: Option<RunnerType>,
+    runner_policy: &RunnerPolicy,
+    container_runtime: Option<&ContainerRuntime>,
+) -> RunnerType {
+    if let Some(force_runner_type) = force_runner_type {
+        return force_runner_type;
+    }
+    match runner_policy {
+        RunnerPolicy::DockerOnly => RunnerType::Docker,
+        RunnerPolicy::HostOnly => RunnerType::Host,
+        RunnerPolicy::DockerPreferred => resolve_runner_type_for_runtime(None, container_runtime),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::tools::container_utils::is_docker_available;
 
     #[tokio::test]
     async fn test_resolve_runner_type() {
@@ -48,4 +160,56 @@ mod tests {
                     && matches!(runner_type, RunnerType::Docker))
         );
     }
+
+    #[tokio::test]
+    async fn test_resolve_runner_type_for_missing_runtime() {
+        let container_runtime = ContainerRuntime::new(
+            crate::tools::container_runtime::ContainerRuntimeKind::Podman,
+            std::path::PathBuf::from("/nonexistent/shinkai/podman"),
+        );
+        let runner_type = resolve_runner_type_for_runtime(None, Some(&container_runtime));
+        assert!(matches!(runner_type, RunnerType::Host));
+    }
+
+    #[test]
+    fn test_resolve_runner_type_for_policy() {
+        let missing_runtime = ContainerRuntime::new(
+            crate::tools::container_runtime::ContainerRuntimeKind::Docker,
+            std::path::PathBuf::from("/nonexistent/shinkai/docker"),
+        );
+        let resolve = |force: Option<RunnerType>, policy: RunnerPolicy| {
+            resolve_runner_type_for_policy(force, &policy, Some(&missing_runtime))
+        };
+        assert_eq!(resolve(None, RunnerPolicy::DockerOnly), RunnerType::Docker);
+        assert_eq!(resolve(None, RunnerPolicy::HostOnly), RunnerType::Host);
+        assert_eq!(
+            resolve(None, RunnerPolicy::DockerPreferred),
+            RunnerType::Host
+        );
+        assert_eq!(
+            resolve(Some(RunnerType::Docker), RunnerPolicy::HostOnly),
+            RunnerType::Docker
+        );
+    }
+
+    #[test]
+    fn test_only_infrastructure_errors_fall_back_to_host() {
+        let infrastructure = ExecutionError::new(String::from("no such image"), None)
+            .with_kind(ExecutionErrorKind::Infrastructure);
+        let tool_failure = ExecutionError::new(String::from("tool threw"), None);
+        let policy = RunnerPolicy::DockerPreferred;
+        assert!(policy.should_fall_back_to_host(None, &RunnerType::Docker, &infrastructure));
+        assert!(!policy.should_fall_back_to_host(None, &RunnerType::Docker, &tool_failure));
+        assert!(!policy.should_fall_back_to_host(None, &RunnerType::Host, &infrastructure));
+        assert!(!policy.should_fall_back_to_host(
+            Some(&RunnerType::Docker),
+            &RunnerType::Docker,
+            &infrastructure
+        ));
+        assert!(!RunnerPolicy::DockerOnly.should_fall_back_to_host(
+            None,
+            &RunnerType::Docker,
+            &infrastructure
+        ));
+    }
 }
@@ -0,0 +1,149 @@
+use std::sync::Arc;
+
+use serde_json::{Map, Value};
+
+use super::redaction::{RedactionRule, Redactor};
+
+/// Configurations fields delivered to the tool out-of-band
+///
+/// Secret fields are removed from the configurations embedded in the generated entrypoint and
+/// passed through the `SHINKAI_SECRET_CONFIGURATIONS` environment variable instead, the wrapper
+/// merges them back before calling `run`. Their values are redacted from logs.
+#[derive(Debug, Clone, Default)]
+pub struct SecretConfigurations {
+    values: Map<String, Value>,
+    redactor: Redactor,
+}
+
+impl SecretConfigurations {
+    pub const ENV: &'static str = "SHINKAI_SECRET_CONFIGURATIONS";
+
+    /// Merges the secrets into `configurations`, appended after it's declared in the Deno wrapper
+    const DENO_MERGE_CODE: &'static str = r#"Object.assign(configurations, JSON.parse(Deno.env.get("SHINKAI_SECRET_CONFIGURATIONS") ?? "{}"));"#;
+
+    /// Merges the secrets into `configurations`, appended after it's declared in the Node.js wrapper
+    const NODE_MERGE_CODE: &'static str = r#"Object.assign(configurations, JSON.parse(process.env.SHINKAI_SECRET_CONFIGURATIONS ?? "{}"));"#;
+
+    /// Merges the secrets into `configurations`, appended after it's declared in the Python wrapper
+    const PYTHON_MERGE_CODE: &'static str = r#"
+import os as _os
+for _key, _value in json.loads(_os.environ.get("SHINKAI_SECRET_CONFIGURATIONS", "{}")).items():
+    if isinstance(configurations, dict):
+        configurations[_key] = _value
+    else:
+        setattr(configurations, _key, _value)
+"#;
+
+    /// Splits top level `secret_keys` out of `configurations`, returning the public part
+    pub fn split(configurations: &Value, secret_keys: &[String]) -> (Value, Self) {
+        let mut public_configurations = configurations.clone();
+        let mut values = Map::new();
+        if let Some(object) = public_configurations.as_object_mut() {
+            for key in secret_keys {
+                if let Some(value) = object.remove(key) {
+                    values.insert(key.clone(), value);
+                }
+            }
+        }
+        let redactor = Redactor::from_value(&Value::Object(values.clone()));
+        (
+            public_configurations,
+            SecretConfigurations { values, redactor },
+        )
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Adds custom rules to the redactor applied to logs
+    pub fn with_redaction_rules(mut self, rules: &[Arc<dyn RedactionRule>]) -> Self {
+        for rule in rules {
+            self.redactor.add_rule(rule.clone());
+        }
+        self
+    }
+
+    pub fn redactor(&self) -> &Redactor {
+        &self.redactor
+    }
+
+    pub fn deno_merge_code(&self) -> &'static str {
+        if self.is_empty() {
+            ""
+        } else {
+            Self::DENO_MERGE_CODE
+        }
+    }
+
+    pub fn node_merge_code(&self) -> &'static str {
+        if self.is_empty() {
+            ""
+        } else {
+            Self::NODE_MERGE_CODE
+        }
+    }
+
+    pub fn python_merge_code(&self) -> &'static str {
+        if self.is_empty() {
+            ""
+        } else {
+            Self::PYTHON_MERGE_CODE
+        }
+    }
+
+    /// The secret configurations as a JSON object
+    pub fn value(&self) -> Value {
+        Value::Object(self.values.clone())
+    }
+
+    /// Value of the `SHINKAI_SECRET_CONFIGURATIONS` environment variable
+    pub fn env_value(&self) -> String {
+        self.value().to_string()
+    }
+
+    /// The configurations with every secret field replaced by a placeholder, safe to log
+    pub fn masked(&self, configurations: &Value) -> Value {
+        let mut masked = configurations.clone();
+        if let Some(object) = masked.as_object_mut() {
+            for key in self.values.keys() {
+                if object.contains_key(key) {
+                    object.insert(
+                        key.clone(),
+                        Value::String(Redactor::PLACEHOLDER.to_string()),
+                    );
+                }
+            }
+        }
+        masked
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn split_removes_secret_keys() {
+        let configurations = json!({ "api_key": "sk-123", "model": "gpt" });
+        let (public_configurations, secrets) = SecretConfigurations::split(
+            &configurations,
+            &["api_key".to_string(), "missing".to_string()],
+        );
+        assert_eq!(public_configurations, json!({ "model": "gpt" }));
+        assert_eq!(secrets.env_value(), r#"{"api_key":"sk-123"}"#);
+        assert_eq!(
+            secrets.masked(&configurations),
+            json!({ "api_key": "[REDACTED]", "model": "gpt" })
+        );
+        assert_eq!(
+            secrets.redactor().redact("using sk-123"),
+            "using [REDACTED]"
+        );
+
+        let (_, secrets) = SecretConfigurations::split(&configurations, &[]);
+        assert!(secrets.is_empty());
+    }
+}
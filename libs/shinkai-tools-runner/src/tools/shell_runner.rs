@@ -0,0 +1,608 @@
+use serde_json::Value;
+use std::{
+    collections::{HashMap, HashSet},
+    path,
+    time::Duration,
+};
+
+use crate::tools::{
+    container_runtime::{BindMount, ContainerRuntime},
+    docker_engine_api::{run_in_engine, ContainerSpec},
+    file_name_utils::{adapt_paths_in_value, normalize_for_docker_path},
+    image_manager::ImageManager,
+    path_buf_ext::PathBufExt,
+    process_output::{parse_code_result, wait_for_output},
+    runner_type::resolve_runner_type_for_policy,
+    secret_configurations::SecretConfigurations,
+};
+
+use super::{
+    code_files::CodeFiles,
+    execution_error::{ExecutionError, InfrastructureError},
+    execution_storage::ExecutionStorage,
+    run_result::RunResult,
+    runner_type::RunnerType,
+    shell_runner_options::ShellRunnerOptions,
+};
+
+/// Environment variable names and values
+type Envs = Vec<(String, String)>;
+
+/// Runs `.sh` tools, quick shell pipelines that don't need a full language runtime
+///
+/// The script gets the inputs as JSON in the `SHINKAI_INPUTS_FILE` file and flattened in
+/// `SHINKAI_CONFIG_*` and `SHINKAI_PARAM_*` variables, secret configurations are only in the
+/// variables. It prints its JSON result between the `<shinkai-code-result>` and
+/// `</shinkai-code-result>` lines.
+#[derive(Default)]
+pub struct ShellRunner {
+    code: CodeFiles,
+    configurations: Value,
+    options: ShellRunnerOptions,
+}
+
+impl ShellRunner {
+    pub const CONFIG_ENV_PREFIX: &'static str = "SHINKAI_CONFIG";
+    pub const PARAM_ENV_PREFIX: &'static str = "SHINKAI_PARAM";
+    /// Larger values are left out of the variables, MAX_ARG_STRLEN caps each one at 128 KiB
+    pub const MAX_ENV_VALUE_BYTES: usize = 64 * 1024;
+    /// Budget of all the input variables, so the spawn or `docker run` stays under ARG_MAX
+    pub const MAX_ENV_TOTAL_BYTES: usize = 512 * 1024;
+
+    pub fn new(
+        code_files: CodeFiles,
+        configurations: Value,
+        options: Option<ShellRunnerOptions>,
+    ) -> Self {
+        let options = options.unwrap_or_default();
+        ShellRunner {
+            code: code_files,
+            configurations,
+            options,
+        }
+    }
+
+    pub async fn run(
+        &self,
+        envs: Option<HashMap<String, String>>,
+        parameters: Value,
+        max_execution_timeout: Option<Duration>,
+    ) -> Result<RunResult, ExecutionError> {
+        log::info!("preparing to run shell tool");
+        self.options
+            .trust_store
+            .verify_code_files(&self.code, self.options.code_signature.as_ref())?;
+        if !self.code.entrypoint.ends_with(".sh") {
+            return Err(ExecutionError::new(
+                format!("entrypoint {} isn't a .sh script", self.code.entrypoint),
+                None,
+            ));
+        }
+        if !self.code.files.contains_key(&self.code.entrypoint) {
+            return Err(ExecutionError::new(
+                format!("no entrypoint found {}", self.code.entrypoint),
+                None,
+            ));
+        }
+        let (public_configurations, secrets) = SecretConfigurations::split(
+            &self.configurations,
+            &self.options.secret_configuration_keys,
+        );
+        let secrets = secrets.with_redaction_rules(&self.options.redaction_rules);
+        log::info!("configurations: {}", secrets.masked(&self.configurations));
+        log::info!(
+            "parameters: {}",
+            secrets.redactor().redact(&parameters.to_string())
+        );
+
+        let resolved_runner_type = resolve_runner_type_for_policy(
+            self.options.force_runner_type.clone(),
+            &self.options.runner_policy,
+            self.options.container_runtime.as_ref(),
+        );
+        let (output, metadata) = self
+            .options
+            .runner_policy
+            .run_with_fallback(
+                self.options.force_runner_type.as_ref(),
+                resolved_runner_type,
+                |runner_type| {
+                    let envs = envs.clone();
+                    let (parameters, public_configurations, secrets) =
+                        (&parameters, &public_configurations, &secrets);
+                    async move {
+                        self.run_with_runner_type(
+                            &runner_type,
+                            envs,
+                            parameters,
+                            public_configurations,
+                            secrets,
+                            max_execution_timeout,
+                        )
+                        .await
+                    }
+                },
+            )
+            .await?;
+        Ok(RunResult {
+            data: parse_code_result(&output, secrets.redactor())?,
+            metadata,
+        })
+    }
+
+    /// `SHINKAI_CONFIG_*` and `SHINKAI_PARAM_*` variables, split into public ones and the ones
+    /// holding secret configurations
+    ///
+    /// Names taken by an earlier variable, e.g. `a-b` after `a_b`, and values over the size
+    /// limits are left out with a warning, the script still finds them in the inputs file.
+    /// Secrets go first since they aren't in it.
+    fn input_envs(
+        configurations: &Value,
+        parameters: &Value,
+        secrets: &SecretConfigurations,
+    ) -> (Envs, Envs) {
+        let mut public_envs = flatten_to_envs(Self::CONFIG_ENV_PREFIX, configurations);
+        public_envs.extend(flatten_to_envs(Self::PARAM_ENV_PREFIX, parameters));
+        let secret_envs = if secrets.is_empty() {
+            Vec::new()
+        } else {
+            flatten_to_envs(Self::CONFIG_ENV_PREFIX, &secrets.value())
+                .into_iter()
+                // The prefix alone holds the whole public configurations
+                .filter(|(key, _)| key != Self::CONFIG_ENV_PREFIX)
+                .collect()
+        };
+
+        let mut names = HashSet::new();
+        let mut total_bytes = 0;
+        let mut fits = |(name, value): &(String, String)| {
+            if !names.insert(name.clone()) {
+                log::warn!("skipping variable {}, another input key maps to it", name);
+                return false;
+            }
+            let bytes = name.len() + value.len() + 1;
+            if value.len() > Self::MAX_ENV_VALUE_BYTES
+                || total_bytes + bytes > Self::MAX_ENV_TOTAL_BYTES
+            {
+                log::warn!(
+                    "skipping variable {} of {} bytes, it is only in the inputs file",
+                    name,
+                    value.len()
+                );
+                return false;
+            }
+            total_bytes += bytes;
+            true
+        };
+        let secret_envs = secret_envs.into_iter().filter(&mut fits).collect();
+        let public_envs = public_envs.into_iter().filter(&mut fits).collect();
+        (public_envs, secret_envs)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn run_with_runner_type(
+        &self,
+        resolved_runner_type: &RunnerType,
+        envs: Option<HashMap<String, String>>,
+        parameters: &Value,
+        public_configurations: &Value,
+        secrets: &SecretConfigurations,
+        max_execution_timeout: Option<Duration>,
+    ) -> Result<Vec<String>, ExecutionError> {
+        let mut adapted_configurations = public_configurations.clone();
+        let mut adapted_parameters = parameters.clone();
+        if !self.options.context.mount_files.is_empty()
+            && matches!(resolved_runner_type, RunnerType::Docker)
+        {
+            let mount_files = self
+                .options
+                .context
+                .mount_files
+                .iter()
+                .map(|p| path::absolute(p).unwrap().to_string_lossy().to_string())
+                .collect::<HashSet<String>>();
+            adapted_configurations = adapt_paths_in_value(&adapted_configurations, &mount_files);
+            adapted_parameters = adapt_paths_in_value(&adapted_parameters, &mount_files);
+        }
+
+        match resolved_runner_type {
+            RunnerType::Host | RunnerType::Sandbox => {
+                self.run_in_host(
+                    resolved_runner_type,
+                    envs,
+                    (&adapted_configurations, &adapted_parameters),
+                    secrets,
+                    max_execution_timeout,
+                )
+                .await
+            }
+            RunnerType::Docker => {
+                self.run_in_docker(
+                    envs,
+                    (&adapted_configurations, &adapted_parameters),
+                    secrets,
+                    max_execution_timeout,
+                )
+                .await
+            }
+        }
+        .map_err(|e| {
+            ExecutionError::from_runner_error(secrets.redactor().redact(&e.to_string()), &e)
+        })
+    }
+
+    async fn run_in_docker(
+        &self,
+        envs: Option<HashMap<String, String>>,
+        (configurations, parameters): (&Value, &Value),
+        secrets: &SecretConfigurations,
+        max_execution_timeout: Option<Duration>,
+    ) -> anyhow::Result<Vec<String>> {
+        log::info!(
+            "using bash from container image: {}",
+            self.options.docker_image_name
+        );
+
+        let execution_storage =
+            ExecutionStorage::new(self.code.clone(), self.options.context.clone())
+                .with_redactor(secrets.redactor().clone());
+        let _lease = execution_storage.acquire_lease()?;
+        execution_storage.init(None)?;
+        let home = execution_storage.prepare_home()?;
+        let inputs = execution_storage.write_inputs(configurations, parameters)?;
+
+        let container_runtime = self
+            .options
+            .container_runtime
+            .clone()
+            .or_else(ContainerRuntime::detect)
+            .unwrap_or_default();
+        log::info!("using container runtime: {:?}", container_runtime);
+        // Pulled here so a missing image doesn't eat the execution timeout
+        let image = ImageManager::new(container_runtime.clone())
+            .prepare(
+                &self.options.docker_image_name,
+                self.options.docker_image_digest.as_deref(),
+            )
+            .await
+            .map_err(|e| InfrastructureError(format!("failed to prepare image: {}", e)))?;
+
+        let inputs_file_path = format!(
+            "/app/{}",
+            execution_storage.relative_to_root(inputs.path().to_path_buf())
+        );
+        let mut mounts = vec![
+            BindMount {
+                source: execution_storage.code_folder_path.as_normalized_string(),
+                target: format!(
+                    "/app/{}",
+                    execution_storage.relative_to_root(execution_storage.code_folder_path.clone())
+                ),
+                readonly: true,
+            },
+            BindMount {
+                source: execution_storage.home_folder_path.as_normalized_string(),
+                target: String::from("/app/home"),
+                readonly: false,
+            },
+            BindMount {
+                source: inputs.path().to_path_buf().as_normalized_string(),
+                target: inputs_file_path.clone(),
+                readonly: true,
+            },
+        ];
+
+        let mut mount_env = String::from("");
+        log::info!("mount files: {:?}", self.options.context.mount_files);
+        // Mounted at the same path so the code sees the same paths as in the host
+        for file in &self.options.context.mount_files {
+            let path = normalize_for_docker_path(file.to_path_buf());
+            mount_env += &format!("{},", path);
+            mounts.push(BindMount {
+                source: path.clone(),
+                target: path,
+                readonly: false,
+            });
+        }
+
+        let mut mount_assets_env = String::from("");
+        for file in &self.options.context.assets_files {
+            let target_path = format!(
+                "/app/{}/{}",
+                execution_storage.relative_to_root(execution_storage.assets_folder_path.clone()),
+                file.file_name().unwrap().to_str().unwrap()
+            );
+            mount_assets_env += &format!("{},", target_path);
+            mounts.push(BindMount {
+                source: path::absolute(file).unwrap().as_normalized_string(),
+                target: target_path,
+                readonly: true,
+            });
+        }
+
+        let mut container_envs = vec![
+            String::from("NO_COLOR=true"),
+            format!(
+                "SHINKAI_NODE_LOCATION={}://host.docker.internal:{}",
+                self.options.shinkai_node_location.protocol,
+                self.options.shinkai_node_location.port
+            ),
+            String::from("SHINKAI_HOME=/app/home"),
+            format!("SHINKAI_ASSETS={}", mount_assets_env),
+            format!("SHINKAI_MOUNT={}", mount_env),
+            format!("SHINKAI_CONTEXT_ID={}", self.options.context.context_id),
+            format!("SHINKAI_EXECUTION_ID={}", self.options.context.execution_id),
+            format!("{}={}", ExecutionStorage::INPUTS_FILE_ENV, inputs_file_path),
+        ];
+        let (input_envs, secret_envs) = Self::input_envs(configurations, parameters, secrets);
+        for (key, value) in input_envs {
+            container_envs.push(format!("{}={}", key, value));
+        }
+        if let Some(envs) = envs {
+            for (key, value) in envs {
+                container_envs.push(format!("{}={}", key, value));
+            }
+        }
+
+        let container_command = vec![
+            String::from("bash"),
+            execution_storage.relative_to_root(execution_storage.code_entrypoint_file_path.clone()),
+        ];
+
+        if let Some(engine_client) = container_runtime.engine_client() {
+            let mut env = container_envs;
+            // Sent over the engine socket, they never show up in a process command line
+            if !secrets.is_empty() {
+                env.push(format!(
+                    "{}={}",
+                    SecretConfigurations::ENV,
+                    secrets.env_value()
+                ));
+                env.extend(
+                    secret_envs
+                        .iter()
+                        .map(|(key, value)| format!("{}={}", key, value)),
+                );
+            }
+            let spec = ContainerSpec {
+                image,
+                cmd: container_command,
+                env,
+                working_dir: Some(String::from("/app")),
+                mounts,
+            };
+            let stdout = run_in_engine(
+                &engine_client,
+                &spec,
+                &execution_storage,
+                "bash",
+                max_execution_timeout,
+            )
+            .await?;
+            home.succeeded()?;
+            return Ok(stdout);
+        }
+
+        let mut command = container_runtime.command();
+        let mut args = vec![String::from("run"), String::from("--rm")];
+        args.extend(container_runtime.run_args());
+        for mount in &mounts {
+            args.extend([String::from("--mount"), container_runtime.bind_mount(mount)]);
+        }
+        for env in container_envs {
+            args.extend([String::from("-e"), env]);
+        }
+        // Passed by name so the values are read from the docker cli environment and never show up in its arguments
+        if !secrets.is_empty() {
+            args.extend([String::from("-e"), SecretConfigurations::ENV.to_string()]);
+            for (key, _) in &secret_envs {
+                args.extend([String::from("-e"), key.clone()]);
+            }
+        }
+        args.extend([String::from("--workdir"), String::from("/app"), image]);
+        args.extend(container_command);
+        let command = command
+            .args(args)
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .kill_on_drop(true);
+        if !secrets.is_empty() {
+            command.env(SecretConfigurations::ENV, secrets.env_value());
+            command.envs(secret_envs);
+        }
+
+        log::info!(
+            "spawning docker command: {}",
+            execution_storage.redactor.redact(&format!("{:?}", command))
+        );
+        let child = command.spawn().map_err(|e| {
+            let error_msg = execution_storage.redactor.redact(&format!(
+                "failed to spawn command: {:?}, error: {}",
+                command, e
+            ));
+            log::error!("{}", error_msg);
+            InfrastructureError(error_msg)
+        })?;
+        let (status, stdout, stderr) =
+            wait_for_output(child, &execution_storage, "bash", max_execution_timeout).await?;
+        if !status.success() {
+            let stderr = execution_storage.redactor.redact(&stderr.join("\n"));
+            log::error!("command execution failed: {}", stderr);
+            // The container never ran the tool, e.g. a rejected mount or a missing shell
+            if InfrastructureError::is_infrastructure_exit_code(status.code()) {
+                return Err(InfrastructureError(stderr).into());
+            }
+            return Err(anyhow::Error::new(std::io::Error::other(stderr)));
+        }
+        home.succeeded()?;
+        Ok(stdout)
+    }
+
+    /// Runs with the host shell, inside the Linux sandbox for `RunnerType::Sandbox`
+    async fn run_in_host(
+        &self,
+        runner_type: &RunnerType,
+        envs: Option<HashMap<String, String>>,
+        (configurations, parameters): (&Value, &Value),
+        secrets: &SecretConfigurations,
+        max_execution_timeout: Option<Duration>,
+    ) -> anyhow::Result<Vec<String>> {
+        let execution_storage =
+            ExecutionStorage::new(self.code.clone(), self.options.context.clone())
+                .with_redactor(secrets.redactor().clone());
+        let _lease = execution_storage.acquire_lease()?;
+        execution_storage.init(None)?;
+        let home = execution_storage.prepare_home()?;
+        let inputs = execution_storage.write_inputs(configurations, parameters)?;
+
+        let shell_binary_path = &self.options.shell_binary_path;
+        log::info!(
+            "using shell from host at path: {}",
+            shell_binary_path.display()
+        );
+        let mut command = match runner_type {
+            RunnerType::Sandbox => {
+                let mut mounts = execution_storage.sandbox_mounts(&[]);
+                let inputs_file_path = inputs.path().to_string_lossy().to_string();
+                mounts.push(BindMount {
+                    source: inputs_file_path.clone(),
+                    target: inputs_file_path,
+                    readonly: true,
+                });
+                self.options.sandbox.command(
+                    shell_binary_path,
+                    &mounts,
+                    &execution_storage.root_folder_path,
+                )?
+            }
+            _ => tokio::process::Command::new(shell_binary_path),
+        };
+        let command = command
+            .arg(execution_storage.code_entrypoint_file_path.clone())
+            .current_dir(execution_storage.root_folder_path.clone())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .kill_on_drop(true);
+
+        command.env("NO_COLOR", "true");
+        // The host home isn't visible in the sandbox
+        if matches!(runner_type, RunnerType::Sandbox) {
+            command.env("HOME", execution_storage.home_folder_path.clone());
+        }
+        command.env(
+            "SHINKAI_NODE_LOCATION",
+            format!(
+                "{}://{}:{}",
+                self.options.shinkai_node_location.protocol,
+                self.options.shinkai_node_location.host,
+                self.options.shinkai_node_location.port
+            ),
+        );
+        command.env("SHINKAI_HOME", execution_storage.home_folder_path.clone());
+        command.env(
+            "SHINKAI_ASSETS",
+            self.options
+                .context
+                .assets_files
+                .iter()
+                .map(|p| path::absolute(p).unwrap().to_string_lossy().to_string())
+                .collect::<Vec<_>>()
+                .join(","),
+        );
+        command.env(
+            "SHINKAI_MOUNT",
+            self.options
+                .context
+                .mount_files
+                .iter()
+                .map(|p| path::absolute(p).unwrap().to_string_lossy().to_string())
+                .collect::<Vec<_>>()
+                .join(","),
+        );
+        command.env(
+            "SHINKAI_CONTEXT_ID",
+            self.options.context.context_id.clone(),
+        );
+        command.env(
+            "SHINKAI_EXECUTION_ID",
+            self.options.context.execution_id.clone(),
+        );
+        command.env(ExecutionStorage::INPUTS_FILE_ENV, inputs.path());
+        let (input_envs, secret_envs) = Self::input_envs(configurations, parameters, secrets);
+        command.envs(input_envs);
+        if let Some(envs) = envs {
+            command.envs(envs);
+        }
+        if !secrets.is_empty() {
+            command.env(SecretConfigurations::ENV, secrets.env_value());
+            command.envs(secret_envs);
+        }
+        log::info!(
+            "prepared command with arguments: {}",
+            execution_storage.redactor.redact(&format!("{:?}", command))
+        );
+        let child = command.spawn().map_err(|e| {
+            let error_msg = execution_storage.redactor.redact(&format!(
+                "failed to spawn command: {:?} error: {}",
+                command, e
+            ));
+            log::error!("{}", error_msg);
+            anyhow::anyhow!("{}", error_msg)
+        })?;
+        let (status, stdout, stderr) =
+            wait_for_output(child, &execution_storage, "bash", max_execution_timeout).await?;
+        if !status.success() {
+            let stderr = execution_storage.redactor.redact(&stderr.join("\n"));
+            log::error!("command execution failed: {}", stderr);
+            return Err(anyhow::Error::new(std::io::Error::other(stderr)));
+        }
+        home.succeeded()?;
+        Ok(stdout)
+    }
+}
+
+/// Environment variables for `value` under `prefix`
+///
+/// Object keys and array indexes are appended upper-cased and joined with `_`, strings are kept
+/// as is, `null` is empty and objects and arrays also get their JSON so they can be fed to `jq`.
+fn flatten_to_envs(prefix: &str, value: &Value) -> Envs {
+    let mut envs = Vec::new();
+    match value {
+        Value::Object(object) => {
+            envs.push((prefix.to_string(), value.to_string()));
+            for (key, value) in object {
+                envs.extend(flatten_to_envs(
+                    &format!("{}_{}", prefix, env_name_segment(key)),
+                    value,
+                ));
+            }
+        }
+        Value::Array(array) => {
+            envs.push((prefix.to_string(), value.to_string()));
+            for (index, value) in array.iter().enumerate() {
+                envs.extend(flatten_to_envs(&format!("{}_{}", prefix, index), value));
+            }
+        }
+        Value::String(string) => envs.push((prefix.to_string(), string.clone())),
+        Value::Null => envs.push((prefix.to_string(), String::new())),
+        _ => envs.push((prefix.to_string(), value.to_string())),
+    }
+    envs
+}
+
+/// `key` upper-cased with anything outside `[A-Z0-9_]` replaced by `_`
+fn env_name_segment(key: &str) -> String {
+    key.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+#[path = "shell_runner.test.rs"]
+mod tests;
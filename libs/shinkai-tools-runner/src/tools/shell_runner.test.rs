@@ -0,0 +1,230 @@
+use serde_json::json;
+
+#[cfg(unix)]
+use crate::tools::test_utils::fake_docker;
+use crate::tools::{
+    code_files::CodeFiles,
+    execution_context::ExecutionContext,
+    execution_storage::ExecutionStorage,
+    runner_type::RunnerType,
+    secret_configurations::SecretConfigurations,
+    shell_runner::{flatten_to_envs, ShellRunner},
+    shell_runner_options::ShellRunnerOptions,
+};
+
+use std::collections::HashMap;
+
+fn code_files(script: &str) -> CodeFiles {
+    CodeFiles {
+        files: HashMap::from([("main.sh".to_string(), script.to_string())]),
+        entrypoint: "main.sh".to_string(),
+    }
+}
+
+#[test]
+fn flattens_values_into_env_names() {
+    let envs = flatten_to_envs(
+        "SHINKAI_PARAM",
+        &json!({ "user-name": "ana", "count": 2, "tags": ["a"], "extra": null }),
+    )
+    .into_iter()
+    .collect::<HashMap<_, _>>();
+    assert_eq!(envs["SHINKAI_PARAM_USER_NAME"], "ana");
+    assert_eq!(envs["SHINKAI_PARAM_COUNT"], "2");
+    assert_eq!(envs["SHINKAI_PARAM_TAGS"], r#"["a"]"#);
+    assert_eq!(envs["SHINKAI_PARAM_TAGS_0"], "a");
+    assert_eq!(envs["SHINKAI_PARAM_EXTRA"], "");
+    assert_eq!(
+        serde_json::from_str::<serde_json::Value>(&envs["SHINKAI_PARAM"]).unwrap()["count"],
+        2
+    );
+}
+
+#[test]
+fn input_envs_skip_colliding_names_and_oversized_values() {
+    let (public_configurations, secrets) = SecretConfigurations::split(
+        &json!({ "api-key": "public", "api_key": "secret" }),
+        &[String::from("api_key")],
+    );
+    let large = "x".repeat(ShellRunner::MAX_ENV_VALUE_BYTES + 1);
+    let (public_envs, secret_envs) = ShellRunner::input_envs(
+        &public_configurations,
+        &json!({ "a-b": "dash", "a_b": "underscore", "large": large, "small": "ok" }),
+        &secrets,
+    );
+    let public_envs = public_envs.into_iter().collect::<HashMap<_, _>>();
+    let secret_envs = secret_envs.into_iter().collect::<HashMap<_, _>>();
+    // Secrets keep their name, the public key mapping to it is left out
+    assert_eq!(secret_envs["SHINKAI_CONFIG_API_KEY"], "secret");
+    assert!(!public_envs.contains_key("SHINKAI_CONFIG_API_KEY"));
+    // The first key mapping to a name keeps it
+    assert_eq!(public_envs["SHINKAI_PARAM_A_B"], "dash");
+    // Oversized values, and the JSON holding them, are only in the inputs file
+    assert!(!public_envs.contains_key("SHINKAI_PARAM_LARGE"));
+    assert!(!public_envs.contains_key("SHINKAI_PARAM"));
+    assert_eq!(public_envs["SHINKAI_PARAM_SMALL"], "ok");
+}
+
+#[test]
+fn input_envs_stay_within_the_total_budget() {
+    let value = "x".repeat(ShellRunner::MAX_ENV_VALUE_BYTES / 2);
+    let parameters = (0..32)
+        .map(|index| (format!("key{}", index), json!(value)))
+        .collect::<serde_json::Map<_, _>>();
+    let (public_envs, _) = ShellRunner::input_envs(
+        &json!({}),
+        &serde_json::Value::Object(parameters),
+        &SecretConfigurations::default(),
+    );
+    let total_bytes = public_envs
+        .iter()
+        .map(|(name, value)| name.len() + value.len() + 1)
+        .sum::<usize>();
+    assert!(total_bytes <= ShellRunner::MAX_ENV_TOTAL_BYTES);
+    assert!(public_envs.len() > 1);
+}
+
+#[tokio::test]
+async fn run_script_with_host_shell() {
+    let _ = env_logger::builder()
+        .filter_level(log::LevelFilter::Info)
+        .is_test(true)
+        .try_init();
+    let folder = tempfile::tempdir().unwrap();
+    let context = ExecutionContext {
+        storage: folder.path().join("storage"),
+        ..Default::default()
+    };
+    let shell_runner = ShellRunner::new(
+        code_files(
+            r#"
+name=$(grep -o '"name":"[^"]*"' "$SHINKAI_INPUTS_FILE" | cut -d'"' -f4)
+echo "not the result"
+echo "<shinkai-code-result>"
+printf '{"message":"hello %s","key":"%s","secret":"%s","home":"%s"}\n' \
+  "$name" "$SHINKAI_CONFIG_KEY" "$SHINKAI_CONFIG_SECRET" "$SHINKAI_HOME"
+echo "</shinkai-code-result>"
+"#,
+        ),
+        json!({ "key": "public", "secret": "s3cr3t" }),
+        Some(ShellRunnerOptions {
+            context: context.clone(),
+            force_runner_type: Some(RunnerType::Host),
+            secret_configuration_keys: vec![String::from("secret")],
+            ..Default::default()
+        }),
+    );
+
+    let result = shell_runner
+        .run(None, json!({ "name": "world" }), None)
+        .await
+        .unwrap();
+    let storage = ExecutionStorage::new(Default::default(), context);
+    assert_eq!(result.data["message"], "hello world");
+    assert_eq!(result.data["key"], "public");
+    assert_eq!(result.data["secret"], "s3cr3t");
+    assert_eq!(
+        result.data["home"],
+        storage.home_folder_path.to_string_lossy().to_string()
+    );
+    assert_eq!(result.metadata.runner_type, Some(RunnerType::Host));
+    assert!(!storage.inputs_file_path().exists());
+}
+
+#[tokio::test]
+async fn rejects_non_shell_entrypoint() {
+    let shell_runner = ShellRunner::new(
+        CodeFiles {
+            files: HashMap::from([("main.py".to_string(), String::new())]),
+            entrypoint: "main.py".to_string(),
+        },
+        json!({}),
+        None,
+    );
+    let error = shell_runner.run(None, json!({}), None).await.unwrap_err();
+    assert!(error.message().contains("isn't a .sh script"), "{}", error);
+}
+
+#[tokio::test]
+async fn script_failures_are_reported() {
+    let folder = tempfile::tempdir().unwrap();
+    let shell_runner = ShellRunner::new(
+        code_files("echo 'tool exploded' >&2; exit 2"),
+        json!({}),
+        Some(ShellRunnerOptions {
+            context: ExecutionContext {
+                storage: folder.path().join("storage"),
+                ..Default::default()
+            },
+            force_runner_type: Some(RunnerType::Host),
+            ..Default::default()
+        }),
+    );
+    let error = shell_runner.run(None, json!({}), None).await.unwrap_err();
+    assert!(error.message().contains("tool exploded"), "{}", error);
+}
+
+/// Docker cli look-alike recording the `run` arguments and environment and printing a fixed result
+#[cfg(unix)]
+fn recording_docker(folder: &std::path::Path) -> crate::tools::container_runtime::ContainerRuntime {
+    fake_docker(
+        folder,
+        &format!(
+            r#"    printf '%s\n' "$@" > "{folder}/run-args.txt"
+    printf '%s' "$SHINKAI_CONFIG_TOKEN" > "{folder}/run-secret.txt"
+    echo "<shinkai-code-result>"
+    echo '{{"from":"docker"}}'
+    echo "</shinkai-code-result>"
+    exit 0"#,
+            folder = folder.display()
+        ),
+    )
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn runs_in_docker_by_default() {
+    let folder = tempfile::tempdir().unwrap();
+    let shell_runner = ShellRunner::new(
+        code_files("echo hi"),
+        json!({ "region": "eu", "token": "t0ken" }),
+        Some(ShellRunnerOptions {
+            context: ExecutionContext {
+                storage: folder.path().join("storage"),
+                ..Default::default()
+            },
+            container_runtime: Some(recording_docker(folder.path())),
+            secret_configuration_keys: vec![String::from("token")],
+            ..Default::default()
+        }),
+    );
+
+    let result = shell_runner
+        .run(None, json!({ "query": "rust" }), None)
+        .await
+        .unwrap();
+    assert_eq!(result.data, json!({ "from": "docker" }));
+    assert_eq!(result.metadata.runner_type, Some(RunnerType::Docker));
+
+    let args = std::fs::read_to_string(folder.path().join("run-args.txt")).unwrap();
+    let args = args.lines().collect::<Vec<_>>();
+    let image_index = args
+        .iter()
+        .position(|arg| *arg == "dcspark/shinkai-code-runner:0.9.4")
+        .unwrap();
+    assert_eq!(args[image_index + 1], "bash");
+    assert!(args[image_index + 2].ends_with("main.sh"));
+    assert!(args.contains(&"SHINKAI_HOME=/app/home"));
+    assert!(args.contains(&"SHINKAI_CONFIG_REGION=eu"));
+    assert!(args.contains(&"SHINKAI_PARAM_QUERY=rust"));
+    assert!(args
+        .iter()
+        .any(|arg| arg.starts_with("SHINKAI_INPUTS_FILE=/app/inputs/")));
+    // Secrets are passed by name and never show up in the arguments
+    assert!(args.contains(&"SHINKAI_CONFIG_TOKEN"));
+    assert!(!args.iter().any(|arg| arg.contains("t0ken")));
+    assert_eq!(
+        std::fs::read_to_string(folder.path().join("run-secret.txt")).unwrap(),
+        "t0ken"
+    );
+}
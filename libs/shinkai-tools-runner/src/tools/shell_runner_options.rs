@@ -0,0 +1,60 @@
+use std::{path::PathBuf, sync::Arc};
+
+use super::{
+    container_runtime::ContainerRuntime,
+    execution_context::ExecutionContext,
+    linux_sandbox::LinuxSandbox,
+    redaction::RedactionRule,
+    runner_type::{RunnerPolicy, RunnerType},
+    shinkai_node_location::ShinkaiNodeLocation,
+    tool_signature::{ToolSignature, TrustStore},
+};
+
+#[derive(Clone)]
+pub struct ShellRunnerOptions {
+    pub context: ExecutionContext,
+    /// Shell for the Host and Sandbox runner types
+    pub shell_binary_path: PathBuf,
+    pub docker_image_name: String,
+    /// Pins the image, it is pulled by digest and verified before running
+    pub docker_image_digest: Option<String>,
+    pub force_runner_type: Option<RunnerType>,
+    /// Runner types allowed when `force_runner_type` isn't set, Docker only by default
+    pub runner_policy: RunnerPolicy,
+    /// Container runtime used by the Docker runner type, detected when None
+    pub container_runtime: Option<ContainerRuntime>,
+    /// bubblewrap sandbox used by the Sandbox runner type
+    pub sandbox: LinuxSandbox,
+    pub shinkai_node_location: ShinkaiNodeLocation,
+    pub trust_store: TrustStore,
+    pub code_signature: Option<ToolSignature>,
+    /// Top level configurations keys delivered out-of-band and redacted from logs
+    pub secret_configuration_keys: Vec<String>,
+    /// Extra redaction applied to logs and execution log entries
+    pub redaction_rules: Vec<Arc<dyn RedactionRule>>,
+}
+
+impl Default for ShellRunnerOptions {
+    fn default() -> Self {
+        Self {
+            context: ExecutionContext::default(),
+            shell_binary_path: PathBuf::from("/bin/bash"),
+            docker_image_name: String::from("dcspark/shinkai-code-runner:0.9.4"),
+            docker_image_digest: None,
+            force_runner_type: None,
+            // Scripts have the whole host at hand, they only leave the container when asked to
+            runner_policy: RunnerPolicy::DockerOnly,
+            container_runtime: None,
+            sandbox: LinuxSandbox::default(),
+            shinkai_node_location: ShinkaiNodeLocation {
+                protocol: String::from("http"),
+                host: String::from("127.0.0.1"),
+                port: 9550,
+            },
+            trust_store: TrustStore::default(),
+            code_signature: None,
+            secret_configuration_keys: Vec::new(),
+            redaction_rules: Vec::new(),
+        }
+    }
+}
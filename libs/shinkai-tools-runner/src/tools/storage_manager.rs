@@ -0,0 +1,403 @@
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use fs4::fs_std::FileExt;
+
+use super::{
+    execution_storage::ExecutionStorage,
+    log_retention::{LogCleanupReport, LogRetentionPolicy},
+    tool_registry::ToolRegistry,
+};
+
+/// Size and usage of one context folder of the execution storage
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContextUsage {
+    /// Name of the context folder, the sanitized context id
+    pub context_folder: String,
+    pub path: PathBuf,
+    pub total_bytes: u64,
+    pub code_bytes: u64,
+    pub cache_bytes: u64,
+    pub home_bytes: u64,
+    pub logs_bytes: u64,
+    /// Most recent modification inside the context, written by every execution
+    pub last_used: SystemTime,
+    /// Whether a live execution holds a lease on the context
+    pub in_use: bool,
+}
+
+/// Storage limits enforced by `StorageManager::enforce_quota`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StorageQuota {
+    pub max_context_bytes: Option<u64>,
+    pub max_total_bytes: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StorageGcReport {
+    pub evicted: Vec<PathBuf>,
+    pub freed_bytes: u64,
+    /// Contexts over quota that were skipped because a live execution uses them
+    pub skipped_in_use: Vec<String>,
+    pub total_bytes_after: u64,
+}
+
+/// Shared lock held on a context while an execution uses it, released on drop
+///
+/// The storage manager never evicts nor removes a context with a live lease, nor the global cache
+/// while any lease is live.
+pub struct ContextLease {
+    _context: FileLock,
+    _global_cache: Option<FileLock>,
+}
+
+struct FileLock {
+    file: File,
+    path: PathBuf,
+}
+
+impl FileLock {
+    fn open(path: &Path) -> anyhow::Result<File> {
+        Ok(File::options()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(path)
+            .map_err(|e| {
+                log::error!("failed to open lock {}: {}", path.display(), e);
+                e
+            })?)
+    }
+
+    fn try_lock_exclusive(path: &Path) -> anyhow::Result<Option<FileLock>> {
+        let file = Self::open(path)?;
+        if FileExt::try_lock_exclusive(&file)? {
+            Ok(Some(FileLock {
+                file,
+                path: path.to_path_buf(),
+            }))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        if let Err(e) = FileExt::unlock(&self.file) {
+            log::warn!("failed to release lock {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+impl ExecutionStorage {
+    /// Marks the context and the global cache as used by a live execution until the lease drops
+    ///
+    /// Blocks while the storage manager is evicting from the context or the global cache.
+    pub fn acquire_lease(&self) -> anyhow::Result<ContextLease> {
+        let path = self.root_folder_path.join(StorageManager::LEASE_FILE_NAME);
+        let global_cache_path = self
+            .global_cache_folder_path
+            .with_file_name(StorageManager::GLOBAL_CACHE_LEASE_FILE_NAME);
+        loop {
+            std::fs::create_dir_all(&self.root_folder_path)?;
+            let global_cache_file = FileLock::open(&global_cache_path)?;
+            FileExt::lock_shared(&global_cache_file)?;
+            let global_cache = FileLock {
+                file: global_cache_file,
+                path: global_cache_path.clone(),
+            };
+            let file = FileLock::open(&path)?;
+            FileExt::lock_shared(&file)?;
+            // The context may have been removed while waiting for the lock
+            if path.exists() {
+                return Ok(ContextLease {
+                    _context: FileLock { file, path },
+                    _global_cache: Some(global_cache),
+                });
+            }
+            FileExt::unlock(&file)?;
+        }
+    }
+}
+
+/// Lists, measures and garbage collects the contexts of an execution storage folder
+pub struct StorageManager {
+    storage: PathBuf,
+}
+
+impl StorageManager {
+    pub const LEASE_FILE_NAME: &'static str = ".lease.lock";
+    pub const GLOBAL_CACHE_LEASE_FILE_NAME: &'static str = ".global-cache.lock";
+    const GLOBAL_CACHE_FOLDER_NAME: &'static str = "global-cache";
+
+    pub fn new(storage: PathBuf) -> Self {
+        StorageManager { storage }
+    }
+
+    pub fn storage(&self) -> &Path {
+        &self.storage
+    }
+
+    /// Contexts of the storage, least recently used first
+    pub fn list_contexts(&self) -> anyhow::Result<Vec<ContextUsage>> {
+        let mut contexts = Vec::new();
+        if !self.storage.exists() {
+            return Ok(contexts);
+        }
+        for entry in std::fs::read_dir(&self.storage)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            if !entry.file_type()?.is_dir()
+                || name == Self::GLOBAL_CACHE_FOLDER_NAME
+                || name == ToolRegistry::FOLDER_NAME
+            {
+                continue;
+            }
+            let path = entry.path();
+            let is_context = ["code", "cache", "home", "logs"]
+                .iter()
+                .any(|folder| path.join(folder).is_dir());
+            if !is_context {
+                continue;
+            }
+            contexts.push(self.context_usage(name, path)?);
+        }
+        contexts.sort_by_key(|context| context.last_used);
+        Ok(contexts)
+    }
+
+    pub fn global_cache_bytes(&self) -> anyhow::Result<u64> {
+        Ok(Self::folder_usage(&self.storage.join(Self::GLOBAL_CACHE_FOLDER_NAME))?.0)
+    }
+
+    pub fn total_bytes(&self) -> anyhow::Result<u64> {
+        Ok(Self::folder_usage(&self.storage)?.0)
+    }
+
+    /// Evicts code folders and caches until every quota is satisfied or nothing evictable is left
+    ///
+    /// Per context quotas evict the least recently used code folders first, then the cache. The
+    /// global quota evicts code folders then caches of contexts, least recently used first, and as a
+    /// last resort the shared global cache when no execution holds a lease. Home and log folders are
+    /// never evicted, see `LogRetentionPolicy` for logs.
+    pub fn enforce_quota(&self, quota: &StorageQuota) -> anyhow::Result<StorageGcReport> {
+        let mut report = StorageGcReport::default();
+        let contexts = self.list_contexts()?;
+
+        if let Some(max_context_bytes) = quota.max_context_bytes {
+            for context in contexts.iter() {
+                if context.total_bytes <= max_context_bytes {
+                    continue;
+                }
+                let Some(_lock) = Self::try_lock_context(&context.path)? else {
+                    report.skipped_in_use.push(context.context_folder.clone());
+                    continue;
+                };
+                let mut size = context.total_bytes;
+                for code_folder in Self::code_folders_lru(&context.path)? {
+                    if size <= max_context_bytes {
+                        break;
+                    }
+                    size = size.saturating_sub(Self::evict(&code_folder, &mut report)?);
+                }
+                if size > max_context_bytes {
+                    Self::evict(&context.path.join("cache"), &mut report)?;
+                }
+            }
+        }
+
+        if let Some(max_total_bytes) = quota.max_total_bytes {
+            let mut total = self.total_bytes()?;
+            for context in self.list_contexts()? {
+                if total <= max_total_bytes {
+                    break;
+                }
+                let Some(_lock) = Self::try_lock_context(&context.path)? else {
+                    if !report.skipped_in_use.contains(&context.context_folder) {
+                        report.skipped_in_use.push(context.context_folder.clone());
+                    }
+                    continue;
+                };
+                for code_folder in Self::code_folders_lru(&context.path)? {
+                    if total <= max_total_bytes {
+                        break;
+                    }
+                    total = total.saturating_sub(Self::evict(&code_folder, &mut report)?);
+                }
+                if total > max_total_bytes {
+                    total = total
+                        .saturating_sub(Self::evict(&context.path.join("cache"), &mut report)?);
+                }
+            }
+            let global_cache_path = self.storage.join(Self::GLOBAL_CACHE_FOLDER_NAME);
+            if total > max_total_bytes && global_cache_path.exists() {
+                match self.try_lock_global_cache()? {
+                    Some(_lock) => {
+                        Self::evict(&global_cache_path, &mut report)?;
+                    }
+                    None => report
+                        .skipped_in_use
+                        .push(Self::GLOBAL_CACHE_FOLDER_NAME.to_string()),
+                }
+            }
+        }
+
+        report.total_bytes_after = self.total_bytes()?;
+        Ok(report)
+    }
+
+    /// Applies a log retention policy to every context, skipping those in use
+    pub fn cleanup_logs(&self, policy: &LogRetentionPolicy) -> anyhow::Result<LogCleanupReport> {
+        policy.cleanup_storage(&self.storage)
+    }
+
+    /// Evicts every code folder and the cache of a context, keeping its home and logs
+    pub fn evict_context(&self, context_folder: &str) -> anyhow::Result<StorageGcReport> {
+        let path = self.context_path(context_folder)?;
+        let mut report = StorageGcReport::default();
+        let Some(_lock) = Self::try_lock_context(&path)? else {
+            return Err(anyhow::anyhow!(
+                "context {} is in use by a live execution",
+                context_folder
+            ));
+        };
+        for code_folder in Self::code_folders_lru(&path)? {
+            Self::evict(&code_folder, &mut report)?;
+        }
+        Self::evict(&path.join("cache"), &mut report)?;
+        report.total_bytes_after = self.total_bytes()?;
+        Ok(report)
+    }
+
+    /// Deletes a context folder with its home and logs
+    pub fn remove_context(&self, context_folder: &str) -> anyhow::Result<StorageGcReport> {
+        let path = self.context_path(context_folder)?;
+        let mut report = StorageGcReport::default();
+        let Some(_lock) = Self::try_lock_context(&path)? else {
+            return Err(anyhow::anyhow!(
+                "context {} is in use by a live execution",
+                context_folder
+            ));
+        };
+        Self::evict(&path, &mut report)?;
+        report.total_bytes_after = self.total_bytes()?;
+        Ok(report)
+    }
+
+    fn context_path(&self, context_folder: &str) -> anyhow::Result<PathBuf> {
+        let path = self.storage.join(context_folder);
+        let is_nested = Path::new(context_folder).components().count() != 1;
+        if is_nested || !path.is_dir() {
+            return Err(anyhow::anyhow!("context {} not found", context_folder));
+        }
+        Ok(path)
+    }
+
+    fn context_usage(&self, context_folder: String, path: PathBuf) -> anyhow::Result<ContextUsage> {
+        let (code_bytes, code_last_used) = Self::folder_usage(&path.join("code"))?;
+        let (cache_bytes, _) = Self::folder_usage(&path.join("cache"))?;
+        let (home_bytes, _) = Self::folder_usage(&path.join("home"))?;
+        let (logs_bytes, logs_last_used) = Self::folder_usage(&path.join("logs"))?;
+        let (total_bytes, _) = Self::folder_usage(&path)?;
+        let in_use = Self::try_lock_context(&path)?.is_none();
+        Ok(ContextUsage {
+            context_folder,
+            path,
+            total_bytes,
+            code_bytes,
+            cache_bytes,
+            home_bytes,
+            logs_bytes,
+            last_used: code_last_used.max(logs_last_used),
+            in_use,
+        })
+    }
+
+    /// Exclusive lock on the context lease, None while a live execution holds it
+    pub(crate) fn try_lock_context(path: &Path) -> anyhow::Result<Option<ContextLease>> {
+        Ok(
+            FileLock::try_lock_exclusive(&path.join(Self::LEASE_FILE_NAME))?.map(|context| {
+                ContextLease {
+                    _context: context,
+                    _global_cache: None,
+                }
+            }),
+        )
+    }
+
+    /// Exclusive lock on the global cache lease, None while any live execution holds it
+    fn try_lock_global_cache(&self) -> anyhow::Result<Option<FileLock>> {
+        std::fs::create_dir_all(&self.storage)?;
+        FileLock::try_lock_exclusive(&self.storage.join(Self::GLOBAL_CACHE_LEASE_FILE_NAME))
+    }
+
+    /// Code folders of a context, least recently written first
+    fn code_folders_lru(path: &Path) -> anyhow::Result<Vec<PathBuf>> {
+        let code_path = path.join("code");
+        if !code_path.is_dir() {
+            return Ok(Vec::new());
+        }
+        let mut folders = Vec::new();
+        for entry in std::fs::read_dir(&code_path)? {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                let (_, last_used) = Self::folder_usage(&entry.path())?;
+                folders.push((entry.path(), last_used));
+            }
+        }
+        folders.sort_by_key(|(_, last_used)| *last_used);
+        Ok(folders.into_iter().map(|(path, _)| path).collect())
+    }
+
+    /// Removes a folder and returns the freed bytes
+    fn evict(path: &Path, report: &mut StorageGcReport) -> anyhow::Result<u64> {
+        if !path.exists() {
+            return Ok(0);
+        }
+        let (bytes, _) = Self::folder_usage(path)?;
+        log::info!("evicting {} ({} bytes)", path.display(), bytes);
+        std::fs::remove_dir_all(path).map_err(|e| {
+            log::error!("failed to evict {}: {}", path.display(), e);
+            e
+        })?;
+        report.evicted.push(path.to_path_buf());
+        report.freed_bytes += bytes;
+        Ok(bytes)
+    }
+
+    /// Total size and latest modification time of a folder, without following symlinks
+    fn folder_usage(path: &Path) -> anyhow::Result<(u64, SystemTime)> {
+        let mut bytes = 0;
+        let mut last_modified = SystemTime::UNIX_EPOCH;
+        if !path.exists() {
+            return Ok((bytes, last_modified));
+        }
+        let mut pending = vec![path.to_path_buf()];
+        while let Some(folder) = pending.pop() {
+            for entry in std::fs::read_dir(&folder)? {
+                let entry = entry?;
+                let metadata = entry.path().symlink_metadata()?;
+                if metadata.is_dir() {
+                    pending.push(entry.path());
+                    continue;
+                }
+                if entry.file_name() == Self::LEASE_FILE_NAME {
+                    continue;
+                }
+                bytes += metadata.len();
+                if let Ok(modified) = metadata.modified() {
+                    last_modified = last_modified.max(modified);
+                }
+            }
+        }
+        Ok((bytes, last_modified))
+    }
+}
+
+#[cfg(test)]
+#[path = "storage_manager.test.rs"]
+mod tests;
@@ -0,0 +1,173 @@
+use std::{
+    collections::HashMap,
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+use crate::tools::{
+    code_files::CodeFiles,
+    execution_context::ExecutionContext,
+    execution_storage::ExecutionStorage,
+    storage_manager::{StorageManager, StorageQuota},
+};
+
+fn init_context(storage: &Path, context_id: &str, code_id: &str, size: usize) -> ExecutionStorage {
+    let execution_storage = ExecutionStorage::new(
+        CodeFiles {
+            files: HashMap::from([("main.ts".to_string(), "x".repeat(size))]),
+            entrypoint: "main.ts".to_string(),
+        },
+        ExecutionContext {
+            storage: storage.to_path_buf(),
+            context_id: context_id.to_string(),
+            code_id: code_id.to_string(),
+            ..Default::default()
+        },
+    );
+    execution_storage.init(None).unwrap();
+    std::fs::write(
+        execution_storage.cache_folder_path.join("cached"),
+        "c".repeat(size),
+    )
+    .unwrap();
+    execution_storage
+}
+
+fn age(path: &Path, seconds: u64) {
+    let file = std::fs::File::options().write(true).open(path).unwrap();
+    file.set_modified(SystemTime::now() - Duration::from_secs(seconds))
+        .unwrap();
+}
+
+#[test]
+fn lists_contexts_least_recently_used_first() {
+    let storage = tempfile::tempdir().unwrap();
+    let old = init_context(storage.path(), "old", "code-a", 100);
+    let recent = init_context(storage.path(), "recent", "code-b", 200);
+    age(&old.code_entrypoint_file_path, 3600);
+    age(&old.log_file_path, 3600);
+    std::fs::create_dir_all(storage.path().join("global-cache")).unwrap();
+
+    let manager = StorageManager::new(storage.path().to_path_buf());
+    let contexts = manager.list_contexts().unwrap();
+    assert_eq!(
+        contexts
+            .iter()
+            .map(|c| c.context_folder.as_str())
+            .collect::<Vec<_>>(),
+        vec!["old", "recent"]
+    );
+    assert_eq!(contexts[0].code_bytes, 100);
+    assert_eq!(contexts[0].cache_bytes, 100);
+    assert_eq!(contexts[1].code_bytes, 200);
+    assert!(contexts.iter().all(|c| !c.in_use));
+
+    let _lease = recent.acquire_lease().unwrap();
+    let contexts = manager.list_contexts().unwrap();
+    assert!(!contexts[0].in_use);
+    assert!(contexts[1].in_use);
+}
+
+#[test]
+fn context_quota_evicts_lru_code_folders_first() {
+    let storage = tempfile::tempdir().unwrap();
+    let old_code = init_context(storage.path(), "ctx", "code-old", 1000);
+    let new_code = init_context(storage.path(), "ctx", "code-new", 1000);
+    age(&old_code.code_entrypoint_file_path, 3600);
+
+    let manager = StorageManager::new(storage.path().to_path_buf());
+    let report = manager
+        .enforce_quota(&StorageQuota {
+            max_context_bytes: Some(2500),
+            ..Default::default()
+        })
+        .unwrap();
+
+    assert_eq!(report.evicted, vec![old_code.code_folder_path.clone()]);
+    assert!(!old_code.code_folder_path.exists());
+    assert!(new_code.code_folder_path.exists());
+    assert!(new_code.cache_folder_path.exists());
+    assert!(new_code.home_folder_path.exists());
+    assert!(new_code.log_file_path.exists());
+}
+
+#[test]
+fn global_quota_never_evicts_contexts_in_use() {
+    let storage = tempfile::tempdir().unwrap();
+    let idle = init_context(storage.path(), "idle", "code-a", 1000);
+    let busy = init_context(storage.path(), "busy", "code-b", 1000);
+    age(&busy.code_entrypoint_file_path, 3600);
+    age(&busy.log_file_path, 3600);
+    let lease = busy.acquire_lease().unwrap();
+
+    let manager = StorageManager::new(storage.path().to_path_buf());
+    let report = manager
+        .enforce_quota(&StorageQuota {
+            max_total_bytes: Some(100),
+            ..Default::default()
+        })
+        .unwrap();
+
+    assert_eq!(report.skipped_in_use, vec!["busy".to_string()]);
+    assert!(busy.code_folder_path.exists());
+    assert!(busy.cache_folder_path.exists());
+    assert!(!idle.code_folder_path.exists());
+    assert!(!idle.cache_folder_path.exists());
+    assert_eq!(report.freed_bytes, 2000);
+    assert!(manager.remove_context("busy").is_err());
+
+    drop(lease);
+    manager.remove_context("busy").unwrap();
+    assert!(!busy.root_folder_path.exists());
+    assert!(manager.remove_context("../busy").is_err());
+}
+
+#[test]
+fn global_quota_stops_evicting_once_satisfied() {
+    let storage = tempfile::tempdir().unwrap();
+    let old_code = init_context(storage.path(), "ctx", "code-old", 1000);
+    let new_code = init_context(storage.path(), "ctx", "code-new", 1000);
+    age(&old_code.code_entrypoint_file_path, 3600);
+
+    let manager = StorageManager::new(storage.path().to_path_buf());
+    let report = manager
+        .enforce_quota(&StorageQuota {
+            max_total_bytes: Some(2500),
+            ..Default::default()
+        })
+        .unwrap();
+
+    assert_eq!(report.evicted, vec![old_code.code_folder_path.clone()]);
+    assert!(new_code.code_folder_path.exists());
+    assert!(new_code.cache_folder_path.exists());
+}
+
+#[test]
+fn global_cache_is_kept_while_any_execution_holds_a_lease() {
+    let storage = tempfile::tempdir().unwrap();
+    let busy = init_context(storage.path(), "busy", "code-a", 100);
+    std::fs::create_dir_all(&busy.global_cache_folder_path).unwrap();
+    std::fs::write(
+        busy.global_cache_folder_path.join("cached"),
+        "g".repeat(5000),
+    )
+    .unwrap();
+    let lease = busy.acquire_lease().unwrap();
+
+    let manager = StorageManager::new(storage.path().to_path_buf());
+    let quota = StorageQuota {
+        max_total_bytes: Some(100),
+        ..Default::default()
+    };
+    let report = manager.enforce_quota(&quota).unwrap();
+    assert!(busy.global_cache_folder_path.exists());
+    assert_eq!(
+        report.skipped_in_use,
+        vec!["busy".to_string(), "global-cache".to_string()]
+    );
+
+    drop(lease);
+    let report = manager.enforce_quota(&quota).unwrap();
+    assert!(!busy.global_cache_folder_path.exists());
+    assert!(report.skipped_in_use.is_empty());
+}
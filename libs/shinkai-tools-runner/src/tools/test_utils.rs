@@ -0,0 +1,37 @@
+//! Fixtures shared by the `*.test.rs` files
+
+use std::path::{Path, PathBuf};
+
+use super::container_runtime::{ContainerRuntime, ContainerRuntimeKind};
+
+/// Executable `/bin/sh` script named `name` in `folder`
+#[cfg(unix)]
+pub fn fake_binary(folder: &Path, name: &str, script: &str) -> PathBuf {
+    use std::os::unix::fs::PermissionsExt;
+
+    let path = folder.join(name);
+    std::fs::write(&path, format!("#!/bin/sh\n{}\n", script)).unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    path
+}
+
+/// Docker cli look-alike that is running and has every image locally, `run` runs `run_script`
+#[cfg(unix)]
+pub fn fake_docker(folder: &Path, run_script: &str) -> ContainerRuntime {
+    let path = fake_binary(
+        folder,
+        "docker",
+        &format!(
+            r#"case "$1" in
+  info) exit 0 ;;
+  image) echo '{{"Id":"sha256:1111","RepoDigests":[]}}'; exit 0 ;;
+  run)
+{}
+    ;;
+esac
+exit 1"#,
+            run_script
+        ),
+    );
+    ContainerRuntime::new(ContainerRuntimeKind::Docker, path)
+}
@@ -0,0 +1,136 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::execution_error::{ExecutionError, ExecutionErrorKind, ToolCallRejection};
+
+/// Runs tools requested by another running tool through the callback server
+pub trait ToolResolver: Send + Sync {
+    /// Runs `tool_id` with `parameters` and returns its result data
+    ///
+    /// `tool_calls` must be set as the `tool_calls` option of the runner executing the nested tool,
+    /// so depth and cycles keep being enforced if it calls other tools in turn.
+    fn call_tool(
+        &self,
+        tool_id: String,
+        parameters: Value,
+        tool_calls: ToolCallConfig,
+    ) -> BoxFuture<'_, Result<Value, ExecutionError>>;
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolCallRequest {
+    pub tool_id: String,
+    #[serde(default)]
+    pub parameters: Value,
+}
+
+/// Enables tool-to-tool calls for a runner
+#[derive(Clone)]
+pub struct ToolCallConfig {
+    pub resolver: Arc<dyn ToolResolver>,
+    /// Maximum number of tools in a call chain, including the outermost tool
+    pub max_depth: usize,
+    /// Ids of the running tools, outermost first and ending with the tool run by this runner
+    pub call_chain: Vec<String>,
+    /// When the outermost timed run in the call chain must finish, nested calls only get the time left
+    pub deadline: Option<Instant>,
+}
+
+impl ToolCallConfig {
+    pub const DEFAULT_MAX_DEPTH: usize = 5;
+
+    pub fn new(resolver: Arc<dyn ToolResolver>, tool_id: &str) -> Self {
+        ToolCallConfig {
+            resolver,
+            max_depth: Self::DEFAULT_MAX_DEPTH,
+            call_chain: vec![Self::base_id(tool_id).to_string()],
+            deadline: None,
+        }
+    }
+
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Moves the deadline up so it's at most `timeout` from now, used by runners when they start
+    pub fn with_timeout(mut self, timeout: Option<Duration>) -> Self {
+        if let Some(timeout) = timeout {
+            let deadline = Instant::now() + timeout;
+            self.deadline = Some(
+                self.deadline
+                    .map_or(deadline, |current| current.min(deadline)),
+            );
+        }
+        self
+    }
+
+    /// Time left before the deadline, `None` when there is no deadline
+    pub fn remaining_time(&self) -> Option<Duration> {
+        self.deadline
+            .map(|deadline| deadline.saturating_duration_since(Instant::now()))
+    }
+
+    /// Config for a nested call to `tool_id`, failing if it would exceed the depth or form a cycle
+    pub fn nested(&self, tool_id: &str) -> Result<ToolCallConfig, ExecutionError> {
+        let base_id = Self::base_id(tool_id);
+        if self.call_chain.iter().any(|id| id == base_id) {
+            log::error!(
+                "refusing tool call cycle: {} -> {}",
+                self.call_chain.join(" -> "),
+                base_id
+            );
+            return Err(ExecutionError::new(
+                format!(
+                    "tool call cycle detected: {} -> {}",
+                    self.call_chain.join(" -> "),
+                    base_id
+                ),
+                None,
+            )
+            .with_kind(ExecutionErrorKind::ToolCallRejected(
+                ToolCallRejection::Cycle,
+            )));
+        }
+        if self.call_chain.len() >= self.max_depth {
+            log::error!(
+                "refusing tool call to {}, max depth {} reached",
+                base_id,
+                self.max_depth
+            );
+            return Err(ExecutionError::new(
+                format!(
+                    "tool call depth limit of {} exceeded calling {}",
+                    self.max_depth, base_id
+                ),
+                None,
+            )
+            .with_kind(ExecutionErrorKind::ToolCallRejected(
+                ToolCallRejection::DepthLimitExceeded,
+            )));
+        }
+        let mut nested = self.clone();
+        nested.call_chain.push(base_id.to_string());
+        Ok(nested)
+    }
+
+    /// Checks the call and runs it through the resolver
+    pub async fn call(&self, request: ToolCallRequest) -> Result<Value, ExecutionError> {
+        let nested = self.nested(&request.tool_id)?;
+        log::info!("calling tool {}", nested.call_chain.join(" -> "));
+        self.resolver
+            .call_tool(request.tool_id, request.parameters, nested)
+            .await
+    }
+
+    /// Tool id without the `@version` suffix of registry references
+    fn base_id(tool_id: &str) -> &str {
+        tool_id.split('@').next().unwrap_or(tool_id)
+    }
+}
@@ -1,9 +1,14 @@
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
+use super::tool_signature::ToolSignature;
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ToolDefinition {
     pub id: String,
+    /// Semver version, required to install the tool in a `ToolRegistry`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
     pub name: String,
     pub description: String,
     pub author: String,
@@ -13,6 +18,8 @@ pub struct ToolDefinition {
     pub result: Value,
     pub code: Option<String>,
     pub embedding_metadata: Option<EmbeddingMetadata>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<ToolSignature>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
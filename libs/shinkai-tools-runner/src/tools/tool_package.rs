@@ -0,0 +1,485 @@
+use std::{
+    collections::HashMap,
+    io::{Cursor, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use ed25519_dalek::SigningKey;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+use super::{
+    code_files::CodeFiles, deno_runner::DenoRunner, deno_runner_options::DenoRunnerOptions,
+    execution_context::ExecutionContext, execution_storage::ExecutionStorage,
+    python_runner::PythonRunner, python_runner_options::PythonRunnerOptions,
+    tool_definition::ToolDefinition, tool_signature::ToolSignature,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ToolRuntime {
+    Deno,
+    Python,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ToolPackageFileEntry {
+    pub path: String,
+    pub sha256: String,
+    pub size: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolPackageManifest {
+    pub format_version: u32,
+    pub runtime: ToolRuntime,
+    pub entrypoint: String,
+    /// Hash of the code files as returned by `CodeFiles::content_hash`
+    pub content_hash: String,
+    pub definition: Option<ToolDefinition>,
+    pub code_files: Vec<ToolPackageFileEntry>,
+    pub lock_files: Vec<ToolPackageFileEntry>,
+    pub assets: Vec<ToolPackageFileEntry>,
+    /// Signature over the content hash of `ToolPackage::runnable_code_files`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<ToolSignature>,
+}
+
+/// A portable tool archive (`.shinkai-tool`) bundling code, definition, lock files and assets
+///
+/// The archive is a zip file with the following layout:
+/// - `manifest.json`: a `ToolPackageManifest` with the sha256 of every other entry
+/// - `code/<path>`: the code files, one of them being the entrypoint
+/// - `locks/<path>`: dependency lock files (e.g. `deno.lock`, `uv.lock`)
+/// - `assets/<file name>`: files exposed to the tool through `ExecutionContext.assets_files`
+#[derive(Debug, Clone)]
+pub struct ToolPackage {
+    pub runtime: ToolRuntime,
+    pub code_files: CodeFiles,
+    pub definition: Option<ToolDefinition>,
+    pub lock_files: HashMap<String, String>,
+    pub assets: HashMap<String, Vec<u8>>,
+    pub signature: Option<ToolSignature>,
+}
+
+impl ToolPackage {
+    pub const FORMAT_VERSION: u32 = 1;
+    pub const FILE_EXTENSION: &'static str = "shinkai-tool";
+    pub const MANIFEST_FILE_NAME: &'static str = "manifest.json";
+    const CODE_FOLDER: &'static str = "code";
+    const LOCKS_FOLDER: &'static str = "locks";
+    const ASSETS_FOLDER: &'static str = "assets";
+    /// Largest manifest read from a package
+    pub const MAX_MANIFEST_BYTES: u64 = 4 * 1024 * 1024;
+    /// Largest total size of the files of a package, as declared in its manifest
+    pub const MAX_UNPACKED_BYTES: u64 = 256 * 1024 * 1024;
+
+    pub fn new(
+        runtime: ToolRuntime,
+        code_files: CodeFiles,
+        definition: Option<ToolDefinition>,
+    ) -> Self {
+        ToolPackage {
+            runtime,
+            code_files,
+            definition,
+            lock_files: HashMap::new(),
+            assets: HashMap::new(),
+            signature: None,
+        }
+    }
+
+    /// Signs the runnable code (code plus lock files) so runners can verify it before executing
+    pub fn sign(&mut self, signing_key: &SigningKey) {
+        self.signature = Some(ToolSignature::sign_code_files(
+            signing_key,
+            &self.runnable_code_files(),
+        ));
+    }
+
+    pub fn with_lock_file(mut self, path: &str, content: String) -> Self {
+        self.lock_files.insert(path.to_string(), content);
+        self
+    }
+
+    pub fn with_asset(mut self, file_name: &str, content: Vec<u8>) -> Self {
+        self.assets.insert(file_name.to_string(), content);
+        self
+    }
+
+    /// Adds a file from disk as an asset, keeping its file name
+    pub fn with_asset_file(self, path: &Path) -> anyhow::Result<Self> {
+        let file_name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| anyhow::anyhow!("invalid asset path {}", path.display()))?
+            .to_string();
+        let content = std::fs::read(path)?;
+        Ok(self.with_asset(&file_name, content))
+    }
+
+    pub fn manifest(&self) -> ToolPackageManifest {
+        ToolPackageManifest {
+            format_version: Self::FORMAT_VERSION,
+            runtime: self.runtime,
+            entrypoint: self.code_files.entrypoint.clone(),
+            content_hash: self.code_files.content_hash(),
+            definition: self.definition.clone(),
+            code_files: Self::file_entries(
+                self.code_files
+                    .files
+                    .iter()
+                    .map(|(path, content)| (path, content.as_bytes())),
+            ),
+            lock_files: Self::file_entries(
+                self.lock_files
+                    .iter()
+                    .map(|(path, content)| (path, content.as_bytes())),
+            ),
+            assets: Self::file_entries(
+                self.assets
+                    .iter()
+                    .map(|(path, content)| (path, content.as_slice())),
+            ),
+            signature: self.signature.clone(),
+        }
+    }
+
+    pub fn to_bytes(&self) -> anyhow::Result<Vec<u8>> {
+        for path in self
+            .code_files
+            .files
+            .keys()
+            .chain(self.lock_files.keys())
+            .chain(self.assets.keys())
+        {
+            Self::validate_entry_path(path)?;
+        }
+        for file_name in self.assets.keys() {
+            Self::validate_asset_name(file_name)?;
+        }
+
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+        writer.start_file(Self::MANIFEST_FILE_NAME, options)?;
+        writer.write_all(serde_json::to_string_pretty(&self.manifest())?.as_bytes())?;
+        for (folder, files) in [
+            (
+                Self::CODE_FOLDER,
+                self.code_files
+                    .files
+                    .iter()
+                    .map(|(path, content)| (path, content.as_bytes()))
+                    .collect::<Vec<_>>(),
+            ),
+            (
+                Self::LOCKS_FOLDER,
+                self.lock_files
+                    .iter()
+                    .map(|(path, content)| (path, content.as_bytes()))
+                    .collect::<Vec<_>>(),
+            ),
+            (
+                Self::ASSETS_FOLDER,
+                self.assets
+                    .iter()
+                    .map(|(path, content)| (path, content.as_slice()))
+                    .collect::<Vec<_>>(),
+            ),
+        ] {
+            for (path, content) in files {
+                writer.start_file(format!("{}/{}", folder, path), options)?;
+                writer.write_all(content)?;
+            }
+        }
+        Ok(writer.finish()?.into_inner())
+    }
+
+    /// Writes the package archive to disk
+    pub fn pack(&self, path: &Path) -> anyhow::Result<()> {
+        log::info!("packing tool package to {}", path.display());
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, self.to_bytes()?).map_err(|e| {
+            log::error!("failed to write tool package {}: {}", path.display(), e);
+            e
+        })?;
+        Ok(())
+    }
+
+    /// Reads a package archive, verifying every entry against the manifest hashes
+    pub fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        let mut archive = ZipArchive::new(Cursor::new(bytes))?;
+        let manifest: ToolPackageManifest = serde_json::from_slice(&Self::read_entry(
+            &mut archive,
+            Self::MANIFEST_FILE_NAME,
+            Self::MAX_MANIFEST_BYTES,
+        )?)?;
+        if manifest.format_version > Self::FORMAT_VERSION {
+            return Err(anyhow::anyhow!(
+                "unsupported tool package format version {}",
+                manifest.format_version
+            ));
+        }
+        let unpacked_bytes = manifest
+            .code_files
+            .iter()
+            .chain(&manifest.lock_files)
+            .chain(&manifest.assets)
+            .try_fold(0u64, |total, entry| total.checked_add(entry.size))
+            .filter(|total| *total <= Self::MAX_UNPACKED_BYTES);
+        if unpacked_bytes.is_none() {
+            return Err(anyhow::anyhow!(
+                "tool package files exceed {} bytes",
+                Self::MAX_UNPACKED_BYTES
+            ));
+        }
+        for entry in &manifest.assets {
+            Self::validate_asset_name(&entry.path)?;
+        }
+
+        let mut read_files = |folder: &str,
+                              entries: &[ToolPackageFileEntry]|
+         -> anyhow::Result<Vec<(String, Vec<u8>)>> {
+            let mut files = Vec::with_capacity(entries.len());
+            for entry in entries {
+                Self::validate_entry_path(&entry.path)?;
+                let content = Self::read_entry(
+                    &mut archive,
+                    &format!("{}/{}", folder, entry.path),
+                    entry.size,
+                )?;
+                let sha256 = Self::sha256(&content);
+                if sha256 != entry.sha256 || content.len() as u64 != entry.size {
+                    return Err(anyhow::anyhow!(
+                        "integrity check failed for {}/{}: expected sha256 {} got {}",
+                        folder,
+                        entry.path,
+                        entry.sha256,
+                        sha256
+                    ));
+                }
+                files.push((entry.path.clone(), content));
+            }
+            Ok(files)
+        };
+
+        let code_files = CodeFiles {
+            files: Self::into_text_files(read_files(Self::CODE_FOLDER, &manifest.code_files)?)?,
+            entrypoint: manifest.entrypoint.clone(),
+        };
+        let lock_files =
+            Self::into_text_files(read_files(Self::LOCKS_FOLDER, &manifest.lock_files)?)?;
+        let assets = read_files(Self::ASSETS_FOLDER, &manifest.assets)?
+            .into_iter()
+            .collect::<HashMap<_, _>>();
+
+        if code_files.content_hash() != manifest.content_hash {
+            return Err(anyhow::anyhow!(
+                "integrity check failed: code content hash does not match the manifest"
+            ));
+        }
+        if !code_files.files.contains_key(&code_files.entrypoint) {
+            return Err(anyhow::anyhow!(
+                "no entrypoint found {}",
+                code_files.entrypoint
+            ));
+        }
+
+        Ok(ToolPackage {
+            runtime: manifest.runtime,
+            code_files,
+            definition: manifest.definition,
+            lock_files,
+            assets,
+            signature: manifest.signature,
+        })
+    }
+
+    /// Reads a package archive from disk, verifying every entry against the manifest hashes
+    pub fn unpack(path: &Path) -> anyhow::Result<Self> {
+        log::info!("unpacking tool package from {}", path.display());
+        let bytes = std::fs::read(path).map_err(|e| {
+            log::error!("failed to read tool package {}: {}", path.display(), e);
+            e
+        })?;
+        Self::from_bytes(&bytes)
+    }
+
+    /// Code files merged with lock files, ready to be written into the code folder
+    pub fn runnable_code_files(&self) -> CodeFiles {
+        let mut code_files = self.code_files.clone();
+        for (path, content) in self.lock_files.iter() {
+            code_files
+                .files
+                .entry(path.clone())
+                .or_insert_with(|| content.clone());
+        }
+        code_files
+    }
+
+    /// Writes the assets into the context assets folder and appends them to `assets_files`
+    pub fn extract_assets_into_context(
+        &self,
+        mut context: ExecutionContext,
+    ) -> anyhow::Result<ExecutionContext> {
+        if self.assets.is_empty() {
+            return Ok(context);
+        }
+        let execution_storage = ExecutionStorage::new(self.code_files.clone(), context.clone());
+        let assets_folder_path = execution_storage.assets_folder_path.clone();
+        std::fs::create_dir_all(&assets_folder_path).map_err(|e| {
+            log::error!(
+                "failed to create assets directory {}: {}",
+                assets_folder_path.display(),
+                e
+            );
+            e
+        })?;
+        for (file_name, content) in self.assets.iter() {
+            let asset_path: PathBuf = assets_folder_path.join(file_name);
+            log::info!("writing asset: {}", asset_path.display());
+            std::fs::write(&asset_path, content)?;
+            if !context.assets_files.contains(&asset_path) {
+                context.assets_files.push(asset_path);
+            }
+        }
+        Ok(context)
+    }
+
+    pub fn into_deno_runner(
+        self,
+        configurations: Value,
+        options: Option<DenoRunnerOptions>,
+    ) -> anyhow::Result<DenoRunner> {
+        if self.runtime != ToolRuntime::Deno {
+            return Err(anyhow::anyhow!(
+                "tool package runtime is {:?}, expected deno",
+                self.runtime
+            ));
+        }
+        let mut options = options.unwrap_or_default();
+        options.context = self.extract_assets_into_context(options.context)?;
+        if options.code_signature.is_none() {
+            options.code_signature = self.signature.clone();
+        }
+        Ok(DenoRunner::new(
+            self.runnable_code_files(),
+            configurations,
+            Some(options),
+        ))
+    }
+
+    pub fn into_python_runner(
+        self,
+        configurations: Value,
+        options: Option<PythonRunnerOptions>,
+    ) -> anyhow::Result<PythonRunner> {
+        if self.runtime != ToolRuntime::Python {
+            return Err(anyhow::anyhow!(
+                "tool package runtime is {:?}, expected python",
+                self.runtime
+            ));
+        }
+        let mut options = options.unwrap_or_default();
+        options.context = self.extract_assets_into_context(options.context)?;
+        if options.code_signature.is_none() {
+            options.code_signature = self.signature.clone();
+        }
+        Ok(PythonRunner::new(
+            self.runnable_code_files(),
+            configurations,
+            Some(options),
+        ))
+    }
+
+    fn sha256(content: &[u8]) -> String {
+        hex_simd::encode_to_string(Sha256::digest(content), hex_simd::AsciiCase::Lower)
+    }
+
+    fn file_entries<'a>(
+        files: impl Iterator<Item = (&'a String, &'a [u8])>,
+    ) -> Vec<ToolPackageFileEntry> {
+        let mut entries = files
+            .map(|(path, content)| ToolPackageFileEntry {
+                path: path.clone(),
+                sha256: Self::sha256(content),
+                size: content.len() as u64,
+            })
+            .collect::<Vec<_>>();
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        entries
+    }
+
+    fn validate_entry_path(path: &str) -> anyhow::Result<()> {
+        let is_valid = !path.is_empty()
+            && !path.starts_with('/')
+            && !path.starts_with('\\')
+            && !path.contains(':')
+            && path
+                .split(['/', '\\'])
+                .all(|component| !component.is_empty() && component != "..");
+        if !is_valid {
+            return Err(anyhow::anyhow!("invalid path in tool package: {}", path));
+        }
+        Ok(())
+    }
+
+    /// Assets are extracted side by side, so their names can't hold a folder
+    fn validate_asset_name(file_name: &str) -> anyhow::Result<()> {
+        if file_name.contains('/') || file_name.contains('\\') {
+            return Err(anyhow::anyhow!(
+                "asset names must be plain file names: {}",
+                file_name
+            ));
+        }
+        Ok(())
+    }
+
+    /// Reads an entry, refusing to decompress more than `max_bytes`
+    fn read_entry(
+        archive: &mut ZipArchive<Cursor<&[u8]>>,
+        name: &str,
+        max_bytes: u64,
+    ) -> anyhow::Result<Vec<u8>> {
+        let file = archive
+            .by_name(name)
+            .map_err(|e| anyhow::anyhow!("missing {} in tool package: {}", name, e))?;
+        let too_large = || {
+            anyhow::anyhow!(
+                "{} in tool package is larger than {} bytes",
+                name,
+                max_bytes
+            )
+        };
+        if file.size() > max_bytes {
+            return Err(too_large());
+        }
+        let mut content = Vec::new();
+        // The size in the zip headers isn't trusted, the read itself is capped
+        file.take(max_bytes + 1).read_to_end(&mut content)?;
+        if content.len() as u64 > max_bytes {
+            return Err(too_large());
+        }
+        Ok(content)
+    }
+
+    fn into_text_files(files: Vec<(String, Vec<u8>)>) -> anyhow::Result<HashMap<String, String>> {
+        files
+            .into_iter()
+            .map(|(path, content)| {
+                String::from_utf8(content)
+                    .map(|content| (path.clone(), content))
+                    .map_err(|_| anyhow::anyhow!("{} is not valid utf-8", path))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+#[path = "tool_package.test.rs"]
+mod tests;
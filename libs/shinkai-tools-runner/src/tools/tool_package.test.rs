@@ -0,0 +1,207 @@
+use std::{
+    collections::HashMap,
+    io::{Cursor, Read, Write},
+};
+
+use serde_json::json;
+use zip::{write::SimpleFileOptions, ZipArchive, ZipWriter};
+
+use crate::tools::{
+    code_files::CodeFiles,
+    execution_context::ExecutionContext,
+    tool_package::{ToolPackage, ToolRuntime},
+};
+
+fn echo_package() -> ToolPackage {
+    ToolPackage::new(
+        ToolRuntime::Deno,
+        CodeFiles {
+            files: HashMap::from([
+                (
+                    "main.ts".to_string(),
+                    r#"
+                    import { echo } from "./lib/echo.ts";
+                    async function run(configurations, params) {
+                        return { message: echo(params.message) };
+                    }
+                "#
+                    .to_string(),
+                ),
+                (
+                    "lib/echo.ts".to_string(),
+                    "export const echo = (message) => message;".to_string(),
+                ),
+            ]),
+            entrypoint: "main.ts".to_string(),
+        },
+        None,
+    )
+    .with_lock_file("deno.lock", "{}".to_string())
+    .with_asset("data.txt", b"hello asset".to_vec())
+}
+
+/// Rewrites every entry of a package with `rewrite`, which gets and returns names and contents
+fn rewrite_entries(bytes: &[u8], rewrite: impl Fn(&str, Vec<u8>) -> (String, Vec<u8>)) -> Vec<u8> {
+    let mut archive = ZipArchive::new(Cursor::new(bytes)).unwrap();
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    for index in 0..archive.len() {
+        let mut file = archive.by_index(index).unwrap();
+        let mut original = Vec::new();
+        file.read_to_end(&mut original).unwrap();
+        let (name, content) = rewrite(file.name(), original);
+        writer
+            .start_file(name, SimpleFileOptions::default())
+            .unwrap();
+        writer.write_all(&content).unwrap();
+    }
+    writer.finish().unwrap().into_inner()
+}
+
+fn replace_entry(bytes: &[u8], name: &str, content: &[u8]) -> Vec<u8> {
+    rewrite_entries(bytes, |entry_name, original| {
+        if entry_name == name {
+            (entry_name.to_string(), content.to_vec())
+        } else {
+            (entry_name.to_string(), original)
+        }
+    })
+}
+
+fn edit_manifest(bytes: &[u8], edit: impl Fn(&mut serde_json::Value)) -> Vec<u8> {
+    let mut archive = ZipArchive::new(Cursor::new(bytes)).unwrap();
+    let mut manifest = String::new();
+    archive
+        .by_name(ToolPackage::MANIFEST_FILE_NAME)
+        .unwrap()
+        .read_to_string(&mut manifest)
+        .unwrap();
+    let mut manifest: serde_json::Value = serde_json::from_str(&manifest).unwrap();
+    edit(&mut manifest);
+    replace_entry(
+        bytes,
+        ToolPackage::MANIFEST_FILE_NAME,
+        manifest.to_string().as_bytes(),
+    )
+}
+
+#[test]
+fn tool_package_pack_unpack_round_trip() {
+    let package = echo_package();
+    let temp_dir = tempfile::tempdir().unwrap();
+    let package_path = temp_dir
+        .path()
+        .join(format!("echo.{}", ToolPackage::FILE_EXTENSION));
+    package.pack(&package_path).unwrap();
+
+    let unpacked = ToolPackage::unpack(&package_path).unwrap();
+    assert_eq!(unpacked.runtime, ToolRuntime::Deno);
+    assert_eq!(unpacked.code_files.entrypoint, "main.ts");
+    assert_eq!(unpacked.code_files.files, package.code_files.files);
+    assert_eq!(unpacked.lock_files, package.lock_files);
+    assert_eq!(unpacked.assets, package.assets);
+    assert_eq!(
+        unpacked.manifest().content_hash,
+        package.code_files.content_hash()
+    );
+
+    let runnable_code_files = unpacked.runnable_code_files();
+    assert!(runnable_code_files.files.contains_key("deno.lock"));
+}
+
+#[test]
+fn tool_package_detects_tampered_entries() {
+    let bytes = echo_package().to_bytes().unwrap();
+    let tampered = replace_entry(
+        &bytes,
+        "code/lib/echo.ts",
+        b"export const echo = () => 'pwned';",
+    );
+    let result = ToolPackage::from_bytes(&tampered);
+    assert!(result.is_err());
+    assert!(result
+        .err()
+        .unwrap()
+        .to_string()
+        .contains("integrity check failed"));
+
+    let tampered = replace_entry(&bytes, "assets/data.txt", b"other asset");
+    assert!(ToolPackage::from_bytes(&tampered).is_err());
+}
+
+#[test]
+fn tool_package_rejects_unsafe_paths() {
+    let package = echo_package().with_lock_file("../deno.lock", "{}".to_string());
+    assert!(package.to_bytes().is_err());
+
+    let package = echo_package().with_asset("nested/data.txt", vec![]);
+    assert!(package.to_bytes().is_err());
+}
+
+#[test]
+fn tool_package_refuses_entries_larger_than_declared() {
+    let bytes = echo_package().to_bytes().unwrap();
+    // Compresses to a few KiB, far more once inflated
+    let bomb = replace_entry(&bytes, "assets/data.txt", &vec![0u8; 8 * 1024 * 1024]);
+    assert!(bomb.len() < 1024 * 1024);
+    let error = ToolPackage::from_bytes(&bomb).unwrap_err().to_string();
+    assert!(error.contains("is larger than 11 bytes"), "{}", error);
+
+    let oversized = edit_manifest(&bytes, |manifest| {
+        manifest["assets"][0]["size"] = json!(ToolPackage::MAX_UNPACKED_BYTES + 1);
+    });
+    let error = ToolPackage::from_bytes(&oversized).unwrap_err().to_string();
+    assert!(error.contains("files exceed"), "{}", error);
+}
+
+#[test]
+fn tool_package_unpack_rejects_nested_asset_names() {
+    let bytes = echo_package().to_bytes().unwrap();
+    let nested = edit_manifest(&bytes, |manifest| {
+        manifest["assets"][0]["path"] = json!("nested/data.txt");
+    });
+    let nested = rewrite_entries(&nested, |name, content| {
+        if name == "assets/data.txt" {
+            ("assets/nested/data.txt".to_string(), content)
+        } else {
+            (name.to_string(), content)
+        }
+    });
+    let error = ToolPackage::from_bytes(&nested).unwrap_err().to_string();
+    assert!(error.contains("plain file names"), "{}", error);
+}
+
+#[test]
+fn tool_package_extracts_assets_into_context() {
+    let package = echo_package();
+    let storage = tempfile::tempdir().unwrap();
+    let context = package
+        .extract_assets_into_context(ExecutionContext {
+            storage: storage.path().to_path_buf(),
+            ..Default::default()
+        })
+        .unwrap();
+    assert_eq!(context.assets_files.len(), 1);
+    assert!(context.assets_files[0].ends_with("assets/data.txt"));
+    assert_eq!(
+        std::fs::read_to_string(&context.assets_files[0]).unwrap(),
+        "hello asset"
+    );
+}
+
+#[test]
+fn tool_package_runtime_mismatch() {
+    let result = echo_package().into_python_runner(json!({}), None);
+    assert!(result.is_err());
+}
+
+#[test]
+fn tool_package_signature_round_trip() {
+    let signing_key = ed25519_dalek::SigningKey::from_bytes(&[7u8; 32]);
+    let mut package = echo_package();
+    package.sign(&signing_key);
+    let unpacked = ToolPackage::from_bytes(&package.to_bytes().unwrap()).unwrap();
+    let signature = unpacked.signature.clone().unwrap();
+    assert!(signature
+        .verify(&unpacked.runnable_code_files().content_hash())
+        .is_ok());
+}
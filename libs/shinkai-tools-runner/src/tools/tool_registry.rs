@@ -0,0 +1,466 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use fs4::fs_std::FileExt;
+use futures::future::BoxFuture;
+use semver::{Version, VersionReq};
+use serde_json::{json, Value};
+
+use super::{
+    deno_runner_options::DenoRunnerOptions,
+    execution_context::ExecutionContext,
+    execution_error::ExecutionError,
+    file_name_utils::sanitize_for_file_name,
+    python_runner_options::PythonRunnerOptions,
+    tool_call::{ToolCallConfig, ToolResolver},
+    tool_package::{ToolPackage, ToolRuntime},
+    tool_runner::ToolRunner,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ToolRegistryEntry {
+    pub id: String,
+    pub version: Version,
+    pub path: PathBuf,
+}
+
+/// Filesystem backed catalog of tool packages
+///
+/// Packages are stored as `<storage>/registry/<tool id>/<version>.shinkai-tool`. Every operation
+/// holds a lock on `<storage>/registry/.lock` (shared for reads, exclusive for writes) so several
+/// processes can use the same registry, and packages are written to a temporary file and renamed
+/// so a reader never sees a partially written package.
+#[derive(Clone)]
+pub struct ToolRegistry {
+    root: PathBuf,
+    pub deno_options: DenoRunnerOptions,
+    pub python_options: PythonRunnerOptions,
+    configurations: HashMap<String, Value>,
+    tool_call_timeout: Duration,
+}
+
+impl ToolRegistry {
+    pub const FOLDER_NAME: &'static str = "registry";
+    const LOCK_FILE_NAME: &'static str = ".lock";
+    pub const DEFAULT_TOOL_CALL_TIMEOUT: Duration = Duration::from_secs(300);
+
+    pub fn new(storage: PathBuf) -> Self {
+        ToolRegistry {
+            root: storage.join(Self::FOLDER_NAME),
+            deno_options: DenoRunnerOptions::default(),
+            python_options: PythonRunnerOptions::default(),
+            configurations: HashMap::new(),
+            tool_call_timeout: Self::DEFAULT_TOOL_CALL_TIMEOUT,
+        }
+    }
+
+    pub fn with_deno_options(mut self, options: DenoRunnerOptions) -> Self {
+        self.deno_options = options;
+        self
+    }
+
+    pub fn with_python_options(mut self, options: PythonRunnerOptions) -> Self {
+        self.python_options = options;
+        self
+    }
+
+    /// Configurations used when the tool is called by another tool
+    pub fn with_configurations(mut self, id: &str, configurations: Value) -> Self {
+        self.configurations.insert(id.to_string(), configurations);
+        self
+    }
+
+    /// Maximum run time of a tool called by another tool, shortened to what's left of the caller's
+    /// own timeout
+    pub fn with_tool_call_timeout(mut self, timeout: Duration) -> Self {
+        self.tool_call_timeout = timeout;
+        self
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Installs a package, failing if the same id and version is already installed
+    pub fn install(&self, package: &ToolPackage) -> anyhow::Result<ToolRegistryEntry> {
+        let (id, version) = Self::package_id_and_version(package)?;
+        let bytes = package.to_bytes()?;
+        self.with_lock(true, || {
+            let path = self.package_path(&id, &version);
+            if path.exists() {
+                return Err(anyhow::anyhow!(
+                    "tool {}@{} is already installed",
+                    id,
+                    version
+                ));
+            }
+            self.write_package(&path, &bytes)?;
+            log::info!("installed tool {}@{}", id, version);
+            Ok(ToolRegistryEntry { id, version, path })
+        })
+    }
+
+    /// Installs a newer version of an already installed tool
+    pub fn upgrade(&self, package: &ToolPackage) -> anyhow::Result<ToolRegistryEntry> {
+        let (id, version) = Self::package_id_and_version(package)?;
+        let bytes = package.to_bytes()?;
+        self.with_lock(true, || {
+            let latest =
+                self.read_versions(&id)?.into_iter().max().ok_or_else(|| {
+                    anyhow::anyhow!("tool {} is not installed, install it first", id)
+                })?;
+            if version <= latest {
+                return Err(anyhow::anyhow!(
+                    "tool {}@{} is not newer than installed version {}",
+                    id,
+                    version,
+                    latest
+                ));
+            }
+            let path = self.package_path(&id, &version);
+            self.write_package(&path, &bytes)?;
+            log::info!("upgraded tool {} from {} to {}", id, latest, version);
+            Ok(ToolRegistryEntry { id, version, path })
+        })
+    }
+
+    /// Removes one version of a tool, or every version when `version` is None
+    pub fn remove(
+        &self,
+        id: &str,
+        version: Option<&Version>,
+    ) -> anyhow::Result<Vec<ToolRegistryEntry>> {
+        Self::validate_id(id)?;
+        self.with_lock(true, || {
+            let removed = self
+                .read_versions(id)?
+                .into_iter()
+                .filter(|installed| version.is_none_or(|version| version == installed))
+                .map(|version| ToolRegistryEntry {
+                    id: id.to_string(),
+                    path: self.package_path(id, &version),
+                    version,
+                })
+                .collect::<Vec<_>>();
+            if removed.is_empty() {
+                return Err(anyhow::anyhow!(
+                    "tool {}{} is not installed",
+                    id,
+                    version.map(|v| format!("@{}", v)).unwrap_or_default()
+                ));
+            }
+            for entry in removed.iter() {
+                std::fs::remove_file(&entry.path)?;
+                log::info!("removed tool {}@{}", entry.id, entry.version);
+            }
+            if self.read_versions(id)?.is_empty() {
+                let _ = std::fs::remove_dir_all(self.root.join(id));
+            }
+            Ok(removed)
+        })
+    }
+
+    /// Every installed tool version, sorted by id then version
+    pub fn list(&self) -> anyhow::Result<Vec<ToolRegistryEntry>> {
+        if !self.root.exists() {
+            return Ok(Vec::new());
+        }
+        self.with_lock(false, || {
+            let mut entries = Vec::new();
+            for entry in std::fs::read_dir(&self.root)? {
+                let entry = entry?;
+                if !entry.file_type()?.is_dir() {
+                    continue;
+                }
+                let id = entry.file_name().to_string_lossy().to_string();
+                for version in self.read_versions(&id)? {
+                    entries.push(ToolRegistryEntry {
+                        path: self.package_path(&id, &version),
+                        id: id.clone(),
+                        version,
+                    });
+                }
+            }
+            entries.sort_by(|a, b| a.id.cmp(&b.id).then(a.version.cmp(&b.version)));
+            Ok(entries)
+        })
+    }
+
+    /// Installed versions of a tool, sorted ascending
+    pub fn versions(&self, id: &str) -> anyhow::Result<Vec<Version>> {
+        Self::validate_id(id)?;
+        if !self.root.exists() {
+            return Ok(Vec::new());
+        }
+        self.with_lock(false, || self.read_versions(id))
+    }
+
+    /// Loads the package matching a reference
+    ///
+    /// The reference is `id` (latest version), `id@1.2.3` (exact version) or `id@<requirement>`
+    /// with any semver requirement such as `id@^1.2`, in which case the highest match is used.
+    pub fn get(&self, reference: &str) -> anyhow::Result<ToolPackage> {
+        let (id, requirement) = Self::parse_reference(reference)?;
+        let not_installed =
+            || anyhow::anyhow!("no installed version of {} matches {}", id, reference);
+        if !self.root.exists() {
+            return Err(not_installed());
+        }
+        // Resolved and unpacked under one lock so a concurrent remove can't drop the version in between
+        self.with_lock(false, || {
+            let versions = self.read_versions(&id)?;
+            let version = versions
+                .iter()
+                .rev()
+                .find(|version| requirement.matches(version))
+                .ok_or_else(not_installed)?;
+            log::info!("resolved {} to {}@{}", reference, id, version);
+            ToolPackage::unpack(&self.package_path(&id, version))
+        })
+    }
+
+    /// Resolves a reference (see `ToolRegistry::get`) to a runner ready to execute the tool
+    ///
+    /// The runner uses the registry runner options, with `context` replacing their context when provided.
+    pub fn resolve(
+        &self,
+        reference: &str,
+        configurations: Value,
+        context: Option<ExecutionContext>,
+    ) -> anyhow::Result<ToolRunner> {
+        self.resolve_with_tool_calls(reference, configurations, context, None)
+    }
+
+    /// Same as `ToolRegistry::resolve`, overriding the `tool_calls` runner option when provided
+    pub fn resolve_with_tool_calls(
+        &self,
+        reference: &str,
+        configurations: Value,
+        context: Option<ExecutionContext>,
+        tool_calls: Option<ToolCallConfig>,
+    ) -> anyhow::Result<ToolRunner> {
+        let package = self.get(reference)?;
+        self.runner_for(package, configurations, context, tool_calls)
+    }
+
+    fn runner_for(
+        &self,
+        package: ToolPackage,
+        configurations: Value,
+        context: Option<ExecutionContext>,
+        tool_calls: Option<ToolCallConfig>,
+    ) -> anyhow::Result<ToolRunner> {
+        match package.runtime {
+            ToolRuntime::Deno => {
+                let mut options = self.deno_options.clone();
+                if let Some(context) = context {
+                    options.context = context;
+                }
+                if tool_calls.is_some() {
+                    options.tool_calls = tool_calls;
+                }
+                Ok(ToolRunner::Deno(
+                    package.into_deno_runner(configurations, Some(options))?,
+                ))
+            }
+            ToolRuntime::Python => {
+                let mut options = self.python_options.clone();
+                if let Some(context) = context {
+                    options.context = context;
+                }
+                if tool_calls.is_some() {
+                    options.tool_calls = tool_calls;
+                }
+                Ok(ToolRunner::Python(
+                    package.into_python_runner(configurations, Some(options))?,
+                ))
+            }
+        }
+    }
+
+    /// Context of a nested tool run, taken from the options of its runtime with a fresh execution id
+    fn nested_context(&self, runtime: ToolRuntime) -> ExecutionContext {
+        let context = match runtime {
+            ToolRuntime::Deno => &self.deno_options.context,
+            ToolRuntime::Python => &self.python_options.context,
+        };
+        ExecutionContext {
+            execution_id: nanoid::nanoid!(),
+            ..context.clone()
+        }
+    }
+
+    /// Timeout of a nested tool run, failing when the caller has no time left
+    fn nested_timeout(&self, tool_calls: &ToolCallConfig) -> Result<Duration, ExecutionError> {
+        let timeout = match tool_calls.remaining_time() {
+            Some(remaining) => remaining.min(self.tool_call_timeout),
+            None => self.tool_call_timeout,
+        };
+        if timeout.is_zero() {
+            log::error!(
+                "refusing tool call {}, the caller timed out",
+                tool_calls.call_chain.join(" -> ")
+            );
+            return Err(ExecutionError::new(
+                format!(
+                    "no time left to call {}",
+                    tool_calls.call_chain.last().cloned().unwrap_or_default()
+                ),
+                None,
+            ));
+        }
+        Ok(timeout)
+    }
+
+    fn parse_reference(reference: &str) -> anyhow::Result<(String, VersionReq)> {
+        let (id, requirement) = match reference.split_once('@') {
+            None => (reference, VersionReq::STAR),
+            Some((id, version)) => {
+                let requirement = match Version::parse(version) {
+                    Ok(version) => VersionReq::parse(&format!("={}", version))?,
+                    Err(_) => VersionReq::parse(version).map_err(|e| {
+                        anyhow::anyhow!("invalid version requirement {}: {}", version, e)
+                    })?,
+                };
+                (id, requirement)
+            }
+        };
+        Self::validate_id(id)?;
+        Ok((id.to_string(), requirement))
+    }
+
+    fn package_id_and_version(package: &ToolPackage) -> anyhow::Result<(String, Version)> {
+        let definition = package
+            .definition
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("tool package doesn't have a definition"))?;
+        Self::validate_id(&definition.id)?;
+        let version = definition.version.as_ref().ok_or_else(|| {
+            anyhow::anyhow!("tool {} definition doesn't have a version", definition.id)
+        })?;
+        let version = Version::parse(version)
+            .map_err(|e| anyhow::anyhow!("invalid version {}: {}", version, e))?;
+        Ok((definition.id.clone(), version))
+    }
+
+    fn validate_id(id: &str) -> anyhow::Result<()> {
+        if id.is_empty() || sanitize_for_file_name(id.to_string()) != id {
+            return Err(anyhow::anyhow!(
+                "invalid tool id {}, only alphanumeric characters, '-' and '_' are allowed",
+                id
+            ));
+        }
+        Ok(())
+    }
+
+    fn package_path(&self, id: &str, version: &Version) -> PathBuf {
+        self.root
+            .join(id)
+            .join(format!("{}.{}", version, ToolPackage::FILE_EXTENSION))
+    }
+
+    fn read_versions(&self, id: &str) -> anyhow::Result<Vec<Version>> {
+        let tool_folder = self.root.join(id);
+        if !tool_folder.exists() {
+            return Ok(Vec::new());
+        }
+        let mut versions = Vec::new();
+        for entry in std::fs::read_dir(&tool_folder)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some(ToolPackage::FILE_EXTENSION) {
+                continue;
+            }
+            let Some(version) = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| Version::parse(stem).ok())
+            else {
+                continue;
+            };
+            versions.push(version);
+        }
+        versions.sort();
+        Ok(versions)
+    }
+
+    fn write_package(&self, path: &Path, bytes: &[u8]) -> anyhow::Result<()> {
+        let folder = path.parent().unwrap();
+        std::fs::create_dir_all(folder)?;
+        let mut temp_file = tempfile::NamedTempFile::new_in(folder)?;
+        temp_file.write_all(bytes)?;
+        temp_file.as_file().sync_all()?;
+        temp_file.persist(path).map_err(|e| {
+            log::error!("failed to persist tool package {}: {}", path.display(), e);
+            e.error
+        })?;
+        Ok(())
+    }
+
+    fn with_lock<T>(
+        &self,
+        exclusive: bool,
+        f: impl FnOnce() -> anyhow::Result<T>,
+    ) -> anyhow::Result<T> {
+        std::fs::create_dir_all(&self.root)?;
+        let lock_file = File::options()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(self.root.join(Self::LOCK_FILE_NAME))?;
+        if exclusive {
+            FileExt::lock_exclusive(&lock_file)?;
+        } else {
+            FileExt::lock_shared(&lock_file)?;
+        }
+        let result = f();
+        FileExt::unlock(&lock_file)?;
+        result
+    }
+}
+
+/// Runs nested tool calls from the registry
+///
+/// Nested tools get the configurations set with `ToolRegistry::with_configurations` (or an empty
+/// object), run in the options context of their runtime with a fresh execution id, and are limited
+/// to the tool call timeout or whatever is left of the caller's timeout, whichever is shorter.
+impl ToolResolver for ToolRegistry {
+    fn call_tool(
+        &self,
+        tool_id: String,
+        parameters: Value,
+        tool_calls: ToolCallConfig,
+    ) -> BoxFuture<'_, Result<Value, ExecutionError>> {
+        Box::pin(async move {
+            let id = tool_id.split('@').next().unwrap_or_default();
+            let configurations = self
+                .configurations
+                .get(id)
+                .cloned()
+                .unwrap_or_else(|| json!({}));
+            let timeout = self.nested_timeout(&tool_calls)?;
+            let runner = self
+                .get(&tool_id)
+                .and_then(|package| {
+                    let context = self.nested_context(package.runtime);
+                    self.runner_for(package, configurations, Some(context), Some(tool_calls))
+                })
+                .map_err(|e| {
+                    log::error!("failed to resolve tool {}: {}", tool_id, e);
+                    ExecutionError::new(format!("failed to resolve tool {}: {}", tool_id, e), None)
+                })?;
+            runner
+                .run(None, parameters, Some(timeout))
+                .await
+                .map(|result| result.data)
+        })
+    }
+}
+
+#[cfg(test)]
+#[path = "tool_registry.test.rs"]
+mod tests;
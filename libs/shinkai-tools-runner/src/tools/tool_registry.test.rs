@@ -0,0 +1,255 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use semver::Version;
+use serde_json::json;
+
+use crate::tools::{
+    code_files::CodeFiles,
+    deno_runner_options::DenoRunnerOptions,
+    execution_context::ExecutionContext,
+    python_runner_options::PythonRunnerOptions,
+    tool_call::ToolCallConfig,
+    tool_definition::ToolDefinition,
+    tool_package::{ToolPackage, ToolRuntime},
+    tool_registry::ToolRegistry,
+    tool_runner::ToolRunner,
+};
+
+fn package(id: &str, version: &str, runtime: ToolRuntime) -> ToolPackage {
+    let entrypoint = match runtime {
+        ToolRuntime::Deno => "main.ts",
+        ToolRuntime::Python => "main.py",
+    };
+    ToolPackage::new(
+        runtime,
+        CodeFiles {
+            files: HashMap::from([(entrypoint.to_string(), format!("// {} {}", id, version))]),
+            entrypoint: entrypoint.to_string(),
+        },
+        Some(ToolDefinition {
+            id: id.to_string(),
+            version: Some(version.to_string()),
+            name: id.to_string(),
+            description: String::new(),
+            author: "Shinkai".to_string(),
+            keywords: vec![],
+            configurations: json!({}),
+            parameters: json!({}),
+            result: json!({}),
+            code: None,
+            embedding_metadata: None,
+            signature: None,
+        }),
+    )
+}
+
+#[test]
+fn install_list_and_get() {
+    let storage = tempfile::tempdir().unwrap();
+    let registry = ToolRegistry::new(storage.path().to_path_buf());
+    registry
+        .install(&package("echo", "1.0.0", ToolRuntime::Deno))
+        .unwrap();
+    registry
+        .install(&package("echo", "1.2.0", ToolRuntime::Deno))
+        .unwrap();
+    registry
+        .install(&package("weather", "0.1.0", ToolRuntime::Python))
+        .unwrap();
+    assert!(registry
+        .install(&package("echo", "1.0.0", ToolRuntime::Deno))
+        .is_err());
+
+    let entries = registry
+        .list()
+        .unwrap()
+        .into_iter()
+        .map(|entry| format!("{}@{}", entry.id, entry.version))
+        .collect::<Vec<_>>();
+    assert_eq!(entries, vec!["echo@1.0.0", "echo@1.2.0", "weather@0.1.0"]);
+
+    let latest = registry.get("echo").unwrap();
+    assert_eq!(
+        latest.code_files.files["main.ts"],
+        "// echo 1.2.0".to_string()
+    );
+    let exact = registry.get("echo@1.0.0").unwrap();
+    assert_eq!(
+        exact.code_files.files["main.ts"],
+        "// echo 1.0.0".to_string()
+    );
+    let requirement = registry.get("echo@~1.0").unwrap();
+    assert_eq!(
+        requirement.code_files.files["main.ts"],
+        "// echo 1.0.0".to_string()
+    );
+    assert!(registry.get("echo@2").is_err());
+    assert!(registry.get("missing").is_err());
+}
+
+#[test]
+fn install_requires_valid_id_and_version() {
+    let storage = tempfile::tempdir().unwrap();
+    let registry = ToolRegistry::new(storage.path().to_path_buf());
+    assert!(registry
+        .install(&package("../escape", "1.0.0", ToolRuntime::Deno))
+        .is_err());
+    assert!(registry
+        .install(&package("echo", "latest", ToolRuntime::Deno))
+        .is_err());
+    let mut unversioned = package("echo", "1.0.0", ToolRuntime::Deno);
+    unversioned.definition.as_mut().unwrap().version = None;
+    assert!(registry.install(&unversioned).is_err());
+    assert!(registry.list().unwrap().is_empty());
+}
+
+#[test]
+fn upgrade_and_remove() {
+    let storage = tempfile::tempdir().unwrap();
+    let registry = ToolRegistry::new(storage.path().to_path_buf());
+    assert!(registry
+        .upgrade(&package("echo", "1.0.0", ToolRuntime::Deno))
+        .is_err());
+    registry
+        .install(&package("echo", "1.0.0", ToolRuntime::Deno))
+        .unwrap();
+    assert!(registry
+        .upgrade(&package("echo", "0.9.0", ToolRuntime::Deno))
+        .is_err());
+    registry
+        .upgrade(&package("echo", "1.1.0", ToolRuntime::Deno))
+        .unwrap();
+    assert_eq!(
+        registry.versions("echo").unwrap(),
+        vec![Version::new(1, 0, 0), Version::new(1, 1, 0)]
+    );
+
+    let removed = registry
+        .remove("echo", Some(&Version::new(1, 0, 0)))
+        .unwrap();
+    assert_eq!(removed.len(), 1);
+    assert_eq!(
+        registry.versions("echo").unwrap(),
+        vec![Version::new(1, 1, 0)]
+    );
+    assert!(registry
+        .remove("echo", Some(&Version::new(1, 0, 0)))
+        .is_err());
+    registry.remove("echo", None).unwrap();
+    assert!(registry.list().unwrap().is_empty());
+    assert!(!registry.root().join("echo").exists());
+}
+
+#[test]
+fn resolve_returns_runner_for_package_runtime() {
+    let storage = tempfile::tempdir().unwrap();
+    let registry = ToolRegistry::new(storage.path().to_path_buf());
+    registry
+        .install(&package("echo", "1.0.0", ToolRuntime::Deno))
+        .unwrap();
+    registry
+        .install(&package("weather", "0.1.0", ToolRuntime::Python))
+        .unwrap();
+    let context = ExecutionContext {
+        storage: storage.path().join("executions"),
+        ..Default::default()
+    };
+    let runner = registry
+        .resolve("echo@1.0.0", json!({}), Some(context.clone()))
+        .unwrap();
+    assert!(matches!(runner, ToolRunner::Deno(_)));
+    let runner = registry
+        .resolve("weather", json!({}), Some(context))
+        .unwrap();
+    assert_eq!(runner.runtime(), ToolRuntime::Python);
+}
+
+#[test]
+fn nested_calls_use_the_context_of_their_runtime() {
+    let storage = tempfile::tempdir().unwrap();
+    let registry = ToolRegistry::new(storage.path().to_path_buf())
+        .with_deno_options(DenoRunnerOptions {
+            context: ExecutionContext {
+                storage: storage.path().join("deno"),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .with_python_options(PythonRunnerOptions {
+            context: ExecutionContext {
+                storage: storage.path().join("python"),
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+    let context = registry.nested_context(ToolRuntime::Python);
+    assert_eq!(context.storage, storage.path().join("python"));
+    assert_ne!(
+        context.execution_id,
+        registry.python_options.context.execution_id
+    );
+    let context = registry.nested_context(ToolRuntime::Deno);
+    assert_eq!(context.storage, storage.path().join("deno"));
+}
+
+#[test]
+fn nested_calls_get_at_most_the_time_left_to_the_caller() {
+    let storage = tempfile::tempdir().unwrap();
+    let registry = ToolRegistry::new(storage.path().to_path_buf())
+        .with_tool_call_timeout(Duration::from_secs(60));
+    let tool_calls = ToolCallConfig::new(Arc::new(registry.clone()), "parent");
+    assert_eq!(
+        registry.nested_timeout(&tool_calls).unwrap(),
+        Duration::from_secs(60)
+    );
+
+    let timeout = registry
+        .nested_timeout(
+            &tool_calls
+                .clone()
+                .with_timeout(Some(Duration::from_secs(10))),
+        )
+        .unwrap();
+    assert!(timeout <= Duration::from_secs(10) && timeout > Duration::from_secs(5));
+
+    let timeout = registry
+        .nested_timeout(
+            &tool_calls
+                .clone()
+                .with_timeout(Some(Duration::from_secs(600))),
+        )
+        .unwrap();
+    assert_eq!(timeout, Duration::from_secs(60));
+
+    let expired = tool_calls.with_timeout(Some(Duration::ZERO));
+    assert!(registry.nested_timeout(&expired).is_err());
+}
+
+#[test]
+fn concurrent_installs_are_safe() {
+    let storage = tempfile::tempdir().unwrap();
+    let handles = (0..8)
+        .map(|i| {
+            let registry = ToolRegistry::new(storage.path().to_path_buf());
+            std::thread::spawn(move || {
+                let distinct = registry
+                    .install(&package("echo", &format!("1.0.{}", i), ToolRuntime::Deno))
+                    .is_ok();
+                let same = registry
+                    .install(&package("shared", "1.0.0", ToolRuntime::Deno))
+                    .is_ok();
+                (distinct, same)
+            })
+        })
+        .collect::<Vec<_>>();
+    let results = handles
+        .into_iter()
+        .map(|handle| handle.join().unwrap())
+        .collect::<Vec<_>>();
+    assert!(results.iter().all(|(distinct, _)| *distinct));
+    assert_eq!(results.iter().filter(|(_, same)| *same).count(), 1);
+
+    let registry = ToolRegistry::new(storage.path().to_path_buf());
+    assert_eq!(registry.versions("echo").unwrap().len(), 8);
+    assert!(registry.get("shared@1.0.0").is_ok());
+}
@@ -0,0 +1,49 @@
+use std::{collections::HashMap, time::Duration};
+
+use serde_json::Value;
+
+use super::{
+    deno_runner::DenoRunner, execution_error::ExecutionError, python_runner::PythonRunner,
+    run_result::RunResult, tool_definition::ToolDefinition, tool_package::ToolRuntime,
+};
+
+/// A runner for any supported runtime, as returned by `ToolRegistry::resolve`
+pub enum ToolRunner {
+    Deno(DenoRunner),
+    Python(PythonRunner),
+}
+
+impl ToolRunner {
+    pub fn runtime(&self) -> ToolRuntime {
+        match self {
+            ToolRunner::Deno(_) => ToolRuntime::Deno,
+            ToolRunner::Python(_) => ToolRuntime::Python,
+        }
+    }
+
+    pub async fn check(&self) -> anyhow::Result<Vec<String>> {
+        match self {
+            ToolRunner::Deno(runner) => runner.check().await,
+            ToolRunner::Python(runner) => runner.check().await,
+        }
+    }
+
+    pub async fn definition(&self) -> Result<ToolDefinition, ExecutionError> {
+        match self {
+            ToolRunner::Deno(runner) => runner.definition().await,
+            ToolRunner::Python(runner) => runner.definition().await,
+        }
+    }
+
+    pub async fn run(
+        &self,
+        envs: Option<HashMap<String, String>>,
+        parameters: Value,
+        max_execution_timeout: Option<Duration>,
+    ) -> Result<RunResult, ExecutionError> {
+        match self {
+            ToolRunner::Deno(runner) => runner.run(envs, parameters, max_execution_timeout).await,
+            ToolRunner::Python(runner) => runner.run(envs, parameters, max_execution_timeout).await,
+        }
+    }
+}
@@ -0,0 +1,248 @@
+use std::path::Path;
+
+use super::tool_definition::ToolDefinition;
+
+#[derive(Debug, Clone, Default)]
+pub struct ToolSearchFilter {
+    /// Tools must contain every keyword (case insensitive)
+    pub keywords: Vec<String>,
+    /// Tools must have this author (case insensitive)
+    pub author: Option<String>,
+}
+
+impl ToolSearchFilter {
+    fn matches(&self, definition: &ToolDefinition) -> bool {
+        let author_matches = self
+            .author
+            .as_ref()
+            .map(|author| definition.author.eq_ignore_ascii_case(author))
+            .unwrap_or(true);
+        let keywords_match = self.keywords.iter().all(|keyword| {
+            definition
+                .keywords
+                .iter()
+                .any(|tool_keyword| tool_keyword.eq_ignore_ascii_case(keyword))
+        });
+        author_matches && keywords_match
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ToolSearchMatch {
+    pub definition: ToolDefinition,
+    /// Cosine similarity between the query and the tool embeddings, in [-1, 1]
+    pub score: f32,
+}
+
+#[derive(Debug, Clone)]
+struct IndexedTool {
+    definition: ToolDefinition,
+    norm: f32,
+}
+
+/// In-process index answering cosine similarity queries over `ToolDefinition.embedding_metadata`
+///
+/// Every indexed embedding must come from the same model, the first definition added fixes the
+/// model name and dimensions unless the index is created with `ToolSearchIndex::new`.
+#[derive(Debug, Clone, Default)]
+pub struct ToolSearchIndex {
+    model_name: Option<String>,
+    dimensions: Option<usize>,
+    tools: Vec<IndexedTool>,
+}
+
+impl ToolSearchIndex {
+    pub fn new(model_name: &str) -> Self {
+        ToolSearchIndex {
+            model_name: Some(model_name.to_string()),
+            ..Default::default()
+        }
+    }
+
+    pub fn from_definitions(
+        definitions: impl IntoIterator<Item = ToolDefinition>,
+    ) -> anyhow::Result<Self> {
+        let mut index = ToolSearchIndex::default();
+        for definition in definitions {
+            index.add(definition)?;
+        }
+        Ok(index)
+    }
+
+    pub fn model_name(&self) -> Option<&str> {
+        self.model_name.as_deref()
+    }
+
+    pub fn len(&self) -> usize {
+        self.tools.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tools.is_empty()
+    }
+
+    /// Indexes a definition, replacing any previously indexed definition with the same id
+    pub fn add(&mut self, definition: ToolDefinition) -> anyhow::Result<()> {
+        let embedding_metadata = definition.embedding_metadata.as_ref().ok_or_else(|| {
+            anyhow::anyhow!("tool {} doesn't have embedding metadata", definition.id)
+        })?;
+        if embedding_metadata.embeddings.is_empty() {
+            return Err(anyhow::anyhow!(
+                "tool {} has empty embeddings",
+                definition.id
+            ));
+        }
+        if let Some(model_name) = &self.model_name {
+            if model_name != &embedding_metadata.model_name {
+                return Err(anyhow::anyhow!(
+                    "tool {} embeddings come from model {} but the index uses {}",
+                    definition.id,
+                    embedding_metadata.model_name,
+                    model_name
+                ));
+            }
+        }
+        let dimensions = embedding_metadata.embeddings.len();
+        if let Some(index_dimensions) = self.dimensions {
+            if index_dimensions != dimensions {
+                return Err(anyhow::anyhow!(
+                    "tool {} embeddings have {} dimensions but the index uses {}",
+                    definition.id,
+                    dimensions,
+                    index_dimensions
+                ));
+            }
+        }
+
+        self.model_name = Some(embedding_metadata.model_name.clone());
+        self.dimensions = Some(dimensions);
+        let norm = Self::norm(&embedding_metadata.embeddings);
+        self.remove(&definition.id);
+        log::debug!("indexing tool {}", definition.id);
+        self.tools.push(IndexedTool { definition, norm });
+        Ok(())
+    }
+
+    pub fn remove(&mut self, id: &str) -> Option<ToolDefinition> {
+        let position = self
+            .tools
+            .iter()
+            .position(|tool| tool.definition.id == id)?;
+        Some(self.tools.remove(position).definition)
+    }
+
+    pub fn get(&self, id: &str) -> Option<&ToolDefinition> {
+        self.tools
+            .iter()
+            .find(|tool| tool.definition.id == id)
+            .map(|tool| &tool.definition)
+    }
+
+    /// Recursively loads every `definition.json` under `path`
+    ///
+    /// Definitions without embeddings are skipped, the number of indexed definitions is returned.
+    pub fn load_from_dir(&mut self, path: &Path) -> anyhow::Result<usize> {
+        let mut loaded = 0;
+        for entry in std::fs::read_dir(path)? {
+            let entry_path = entry?.path();
+            if entry_path.is_dir() {
+                loaded += self.load_from_dir(&entry_path)?;
+                continue;
+            }
+            if entry_path.file_name().and_then(|name| name.to_str()) != Some("definition.json") {
+                continue;
+            }
+            let definition: ToolDefinition =
+                serde_json::from_str(&std::fs::read_to_string(&entry_path)?).map_err(|e| {
+                    anyhow::anyhow!("failed to parse {}: {}", entry_path.display(), e)
+                })?;
+            let has_embeddings = definition
+                .embedding_metadata
+                .as_ref()
+                .is_some_and(|metadata| !metadata.embeddings.is_empty());
+            if !has_embeddings {
+                log::warn!(
+                    "skipping tool {} without embeddings from {}",
+                    definition.id,
+                    entry_path.display()
+                );
+                continue;
+            }
+            self.add(definition)?;
+            loaded += 1;
+        }
+        Ok(loaded)
+    }
+
+    /// Returns the `k` tools most similar to `query`, best match first
+    ///
+    /// The query must be produced by the same model as the indexed embeddings.
+    pub fn search(
+        &self,
+        query: &[f32],
+        model_name: &str,
+        k: usize,
+        filter: &ToolSearchFilter,
+    ) -> anyhow::Result<Vec<ToolSearchMatch>> {
+        let Some(index_model_name) = &self.model_name else {
+            return Ok(Vec::new());
+        };
+        if index_model_name != model_name {
+            return Err(anyhow::anyhow!(
+                "query embeddings come from model {} but the index uses {}",
+                model_name,
+                index_model_name
+            ));
+        }
+        if let Some(dimensions) = self.dimensions {
+            if query.len() != dimensions {
+                return Err(anyhow::anyhow!(
+                    "query has {} dimensions but the index uses {}",
+                    query.len(),
+                    dimensions
+                ));
+            }
+        }
+
+        let query_norm = Self::norm(query);
+        let mut matches = self
+            .tools
+            .iter()
+            .filter(|tool| filter.matches(&tool.definition))
+            .map(|tool| {
+                let embeddings = &tool
+                    .definition
+                    .embedding_metadata
+                    .as_ref()
+                    .unwrap()
+                    .embeddings;
+                let dot = embeddings
+                    .iter()
+                    .zip(query)
+                    .map(|(a, b)| a * b)
+                    .sum::<f32>();
+                let denominator = tool.norm * query_norm;
+                let score = if denominator == 0.0 {
+                    0.0
+                } else {
+                    dot / denominator
+                };
+                ToolSearchMatch {
+                    definition: tool.definition.clone(),
+                    score,
+                }
+            })
+            .collect::<Vec<_>>();
+        matches.sort_by(|a, b| b.score.total_cmp(&a.score));
+        matches.truncate(k);
+        Ok(matches)
+    }
+
+    fn norm(vector: &[f32]) -> f32 {
+        vector.iter().map(|value| value * value).sum::<f32>().sqrt()
+    }
+}
+
+#[cfg(test)]
+#[path = "tool_search_index.test.rs"]
+mod tests;
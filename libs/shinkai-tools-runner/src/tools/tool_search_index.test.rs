@@ -0,0 +1,152 @@
+use serde_json::json;
+
+use crate::tools::{
+    tool_definition::{EmbeddingMetadata, ToolDefinition},
+    tool_search_index::{ToolSearchFilter, ToolSearchIndex},
+};
+
+fn definition(id: &str, author: &str, keywords: &[&str], embeddings: Vec<f32>) -> ToolDefinition {
+    ToolDefinition {
+        id: id.to_string(),
+        version: None,
+        name: id.to_string(),
+        description: format!("{} description", id),
+        author: author.to_string(),
+        keywords: keywords.iter().map(|k| k.to_string()).collect(),
+        configurations: json!({}),
+        parameters: json!({}),
+        result: json!({}),
+        code: None,
+        embedding_metadata: Some(EmbeddingMetadata {
+            model_name: "snowflake-arctic-embed:xs".to_string(),
+            embeddings,
+        }),
+        signature: None,
+    }
+}
+
+fn index() -> ToolSearchIndex {
+    ToolSearchIndex::from_definitions([
+        definition(
+            "weather",
+            "Shinkai",
+            &["weather", "api"],
+            vec![1.0, 0.0, 0.0],
+        ),
+        definition("news", "Shinkai", &["news", "api"], vec![0.0, 1.0, 0.0]),
+        definition("forecast", "Community", &["weather"], vec![0.9, 0.1, 0.0]),
+    ])
+    .unwrap()
+}
+
+#[test]
+fn search_returns_top_k_by_cosine_similarity() {
+    let index = index();
+    assert_eq!(index.len(), 3);
+    let matches = index
+        .search(
+            &[2.0, 0.0, 0.0],
+            "snowflake-arctic-embed:xs",
+            2,
+            &ToolSearchFilter::default(),
+        )
+        .unwrap();
+    assert_eq!(matches.len(), 2);
+    assert_eq!(matches[0].definition.id, "weather");
+    assert!((matches[0].score - 1.0).abs() < 1e-6);
+    assert_eq!(matches[1].definition.id, "forecast");
+}
+
+#[test]
+fn search_filters_by_keywords_and_author() {
+    let index = index();
+    let matches = index
+        .search(
+            &[1.0, 0.0, 0.0],
+            "snowflake-arctic-embed:xs",
+            10,
+            &ToolSearchFilter {
+                keywords: vec!["API".to_string()],
+                author: Some("shinkai".to_string()),
+            },
+        )
+        .unwrap();
+    let ids = matches
+        .iter()
+        .map(|m| m.definition.id.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(ids, vec!["weather", "news"]);
+}
+
+#[test]
+fn refuses_mismatched_models_and_dimensions() {
+    let mut index = index();
+    assert!(index
+        .search(
+            &[1.0, 0.0, 0.0],
+            "other-model",
+            1,
+            &ToolSearchFilter::default()
+        )
+        .is_err());
+    assert!(index
+        .search(
+            &[1.0, 0.0],
+            "snowflake-arctic-embed:xs",
+            1,
+            &ToolSearchFilter::default()
+        )
+        .is_err());
+
+    let mut other_model = definition("other", "Shinkai", &[], vec![1.0, 0.0, 0.0]);
+    other_model.embedding_metadata.as_mut().unwrap().model_name = "other-model".to_string();
+    assert!(index.add(other_model).is_err());
+    assert!(index
+        .add(definition("short", "Shinkai", &[], vec![1.0]))
+        .is_err());
+    assert_eq!(index.len(), 3);
+}
+
+#[test]
+fn add_replaces_definitions_with_same_id() {
+    let mut index = index();
+    index
+        .add(definition(
+            "news",
+            "Shinkai",
+            &["news"],
+            vec![1.0, 0.0, 0.0],
+        ))
+        .unwrap();
+    assert_eq!(index.len(), 3);
+    assert_eq!(
+        index.get("news").unwrap().keywords,
+        vec!["news".to_string()]
+    );
+    assert!(index.remove("news").is_some());
+    assert!(index.get("news").is_none());
+}
+
+#[test]
+fn load_from_dir_skips_definitions_without_embeddings() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    for (folder, definition) in [
+        (
+            "weather",
+            definition("weather", "Shinkai", &[], vec![1.0, 0.0]),
+        ),
+        ("empty", definition("empty", "Shinkai", &[], vec![])),
+    ] {
+        let folder = temp_dir.path().join(folder);
+        std::fs::create_dir_all(&folder).unwrap();
+        std::fs::write(
+            folder.join("definition.json"),
+            serde_json::to_string(&definition).unwrap(),
+        )
+        .unwrap();
+    }
+
+    let mut index = ToolSearchIndex::new("snowflake-arctic-embed:xs");
+    assert_eq!(index.load_from_dir(temp_dir.path()).unwrap(), 1);
+    assert!(index.get("weather").is_some());
+}
@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::{
+    code_files::CodeFiles,
+    execution_error::{ExecutionError, ExecutionErrorKind, SignatureRejection},
+    tool_definition::ToolDefinition,
+};
+
+/// Domain separator so a tool signature can't be replayed as a signature over anything else
+const SIGNATURE_CONTEXT: &[u8] = b"shinkai-tool-signature-v1:";
+
+/// An ed25519 signature over a content hash, with every field hex encoded
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ToolSignature {
+    pub public_key: String,
+    pub content_hash: String,
+    pub signature: String,
+}
+
+impl ToolSignature {
+    pub fn sign(signing_key: &SigningKey, content_hash: &str) -> Self {
+        let signature = signing_key.sign(&Self::message(content_hash));
+        ToolSignature {
+            public_key: hex_simd::encode_to_string(
+                signing_key.verifying_key().as_bytes(),
+                hex_simd::AsciiCase::Lower,
+            ),
+            content_hash: content_hash.to_string(),
+            signature: hex_simd::encode_to_string(signature.to_bytes(), hex_simd::AsciiCase::Lower),
+        }
+    }
+
+    pub fn sign_code_files(signing_key: &SigningKey, code_files: &CodeFiles) -> Self {
+        Self::sign(signing_key, &code_files.content_hash())
+    }
+
+    /// Signs the definition content (everything but the signature itself) and attaches the signature
+    pub fn sign_definition(signing_key: &SigningKey, definition: &mut ToolDefinition) {
+        definition.signature = Some(Self::sign(
+            signing_key,
+            &Self::definition_content_hash(definition),
+        ));
+    }
+
+    /// Hex encoded sha256 of the definition serialized without its signature
+    pub fn definition_content_hash(definition: &ToolDefinition) -> String {
+        let mut unsigned = definition.clone();
+        unsigned.signature = None;
+        let serialized = serde_json::to_vec(&unsigned).unwrap_or_default();
+        hex_simd::encode_to_string(Sha256::digest(serialized), hex_simd::AsciiCase::Lower)
+    }
+
+    pub fn verifying_key(&self) -> Result<VerifyingKey, SignatureRejection> {
+        let bytes: [u8; 32] = hex_simd::decode_to_vec(&self.public_key)
+            .ok()
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or(SignatureRejection::UntrustedKey)?;
+        VerifyingKey::from_bytes(&bytes).map_err(|_| SignatureRejection::UntrustedKey)
+    }
+
+    /// Checks the signature is valid for its own key and that it covers `content_hash`
+    pub fn verify(&self, content_hash: &str) -> Result<(), SignatureRejection> {
+        if self.content_hash != content_hash {
+            return Err(SignatureRejection::ContentHashMismatch);
+        }
+        let verifying_key = self.verifying_key()?;
+        let signature_bytes: [u8; 64] = hex_simd::decode_to_vec(&self.signature)
+            .ok()
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or(SignatureRejection::InvalidSignature)?;
+        verifying_key
+            .verify_strict(
+                &Self::message(content_hash),
+                &Signature::from_bytes(&signature_bytes),
+            )
+            .map_err(|_| SignatureRejection::InvalidSignature)
+    }
+
+    fn message(content_hash: &str) -> Vec<u8> {
+        [SIGNATURE_CONTEXT, content_hash.as_bytes()].concat()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SignaturePolicy {
+    /// Signatures are ignored
+    #[default]
+    Disabled,
+    /// Unsigned code runs, but a present signature must be valid and from a trusted key
+    VerifyIfPresent,
+    /// Code only runs with a valid signature from a trusted key
+    Required,
+}
+
+/// Set of trusted ed25519 public keys plus the policy runners apply before executing code
+#[derive(Debug, Clone, Default)]
+pub struct TrustStore {
+    pub policy: SignaturePolicy,
+    trusted_keys: HashMap<String, VerifyingKey>,
+}
+
+impl TrustStore {
+    pub fn new(policy: SignaturePolicy) -> Self {
+        TrustStore {
+            policy,
+            trusted_keys: HashMap::new(),
+        }
+    }
+
+    pub fn with_trusted_key(mut self, verifying_key: VerifyingKey) -> Self {
+        self.add_trusted_key(verifying_key);
+        self
+    }
+
+    pub fn add_trusted_key(&mut self, verifying_key: VerifyingKey) {
+        self.trusted_keys.insert(
+            hex_simd::encode_to_string(verifying_key.as_bytes(), hex_simd::AsciiCase::Lower),
+            verifying_key,
+        );
+    }
+
+    /// Adds a hex encoded ed25519 public key
+    pub fn add_trusted_key_hex(&mut self, public_key: &str) -> anyhow::Result<()> {
+        let bytes: [u8; 32] = hex_simd::decode_to_vec(public_key)
+            .map_err(|e| anyhow::anyhow!("invalid public key hex: {}", e))?
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("public key must be 32 bytes"))?;
+        self.add_trusted_key(VerifyingKey::from_bytes(&bytes)?);
+        Ok(())
+    }
+
+    pub fn is_trusted(&self, public_key: &str) -> bool {
+        self.trusted_keys
+            .contains_key(&public_key.to_ascii_lowercase())
+    }
+
+    /// Applies the policy to a content hash and its optional signature
+    pub fn verify(
+        &self,
+        content_hash: &str,
+        signature: Option<&ToolSignature>,
+    ) -> Result<(), SignatureRejection> {
+        let signature = match (self.policy, signature) {
+            (SignaturePolicy::Disabled, _) => return Ok(()),
+            (SignaturePolicy::VerifyIfPresent, None) => return Ok(()),
+            (SignaturePolicy::Required, None) => return Err(SignatureRejection::MissingSignature),
+            (_, Some(signature)) => signature,
+        };
+        if !self.is_trusted(&signature.public_key) {
+            return Err(SignatureRejection::UntrustedKey);
+        }
+        signature.verify(content_hash)
+    }
+
+    /// Verifies code before it runs, returning a `SignatureRejected` execution error on refusal
+    pub fn verify_code_files(
+        &self,
+        code_files: &CodeFiles,
+        signature: Option<&ToolSignature>,
+    ) -> Result<(), ExecutionError> {
+        self.verify(&code_files.content_hash(), signature)
+            .map_err(|rejection| {
+                log::error!("refusing to execute code: {:?}", rejection);
+                ExecutionError::new(format!("code signature rejected: {:?}", rejection), None)
+                    .with_kind(ExecutionErrorKind::SignatureRejected(rejection))
+            })
+    }
+
+    pub fn verify_definition(&self, definition: &ToolDefinition) -> Result<(), SignatureRejection> {
+        self.verify(
+            &ToolSignature::definition_content_hash(definition),
+            definition.signature.as_ref(),
+        )
+    }
+}
+
+#[cfg(test)]
+#[path = "tool_signature.test.rs"]
+mod tests;
@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+
+use ed25519_dalek::SigningKey;
+use serde_json::json;
+
+use crate::tools::{
+    code_files::CodeFiles,
+    deno_runner::DenoRunner,
+    deno_runner_options::DenoRunnerOptions,
+    execution_error::{ExecutionErrorKind, SignatureRejection},
+    python_runner::PythonRunner,
+    python_runner_options::PythonRunnerOptions,
+    tool_definition::ToolDefinition,
+    tool_signature::{SignaturePolicy, ToolSignature, TrustStore},
+};
+
+fn code_files() -> CodeFiles {
+    CodeFiles {
+        files: HashMap::from([(
+            "main.ts".to_string(),
+            r#"
+                async function run(configurations, params) {
+                    return { message: "hello world" };
+                }
+            "#
+            .to_string(),
+        )]),
+        entrypoint: "main.ts".to_string(),
+    }
+}
+
+fn definition() -> ToolDefinition {
+    ToolDefinition {
+        id: "shinkai-tool-echo".to_string(),
+        version: None,
+        name: "Shinkai: Echo".to_string(),
+        description: "Echoes the message".to_string(),
+        author: "Shinkai".to_string(),
+        keywords: vec!["echo".to_string()],
+        configurations: json!({}),
+        parameters: json!({}),
+        result: json!({}),
+        code: Some("function run() {}".to_string()),
+        embedding_metadata: None,
+        signature: None,
+    }
+}
+
+#[test]
+fn sign_and_verify_code_files() {
+    let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+    let trust_store =
+        TrustStore::new(SignaturePolicy::Required).with_trusted_key(signing_key.verifying_key());
+    let code_files = code_files();
+    let signature = ToolSignature::sign_code_files(&signing_key, &code_files);
+
+    assert!(trust_store
+        .verify_code_files(&code_files, Some(&signature))
+        .is_ok());
+
+    let mut tampered = code_files.clone();
+    tampered
+        .files
+        .insert("main.ts".to_string(), "function run() {}".to_string());
+    let error = trust_store
+        .verify_code_files(&tampered, Some(&signature))
+        .unwrap_err();
+    assert_eq!(
+        error.kind(),
+        &ExecutionErrorKind::SignatureRejected(SignatureRejection::ContentHashMismatch)
+    );
+
+    let mut forged = signature.clone();
+    forged.content_hash = tampered.content_hash();
+    assert_eq!(
+        trust_store.verify(&tampered.content_hash(), Some(&forged)),
+        Err(SignatureRejection::InvalidSignature)
+    );
+}
+
+#[test]
+fn trust_store_policies() {
+    let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+    let untrusted_key = SigningKey::from_bytes(&[9u8; 32]);
+    let content_hash = code_files().content_hash();
+    let untrusted_signature = ToolSignature::sign(&untrusted_key, &content_hash);
+
+    let disabled = TrustStore::default();
+    assert!(disabled.verify(&content_hash, None).is_ok());
+    assert!(disabled
+        .verify(&content_hash, Some(&untrusted_signature))
+        .is_ok());
+
+    let verify_if_present = TrustStore::new(SignaturePolicy::VerifyIfPresent)
+        .with_trusted_key(signing_key.verifying_key());
+    assert!(verify_if_present.verify(&content_hash, None).is_ok());
+    assert_eq!(
+        verify_if_present.verify(&content_hash, Some(&untrusted_signature)),
+        Err(SignatureRejection::UntrustedKey)
+    );
+
+    let mut required = TrustStore::new(SignaturePolicy::Required);
+    required
+        .add_trusted_key_hex(&hex_simd::encode_to_string(
+            signing_key.verifying_key().as_bytes(),
+            hex_simd::AsciiCase::Lower,
+        ))
+        .unwrap();
+    assert_eq!(
+        required.verify(&content_hash, None),
+        Err(SignatureRejection::MissingSignature)
+    );
+    assert!(required
+        .verify(
+            &content_hash,
+            Some(&ToolSignature::sign(&signing_key, &content_hash))
+        )
+        .is_ok());
+}
+
+#[test]
+fn sign_and_verify_definition() {
+    let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+    let trust_store =
+        TrustStore::new(SignaturePolicy::Required).with_trusted_key(signing_key.verifying_key());
+    let mut definition = definition();
+    ToolSignature::sign_definition(&signing_key, &mut definition);
+
+    let serialized = serde_json::to_string(&definition).unwrap();
+    let deserialized: ToolDefinition = serde_json::from_str(&serialized).unwrap();
+    assert!(trust_store.verify_definition(&deserialized).is_ok());
+
+    let mut tampered = deserialized.clone();
+    tampered.code = Some("function run() { steal() }".to_string());
+    assert_eq!(
+        trust_store.verify_definition(&tampered),
+        Err(SignatureRejection::ContentHashMismatch)
+    );
+}
+
+#[tokio::test]
+async fn runners_refuse_unsigned_code_when_required() {
+    let trust_store = TrustStore::new(SignaturePolicy::Required)
+        .with_trusted_key(SigningKey::from_bytes(&[7u8; 32]).verifying_key());
+
+    let deno_runner = DenoRunner::new(
+        code_files(),
+        json!({}),
+        Some(DenoRunnerOptions {
+            trust_store: trust_store.clone(),
+            ..Default::default()
+        }),
+    );
+    let error = deno_runner.run(None, json!({}), None).await.unwrap_err();
+    assert_eq!(
+        error.kind(),
+        &ExecutionErrorKind::SignatureRejected(SignatureRejection::MissingSignature)
+    );
+
+    let python_runner = PythonRunner::new(
+        CodeFiles {
+            files: HashMap::from([(
+                "main.py".to_string(),
+                "def run(c, p):\n    return 1".to_string(),
+            )]),
+            entrypoint: "main.py".to_string(),
+        },
+        json!({}),
+        Some(PythonRunnerOptions {
+            trust_store,
+            code_signature: Some(ToolSignature::sign(
+                &SigningKey::from_bytes(&[9u8; 32]),
+                "other-hash",
+            )),
+            ..Default::default()
+        }),
+    );
+    let error = python_runner.definition().await.unwrap_err();
+    assert_eq!(
+        error.kind(),
+        &ExecutionErrorKind::SignatureRejected(SignatureRejection::UntrustedKey)
+    );
+}
@@ -0,0 +1,418 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{self, Path, PathBuf},
+    sync::mpsc,
+    time::Duration,
+};
+
+use serde_json::{json, Value};
+use wasmtime::{Config, Engine, Linker, Module, Store, Trap};
+use wasmtime_wasi::{
+    p2::pipe::{MemoryInputPipe, MemoryOutputPipe},
+    preview1::WasiP1Ctx,
+    DirPerms, FilePerms, I32Exit, WasiCtxBuilder,
+};
+
+use super::{
+    code_files::CodeFiles,
+    execution_error::ExecutionError,
+    execution_log::LogStream,
+    execution_storage::ExecutionStorage,
+    file_name_utils::sanitize_for_file_name,
+    run_result::{RunMetadata, RunResult},
+    secret_configurations::SecretConfigurations,
+    wasm_runner_options::WasmRunnerOptions,
+};
+
+/// Runs a WASI preview 1 module in-process
+///
+/// The module reads `{"configurations": ..., "parameters": ...}` from stdin and writes its JSON
+/// result to stdout, stderr goes to the execution log. Secret configurations are left out of
+/// stdin and passed in the `SHINKAI_SECRET_CONFIGURATIONS` variable instead. The home folder, assets and mount files
+/// are the only host files it can reach, as preopens at `/home`, `/assets` and `/mount`.
+pub struct WasmRunner {
+    module: Vec<u8>,
+    configurations: Value,
+    options: WasmRunnerOptions,
+}
+
+/// Everything the blocking wasm thread needs, owned so it can outlive the caller's borrows
+struct WasiRun {
+    module: Vec<u8>,
+    input: Vec<u8>,
+    envs: Vec<(String, String)>,
+    preopens: Vec<(PathBuf, &'static str, bool)>,
+    fuel: Option<u64>,
+    max_output_bytes: usize,
+    max_execution_timeout: Option<Duration>,
+}
+
+struct WasiOutput {
+    stdout: Vec<u8>,
+    stderr: Vec<u8>,
+    result: anyhow::Result<()>,
+}
+
+impl WasmRunner {
+    pub const ENTRYPOINT_FILE_NAME: &'static str = "module.wasm";
+    pub const HOME_GUEST_PATH: &'static str = "/home";
+    pub const ASSETS_GUEST_PATH: &'static str = "/assets";
+    pub const MOUNT_GUEST_PATH: &'static str = "/mount";
+
+    /// `module` is a binary module, or its text format
+    pub fn new(module: Vec<u8>, configurations: Value, options: Option<WasmRunnerOptions>) -> Self {
+        WasmRunner {
+            module,
+            configurations,
+            options: options.unwrap_or_default(),
+        }
+    }
+
+    /// The module as the single file signed and verified against the trust store, hex encoded
+    pub fn code_files(module: &[u8]) -> CodeFiles {
+        CodeFiles {
+            files: HashMap::from([(
+                String::from(Self::ENTRYPOINT_FILE_NAME),
+                hex_simd::encode_to_string(module, hex_simd::AsciiCase::Lower),
+            )]),
+            entrypoint: String::from(Self::ENTRYPOINT_FILE_NAME),
+        }
+    }
+
+    pub async fn run(
+        &self,
+        envs: Option<HashMap<String, String>>,
+        parameters: Value,
+        max_execution_timeout: Option<Duration>,
+    ) -> Result<RunResult, ExecutionError> {
+        log::info!("preparing to run wasm tool");
+        self.options.trust_store.verify_code_files(
+            &Self::code_files(&self.module),
+            self.options.code_signature.as_ref(),
+        )?;
+        let (public_configurations, secrets) = SecretConfigurations::split(
+            &self.configurations,
+            &self.options.secret_configuration_keys,
+        );
+        let secrets = secrets.with_redaction_rules(&self.options.redaction_rules);
+        log::info!("configurations: {}", secrets.masked(&self.configurations));
+        log::info!(
+            "parameters: {}",
+            secrets.redactor().redact(&parameters.to_string())
+        );
+        let stdout = self
+            .run_module(
+                envs,
+                &parameters,
+                &public_configurations,
+                &secrets,
+                max_execution_timeout,
+            )
+            .await
+            .map_err(|e| ExecutionError::from_runner_error(e.to_string(), &e))?;
+        let result: Value = serde_json::from_slice(&stdout).map_err(|e| {
+            log::info!("failed to parse result: {}", e);
+            ExecutionError::new(format!("failed to parse result: {}", e), None)
+        })?;
+        Ok(RunResult {
+            data: result,
+            metadata: RunMetadata::default(),
+        })
+    }
+
+    async fn run_module(
+        &self,
+        envs: Option<HashMap<String, String>>,
+        parameters: &Value,
+        public_configurations: &Value,
+        secrets: &SecretConfigurations,
+        max_execution_timeout: Option<Duration>,
+    ) -> anyhow::Result<Vec<u8>> {
+        let context = &self.options.context;
+        let execution_storage = ExecutionStorage::new(
+            CodeFiles {
+                files: HashMap::new(),
+                entrypoint: String::from(Self::ENTRYPOINT_FILE_NAME),
+            },
+            context.clone(),
+        )
+        .with_redactor(secrets.redactor().clone());
+        let _lease = execution_storage.acquire_lease()?;
+        execution_storage.init(None)?;
+        let home = execution_storage.prepare_home()?;
+
+        // Files are copied into per execution folders since WASI only preopens folders
+        let execution_folder_name = sanitize_for_file_name(context.execution_id.clone());
+        let assets_folder_path = execution_storage
+            .assets_folder_path
+            .join(&execution_folder_name);
+        let mount_folder_path = execution_storage
+            .mount_folder_path
+            .join(&execution_folder_name);
+        let _copies =
+            RemoveFoldersGuard(vec![assets_folder_path.clone(), mount_folder_path.clone()]);
+        let assets_files = copy_files_into(&context.assets_files, &assets_folder_path)?;
+        let mount_files = copy_files_into(&context.mount_files, &mount_folder_path)?;
+        let guest_paths: HashMap<String, String> = mount_files
+            .iter()
+            .map(|(host_path, file_name)| {
+                (
+                    path::absolute(host_path)
+                        .unwrap_or(host_path.clone())
+                        .to_string_lossy()
+                        .to_string(),
+                    format!("{}/{}", Self::MOUNT_GUEST_PATH, file_name),
+                )
+            })
+            .collect();
+
+        let mut wasi_envs = vec![
+            (
+                String::from("SHINKAI_HOME"),
+                String::from(Self::HOME_GUEST_PATH),
+            ),
+            (
+                String::from("SHINKAI_ASSETS"),
+                assets_files
+                    .iter()
+                    .map(|(_, file_name)| format!("{}/{}", Self::ASSETS_GUEST_PATH, file_name))
+                    .collect::<Vec<_>>()
+                    .join(","),
+            ),
+            (
+                String::from("SHINKAI_MOUNT"),
+                mount_files
+                    .iter()
+                    .map(|(_, file_name)| format!("{}/{}", Self::MOUNT_GUEST_PATH, file_name))
+                    .collect::<Vec<_>>()
+                    .join(","),
+            ),
+            (
+                String::from("SHINKAI_CONTEXT_ID"),
+                context.context_id.clone(),
+            ),
+            (
+                String::from("SHINKAI_EXECUTION_ID"),
+                context.execution_id.clone(),
+            ),
+        ];
+        if !secrets.is_empty() {
+            wasi_envs.push((String::from(SecretConfigurations::ENV), secrets.env_value()));
+        }
+        wasi_envs.extend(envs.unwrap_or_default());
+
+        let mut preopens = vec![(
+            execution_storage.home_folder_path.clone(),
+            Self::HOME_GUEST_PATH,
+            true,
+        )];
+        if !assets_files.is_empty() {
+            preopens.push((assets_folder_path.clone(), Self::ASSETS_GUEST_PATH, false));
+        }
+        if !mount_files.is_empty() {
+            preopens.push((mount_folder_path.clone(), Self::MOUNT_GUEST_PATH, true));
+        }
+
+        let input = json!({
+            "configurations": adapt_paths(public_configurations, &guest_paths),
+            "parameters": adapt_paths(parameters, &guest_paths),
+        });
+        let run = WasiRun {
+            module: self.module.clone(),
+            input: serde_json::to_vec(&input)?,
+            envs: wasi_envs,
+            preopens,
+            fuel: self.options.fuel,
+            max_output_bytes: self.options.max_output_bytes,
+            max_execution_timeout: max_execution_timeout.or(self.options.max_execution_timeout),
+        };
+        log::info!(
+            "running wasm module with fuel {:?} and timeout {:?}",
+            run.fuel,
+            run.max_execution_timeout
+        );
+        let output = tokio::task::spawn_blocking(move || run_wasi(run)).await??;
+
+        for (stream, bytes) in [
+            (LogStream::Stdout, &output.stdout),
+            (LogStream::Stderr, &output.stderr),
+        ] {
+            for line in String::from_utf8_lossy(bytes).lines() {
+                let _ = execution_storage.append_log_entry(stream, None, line);
+            }
+        }
+        copy_files_back(&mount_files, &mount_folder_path)?;
+        if let Err(e) = output.result {
+            log::error!("wasm module failed: {}", e);
+            return Err(e);
+        }
+        home.succeeded()?;
+        Ok(output.stdout)
+    }
+}
+
+/// Removes the per execution copies when dropped, even if the run failed midway
+struct RemoveFoldersGuard(Vec<PathBuf>);
+
+impl Drop for RemoveFoldersGuard {
+    fn drop(&mut self) {
+        for folder_path in &self.0 {
+            if !folder_path.exists() {
+                continue;
+            }
+            if let Err(e) = std::fs::remove_dir_all(folder_path) {
+                log::warn!("failed to remove {}: {}", folder_path.display(), e);
+            }
+        }
+    }
+}
+
+/// Copies `files` into `folder_path`, returning them with their file names in it
+fn copy_files_into(
+    files: &[PathBuf],
+    folder_path: &Path,
+) -> anyhow::Result<Vec<(PathBuf, String)>> {
+    if folder_path.exists() {
+        std::fs::remove_dir_all(folder_path)?;
+    }
+    let mut file_names = HashSet::new();
+    let mut copied_files = Vec::new();
+    for host_path in files {
+        let file_name = host_path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .ok_or_else(|| anyhow::anyhow!("invalid file path {}", host_path.display()))?;
+        if !file_names.insert(file_name.clone()) {
+            return Err(anyhow::anyhow!(
+                "files share the file name {}, the wasm runner exposes them in one folder",
+                file_name
+            ));
+        }
+        std::fs::create_dir_all(folder_path)?;
+        std::fs::copy(host_path, folder_path.join(&file_name))?;
+        copied_files.push((host_path.clone(), file_name));
+    }
+    Ok(copied_files)
+}
+
+/// Writes the module changes back to the host files and removes the copies
+fn copy_files_back(files: &[(PathBuf, String)], folder_path: &Path) -> anyhow::Result<()> {
+    for (host_path, file_name) in files {
+        let copy = folder_path.join(file_name);
+        if copy.exists() {
+            std::fs::copy(&copy, host_path)?;
+        }
+    }
+    if folder_path.exists() {
+        std::fs::remove_dir_all(folder_path)?;
+    }
+    Ok(())
+}
+
+/// Replaces the host paths of mount files with their guest paths
+fn adapt_paths(value: &Value, guest_paths: &HashMap<String, String>) -> Value {
+    match value {
+        Value::String(s) => guest_paths
+            .get(s)
+            .map(|guest_path| Value::String(guest_path.clone()))
+            .unwrap_or(value.clone()),
+        Value::Array(items) => Value::Array(
+            items
+                .iter()
+                .map(|item| adapt_paths(item, guest_paths))
+                .collect(),
+        ),
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(key, item)| (key.clone(), adapt_paths(item, guest_paths)))
+                .collect(),
+        ),
+        _ => value.clone(),
+    }
+}
+
+/// Instantiates and runs the module's `_start`, blocking until it exits, traps or times out
+///
+/// Each run gets its own engine since epoch interruption is engine wide.
+fn run_wasi(run: WasiRun) -> anyhow::Result<WasiOutput> {
+    let mut config = Config::new();
+    config.consume_fuel(true).epoch_interruption(true);
+    let engine = Engine::new(&config)?;
+    let module = Module::new(&engine, &run.module)
+        .map_err(|e| anyhow::anyhow!("failed to load wasm module: {}", e))?;
+
+    let mut linker: Linker<WasiP1Ctx> = Linker::new(&engine);
+    wasmtime_wasi::preview1::add_to_linker_sync(&mut linker, |ctx| ctx)?;
+
+    let stdout = MemoryOutputPipe::new(run.max_output_bytes);
+    let stderr = MemoryOutputPipe::new(run.max_output_bytes);
+    let mut builder = WasiCtxBuilder::new();
+    builder
+        .stdin(MemoryInputPipe::new(run.input))
+        .stdout(stdout.clone())
+        .stderr(stderr.clone())
+        .args(&[WasmRunner::ENTRYPOINT_FILE_NAME])
+        .envs(&run.envs);
+    for (host_path, guest_path, writable) in &run.preopens {
+        let (dir_perms, file_perms) = if *writable {
+            (DirPerms::all(), FilePerms::all())
+        } else {
+            (DirPerms::READ, FilePerms::READ)
+        };
+        builder
+            .preopened_dir(host_path, *guest_path, dir_perms, file_perms)
+            .map_err(|e| anyhow::anyhow!("failed to preopen {}: {}", host_path.display(), e))?;
+    }
+
+    let mut store = Store::new(&engine, builder.build_p1());
+    store.set_fuel(run.fuel.unwrap_or(u64::MAX))?;
+    store.set_epoch_deadline(1);
+    let instance = linker.instantiate(&mut store, &module)?;
+    let start = instance.get_typed_func::<(), ()>(&mut store, "_start")?;
+
+    let (done_sender, done_receiver) = mpsc::channel::<()>();
+    let timer = run.max_execution_timeout.map(|timeout| {
+        let engine = engine.clone();
+        std::thread::spawn(move || {
+            if let Err(mpsc::RecvTimeoutError::Timeout) = done_receiver.recv_timeout(timeout) {
+                engine.increment_epoch();
+            }
+        })
+    });
+    let call_result = start.call(&mut store, ());
+    drop(done_sender);
+    if let Some(timer) = timer {
+        let _ = timer.join();
+    }
+
+    let stderr = stderr.contents().to_vec();
+    let result = match call_result {
+        Ok(()) => Ok(()),
+        Err(e) => match (e.downcast_ref::<I32Exit>(), e.downcast_ref::<Trap>()) {
+            (Some(I32Exit(0)), _) => Ok(()),
+            (Some(I32Exit(code)), _) => Err(anyhow::anyhow!(
+                "wasm module exited with code {}: {}",
+                code,
+                String::from_utf8_lossy(&stderr).trim()
+            )),
+            (_, Some(Trap::OutOfFuel)) => Err(anyhow::anyhow!(
+                "tool ran out of fuel after {} instructions",
+                run.fuel.unwrap_or(u64::MAX)
+            )),
+            (_, Some(Trap::Interrupt)) => Err(anyhow::anyhow!(
+                "process timed out after {}[s]",
+                run.max_execution_timeout.unwrap_or_default().as_secs()
+            )),
+            _ => Err(anyhow::anyhow!("wasm module trapped: {:?}", e)),
+        },
+    };
+    Ok(WasiOutput {
+        stdout: stdout.contents().to_vec(),
+        stderr,
+        result,
+    })
+}
+
+#[cfg(test)]
+#[path = "wasm_runner.test.rs"]
+mod tests;
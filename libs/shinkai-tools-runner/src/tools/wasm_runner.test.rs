@@ -0,0 +1,278 @@
+use std::time::Duration;
+
+use serde_json::json;
+
+use ed25519_dalek::SigningKey;
+
+use crate::tools::{
+    execution_context::ExecutionContext,
+    execution_error::{ExecutionErrorKind, SignatureRejection},
+    tool_signature::{SignaturePolicy, ToolSignature, TrustStore},
+    wasm_runner::WasmRunner,
+    wasm_runner_options::WasmRunnerOptions,
+};
+
+/// Writes stdin back to stdout
+const ECHO_MODULE: &str = r#"
+(module
+  (import "wasi_snapshot_preview1" "fd_read" (func $fd_read (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (func (export "_start")
+    (i32.store (i32.const 0) (i32.const 64))
+    (i32.store (i32.const 4) (i32.const 4000))
+    (drop (call $fd_read (i32.const 0) (i32.const 0) (i32.const 1) (i32.const 8)))
+    (i32.store (i32.const 4) (i32.load (i32.const 8)))
+    (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 8)))))
+"#;
+
+const LOOP_MODULE: &str = r#"
+(module
+  (func (export "_start")
+    (loop $forever (br $forever))))
+"#;
+
+/// Writes `out.txt` in the first preopen, appends to `data.txt` in the second one
+const FILES_MODULE: &str = r#"
+(module
+  (import "wasi_snapshot_preview1" "path_open" (func $path_open (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 100) "out.txt")
+  (data (i32.const 120) "data.txt")
+  (data (i32.const 200) "hello")
+  (data (i32.const 300) "{\"written\":true}")
+  (func $write (param $fd i32) (param $ptr i32) (param $len i32)
+    (i32.store (i32.const 0) (local.get $ptr))
+    (i32.store (i32.const 4) (local.get $len))
+    (drop (call $fd_write (local.get $fd) (i32.const 0) (i32.const 1) (i32.const 8))))
+  (func (export "_start")
+    (drop (call $path_open (i32.const 3) (i32.const 0) (i32.const 100) (i32.const 7) (i32.const 1) (i64.const 64) (i64.const 0) (i32.const 0) (i32.const 16)))
+    (call $write (i32.load (i32.const 16)) (i32.const 200) (i32.const 5))
+    (drop (call $path_open (i32.const 4) (i32.const 0) (i32.const 120) (i32.const 8) (i32.const 0) (i64.const 64) (i64.const 0) (i32.const 1) (i32.const 16)))
+    (call $write (i32.load (i32.const 16)) (i32.const 200) (i32.const 5))
+    (call $write (i32.const 1) (i32.const 300) (i32.const 16))))
+"#;
+
+const EXIT_MODULE: &str = r#"
+(module
+  (import "wasi_snapshot_preview1" "proc_exit" (func $proc_exit (param i32)))
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 100) "bad input")
+  (func (export "_start")
+    (i32.store (i32.const 0) (i32.const 100))
+    (i32.store (i32.const 4) (i32.const 9))
+    (drop (call $fd_write (i32.const 2) (i32.const 0) (i32.const 1) (i32.const 8)))
+    (call $proc_exit (i32.const 3))))
+"#;
+
+fn options(storage: &std::path::Path) -> WasmRunnerOptions {
+    WasmRunnerOptions {
+        context: ExecutionContext {
+            storage: storage.to_path_buf(),
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn passes_configurations_and_parameters_on_stdin() {
+    let folder = tempfile::tempdir().unwrap();
+    let runner = WasmRunner::new(
+        ECHO_MODULE.as_bytes().to_vec(),
+        json!({ "api_key": "123" }),
+        Some(options(folder.path())),
+    );
+    let result = runner
+        .run(None, json!({ "name": "world" }), None)
+        .await
+        .unwrap();
+    assert_eq!(
+        result.data,
+        json!({
+            "configurations": { "api_key": "123" },
+            "parameters": { "name": "world" },
+        })
+    );
+    assert_eq!(result.metadata.runner_type, None);
+}
+
+#[tokio::test]
+async fn keeps_secret_configurations_out_of_stdin_and_logs() {
+    let folder = tempfile::tempdir().unwrap();
+    let mut options = options(folder.path());
+    options.secret_configuration_keys = vec![String::from("api_key")];
+    let runner = WasmRunner::new(
+        ECHO_MODULE.as_bytes().to_vec(),
+        json!({ "api_key": "sk-secret-123", "model": "small" }),
+        Some(options),
+    );
+    let result = runner
+        .run(None, json!({ "echo": "sk-secret-123" }), None)
+        .await
+        .unwrap();
+    assert_eq!(result.data["configurations"], json!({ "model": "small" }));
+
+    let logs_folder = folder
+        .path()
+        .join(&runner.options.context.context_id)
+        .join("logs");
+    let logs = std::fs::read_dir(logs_folder)
+        .unwrap()
+        .map(|entry| std::fs::read_to_string(entry.unwrap().path()).unwrap())
+        .collect::<String>();
+    assert!(logs.contains("small"), "{}", logs);
+    assert!(!logs.contains("sk-secret-123"), "{}", logs);
+}
+
+#[tokio::test]
+async fn verifies_the_module_signature() {
+    let folder = tempfile::tempdir().unwrap();
+    let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+    let mut options = options(folder.path());
+    options.trust_store =
+        TrustStore::new(SignaturePolicy::Required).with_trusted_key(signing_key.verifying_key());
+    let module = ECHO_MODULE.as_bytes().to_vec();
+
+    let unsigned = WasmRunner::new(module.clone(), json!({}), Some(options.clone()));
+    let error = unsigned.run(None, json!({}), None).await.unwrap_err();
+    assert_eq!(
+        error.kind(),
+        &ExecutionErrorKind::SignatureRejected(SignatureRejection::MissingSignature)
+    );
+
+    options.code_signature = Some(ToolSignature::sign_code_files(
+        &signing_key,
+        &WasmRunner::code_files(&module),
+    ));
+    let tampered = WasmRunner::new(
+        LOOP_MODULE.as_bytes().to_vec(),
+        json!({}),
+        Some(options.clone()),
+    );
+    let error = tampered.run(None, json!({}), None).await.unwrap_err();
+    assert_eq!(
+        error.kind(),
+        &ExecutionErrorKind::SignatureRejected(SignatureRejection::ContentHashMismatch)
+    );
+
+    let signed = WasmRunner::new(module, json!({}), Some(options));
+    assert!(signed.run(None, json!({}), None).await.is_ok());
+}
+
+#[tokio::test]
+async fn writes_home_and_mount_files_through_preopens() {
+    let folder = tempfile::tempdir().unwrap();
+    let mount_file = folder.path().join("data.txt");
+    std::fs::write(&mount_file, "mounted ").unwrap();
+    let mut options = options(&folder.path().join("storage"));
+    options.context.mount_files = vec![mount_file.clone()];
+    let runner = WasmRunner::new(FILES_MODULE.as_bytes().to_vec(), json!({}), Some(options));
+
+    let result = runner.run(None, json!({}), None).await.unwrap();
+    assert_eq!(result.data, json!({ "written": true }));
+    let home = folder
+        .path()
+        .join("storage")
+        .join(&runner.options.context.context_id)
+        .join("home");
+    assert_eq!(
+        std::fs::read_to_string(home.join("out.txt")).unwrap(),
+        "hello"
+    );
+    assert_eq!(
+        std::fs::read_to_string(&mount_file).unwrap(),
+        "mounted hello"
+    );
+}
+
+#[tokio::test]
+async fn stops_module_out_of_fuel() {
+    let folder = tempfile::tempdir().unwrap();
+    let mut options = options(folder.path());
+    options.fuel = Some(100_000);
+    let runner = WasmRunner::new(LOOP_MODULE.as_bytes().to_vec(), json!({}), Some(options));
+    let error = runner.run(None, json!({}), None).await.unwrap_err();
+    assert!(error.message().contains("ran out of fuel"), "{}", error);
+}
+
+#[tokio::test]
+async fn stops_module_on_timeout() {
+    let folder = tempfile::tempdir().unwrap();
+    let mut options = options(folder.path());
+    options.fuel = None;
+    let runner = WasmRunner::new(LOOP_MODULE.as_bytes().to_vec(), json!({}), Some(options));
+    let error = runner
+        .run(None, json!({}), Some(Duration::from_secs(1)))
+        .await
+        .unwrap_err();
+    assert!(
+        error.message().contains("timed out after 1[s]"),
+        "{}",
+        error
+    );
+}
+
+#[tokio::test]
+async fn options_timeout_applies_when_run_has_none() {
+    let folder = tempfile::tempdir().unwrap();
+    let mut options = options(folder.path());
+    options.fuel = None;
+    options.max_execution_timeout = Some(Duration::from_secs(1));
+    let runner = WasmRunner::new(LOOP_MODULE.as_bytes().to_vec(), json!({}), Some(options));
+    let error = runner.run(None, json!({}), None).await.unwrap_err();
+    assert!(
+        error.message().contains("timed out after 1[s]"),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn default_options_limit_fuel_and_time() {
+    let options = WasmRunnerOptions::default();
+    assert!(options.fuel.is_some());
+    assert!(options.max_execution_timeout.is_some());
+}
+
+#[tokio::test]
+async fn removes_copied_files_when_the_module_fails_to_load() {
+    let folder = tempfile::tempdir().unwrap();
+    let mount_file = folder.path().join("data.txt");
+    std::fs::write(&mount_file, "mounted").unwrap();
+    let mut options = options(&folder.path().join("storage"));
+    options.context.mount_files = vec![mount_file.clone()];
+    options.context.assets_files = vec![mount_file.clone()];
+    let runner = WasmRunner::new(b"not a module".to_vec(), json!({}), Some(options));
+
+    runner.run(None, json!({}), None).await.unwrap_err();
+    let context_folder = folder
+        .path()
+        .join("storage")
+        .join(&runner.options.context.context_id);
+    for folder_name in ["assets", "mount"] {
+        let copies = std::fs::read_dir(context_folder.join(folder_name))
+            .map(|entries| entries.count())
+            .unwrap_or(0);
+        assert_eq!(copies, 0, "{} copies were left behind", folder_name);
+    }
+    assert_eq!(std::fs::read_to_string(&mount_file).unwrap(), "mounted");
+}
+
+#[tokio::test]
+async fn non_zero_exit_reports_stderr() {
+    let folder = tempfile::tempdir().unwrap();
+    let runner = WasmRunner::new(
+        EXIT_MODULE.as_bytes().to_vec(),
+        json!({}),
+        Some(options(folder.path())),
+    );
+    let error = runner.run(None, json!({}), None).await.unwrap_err();
+    assert!(
+        error.message().contains("exited with code 3: bad input"),
+        "{}",
+        error
+    );
+}
@@ -0,0 +1,40 @@
+use std::{sync::Arc, time::Duration};
+
+use super::{
+    execution_context::ExecutionContext,
+    redaction::RedactionRule,
+    tool_signature::{ToolSignature, TrustStore},
+};
+
+#[derive(Clone)]
+pub struct WasmRunnerOptions {
+    pub context: ExecutionContext,
+    /// Instructions budget of a run, unlimited when None so only the timeout applies
+    pub fuel: Option<u64>,
+    /// Used when `run` isn't given a timeout, unlimited when None
+    pub max_execution_timeout: Option<Duration>,
+    /// Bytes of stdout and stderr kept each, writes beyond it fail in the module
+    pub max_output_bytes: usize,
+    pub trust_store: TrustStore,
+    /// Signature over `WasmRunner::code_files` of the module
+    pub code_signature: Option<ToolSignature>,
+    /// Top level configurations keys delivered out-of-band and redacted from logs
+    pub secret_configuration_keys: Vec<String>,
+    /// Extra redaction applied to logs and execution log entries
+    pub redaction_rules: Vec<Arc<dyn RedactionRule>>,
+}
+
+impl Default for WasmRunnerOptions {
+    fn default() -> Self {
+        Self {
+            context: ExecutionContext::default(),
+            fuel: Some(10_000_000_000),
+            max_execution_timeout: Some(Duration::from_secs(60)),
+            max_output_bytes: 16 * 1024 * 1024,
+            trust_store: TrustStore::default(),
+            code_signature: None,
+            secret_configuration_keys: Vec::new(),
+            redaction_rules: Vec::new(),
+        }
+    }
+}